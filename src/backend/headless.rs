@@ -0,0 +1,724 @@
+use winapi::*;
+
+use ::backend::{ self, Backend, ClassDesc, Surface, Waker, WindowDesc };
+use ::colors::Color;
+use ::event::Event;
+use ::event_loop::Dispatch;
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::raster;
+use ::region::Region;
+use ::window::{ self, Window };
+
+use std::cell::{ Cell, RefCell };
+use std::collections::{ HashMap, VecDeque };
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{ Arc, Condvar, Mutex };
+use std::time::Duration;
+
+/// Client size used for windows created with `CW_USEDEFAULT`.
+pub const DEFAULT_WIDTH: i32 = 640;
+pub const DEFAULT_HEIGHT: i32 = 480;
+
+/// Messages posted from other threads, waiting to be moved into the queue.
+struct Inbox {
+    messages: Mutex<Vec<(usize, UINT, WPARAM, LPARAM)>>,
+    posted: Condvar,
+}
+
+impl Inbox {
+    /// Blocks until another thread posts something.
+    fn wait(&self) {
+        let mut messages = self.messages.lock().unwrap();
+        while messages.is_empty() {
+            messages = self.posted.wait(messages).unwrap();
+        }
+    }
+}
+
+impl Waker for Inbox {
+    fn post(&self, handle: usize, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool {
+        self.messages.lock().unwrap().push((handle, msg, w_param, l_param));
+        self.posted.notify_one();
+        true
+    }
+}
+
+/// Like Win32, shorter timer intervals are rounded up to this.
+const USER_TIMER_MINIMUM: u64 = 10;
+
+struct HeadlessTimer {
+    handle: usize,
+    id: usize,
+    interval: u64,
+    /// When it fires next, on the virtual clock.
+    due: u64,
+}
+
+/// A drawing operation recorded by a headless surface.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DrawCall {
+    FillRect { rect: Rect, color: Color },
+    Line { from: Point, to: Point, color: Color },
+    Text { text: String, rect: Rect, font_height: i32, color: Color, format: UINT },
+}
+
+struct HeadlessWindow {
+    title: String,
+    /// Where the client area is on the screen.
+    position: Point,
+    width: i32,
+    height: i32,
+    /// The window an overlay belongs to.
+    owner: Option<usize>,
+    visible: bool,
+    user_data: LONG_PTR,
+    invalid: Region,
+    draw_calls: Rc<RefCell<Vec<DrawCall>>>,
+}
+
+/// A backend that keeps all windows in memory.
+///
+/// Messages are delivered straight to `window_proc`, posted messages are
+/// queued until `dispatch_pending` is called and painting records
+/// `DrawCall`s instead of touching any pixels. Invalidated areas collect in
+/// a per-window `Region` until the window gets painted.
+///
+/// Top-level windows all sit in the top left corner of the screen. Popup
+/// windows with an owner become overlays of it instead: they are painted on
+/// top of the owner and get the mouse input over them that was sent to the
+/// owner, like an owned popup window would.
+pub struct HeadlessBackend {
+    classes: RefCell<Vec<String>>,
+    messages: RefCell<Vec<String>>,
+    windows: RefCell<HashMap<usize, HeadlessWindow>>,
+    queue: RefCell<VecDeque<MSG>>,
+    inbox: Arc<Inbox>,
+    next_handle: Cell<usize>,
+    live_fonts: Cell<usize>,
+    quit_code: Cell<Option<i32>>,
+    /// Set along with `quit_code` until the event loop has seen it.
+    quit_pending: Cell<Option<i32>>,
+    /// Milliseconds of virtual time passed, only ever moved by `advance`.
+    clock: Cell<u64>,
+    timers: RefCell<Vec<HeadlessTimer>>,
+}
+
+impl HeadlessBackend {
+    pub fn new() -> Self {
+        HeadlessBackend {
+            classes: RefCell::new(Vec::new()),
+            messages: RefCell::new(Vec::new()),
+            windows: RefCell::new(HashMap::new()),
+            queue: RefCell::new(VecDeque::new()),
+            inbox: Arc::new(Inbox { messages: Mutex::new(Vec::new()), posted: Condvar::new() }),
+            next_handle: Cell::new(1),
+            live_fonts: Cell::new(0),
+            quit_code: Cell::new(None),
+            quit_pending: Cell::new(None),
+            clock: Cell::new(0),
+            timers: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Creates a headless backend and makes it the backend of this thread.
+    pub fn install() -> Rc<HeadlessBackend> {
+        let backend = Rc::new(HeadlessBackend::new());
+        backend::set_current(backend.clone());
+        backend
+    }
+
+    pub fn is_window(&self, handle: HWND) -> bool {
+        self.windows.borrow().contains_key(&(handle as usize))
+    }
+
+    pub fn is_visible(&self, handle: HWND) -> bool {
+        self.windows.borrow().get(&(handle as usize)).map_or(false, |w| w.visible)
+    }
+
+    pub fn title(&self, handle: HWND) -> Option<String> {
+        self.windows.borrow().get(&(handle as usize)).map(|w| w.title.clone())
+    }
+
+    pub fn client_size(&self, handle: HWND) -> Option<(i32, i32)> {
+        self.windows.borrow().get(&(handle as usize)).map(|w| (w.width, w.height))
+    }
+
+    /// The visible overlays of the window, bottom to top.
+    pub fn overlays(&self, handle: HWND) -> Vec<HWND> {
+        let mut overlays = self.windows.borrow().iter()
+            .filter(|&(_, w)| w.visible && w.owner == Some(handle as usize))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        overlays.sort();
+        overlays.into_iter().map(|id| id as HWND).collect()
+    }
+
+    /// Where the client area of the window is on the screen.
+    pub fn screen_rect(&self, handle: HWND) -> Option<Rect> {
+        self.windows.borrow().get(&(handle as usize))
+            .map(|w| Rect::from_origin_size(w.position, Size::new(w.width, w.height)))
+    }
+
+    /// Changes the client size and sends `WM_SIZE`, like dragging the
+    /// window border would.
+    pub fn resize(&self, handle: HWND, width: i32, height: i32) {
+        match self.windows.borrow_mut().get_mut(&(handle as usize)) {
+            Some(w) => {
+                w.width = width;
+                w.height = height;
+            },
+            None => return,
+        }
+        self.send_message(handle, WM_SIZE, SIZE_RESTORED as WPARAM, ((height << 16) | (width & 0xFFFF)) as LPARAM);
+    }
+
+    /// The coalesced update region of the window.
+    pub fn invalid_region(&self, handle: HWND) -> Vec<Rect> {
+        self.windows.borrow().get(&(handle as usize)).map_or(Vec::new(), |w| w.invalid.rects().to_vec())
+    }
+
+    /// Sends `WM_PAINT` to the window and returns what got drawn.
+    ///
+    /// The paint rectangle covers the update region, or the whole client
+    /// area if nothing was invalidated. Visible overlays get painted after
+    /// the window, with what they drew moved into its coordinates.
+    pub fn paint(&self, handle: HWND) -> Vec<DrawCall> {
+        self.send_message(handle, WM_PAINT, 0, 0);
+        let mut calls = self.draw_calls(handle);
+        let origin = self.screen_rect(handle).map_or(Point::default(), |r| r.origin());
+        for overlay in self.overlays(handle) {
+            let offset = self.screen_rect(overlay).map_or(Point::default(), |r| r.origin()) - origin;
+            calls.extend(self.paint(overlay).iter().map(|call| call.offset(offset)));
+        }
+        calls
+    }
+
+    /// Returns the calls recorded since the window was last painted.
+    pub fn draw_calls(&self, handle: HWND) -> Vec<DrawCall> {
+        match self.windows.borrow().get(&(handle as usize)) {
+            Some(w) => w.draw_calls.borrow().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Delivers all queued messages, returning how many there were.
+    ///
+    /// Like `GetMessage`, windows with a non-empty update region get their
+    /// `WM_PAINT` once the queue is empty.
+    pub fn dispatch_pending(&self) -> usize {
+        let mut count = 0;
+        loop {
+            self.collect_inbox();
+            let msg = match self.queue.borrow_mut().pop_front() {
+                Some(msg) => msg,
+                None => break,
+            };
+            self.deliver(msg.hwnd, msg.message, msg.wParam, msg.lParam);
+            count += 1;
+        }
+
+        let dirty = self.windows.borrow().iter()
+            .filter(|&(_, w)| !w.invalid.is_empty())
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for id in dirty {
+            self.send_message(id as HWND, WM_PAINT, 0, 0);
+        }
+        count
+    }
+
+    pub fn pending_messages(&self) -> usize {
+        self.collect_inbox();
+        self.queue.borrow().len()
+    }
+
+    /// Moves what other threads posted into the queue. Messages for windows
+    /// that are gone by now are dropped, like `PostMessage` would.
+    fn collect_inbox(&self) {
+        let posted = mem::replace(&mut *self.inbox.messages.lock().unwrap(), Vec::new());
+        for (handle, msg, w_param, l_param) in posted {
+            self.post_message(handle as HWND, msg, w_param, l_param);
+        }
+    }
+
+    /// The exit code passed to `PostQuitMessage`, if it was called.
+    pub fn quit_code(&self) -> Option<i32> {
+        self.quit_code.get()
+    }
+
+    /// How much virtual time has passed.
+    pub fn now(&self) -> Duration {
+        Duration::from_millis(self.clock.get())
+    }
+
+    /// Moves the virtual clock forward, sending `WM_TIMER` for every timer
+    /// that comes due on the way. Timers fire in the order they are due in,
+    /// ties in the order they were set, and repeating ones as often as they
+    /// fit into `by`.
+    pub fn advance(&self, by: Duration) {
+        let target = self.clock.get() + ::timer::interval(by) as u64;
+        loop {
+            let next = self.timers.borrow().iter().enumerate()
+                .filter(|&(_, t)| t.due <= target)
+                .min_by_key(|&(i, t)| (t.due, i))
+                .map(|(i, t)| (i, t.handle, t.id, t.due));
+            let (index, handle, id, due) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            self.clock.set(due);
+            {
+                let mut timers = self.timers.borrow_mut();
+                let timer = &mut timers[index];
+                timer.due = due + timer.interval;
+            }
+            self.send_message(handle as HWND, WM_TIMER, id as WPARAM, 0);
+        }
+        self.clock.set(target);
+    }
+
+    /// The number of timers running for `handle`.
+    pub fn timer_count(&self, handle: HWND) -> usize {
+        self.timers.borrow().iter().filter(|t| t.handle == handle as usize).count()
+    }
+
+    /// The number of fonts that were created but not yet deleted.
+    pub fn live_fonts(&self) -> usize {
+        self.live_fonts.get()
+    }
+
+    /// Hands a message to `window_proc`, or to the overlay under the mouse
+    /// for mouse input.
+    fn deliver(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        if !self.is_window(handle) {
+            return 0;
+        }
+        let (handle, l_param) = self.route_mouse(handle, msg, w_param, l_param);
+        unsafe { window::window_proc(handle, msg, w_param, l_param) }
+    }
+
+    /// The topmost visible overlay of `handle` under the mouse, if there is
+    /// one, with `l_param` in its coordinates.
+    fn route_mouse(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> (HWND, LPARAM) {
+        if msg < WM_MOUSEFIRST || msg > WM_MOUSELAST {
+            return (handle, l_param);
+        }
+        let event = Event::decode(msg, w_param, l_param);
+        // Wheel messages come in screen coordinates already.
+        let screen = match event {
+            Event::MouseMove { position, .. } | Event::MouseDown { position, .. } |
+            Event::MouseUp { position, .. } | Event::DoubleClick { position, .. } =>
+                self.client_to_screen(handle, position),
+            Event::MouseWheel { position, .. } => position,
+            _ => return (handle, l_param),
+        };
+        let overlay = self.overlays(handle).into_iter().rev()
+            .filter_map(|overlay| self.screen_rect(overlay).map(|rect| (overlay, rect)))
+            .find(|&(_, rect)| rect.contains(screen));
+        match overlay {
+            Some((overlay, rect)) => {
+                let origin = self.client_to_screen(handle, Point::default());
+                (overlay, event.relative_to(rect.origin() - origin).encode().2)
+            },
+            None => (handle, l_param),
+        }
+    }
+
+    fn allocate_handle(&self) -> usize {
+        let handle = self.next_handle.get();
+        self.next_handle.set(handle + 1);
+        handle
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn register_class(&self, class: &ClassDesc) {
+        self.classes.borrow_mut().push(class.class_name.clone());
+    }
+
+    fn create_window(&self, desc: &WindowDesc, window: Box<Box<Window>>) -> Result<HWND, DWORD> {
+        if !self.classes.borrow().contains(&desc.class_name) {
+            return Err(ERROR_CANNOT_FIND_WND_CLASS);
+        }
+
+        let id = self.allocate_handle();
+        let width = if desc.width == CW_USEDEFAULT { DEFAULT_WIDTH } else { desc.width };
+        let height = if desc.height == CW_USEDEFAULT { DEFAULT_HEIGHT } else { desc.height };
+        let owner = if desc.style & WS_POPUP != 0 && self.is_window(desc.parent) {
+            Some(desc.parent as usize)
+        } else {
+            None
+        };
+        let position = match owner {
+            Some(_) if desc.pos_x != CW_USEDEFAULT => Point::new(desc.pos_x, desc.pos_y),
+            _ => Point::default(),
+        };
+        self.windows.borrow_mut().insert(id, HeadlessWindow {
+            title: desc.window_name.clone(),
+            position: position,
+            width: width,
+            height: height,
+            owner: owner,
+            visible: false,
+            user_data: 0,
+            invalid: Region::new(),
+            draw_calls: Rc::new(RefCell::new(Vec::new())),
+        });
+
+        let handle = id as HWND;
+        unsafe {
+            let mut create: CREATESTRUCTW = mem::zeroed();
+            create.lpCreateParams = Box::into_raw(window) as LPVOID;
+            create.hInstance = desc.instance;
+            create.hMenu = desc.menu;
+            create.hwndParent = desc.parent;
+            create.cx = width;
+            create.cy = height;
+            create.x = desc.pos_x;
+            create.y = desc.pos_y;
+            create.style = desc.style as LONG;
+            create.dwExStyle = desc.ex_style;
+            window::window_proc(handle, WM_CREATE, 0, &mut create as *mut CREATESTRUCTW as LPARAM);
+        }
+        self.send_message(handle, WM_SIZE, SIZE_RESTORED as WPARAM, ((height << 16) | (width & 0xFFFF)) as LPARAM);
+        Ok(handle)
+    }
+
+    /// Overlays go first, the way owned windows do.
+    fn destroy_window(&self, handle: HWND) {
+        let overlays = self.windows.borrow().iter()
+            .filter(|&(_, w)| w.owner == Some(handle as usize))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for overlay in overlays {
+            self.destroy_window(overlay as HWND);
+        }
+        if self.is_window(handle) {
+            self.send_message(handle, WM_DESTROY, 0, 0);
+            self.windows.borrow_mut().remove(&(handle as usize));
+            self.timers.borrow_mut().retain(|t| t.handle != handle as usize);
+        }
+    }
+
+    fn show_window(&self, handle: HWND, cmd_show: i32) {
+        if let Some(w) = self.windows.borrow_mut().get_mut(&(handle as usize)) {
+            w.visible = cmd_show != SW_HIDE;
+        }
+    }
+
+    fn update_window(&self, _handle: HWND) { }
+
+    fn move_window(&self, handle: HWND, rect: &Rect) {
+        let resized = match self.windows.borrow_mut().get_mut(&(handle as usize)) {
+            Some(w) => {
+                let resized = w.width != rect.width() || w.height != rect.height();
+                w.position = rect.origin();
+                w.width = rect.width();
+                w.height = rect.height();
+                resized
+            },
+            None => return,
+        };
+        if resized {
+            let (width, height) = (rect.width(), rect.height());
+            self.send_message(handle, WM_SIZE, SIZE_RESTORED as WPARAM, ((height << 16) | (width & 0xFFFF)) as LPARAM);
+        }
+    }
+
+    fn client_to_screen(&self, handle: HWND, point: Point) -> Point {
+        self.screen_rect(handle).map_or(point, |r| point + r.origin())
+    }
+
+    /// The client area of the top-level window the window belongs to.
+    fn work_area(&self, handle: HWND) -> Rect {
+        let mut current = handle as usize;
+        loop {
+            match self.windows.borrow().get(&current) {
+                Some(w) => match w.owner {
+                    Some(owner) => current = owner,
+                    None => return Rect::from_origin_size(w.position, Size::new(w.width, w.height)),
+                },
+                None => return Rect::default(),
+            }
+        }
+    }
+
+    // Mouse input only comes from `send_message`, so there is nothing to
+    // capture or track.
+    fn set_capture(&self, _handle: HWND) { }
+
+    fn release_capture(&self) { }
+
+    fn track_mouse_leave(&self, _handle: HWND) { }
+
+    fn user_data(&self, handle: HWND) -> LONG_PTR {
+        self.windows.borrow().get(&(handle as usize)).map_or(0, |w| w.user_data)
+    }
+
+    fn set_user_data(&self, handle: HWND, data: LONG_PTR) {
+        if let Some(w) = self.windows.borrow_mut().get_mut(&(handle as usize)) {
+            w.user_data = data;
+        }
+    }
+
+    fn invalidate_rect(&self, handle: HWND, rect: Option<&Rect>) {
+        if let Some(w) = self.windows.borrow_mut().get_mut(&(handle as usize)) {
+            let client = Rect::new(0, 0, w.width, w.height);
+            let rect = rect.map_or(Some(client), |r| r.intersection(&client));
+            if let Some(rect) = rect {
+                w.invalid.add(rect);
+            }
+        }
+    }
+
+    fn begin_paint(&self, handle: HWND) -> (Rect, Box<Surface>) {
+        let mut windows = self.windows.borrow_mut();
+        let w = windows.get_mut(&(handle as usize)).expect("painting a window that does not exist");
+        w.draw_calls.borrow_mut().clear();
+        let rect = if w.invalid.is_empty() {
+            Rect::new(0, 0, w.width, w.height)
+        } else {
+            w.invalid.take().bounds()
+        };
+        (rect, Box::new(RecordingSurface { calls: w.draw_calls.clone() }))
+    }
+
+    fn get_dc(&self, handle: HWND) -> Box<Surface> {
+        let windows = self.windows.borrow();
+        let w = windows.get(&(handle as usize)).expect("drawing on a window that does not exist");
+        Box::new(RecordingSurface { calls: w.draw_calls.clone() })
+    }
+
+    fn create_font(&self, _builder: &FontBuilder) -> Result<HFONT, DWORD> {
+        self.live_fonts.set(self.live_fonts.get() + 1);
+        Ok(self.allocate_handle() as HFONT)
+    }
+
+    fn delete_font(&self, _font: HFONT) {
+        self.live_fonts.set(self.live_fonts.get() - 1);
+    }
+
+    /// Measured with the raster renderer's font, so layouts come out the
+    /// same as what `raster::render` draws.
+    fn text_extent(&self, font: &Font, text: &str) -> Size {
+        let (width, height) = raster::text_extent(text, font.height(), font.width());
+        Size::new(width, height)
+    }
+
+    fn load_icon(&self, _name: LPCWSTR) -> HICON {
+        ptr::null_mut()
+    }
+
+    fn load_cursor(&self, _name: LPCWSTR) -> HCURSOR {
+        ptr::null_mut()
+    }
+
+    fn create_solid_brush(&self, _color: Color) -> HBRUSH {
+        ptr::null_mut()
+    }
+
+    fn set_timer(&self, handle: HWND, id: usize, interval: UINT) {
+        if !self.is_window(handle) {
+            return;
+        }
+        self.kill_timer(handle, id);
+        let interval = ::std::cmp::max(interval as u64, USER_TIMER_MINIMUM);
+        self.timers.borrow_mut().push(HeadlessTimer {
+            handle: handle as usize,
+            id: id,
+            interval: interval,
+            due: self.clock.get() + interval,
+        });
+    }
+
+    fn kill_timer(&self, handle: HWND, id: usize) {
+        self.timers.borrow_mut().retain(|t| t.handle != handle as usize || t.id != id);
+    }
+
+    /// The virtual clock, so only `advance` makes time pass.
+    fn tick_count(&self) -> u32 {
+        self.clock.get() as u32
+    }
+
+    fn register_message(&self, name: &str) -> UINT {
+        let mut messages = self.messages.borrow_mut();
+        let index = match messages.iter().position(|m| m == name) {
+            Some(index) => index,
+            None => {
+                messages.push(name.to_owned());
+                messages.len() - 1
+            },
+        };
+        0xC000 + index as UINT
+    }
+
+    fn send_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        self.deliver(handle, msg, w_param, l_param)
+    }
+
+    fn post_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool {
+        if !self.is_window(handle) {
+            return false;
+        }
+        let mut message: MSG = unsafe { mem::zeroed() };
+        message.hwnd = handle;
+        message.message = msg;
+        message.wParam = w_param;
+        message.lParam = l_param;
+        self.queue.borrow_mut().push_back(message);
+        true
+    }
+
+    fn post_quit_message(&self, exit_code: i32) {
+        self.quit_code.set(Some(exit_code));
+        self.quit_pending.set(Some(exit_code));
+    }
+
+    /// Goes through the queue in order, then quits if that was asked for,
+    /// then paints the windows that need it one at a time, like `GetMessage`
+    /// would. Waiting only makes sense with other threads around to post
+    /// something.
+    fn dispatch_next(&self, wait: bool) -> Dispatch {
+        loop {
+            self.collect_inbox();
+            let next = self.queue.borrow_mut().pop_front();
+            if let Some(msg) = next {
+                self.deliver(msg.hwnd, msg.message, msg.wParam, msg.lParam);
+                return Dispatch::Dispatched;
+            }
+            if let Some(code) = self.quit_pending.get() {
+                self.quit_pending.set(None);
+                return Dispatch::Quit(code);
+            }
+            let dirty = self.windows.borrow().iter()
+                .find(|&(_, w)| !w.invalid.is_empty())
+                .map(|(&id, _)| id);
+            if let Some(id) = dirty {
+                self.send_message(id as HWND, WM_PAINT, 0, 0);
+                return Dispatch::Dispatched;
+            }
+            if !wait {
+                return Dispatch::Empty;
+            }
+            self.inbox.wait();
+        }
+    }
+
+    fn waker(&self) -> Arc<Waker> {
+        self.inbox.clone()
+    }
+
+    fn def_window_proc(&self, _handle: HWND, _msg: UINT, _w_param: WPARAM, _l_param: LPARAM) -> LRESULT {
+        0
+    }
+}
+
+impl DrawCall {
+    /// The same call, moved by `offset`.
+    pub fn offset(&self, offset: Point) -> DrawCall {
+        match *self {
+            DrawCall::FillRect { rect, color } =>
+                DrawCall::FillRect { rect: rect.offset(offset.x, offset.y), color: color },
+            DrawCall::Line { from, to, color } =>
+                DrawCall::Line { from: from + offset, to: to + offset, color: color },
+            DrawCall::Text { ref text, rect, font_height, color, format } => DrawCall::Text {
+                text: text.clone(),
+                rect: rect.offset(offset.x, offset.y),
+                font_height: font_height,
+                color: color,
+                format: format,
+            },
+        }
+    }
+}
+
+struct RecordingSurface {
+    calls: Rc<RefCell<Vec<DrawCall>>>,
+}
+
+impl Surface for RecordingSurface {
+    fn fill_rect(&self, rect: &Rect, color: Color) {
+        self.calls.borrow_mut().push(DrawCall::FillRect { rect: *rect, color: color });
+    }
+
+    fn draw_line(&self, from: Point, to: Point, color: Color) {
+        self.calls.borrow_mut().push(DrawCall::Line { from: from, to: to, color: color });
+    }
+
+    fn draw_text(&self, text: &str, font: &Font, color: Color, rect: &Rect, format: UINT) {
+        self.calls.borrow_mut().push(DrawCall::Text {
+            text: text.to_string(),
+            rect: *rect,
+            font_height: font.height(),
+            color: color,
+            format: format,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::colors::RED;
+    use ::controls::label::Label;
+    use ::geometry::Rect;
+    use ::testing::{ self, open_window };
+
+    #[test]
+    fn paints_the_controls_of_a_window() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut label = Label::new();
+        label.text = "Hello".to_string();
+        label.foreground_color = RED;
+        label.set_position(10, 20);
+        label.set_size(60, 14);
+        testing::core(&mut handle).add_control(Box::new(label));
+
+        let calls = backend.paint(handle);
+        assert_eq!(calls.len(), 1);
+        match calls[0] {
+            DrawCall::Text { ref text, rect, color, .. } => {
+                assert_eq!(text, "Hello");
+                assert_eq!(rect, Rect::new(10, 20, 70, 34));
+                assert_eq!(color, RED);
+            },
+            ref call => panic!("expected text, got {:?}", call),
+        }
+        assert_eq!(backend.draw_calls(handle), calls);
+    }
+
+    #[test]
+    fn paints_only_the_invalid_controls() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut first = Label::new();
+        first.text = "first".to_string();
+        first.set_position(0, 0);
+        first.set_size(50, 14);
+        let mut second = Label::new();
+        second.text = "second".to_string();
+        second.set_position(0, 50);
+        second.set_size(60, 14);
+        testing::core(&mut handle).add_control(Box::new(first));
+        let id = testing::core(&mut handle).add_control(Box::new(second));
+        backend.paint(handle);
+
+        testing::core(&mut handle).invalidate(id);
+        let texts = backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::Text { text, .. } => Some(text),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["second".to_string()]);
+    }
+
+    #[test]
+    fn destroying_the_window_quits() {
+        let (backend, handle) = open_window(200, 100);
+        assert!(backend.is_window(handle));
+        backend.destroy_window(handle);
+        assert!(!backend.is_window(handle));
+        assert_eq!(backend.quit_code(), Some(0));
+    }
+}
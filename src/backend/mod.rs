@@ -0,0 +1,164 @@
+//! Platform layer underneath `WindowCore`, the controls and the font code.
+//!
+//! Nothing outside of this module talks to user32/gdi32 directly anymore.
+//! The native implementation lives in `win32`, the in-memory one used for
+//! tests and non-Windows builds in `headless`.
+
+#[cfg(windows)]
+pub mod win32;
+pub mod headless;
+
+use winapi::*;
+
+use ::colors::Color;
+use ::event_loop::Dispatch;
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::window::Window;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The properties of a window class, as collected by `WindowClassBuilder`.
+pub struct ClassDesc {
+    pub class_name: String,
+    pub style: UINT,
+    pub icon: HICON,
+    pub icon_small: HICON,
+    pub cursor: HCURSOR,
+    pub background_brush: HBRUSH,
+    pub menu_name: String,
+    pub instance: HINSTANCE,
+}
+
+/// The properties of a single window, as collected by `WindowBuilder`.
+pub struct WindowDesc {
+    pub ex_style: DWORD,
+    pub class_name: String,
+    pub window_name: String,
+    pub style: DWORD,
+    pub pos_x: i32,
+    pub pos_y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub parent: HWND,
+    pub menu: HMENU,
+    pub instance: HINSTANCE,
+}
+
+/// Something controls can draw onto.
+///
+/// Surfaces are handed out by `Backend::begin_paint` and `Backend::get_dc`;
+/// dropping one releases whatever the backend acquired for it.
+pub trait Surface {
+    fn fill_rect(&self, rect: &Rect, color: Color);
+    fn draw_line(&self, from: Point, to: Point, color: Color);
+    fn draw_text(&self, text: &str, font: &Font, color: Color, rect: &Rect, format: UINT);
+}
+
+/// Posts messages to windows from any thread.
+///
+/// Window handles can't cross threads, so they are passed as `usize`.
+pub trait Waker: Send + Sync {
+    /// Queues a message for the window and wakes up the thread it belongs
+    /// to, returning false if the message couldn't be queued.
+    fn post(&self, handle: usize, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool;
+}
+
+pub trait Backend {
+    fn register_class(&self, class: &ClassDesc);
+    /// Creates a window and delivers `WM_CREATE` to `window_proc` with
+    /// `window` as the create parameter.
+    fn create_window(&self, desc: &WindowDesc, window: Box<Box<Window>>) -> Result<HWND, DWORD>;
+    fn destroy_window(&self, handle: HWND);
+    fn show_window(&self, handle: HWND, cmd_show: i32);
+    fn update_window(&self, handle: HWND);
+    /// Moves and resizes a window, in screen coordinates unless it is a
+    /// child window.
+    fn move_window(&self, handle: HWND, rect: &Rect);
+    fn client_to_screen(&self, handle: HWND, point: Point) -> Point;
+    /// The part of the screen popups of the window should stay within, in
+    /// screen coordinates.
+    fn work_area(&self, handle: HWND) -> Rect;
+
+    /// Sends all mouse input to the window until `release_capture`, even
+    /// when the mouse is outside of it.
+    fn set_capture(&self, handle: HWND);
+    fn release_capture(&self);
+    /// Has the window sent `WM_MOUSELEAVE` once the mouse leaves it.
+    fn track_mouse_leave(&self, handle: HWND);
+
+    fn user_data(&self, handle: HWND) -> LONG_PTR;
+    fn set_user_data(&self, handle: HWND, data: LONG_PTR);
+
+    /// Adds `rect`, or the whole client area for `None`, to the window's
+    /// update region.
+    fn invalidate_rect(&self, handle: HWND, rect: Option<&Rect>);
+    /// Returns the area that needs repainting together with a surface for it.
+    fn begin_paint(&self, handle: HWND) -> (Rect, Box<Surface>);
+    fn get_dc(&self, handle: HWND) -> Box<Surface>;
+
+    fn create_font(&self, builder: &FontBuilder) -> Result<HFONT, DWORD>;
+    fn delete_font(&self, font: HFONT);
+    /// The size `text` takes up when drawn with `font`, line breaks included.
+    fn text_extent(&self, font: &Font, text: &str) -> Size;
+    fn load_icon(&self, name: LPCWSTR) -> HICON;
+    fn load_cursor(&self, name: LPCWSTR) -> HCURSOR;
+    fn create_solid_brush(&self, color: Color) -> HBRUSH;
+
+    /// Starts sending `WM_TIMER` with `id` to the window every `interval`
+    /// milliseconds, replacing the window's timer with the same id.
+    fn set_timer(&self, handle: HWND, id: usize, interval: UINT);
+    fn kill_timer(&self, handle: HWND, id: usize);
+    /// Milliseconds since some fixed point in the past, wrapping around
+    /// like `GetTickCount`.
+    fn tick_count(&self) -> u32;
+
+    /// Returns the id of the application-wide message called `name`, the
+    /// same one every time.
+    fn register_message(&self, name: &str) -> UINT;
+    fn send_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT;
+    fn post_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool;
+    fn post_quit_message(&self, exit_code: i32);
+    /// Delivers the next message of the calling thread, see
+    /// `EventSource::dispatch_next`.
+    fn dispatch_next(&self, wait: bool) -> Dispatch;
+    /// A handle other threads can post messages to this backend's windows
+    /// with.
+    fn waker(&self) -> Arc<Waker>;
+    fn def_window_proc(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT;
+}
+
+thread_local!(static CURRENT: RefCell<Option<Rc<Backend>>> = RefCell::new(None));
+
+/// Returns the backend of the calling thread.
+///
+/// Unless `set_current` was called before, this is the Win32 backend on
+/// Windows and the headless one everywhere else.
+pub fn current() -> Rc<Backend> {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if current.is_none() {
+            *current = Some(default_backend());
+        }
+        current.as_ref().unwrap().clone()
+    })
+}
+
+/// Replaces the backend of the calling thread.
+///
+/// This has to happen before the first window class is registered.
+pub fn set_current(backend: Rc<Backend>) {
+    CURRENT.with(|current| *current.borrow_mut() = Some(backend));
+}
+
+#[cfg(windows)]
+fn default_backend() -> Rc<Backend> {
+    Rc::new(win32::Win32Backend::new())
+}
+
+#[cfg(not(windows))]
+fn default_backend() -> Rc<Backend> {
+    Rc::new(headless::HeadlessBackend::new())
+}
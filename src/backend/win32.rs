@@ -0,0 +1,380 @@
+use winapi::*;
+use user32::*;
+use gdi32::*;
+use kernel32::*;
+use wio::wide::*;
+
+use ::backend::{ Backend, ClassDesc, Surface, Waker, WindowDesc };
+use ::colors::Color;
+use ::event_loop::Dispatch;
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::window::Window;
+
+use std::ffi::OsString;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+/// The native backend, a thin layer over user32 and gdi32.
+pub struct Win32Backend;
+
+impl Win32Backend {
+    pub fn new() -> Self {
+        Win32Backend
+    }
+}
+
+// Not in every winapi 0.2 release.
+const TME_LEAVE: DWORD = 0x0000_0002;
+
+/// `PostMessageW` can be called from any thread as it is.
+struct Win32Waker;
+
+impl Waker for Win32Waker {
+    fn post(&self, handle: usize, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool {
+        unsafe { PostMessageW(handle as HWND, msg, w_param, l_param) != 0 }
+    }
+}
+
+impl Backend for Win32Backend {
+    fn register_class(&self, class: &ClassDesc) {
+        unsafe {
+            let mut class_name = class.class_name.to_wide_null();
+            let mut menu_name_raw = class.menu_name.to_wide_null();
+            let menu_name = if class.menu_name.is_empty() {
+                ptr::null_mut()
+            } else {
+                menu_name_raw.as_mut_ptr()
+            };
+            let mut wndclass: WNDCLASSEXW = mem::zeroed();
+            wndclass.cbSize = mem::size_of::<WNDCLASSEXW>() as UINT;
+            wndclass.style = class.style;
+            wndclass.lpfnWndProc = Some(wnd_proc);
+            wndclass.hInstance = class.instance;
+            wndclass.hIcon = class.icon;
+            wndclass.hIconSm = class.icon_small;
+            wndclass.hCursor = class.cursor;
+            wndclass.lpszClassName = class_name.as_mut_ptr();
+            wndclass.lpszMenuName = menu_name;
+            wndclass.hbrBackground = class.background_brush;
+            RegisterClassExW(&wndclass);
+        }
+    }
+
+    fn create_window(&self, desc: &WindowDesc, window: Box<Box<Window>>) -> Result<HWND, DWORD> {
+        unsafe {
+            let class_name = OsString::from(&desc.class_name).to_wide_null();
+            let window_name = OsString::from(&desc.window_name).to_wide_null();
+            let handle = CreateWindowExW(
+                desc.ex_style,
+                class_name.as_ptr(),
+                window_name.as_ptr(),
+                desc.style,
+                desc.pos_x,
+                desc.pos_y,
+                desc.width,
+                desc.height,
+                desc.parent,
+                desc.menu,
+                desc.instance,
+                Box::into_raw(window) as LPVOID
+            );
+
+            if handle.is_null() {
+                Err(GetLastError())
+            } else {
+                Ok(handle)
+            }
+        }
+    }
+
+    fn destroy_window(&self, handle: HWND) {
+        unsafe {
+            DestroyWindow(handle);
+        }
+    }
+
+    fn show_window(&self, handle: HWND, cmd_show: i32) {
+        unsafe {
+            ShowWindow(handle, cmd_show);
+        }
+    }
+
+    fn update_window(&self, handle: HWND) {
+        unsafe {
+            UpdateWindow(handle);
+        }
+    }
+
+    fn move_window(&self, handle: HWND, rect: &Rect) {
+        unsafe {
+            MoveWindow(handle, rect.left, rect.top, rect.width(), rect.height(), TRUE);
+        }
+    }
+
+    fn client_to_screen(&self, handle: HWND, point: Point) -> Point {
+        let mut point = POINT { x: point.x, y: point.y };
+        unsafe {
+            ClientToScreen(handle, &mut point);
+        }
+        Point::new(point.x, point.y)
+    }
+
+    /// The work area of the primary monitor, as user32-sys has no
+    /// `MonitorFromWindow` yet.
+    fn work_area(&self, _handle: HWND) -> Rect {
+        unsafe {
+            let mut rect: RECT = mem::zeroed();
+            SystemParametersInfoW(SPI_GETWORKAREA, 0, &mut rect as *mut RECT as PVOID, 0);
+            Rect::from(rect)
+        }
+    }
+
+    fn set_capture(&self, handle: HWND) {
+        unsafe {
+            SetCapture(handle);
+        }
+    }
+
+    fn release_capture(&self) {
+        unsafe {
+            ReleaseCapture();
+        }
+    }
+
+    fn track_mouse_leave(&self, handle: HWND) {
+        unsafe {
+            let mut track = TRACKMOUSEEVENT {
+                cbSize: mem::size_of::<TRACKMOUSEEVENT>() as DWORD,
+                dwFlags: TME_LEAVE,
+                hwndTrack: handle,
+                dwHoverTime: 0,
+            };
+            TrackMouseEvent(&mut track);
+        }
+    }
+
+    fn user_data(&self, handle: HWND) -> LONG_PTR {
+        unsafe { GetWindowLongPtrW(handle, GWLP_USERDATA) }
+    }
+
+    fn set_user_data(&self, handle: HWND, data: LONG_PTR) {
+        unsafe {
+            SetWindowLongPtrW(handle, GWLP_USERDATA, data);
+        }
+    }
+
+    fn invalidate_rect(&self, handle: HWND, rect: Option<&Rect>) {
+        let rect = rect.map(|r| RECT::from(*r));
+        let rect_ptr = rect.as_ref().map_or(ptr::null(), |r| r as *const RECT);
+        unsafe {
+            InvalidateRect(handle, rect_ptr, FALSE);
+        }
+    }
+
+    fn begin_paint(&self, handle: HWND) -> (Rect, Box<Surface>) {
+        unsafe {
+            let mut ps: PAINTSTRUCT = mem::zeroed();
+            let hdc = BeginPaint(handle, &mut ps);
+            let rect = Rect::from(ps.rcPaint);
+            (rect, Box::new(GdiSurface {
+                hdc: hdc,
+                release: Release::EndPaint(handle, ps),
+            }))
+        }
+    }
+
+    fn get_dc(&self, handle: HWND) -> Box<Surface> {
+        unsafe {
+            Box::new(GdiSurface {
+                hdc: GetDC(handle),
+                release: Release::ReleaseDC(handle),
+            })
+        }
+    }
+
+    fn create_font(&self, builder: &FontBuilder) -> Result<HFONT, DWORD> {
+        unsafe {
+            let mut face_wide = builder.face().to_wide_null();
+            let italic = if builder.italic() { TRUE as DWORD } else { FALSE as DWORD };
+            let underline = if builder.underline() { TRUE as DWORD } else { FALSE as DWORD };
+            let strikeout = if builder.strikeout() { TRUE as DWORD } else { FALSE as DWORD };
+
+            let handle = CreateFontW(
+                builder.height(),
+                builder.width(),
+                builder.escapement(),
+                builder.orientation(),
+                builder.weight().to_int(),
+                italic,
+                underline,
+                strikeout,
+                builder.charset().to_uint(),
+                builder.output_precision().to_uint(),
+                builder.clip_precision().to_uint(),
+                builder.quality().to_uint(),
+                builder.pitch().to_uint(),
+                face_wide.as_mut_ptr());
+
+            if handle.is_null() {
+                Err(GetLastError())
+            } else {
+                Ok(handle)
+            }
+        }
+    }
+
+    fn delete_font(&self, font: HFONT) {
+        unsafe {
+            DeleteObject(font as *mut c_void);
+        }
+    }
+
+    fn text_extent(&self, font: &Font, text: &str) -> Size {
+        let text = text.to_wide_null();
+        unsafe {
+            let hdc = GetDC(ptr::null_mut());
+            let old_font = SelectObject(hdc, **font as *mut c_void);
+            let mut rect: RECT = mem::zeroed();
+            DrawTextW(hdc, text.as_ptr(), -1, &mut rect, DT_CALCRECT | DT_NOPREFIX);
+            SelectObject(hdc, old_font);
+            ReleaseDC(ptr::null_mut(), hdc);
+            Rect::from(rect).size()
+        }
+    }
+
+    fn load_icon(&self, name: LPCWSTR) -> HICON {
+        unsafe { LoadIconW(ptr::null_mut(), name) }
+    }
+
+    fn load_cursor(&self, name: LPCWSTR) -> HCURSOR {
+        unsafe { LoadCursorW(ptr::null_mut(), name) }
+    }
+
+    fn create_solid_brush(&self, color: Color) -> HBRUSH {
+        unsafe { CreateSolidBrush(color.to_int()) }
+    }
+
+    fn set_timer(&self, handle: HWND, id: usize, interval: UINT) {
+        unsafe {
+            SetTimer(handle, id as UINT_PTR, interval, None);
+        }
+    }
+
+    fn kill_timer(&self, handle: HWND, id: usize) {
+        unsafe {
+            KillTimer(handle, id as UINT_PTR);
+        }
+    }
+
+    fn tick_count(&self) -> u32 {
+        unsafe { GetTickCount() }
+    }
+
+    fn register_message(&self, name: &str) -> UINT {
+        unsafe { RegisterWindowMessageW(name.to_wide_null().as_ptr()) }
+    }
+
+    fn send_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { SendMessageW(handle, msg, w_param, l_param) }
+    }
+
+    fn post_message(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool {
+        unsafe { PostMessageW(handle, msg, w_param, l_param) != 0 }
+    }
+
+    fn post_quit_message(&self, exit_code: i32) {
+        unsafe {
+            PostQuitMessage(exit_code);
+        }
+    }
+
+    fn dispatch_next(&self, wait: bool) -> Dispatch {
+        unsafe {
+            let mut msg: MSG = mem::zeroed();
+            if wait {
+                match GetMessageW(&mut msg, ptr::null_mut(), 0, 0) {
+                    0 => return Dispatch::Quit(msg.wParam as i32),
+                    // Only happens for invalid arguments, nothing to retry.
+                    -1 => return Dispatch::Quit(-1),
+                    _ => { },
+                }
+            } else if PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_REMOVE) == 0 {
+                return Dispatch::Empty;
+            } else if msg.message == WM_QUIT {
+                return Dispatch::Quit(msg.wParam as i32);
+            }
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+            Dispatch::Dispatched
+        }
+    }
+
+    fn waker(&self) -> Arc<Waker> {
+        Arc::new(Win32Waker)
+    }
+
+    fn def_window_proc(&self, handle: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { DefWindowProcW(handle, msg, w_param, l_param) }
+    }
+}
+
+unsafe extern "system" fn wnd_proc(hwnd: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    ::window::window_proc(hwnd, msg, w_param, l_param)
+}
+
+enum Release {
+    EndPaint(HWND, PAINTSTRUCT),
+    ReleaseDC(HWND),
+}
+
+struct GdiSurface {
+    hdc: HDC,
+    release: Release,
+}
+
+impl Surface for GdiSurface {
+    fn fill_rect(&self, rect: &Rect, color: Color) {
+        let rect = RECT::from(*rect);
+        unsafe {
+            let brush = CreateSolidBrush(color.to_int());
+            FillRect(self.hdc, &rect, brush);
+            DeleteObject(brush as *mut c_void);
+        }
+    }
+
+    fn draw_line(&self, from: Point, to: Point, color: Color) {
+        unsafe {
+            let pen = CreatePen(PS_SOLID, 1, color.to_int());
+            let old_pen = SelectObject(self.hdc, pen as *mut c_void);
+            MoveToEx(self.hdc, from.x, from.y, ptr::null_mut());
+            LineTo(self.hdc, to.x, to.y);
+            SelectObject(self.hdc, old_pen);
+            DeleteObject(pen as *mut c_void);
+        }
+    }
+
+    fn draw_text(&self, text: &str, font: &Font, color: Color, rect: &Rect, format: UINT) {
+        let text = text.to_wide_null();
+        let mut rect = RECT::from(*rect);
+        unsafe {
+            let old_font = SelectObject(self.hdc, **font as *mut c_void);
+            SetTextColor(self.hdc, color.to_int());
+            SetBkMode(self.hdc, TRANSPARENT);
+            DrawTextW(self.hdc, text.as_ptr(), -1, &mut rect, format);
+            SelectObject(self.hdc, old_font);
+        }
+    }
+}
+
+impl Drop for GdiSurface {
+    fn drop(&mut self) {
+        unsafe {
+            match self.release {
+                Release::EndPaint(hwnd, ref ps) => { EndPaint(hwnd, ps); },
+                Release::ReleaseDC(hwnd) => { ReleaseDC(hwnd, self.hdc); },
+            }
+        }
+    }
+}
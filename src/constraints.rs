@@ -0,0 +1,546 @@
+//! Anchors and constraints, for dialogs whose controls are placed by hand
+//! but should follow the window when it gets resized.
+//!
+//! Both run on every layout pass, after the panels are done: anchored
+//! controls first, then the constraints get solved.
+
+use ::geometry::{ Point, Rect, Size };
+use ::tree::ControlTree;
+
+use std::collections::{ HashMap, HashSet };
+use std::fmt;
+
+bitflags! {
+    /// The parent edges a control keeps its distance to, like WinForms'
+    /// `Anchor` property. Anchoring two opposite edges stretches the
+    /// control, anchoring neither keeps it centered on the same spot.
+    flags Anchor: u32 {
+        const ANCHOR_LEFT   = 0b0001,
+        const ANCHOR_TOP    = 0b0010,
+        const ANCHOR_RIGHT  = 0b0100,
+        const ANCHOR_BOTTOM = 0b1000,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    Left,
+    Top,
+    Right,
+    Bottom,
+    Width,
+    Height,
+    CenterX,
+    CenterY,
+}
+
+impl Attribute {
+    fn of(&self, rect: &Rect) -> i32 {
+        match *self {
+            Attribute::Left => rect.left,
+            Attribute::Top => rect.top,
+            Attribute::Right => rect.right,
+            Attribute::Bottom => rect.bottom,
+            Attribute::Width => rect.width(),
+            Attribute::Height => rect.height(),
+            Attribute::CenterX => (rect.left + rect.right) / 2,
+            Attribute::CenterY => (rect.top + rect.bottom) / 2,
+        }
+    }
+
+    fn is_horizontal(&self) -> bool {
+        match *self {
+            Attribute::Left | Attribute::Right | Attribute::Width | Attribute::CenterX => true,
+            _ => false,
+        }
+    }
+}
+
+/// What an attribute belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Item {
+    /// The client area of the window.
+    Window,
+    Control(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Relation {
+    Equal,
+    GreaterOrEqual,
+    LessOrEqual,
+}
+
+/// `target relation source * multiplier + constant`, all in client
+/// coordinates. Without a source the target is compared to the constant.
+///
+/// `label.right + 8 == textbox.left` reads
+/// `Constraint::new(textbox, Left).equal_to(label, Right).plus(8)`, and
+/// `button.width >= 80` is `Constraint::new(button, Width).at_least(80)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Constraint {
+    pub target: i32,
+    pub attribute: Attribute,
+    pub relation: Relation,
+    pub source: Option<(Item, Attribute)>,
+    pub multiplier: f32,
+    pub constant: i32,
+}
+
+impl Constraint {
+    pub fn new(target: i32, attribute: Attribute) -> Self {
+        Constraint {
+            target: target,
+            attribute: attribute,
+            relation: Relation::Equal,
+            source: None,
+            multiplier: 1.0,
+            constant: 0,
+        }
+    }
+
+    pub fn equal_to(mut self, source: i32, attribute: Attribute) -> Self {
+        self.relation = Relation::Equal;
+        self.source = Some((Item::Control(source), attribute));
+        self
+    }
+
+    pub fn equal_to_window(mut self, attribute: Attribute) -> Self {
+        self.relation = Relation::Equal;
+        self.source = Some((Item::Window, attribute));
+        self
+    }
+
+    pub fn relative_to(mut self, relation: Relation, source: Item, attribute: Attribute) -> Self {
+        self.relation = relation;
+        self.source = Some((source, attribute));
+        self
+    }
+
+    pub fn at_least(mut self, value: i32) -> Self {
+        self.relation = Relation::GreaterOrEqual;
+        self.source = None;
+        self.constant = value;
+        self
+    }
+
+    pub fn at_most(mut self, value: i32) -> Self {
+        self.relation = Relation::LessOrEqual;
+        self.source = None;
+        self.constant = value;
+        self
+    }
+
+    pub fn times(mut self, multiplier: f32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn plus(mut self, constant: i32) -> Self {
+        self.constant = constant;
+        self
+    }
+
+    fn involves(&self, id: i32) -> bool {
+        self.target == id || self.source.map_or(false, |(item, _)| item == Item::Control(id))
+    }
+}
+
+/// Returned when the constraints contradict each other, listing the ones
+/// that still don't hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unsatisfiable(pub Vec<Constraint>);
+
+impl fmt::Display for Unsatisfiable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} constraint(s) could not be satisfied", self.0.len())
+    }
+}
+
+struct Anchored {
+    anchor: Anchor,
+    /// The bounds and parent size the distances are kept from, taken on the
+    /// first layout pass with a non-empty parent.
+    reference: Option<(Rect, Size)>,
+}
+
+/// Gives up on constraints that still change something after this many
+/// rounds.
+const MAX_ROUNDS: usize = 64;
+
+/// The anchors and constraints of a window.
+///
+/// Constraints are solved by relaxation: each one in turn moves or resizes
+/// its target until it holds, until a whole round changes nothing. Setting
+/// an edge stretches the control if the opposite edge is constrained or
+/// anchored as well and moves it otherwise; setting the size keeps whichever
+/// edge is constrained or anchored in place.
+pub struct Constraints {
+    anchors: HashMap<i32, Anchored>,
+    constraints: Vec<Constraint>,
+}
+
+impl Constraints {
+    pub fn new() -> Self {
+        Constraints {
+            anchors: HashMap::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty() && self.constraints.is_empty()
+    }
+
+    /// Anchors a control. Its distances are measured the next time the
+    /// layout gets updated, so it should be placed by then.
+    pub fn set_anchor(&mut self, id: i32, anchor: Anchor) {
+        self.anchors.insert(id, Anchored { anchor: anchor, reference: None });
+    }
+
+    pub fn anchor(&self, id: i32) -> Option<Anchor> {
+        self.anchors.get(&id).map(|a| a.anchor)
+    }
+
+    pub fn add(&mut self, constraint: Constraint) {
+        self.constraints.push(constraint);
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    /// Forgets the anchor of a control and every constraint mentioning it.
+    pub fn remove(&mut self, id: i32) {
+        self.anchors.remove(&id);
+        self.constraints.retain(|c| !c.involves(id));
+    }
+
+    pub fn clear(&mut self) {
+        self.anchors.clear();
+        self.constraints.clear();
+    }
+
+    /// Moves the anchored and constrained controls in `tree` to fit a client
+    /// area of `client`.
+    pub fn apply(&mut self, tree: &mut ControlTree, client: Size) -> Result<(), Unsatisfiable> {
+        self.apply_anchors(tree, None, client);
+        if self.constraints.is_empty() {
+            return Ok(());
+        }
+
+        // Solve in client coordinates, then hand the results back relative
+        // to each control's parent.
+        let mut bounds = HashMap::new();
+        for c in self.constraints.iter() {
+            let mut ids = vec![c.target];
+            if let Some((Item::Control(id), _)) = c.source {
+                ids.push(id);
+            }
+            for id in ids {
+                if let Some(rect) = tree.client_bounds(id) {
+                    bounds.insert(id, rect);
+                }
+            }
+        }
+        let original = bounds.clone();
+        let window = Rect::from_origin_size(Point::default(), client);
+        let result = solve(&self.constraints, &self.pinned(), &mut bounds, window);
+
+        // Parents first, so their children are converted with their new
+        // origin.
+        let mut ids = bounds.keys().cloned().collect::<Vec<_>>();
+        ids.sort_by_key(|&id| depth(tree, id));
+        for id in ids {
+            let origin = tree.origin(tree.parent(id));
+            let rect = bounds[&id];
+            if rect == original[&id] {
+                continue;
+            }
+            if let Some(control) = tree.get_mut(id) {
+                control.set_bounds(rect.offset(-origin.x, -origin.y));
+            }
+        }
+        result
+    }
+
+    /// The edges that stay put when a constraint resizes a control: the
+    /// constrained and the anchored ones.
+    fn pinned(&self) -> HashSet<(i32, Attribute)> {
+        let mut pinned = self.constraints.iter()
+            .map(|c| (c.target, c.attribute))
+            .collect::<HashSet<_>>();
+        for (&id, anchored) in self.anchors.iter() {
+            let edges = [(ANCHOR_LEFT, Attribute::Left), (ANCHOR_TOP, Attribute::Top),
+                         (ANCHOR_RIGHT, Attribute::Right), (ANCHOR_BOTTOM, Attribute::Bottom)];
+            for &(flag, attribute) in edges.iter() {
+                if anchored.anchor.contains(flag) {
+                    pinned.insert((id, attribute));
+                }
+            }
+        }
+        pinned
+    }
+
+    fn apply_anchors(&mut self, tree: &mut ControlTree, parent: Option<i32>, size: Size) {
+        for id in tree.children(parent).to_vec() {
+            let bounds = match tree.get(id).and_then(|c| c.bounds()) {
+                Some(bounds) => bounds,
+                None => Rect::default(),
+            };
+            if let Some(anchored) = self.anchors.get_mut(&id) {
+                if anchored.reference.is_none() && !size.is_empty() {
+                    anchored.reference = Some((bounds, size));
+                }
+                if let Some((reference, reference_size)) = anchored.reference {
+                    let rect = anchor_rect(anchored.anchor, reference, reference_size, size);
+                    if rect != bounds {
+                        tree.get_mut(id).unwrap().set_bounds(rect);
+                    }
+                }
+            }
+
+            let size = tree.get(id).and_then(|c| c.bounds()).map_or(Size::default(), |b| b.size());
+            self.apply_anchors(tree, Some(id), size);
+        }
+    }
+}
+
+fn depth(tree: &ControlTree, id: i32) -> usize {
+    let mut depth = 0;
+    let mut current = tree.parent(id);
+    while let Some(parent) = current {
+        depth += 1;
+        current = tree.parent(parent);
+    }
+    depth
+}
+
+/// Where an anchored control goes once its parent went from `from` to `to`.
+pub fn anchor_rect(anchor: Anchor, reference: Rect, from: Size, to: Size) -> Rect {
+    let (left, right) = anchor_span(anchor.contains(ANCHOR_LEFT), anchor.contains(ANCHOR_RIGHT),
+                                    reference.left, reference.right, to.width - from.width);
+    let (top, bottom) = anchor_span(anchor.contains(ANCHOR_TOP), anchor.contains(ANCHOR_BOTTOM),
+                                    reference.top, reference.bottom, to.height - from.height);
+    Rect::new(left, top, right, bottom)
+}
+
+fn anchor_span(start: bool, end: bool, from: i32, to: i32, delta: i32) -> (i32, i32) {
+    match (start, end) {
+        (true, true) => (from, ::std::cmp::max(from, to + delta)),
+        (true, false) => (from, to),
+        (false, true) => (from + delta, to + delta),
+        (false, false) => (from + delta / 2, to + delta / 2),
+    }
+}
+
+/// Runs the relaxation over `bounds`, which holds the client rectangles of
+/// every control involved.
+fn solve(constraints: &[Constraint], pinned: &HashSet<(i32, Attribute)>,
+         bounds: &mut HashMap<i32, Rect>, window: Rect) -> Result<(), Unsatisfiable> {
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for c in constraints {
+            let current = match bounds.get(&c.target) {
+                Some(rect) => *rect,
+                None => continue,
+            };
+            let wanted = match value(c, bounds, window) {
+                Some(wanted) => wanted,
+                None => continue,
+            };
+            let actual = c.attribute.of(&current);
+            let goal = match c.relation {
+                Relation::Equal if actual != wanted => wanted,
+                Relation::GreaterOrEqual if actual < wanted => wanted,
+                Relation::LessOrEqual if actual > wanted => wanted,
+                _ => continue,
+            };
+            let rect = set_attribute(current, c.target, c.attribute, goal, pinned);
+            if rect != current {
+                bounds.insert(c.target, rect);
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+    }
+
+    let unsatisfied = constraints.iter().filter(|c| !holds(c, bounds, window)).cloned().collect::<Vec<_>>();
+    if unsatisfied.is_empty() { Ok(()) } else { Err(Unsatisfiable(unsatisfied)) }
+}
+
+/// The right hand side of a constraint.
+fn value(c: &Constraint, bounds: &HashMap<i32, Rect>, window: Rect) -> Option<i32> {
+    let source = match c.source {
+        Some((Item::Window, attribute)) => attribute.of(&window),
+        Some((Item::Control(id), attribute)) => match bounds.get(&id) {
+            Some(rect) => attribute.of(rect),
+            None => return None,
+        },
+        None => return Some(c.constant),
+    };
+    Some((source as f32 * c.multiplier).round() as i32 + c.constant)
+}
+
+fn holds(c: &Constraint, bounds: &HashMap<i32, Rect>, window: Rect) -> bool {
+    let (rect, wanted) = match (bounds.get(&c.target), value(c, bounds, window)) {
+        (Some(rect), Some(wanted)) => (rect, wanted),
+        _ => return true,
+    };
+    let actual = c.attribute.of(rect);
+    match c.relation {
+        Relation::Equal => actual == wanted,
+        Relation::GreaterOrEqual => actual >= wanted,
+        Relation::LessOrEqual => actual <= wanted,
+    }
+}
+
+fn set_attribute(rect: Rect, id: i32, attribute: Attribute, value: i32,
+                 pinned: &HashSet<(i32, Attribute)>) -> Rect {
+    let pinned = |a: Attribute| pinned.contains(&(id, a));
+    let (start, end, size) = if attribute.is_horizontal() {
+        (Attribute::Left, Attribute::Right, Attribute::Width)
+    } else {
+        (Attribute::Top, Attribute::Bottom, Attribute::Height)
+    };
+    let (from, to) = if attribute.is_horizontal() { (rect.left, rect.right) } else { (rect.top, rect.bottom) };
+    let length = to - from;
+
+    let (from, to) = if attribute == start {
+        if pinned(end) { (value, ::std::cmp::max(value, to)) } else { (value, value + length) }
+    } else if attribute == end {
+        if pinned(start) { (::std::cmp::min(from, value), value) } else { (value - length, value) }
+    } else if attribute == size {
+        let value = ::std::cmp::max(0, value);
+        if pinned(end) && !pinned(start) { (to - value, to) } else { (from, from + value) }
+    } else {
+        // Centers always move.
+        let from = value - length / 2;
+        (from, from + length)
+    };
+
+    if attribute.is_horizontal() {
+        Rect::new(from, rect.top, to, rect.bottom)
+    } else {
+        Rect::new(rect.left, from, rect.right, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::controls::label::Label;
+    use ::testing::{ self, open_window };
+    use ::winapi::HWND;
+
+    use std::rc::Rc;
+
+    fn add(handle: &mut HWND, bounds: Rect) -> i32 {
+        let mut label = Label::new();
+        label.text = "x".to_string();
+        label.set_position(bounds.left, bounds.top);
+        label.set_size(bounds.width(), bounds.height());
+        testing::core(handle).add_control(Box::new(label))
+    }
+
+    fn bounds_of(handle: &mut HWND, id: i32) -> Rect {
+        testing::core(handle).controls().client_bounds(id).unwrap()
+    }
+
+    /// Resizes the window and returns where the controls `ids` went.
+    fn resize(backend: &HeadlessBackend, handle: &mut HWND, width: i32, height: i32, ids: &[i32]) -> Vec<Rect> {
+        backend.resize(*handle, width, height);
+        ids.iter().map(|&id| bounds_of(handle, id)).collect()
+    }
+
+    /// A 200 by 100 window whose layout already ran once, so anchors took
+    /// their distances.
+    fn window() -> (Rc<HeadlessBackend>, HWND) {
+        open_window(200, 100)
+    }
+
+    #[test]
+    fn anchors_keep_their_distances() {
+        let (backend, mut handle) = window();
+        let stretched = add(&mut handle, Rect::new(10, 10, 190, 20));
+        let corner = add(&mut handle, Rect::new(150, 70, 190, 90));
+        let centered = add(&mut handle, Rect::new(80, 40, 120, 60));
+        testing::core(&mut handle).set_anchor(stretched, ANCHOR_LEFT | ANCHOR_RIGHT | ANCHOR_TOP);
+        testing::core(&mut handle).set_anchor(corner, ANCHOR_RIGHT | ANCHOR_BOTTOM);
+        testing::core(&mut handle).set_anchor(centered, Anchor::empty());
+        let ids = [stretched, corner, centered];
+        assert_eq!(resize(&backend, &mut handle, 200, 100, &ids),
+                   vec![Rect::new(10, 10, 190, 20), Rect::new(150, 70, 190, 90), Rect::new(80, 40, 120, 60)]);
+
+        assert_eq!(resize(&backend, &mut handle, 300, 150, &ids),
+                   vec![Rect::new(10, 10, 290, 20), Rect::new(250, 120, 290, 140), Rect::new(130, 65, 170, 85)]);
+        assert_eq!(resize(&backend, &mut handle, 100, 50, &ids),
+                   vec![Rect::new(10, 10, 90, 20), Rect::new(50, 20, 90, 40), Rect::new(30, 15, 70, 35)]);
+    }
+
+    #[test]
+    fn anchor_rects_follow_the_parent_size() {
+        let reference = Rect::new(10, 10, 30, 30);
+        let (from, to) = (Size::new(100, 100), Size::new(120, 80));
+        assert_eq!(anchor_rect(ANCHOR_LEFT | ANCHOR_TOP, reference, from, to), reference);
+        assert_eq!(anchor_rect(ANCHOR_TOP | ANCHOR_BOTTOM | ANCHOR_RIGHT, reference, from, to),
+                   Rect::new(30, 10, 50, 10));
+        assert_eq!(anchor_rect(Anchor::empty(), reference, from, to), Rect::new(20, 0, 40, 20));
+        // Stretching never turns a control inside out.
+        let shrunk = anchor_rect(ANCHOR_LEFT | ANCHOR_RIGHT, reference, from, Size::new(50, 100));
+        assert_eq!((shrunk.left, shrunk.right), (10, 10));
+    }
+
+    #[test]
+    fn constraints_place_controls_relative_to_each_other() {
+        let (backend, mut handle) = window();
+        let label = add(&mut handle, Rect::new(10, 10, 50, 30));
+        let textbox = add(&mut handle, Rect::new(0, 10, 100, 30));
+        // label.right + 8 == textbox.left, textbox.right == window.right - 10
+        testing::core(&mut handle).add_constraint(Constraint::new(textbox, Attribute::Left)
+                                                  .equal_to(label, Attribute::Right).plus(8));
+        testing::core(&mut handle).add_constraint(Constraint::new(textbox, Attribute::Right)
+                                                  .equal_to_window(Attribute::Right).plus(-10));
+        let ids = [label, textbox];
+        assert_eq!(resize(&backend, &mut handle, 200, 100, &ids),
+                   vec![Rect::new(10, 10, 50, 30), Rect::new(58, 10, 190, 30)]);
+        assert_eq!(resize(&backend, &mut handle, 300, 100, &ids)[1], Rect::new(58, 10, 290, 30));
+        assert!(testing::core(&mut handle).layout_error().is_none());
+    }
+
+    #[test]
+    fn minimum_widths_hold_when_shrinking() {
+        let (backend, mut handle) = window();
+        let button = add(&mut handle, Rect::new(100, 50, 190, 70));
+        testing::core(&mut handle).set_anchor(button, ANCHOR_LEFT | ANCHOR_RIGHT | ANCHOR_TOP);
+        testing::core(&mut handle).add_constraint(Constraint::new(button, Attribute::Width).at_least(80));
+        assert_eq!(resize(&backend, &mut handle, 200, 100, &[button]), vec![Rect::new(100, 50, 190, 70)]);
+
+        // The anchors alone would make it 40 wide.
+        assert_eq!(resize(&backend, &mut handle, 150, 100, &[button]), vec![Rect::new(100, 50, 180, 70)]);
+        assert_eq!(resize(&backend, &mut handle, 250, 100, &[button]), vec![Rect::new(100, 50, 240, 70)]);
+        assert!(testing::core(&mut handle).layout_error().is_none());
+    }
+
+    #[test]
+    fn conflicting_constraints_are_unsatisfiable() {
+        let (backend, mut handle) = window();
+        let button = add(&mut handle, Rect::new(10, 10, 100, 30));
+        let wide = Constraint::new(button, Attribute::Width).at_least(80);
+        let narrow = Constraint::new(button, Attribute::Width).at_most(50);
+        testing::core(&mut handle).add_constraint(wide);
+        testing::core(&mut handle).add_constraint(narrow);
+
+        backend.resize(handle, 200, 100);
+        assert_eq!(testing::core(&mut handle).layout_error(), Some(&Unsatisfiable(vec![wide])));
+        // The controls still end up somewhere, as close as it got.
+        assert_eq!(bounds_of(&mut handle, button), Rect::new(10, 10, 60, 30));
+        match testing::core(&mut handle).update_layout() {
+            Err(Unsatisfiable(unsatisfied)) => assert_eq!(unsatisfied, vec![wide]),
+            Ok(()) => panic!("contradicting constraints were satisfied"),
+        }
+    }
+}
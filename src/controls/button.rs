@@ -0,0 +1,380 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, GRAY, LIGHT_GRAY };
+use ::event::{ Event, MouseButton };
+use ::font::FontBuilder;
+use ::geometry::{ Point, Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+
+/// How a button looks at the moment. Focus is drawn on top of any of these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ButtonState {
+    Normal,
+    Hover,
+    Pressed,
+    Disabled,
+}
+
+/// The colors a button is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonStyle {
+    pub foreground: Color,
+    pub disabled_foreground: Color,
+    pub background: Color,
+    pub hover_background: Color,
+    pub pressed_background: Color,
+    pub border: Color,
+    pub focus: Color,
+}
+
+impl Default for ButtonStyle {
+    fn default() -> Self {
+        ButtonStyle {
+            foreground: BLACK,
+            disabled_foreground: GRAY,
+            background: LIGHT_GRAY,
+            hover_background: Color::from_rbg(220, 220, 220),
+            pressed_background: Color::from_rbg(170, 170, 170),
+            border: GRAY,
+            focus: BLACK,
+        }
+    }
+}
+
+/// A push button.
+///
+/// It draws itself like every other control, so it looks the same on all
+/// backends instead of being a `BS_PUSHBUTTON` child window. Releasing the
+/// left mouse button over it, Enter, or pressing and releasing Space while
+/// it has the focus clicks it.
+pub struct Button {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub text: String,
+    pub enabled: bool,
+    pub style: ButtonStyle,
+    /// Space between the text and the edges.
+    pub padding: Size,
+    on_click: Option<Box<FnMut(&mut InputContext)>>,
+    hovered: bool,
+    /// The left mouse button went down over the button and is still down.
+    mouse_pressed: bool,
+    /// Space is held down.
+    key_pressed: bool,
+    focused: bool,
+}
+
+impl Button {
+    pub fn new(text: &str) -> Self {
+        Button {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            text: text.to_string(),
+            enabled: true,
+            style: ButtonStyle::default(),
+            padding: Size::new(12, 6),
+            on_click: None,
+            hovered: false,
+            mouse_pressed: false,
+            key_pressed: false,
+            focused: false,
+        }
+    }
+
+    /// Calls `f` on every click. To tell the window, post it a message with
+    /// `InputContext::post`.
+    pub fn set_on_click<F: FnMut(&mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_click = Some(Box::new(f));
+        self
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        if !enabled {
+            self.mouse_pressed = false;
+            self.key_pressed = false;
+        }
+        self
+    }
+
+    pub fn state(&self) -> ButtonState {
+        if !self.enabled {
+            ButtonState::Disabled
+        } else if self.key_pressed || (self.mouse_pressed && self.hovered) {
+            ButtonState::Pressed
+        } else if self.hovered {
+            ButtonState::Hover
+        } else {
+            ButtonState::Normal
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    fn click(&mut self, context: &mut InputContext) {
+        if let Some(ref mut on_click) = self.on_click {
+            on_click(context);
+        }
+    }
+
+    /// Whether a position relative to the button is on it.
+    fn contains(&self, position: Point) -> bool {
+        Rect::from_origin_size(Point::default(), self.bounds.size()).contains(position)
+    }
+
+    /// Updates the hover state, returning whether it changed.
+    fn set_hovered(&mut self, hovered: bool) -> bool {
+        let changed = self.hovered != hovered;
+        self.hovered = hovered;
+        changed
+    }
+}
+
+impl Paintable for Button {
+    fn paint(&self, context: &PaintContext) {
+        let state = self.state();
+        let background = match state {
+            ButtonState::Hover => self.style.hover_background,
+            ButtonState::Pressed => self.style.pressed_background,
+            ButtonState::Normal | ButtonState::Disabled => self.style.background,
+        };
+        let foreground = match state {
+            ButtonState::Disabled => self.style.disabled_foreground,
+            _ => self.style.foreground,
+        };
+        context.fill_rect(&self.bounds, background);
+        draw_frame(context, &self.bounds, self.style.border);
+
+        // Pressed buttons look pushed in by moving the text a bit.
+        let text_rect = if state == ButtonState::Pressed { self.bounds.offset(1, 1) } else { self.bounds };
+        if let Ok(font) = self.font_builder.build() {
+            context.draw_text(&self.text, &font, foreground, &text_rect,
+                              DT_CENTER | DT_VCENTER | DT_SINGLELINE);
+        }
+        if self.focused {
+            draw_frame(context, &self.bounds.inset(3, 3), self.style.focus);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for Button {
+    /// The text plus padding.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let text = self.font_builder.text_extent(&self.text).unwrap_or(Size::default());
+        Size::new(text.width + 2 * self.padding.width, text.height + 2 * self.padding.height)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                return true;
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.key_pressed = false;
+                context.invalidate();
+                return true;
+            },
+            Event::MouseLeave => {
+                if self.set_hovered(false) {
+                    context.invalidate();
+                }
+                return true;
+            },
+            _ => { },
+        }
+        if !self.enabled {
+            return false;
+        }
+
+        match *event {
+            Event::MouseMove { position, .. } => {
+                let inside = self.contains(position);
+                if self.set_hovered(inside) {
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                self.mouse_pressed = true;
+                self.set_hovered(self.contains(position));
+                context.invalidate();
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                if !self.mouse_pressed {
+                    return false;
+                }
+                self.mouse_pressed = false;
+                context.invalidate();
+                if self.contains(position) {
+                    self.click(context);
+                }
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    self.key_pressed = true;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::KeyUp { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    return false;
+                }
+                self.key_pressed = false;
+                context.invalidate();
+                self.click(context);
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_RETURN as u32 => {
+                self.click(context);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::message::{ MessageHandler, MessageHandlerBase };
+    use ::testing::{ self, open_window };
+    use ::window::WindowCore;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Clicked;
+
+    thread_local!(static CLICKS: Cell<usize> = Cell::new(0));
+
+    impl MessageHandler<Clicked> for WindowCore {
+        fn handle_message(&mut self, _: &Clicked) {
+            CLICKS.with(|c| c.set(c.get() + 1));
+        }
+    }
+
+    fn clicks() -> usize {
+        CLICKS.with(|c| c.get())
+    }
+
+    /// A window with a button at (10, 10) to (90, 40) that posts `Clicked`.
+    fn window_with(mut button: Button) -> (Rc<HeadlessBackend>, HWND, i32) {
+        let (backend, mut handle) = open_window(200, 100);
+        testing::core(&mut handle).register_message::<Clicked>();
+        button.set_bounds(Rect::new(10, 10, 90, 40));
+        button.set_on_click(|context| context.post(Clicked).unwrap());
+        let id = testing::core(&mut handle).add_control(Box::new(button));
+        backend.paint(handle);
+        (backend, handle, id)
+    }
+
+    /// The background, text color and text rectangle the button was
+    /// painted with.
+    fn look(calls: &[DrawCall]) -> (Color, Color, Rect) {
+        let background = calls.iter().filter_map(|call| match *call {
+            DrawCall::FillRect { color, .. } => Some(color),
+            _ => None,
+        }).next().expect("no background");
+        let (foreground, rect) = calls.iter().filter_map(|call| match *call {
+            DrawCall::Text { color, rect, .. } => Some((color, rect)),
+            _ => None,
+        }).next().expect("no text");
+        (background, foreground, rect)
+    }
+
+    #[test]
+    fn clicking_posts_to_the_window() {
+        let (backend, handle, _) = window_with(Button::new("OK"));
+        testing::click(&backend, handle, 20, 20);
+        assert_eq!(clicks(), 0);
+        backend.dispatch_pending();
+        assert_eq!(clicks(), 1);
+    }
+
+    #[test]
+    fn releasing_outside_doesnt_click() {
+        let (backend, handle, _) = window_with(Button::new("OK"));
+        let style = ButtonStyle::default();
+
+        testing::mouse_down(&backend, handle, 20, 20);
+        let (background, _, rect) = look(&backend.paint(handle));
+        assert_eq!(background, style.pressed_background);
+        assert_eq!(rect, Rect::new(11, 11, 91, 41));
+
+        // Still captured, but no longer over the button.
+        testing::mouse_move(&backend, handle, 150, 80);
+        assert_eq!(look(&backend.paint(handle)).0, style.background);
+        testing::mouse_up(&backend, handle, 150, 80);
+        backend.dispatch_pending();
+        assert_eq!(clicks(), 0);
+
+        testing::mouse_move(&backend, handle, 30, 30);
+        assert_eq!(look(&backend.paint(handle)).0, style.hover_background);
+    }
+
+    #[test]
+    fn space_and_enter_click_the_focused_button() {
+        let (backend, mut handle, id) = window_with(Button::new("OK"));
+        assert!(testing::core(&mut handle).focus(Some(id)));
+        let focus_frame = backend.paint(handle).into_iter().filter(|call| match *call {
+            DrawCall::Line { color, .. } => color == ButtonStyle::default().focus,
+            _ => false,
+        }).count();
+        assert_eq!(focus_frame, 4);
+
+        testing::send(&backend, handle, Event::KeyDown {
+            key: VK_SPACE as u32, system: false, flags: Default::default(),
+        });
+        assert_eq!(look(&backend.paint(handle)).0, ButtonStyle::default().pressed_background);
+        backend.dispatch_pending();
+        assert_eq!(clicks(), 0);
+        testing::send(&backend, handle, Event::KeyUp {
+            key: VK_SPACE as u32, system: false, flags: Default::default(),
+        });
+        testing::key(&backend, handle, VK_RETURN);
+        backend.dispatch_pending();
+        assert_eq!(clicks(), 2);
+    }
+
+    #[test]
+    fn disabled_buttons_ignore_input() {
+        let mut button = Button::new("OK");
+        button.set_enabled(false);
+        let (backend, mut handle, id) = window_with(button);
+        assert!(!testing::core(&mut handle).focus(Some(id)));
+
+        testing::click(&backend, handle, 20, 20);
+        testing::key(&backend, handle, VK_RETURN);
+        backend.dispatch_pending();
+        assert_eq!(clicks(), 0);
+        testing::core(&mut handle).invalidate(id);
+        let (background, foreground, _) = look(&backend.paint(handle));
+        assert_eq!(background, ButtonStyle::default().background);
+        assert_eq!(foreground, ButtonStyle::default().disabled_foreground);
+    }
+}
@@ -0,0 +1,406 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, WHITE };
+use ::event::{ Event, MouseButton };
+use ::font::FontBuilder;
+use ::geometry::{ Point, Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+
+use std::cmp::max;
+
+/// The width and height of the box of a check box or the circle of a radio
+/// button.
+pub const MARK_SIZE: i32 = 13;
+
+/// The space between the mark and the text.
+pub const MARK_SPACING: i32 = 5;
+
+/// Whether a check box is ticked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CheckState {
+    Unchecked,
+    Checked,
+    /// Neither, e.g. for a box standing for several others that disagree.
+    Indeterminate,
+}
+
+/// The colors check boxes and radio buttons are drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ToggleStyle {
+    pub foreground: Color,
+    pub disabled_foreground: Color,
+    /// The inside of the box or circle.
+    pub background: Color,
+    pub border: Color,
+    pub hover_border: Color,
+    /// The tick or the dot.
+    pub mark: Color,
+    pub focus: Color,
+}
+
+impl Default for ToggleStyle {
+    fn default() -> Self {
+        ToggleStyle {
+            foreground: BLACK,
+            disabled_foreground: GRAY,
+            background: WHITE,
+            border: GRAY,
+            hover_border: BLUE,
+            mark: BLACK,
+            focus: BLACK,
+        }
+    }
+}
+
+/// Where the mark of a check box or radio button with these bounds goes:
+/// at the left, centered vertically.
+pub fn mark_rect(bounds: &Rect) -> Rect {
+    let top = bounds.top + (bounds.height() - MARK_SIZE) / 2;
+    Rect::new(bounds.left, top, bounds.left + MARK_SIZE, top + MARK_SIZE)
+}
+
+/// Where the text of a check box or radio button goes, right of the mark.
+pub fn text_rect(bounds: &Rect) -> Rect {
+    Rect::new(bounds.left + MARK_SIZE + MARK_SPACING, bounds.top, bounds.right, bounds.bottom)
+}
+
+/// The size of a mark followed by `text`.
+pub fn measure_toggle(font_builder: &FontBuilder, text: &str) -> Size {
+    let text = font_builder.text_extent(text).unwrap_or(Size::default());
+    Size::new(MARK_SIZE + MARK_SPACING + text.width, max(MARK_SIZE, text.height))
+}
+
+/// Draws the text of a check box or radio button, framed when focused.
+pub fn paint_toggle_text(context: &PaintContext, font_builder: &FontBuilder, text: &str, bounds: &Rect,
+                         style: &ToggleStyle, enabled: bool, focused: bool) {
+    let rect = text_rect(bounds);
+    if let Ok(font) = font_builder.build() {
+        let color = if enabled { style.foreground } else { style.disabled_foreground };
+        context.draw_text(text, &font, color, &rect, DT_LEFT | DT_VCENTER | DT_SINGLELINE);
+    }
+    if focused {
+        draw_frame(context, &rect.offset(-2, 0), style.focus);
+    }
+}
+
+/// A box that gets ticked and unticked by clicking it, with a text next to
+/// it.
+///
+/// Clicking it, or pressing and releasing Space while it has the focus,
+/// goes from unchecked to checked and back. A tri-state check box goes
+/// through indeterminate on its way back to unchecked.
+pub struct CheckBox {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub text: String,
+    pub enabled: bool,
+    pub tri_state: bool,
+    pub style: ToggleStyle,
+    state: CheckState,
+    on_change: Option<Box<FnMut(CheckState, &mut InputContext)>>,
+    hovered: bool,
+    /// The left mouse button went down over the check box and is still down.
+    mouse_pressed: bool,
+    /// Space is held down.
+    key_pressed: bool,
+    focused: bool,
+}
+
+impl CheckBox {
+    pub fn new(text: &str) -> Self {
+        CheckBox {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            text: text.to_string(),
+            enabled: true,
+            tri_state: false,
+            style: ToggleStyle::default(),
+            state: CheckState::Unchecked,
+            on_change: None,
+            hovered: false,
+            mouse_pressed: false,
+            key_pressed: false,
+            focused: false,
+        }
+    }
+
+    pub fn state(&self) -> CheckState {
+        self.state
+    }
+
+    pub fn is_checked(&self) -> bool {
+        self.state == CheckState::Checked
+    }
+
+    /// Changes the state without calling the change callback.
+    pub fn set_state(&mut self, state: CheckState) -> &mut Self {
+        self.state = state;
+        self
+    }
+
+    pub fn set_checked(&mut self, checked: bool) -> &mut Self {
+        self.set_state(if checked { CheckState::Checked } else { CheckState::Unchecked })
+    }
+
+    pub fn set_tri_state(&mut self, tri_state: bool) -> &mut Self {
+        self.tri_state = tri_state;
+        self
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        if !enabled {
+            self.mouse_pressed = false;
+            self.key_pressed = false;
+        }
+        self
+    }
+
+    /// Calls `f` with the new state whenever the user changes it.
+    pub fn set_on_change<F: FnMut(CheckState, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The state a click leads to.
+    pub fn next_state(&self) -> CheckState {
+        match self.state {
+            CheckState::Unchecked => CheckState::Checked,
+            CheckState::Checked if self.tri_state => CheckState::Indeterminate,
+            CheckState::Checked | CheckState::Indeterminate => CheckState::Unchecked,
+        }
+    }
+
+    fn toggle(&mut self, context: &mut InputContext) {
+        self.state = self.next_state();
+        context.invalidate();
+        let state = self.state;
+        if let Some(ref mut on_change) = self.on_change {
+            on_change(state, context);
+        }
+    }
+
+    /// Whether a position relative to the check box is on it.
+    fn contains(&self, position: Point) -> bool {
+        Rect::from_origin_size(Point::default(), self.bounds.size()).contains(position)
+    }
+}
+
+impl Paintable for CheckBox {
+    fn paint(&self, context: &PaintContext) {
+        let mark = mark_rect(&self.bounds);
+        let pressed = self.key_pressed || (self.mouse_pressed && self.hovered);
+        let border = if self.enabled && (self.hovered || pressed) { self.style.hover_border } else { self.style.border };
+        context.fill_rect(&mark, self.style.background);
+        draw_frame(context, &mark, border);
+        if pressed {
+            draw_frame(context, &mark.inset(1, 1), border);
+        }
+
+        let color = if self.enabled { self.style.mark } else { self.style.disabled_foreground };
+        match self.state {
+            CheckState::Checked => {
+                // A tick out of two strokes, doubled up to be bold enough.
+                let left = Point::new(mark.left + 3, mark.top + 6);
+                let bottom = Point::new(mark.left + 5, mark.bottom - 4);
+                let right = Point::new(mark.right - 4, mark.top + 3);
+                for dy in 0..2 {
+                    context.draw_line(left.offset(0, dy), bottom.offset(0, dy), color);
+                    context.draw_line(bottom.offset(0, dy), right.offset(0, dy), color);
+                }
+            },
+            CheckState::Indeterminate => context.fill_rect(&mark.inset(3, 3), color),
+            CheckState::Unchecked => { },
+        }
+
+        paint_toggle_text(context, &self.font_builder, &self.text, &self.bounds, &self.style,
+                          self.enabled, self.focused);
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for CheckBox {
+    /// The box and the text next to it.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        measure_toggle(&self.font_builder, &self.text)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                return true;
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.key_pressed = false;
+                context.invalidate();
+                return true;
+            },
+            Event::MouseLeave => {
+                if self.hovered {
+                    self.hovered = false;
+                    context.invalidate();
+                }
+                return true;
+            },
+            _ => { },
+        }
+        if !self.enabled {
+            return false;
+        }
+
+        match *event {
+            Event::MouseMove { position, .. } => {
+                let inside = self.contains(position);
+                if inside != self.hovered {
+                    self.hovered = inside;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                self.mouse_pressed = true;
+                self.hovered = self.contains(position);
+                context.invalidate();
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                if !self.mouse_pressed {
+                    return false;
+                }
+                self.mouse_pressed = false;
+                context.invalidate();
+                if self.contains(position) {
+                    self.toggle(context);
+                }
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    self.key_pressed = true;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::KeyUp { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    return false;
+                }
+                self.key_pressed = false;
+                self.toggle(context);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Adds `check_box` at (10, 10) to (110, 30) to a new window and returns
+    /// the states it changes to.
+    fn window_with(mut check_box: CheckBox) -> (Rc<HeadlessBackend>, HWND, i32, Rc<RefCell<Vec<CheckState>>>) {
+        let (backend, mut handle) = open_window(200, 100);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        {
+            let changes = changes.clone();
+            check_box.set_on_change(move |state, _| changes.borrow_mut().push(state));
+        }
+        check_box.set_bounds(Rect::new(10, 10, 110, 30));
+        let id = testing::core(&mut handle).add_control(Box::new(check_box));
+        backend.paint(handle);
+        (backend, handle, id, changes)
+    }
+
+    #[test]
+    fn clicking_toggles() {
+        let (backend, handle, _, changes) = window_with(CheckBox::new("Check"));
+        for _ in 0..3 {
+            testing::click(&backend, handle, 15, 20);
+        }
+        assert_eq!(*changes.borrow(), vec![CheckState::Checked, CheckState::Unchecked, CheckState::Checked]);
+    }
+
+    #[test]
+    fn tri_state_boxes_go_through_indeterminate() {
+        let mut check_box = CheckBox::new("Check");
+        check_box.set_tri_state(true);
+        let (backend, handle, _, changes) = window_with(check_box);
+        let indeterminate_mark = DrawCall::FillRect { rect: Rect::new(13, 16, 20, 23), color: BLACK };
+
+        testing::click(&backend, handle, 15, 20);
+        testing::click(&backend, handle, 15, 20);
+        assert!(backend.paint(handle).contains(&indeterminate_mark));
+        testing::click(&backend, handle, 15, 20);
+        assert!(!backend.paint(handle).contains(&indeterminate_mark));
+        testing::click(&backend, handle, 15, 20);
+        assert_eq!(*changes.borrow(), vec![
+            CheckState::Checked, CheckState::Indeterminate, CheckState::Unchecked, CheckState::Checked,
+        ]);
+    }
+
+    #[test]
+    fn space_toggles_on_release() {
+        let (backend, mut handle, id, changes) = window_with(CheckBox::new("Check"));
+        assert!(testing::core(&mut handle).focus(Some(id)));
+        testing::send(&backend, handle, Event::KeyDown {
+            key: VK_SPACE as u32, system: false, flags: Default::default(),
+        });
+        assert!(changes.borrow().is_empty());
+        testing::send(&backend, handle, Event::KeyUp {
+            key: VK_SPACE as u32, system: false, flags: Default::default(),
+        });
+        assert_eq!(*changes.borrow(), vec![CheckState::Checked]);
+
+        // Losing the focus while Space is down doesn't toggle.
+        testing::send(&backend, handle, Event::KeyDown {
+            key: VK_SPACE as u32, system: false, flags: Default::default(),
+        });
+        testing::core(&mut handle).focus(None);
+        assert_eq!(changes.borrow().len(), 1);
+    }
+
+    #[test]
+    fn releasing_elsewhere_or_disabled_boxes_dont_toggle() {
+        let (backend, handle, _, changes) = window_with(CheckBox::new("Check"));
+        testing::mouse_down(&backend, handle, 15, 20);
+        testing::mouse_move(&backend, handle, 150, 80);
+        testing::mouse_up(&backend, handle, 150, 80);
+        assert!(changes.borrow().is_empty());
+
+        let mut check_box = CheckBox::new("Check");
+        check_box.set_enabled(false);
+        let (backend, mut handle, id, changes) = window_with(check_box);
+        assert!(!testing::core(&mut handle).focus(Some(id)));
+        testing::click(&backend, handle, 15, 20);
+        assert!(changes.borrow().is_empty());
+    }
+}
@@ -0,0 +1,793 @@
+use winapi::*;
+
+use ::backend;
+use ::ml::{ Paintable, PaintContext };
+use ::event::{ Event, Modifiers, MouseButton };
+use ::font::FontBuilder;
+use ::geometry::{ Point, Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+use super::listview::{ ListState, SelectionMode };
+use super::popup::{ Popup, PopupError };
+use super::textbox::{ TextBox, TextBoxStyle };
+
+use std::cell::RefCell;
+use std::cmp::{ max, min };
+use std::rc::Rc;
+
+/// How many rows one notch of the mouse wheel scrolls the list.
+const WHEEL_ROWS: isize = 3;
+
+/// The width of the button with the arrow.
+pub const BUTTON_WIDTH: i32 = 17;
+
+/// The list of a combo box, shared with the popup it is painted in.
+struct DropDown {
+    font_builder: FontBuilder,
+    style: TextBoxStyle,
+    padding: Size,
+    /// The items shown, as indices into the items of the combo box.
+    matches: Vec<usize>,
+    rows: Vec<String>,
+    /// The focused row is the highlighted one.
+    state: ListState,
+    size: Size,
+}
+
+impl DropDown {
+    fn row_height(&self) -> i32 {
+        let text = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        text.height + 2 * self.padding.height
+    }
+
+    /// How many whole rows fit, but at least one.
+    fn page_rows(&self) -> usize {
+        let height = self.row_height();
+        if height <= 0 { 1 } else { max(1, (self.size.height - 2) / height) as usize }
+    }
+
+    /// The row at `y`, relative to the list, if there is one.
+    fn row_at(&self, y: i32) -> Option<usize> {
+        let height = self.row_height();
+        if y < 1 || height <= 0 {
+            return None;
+        }
+        let row = self.state.scroll() + ((y - 1) / height) as usize;
+        if row < self.rows.len() { Some(row) } else { None }
+    }
+
+    /// Highlights `row`, scrolling it into view.
+    fn highlight(&mut self, row: usize) {
+        self.state.select(row);
+        let page = self.page_rows();
+        self.state.ensure_visible(row, page);
+    }
+}
+
+impl Paintable for DropDown {
+    fn paint(&self, context: &PaintContext) {
+        let bounds = Rect::from_origin_size(Point::default(), self.size);
+        context.fill_rect(&bounds, self.style.background);
+        draw_frame(context, &bounds, self.style.border);
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+
+        let content = bounds.inset(1, 1);
+        let height = self.row_height();
+        let mut top = content.top;
+        for (row, text) in self.rows.iter().enumerate().skip(self.state.scroll()) {
+            if top >= content.bottom {
+                break;
+            }
+            let rect = Rect::new(content.left, top, content.right, min(top + height, content.bottom));
+            let color = if self.state.focus() == Some(row) {
+                context.fill_rect(&rect, self.style.selection_background);
+                self.style.selection_foreground
+            } else {
+                self.style.foreground
+            };
+            context.draw_text(text, &font, color, &rect.inset(self.padding.width, 0),
+                              DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX);
+            top += height;
+        }
+    }
+}
+
+/// A text field with a list of items to pick from below it, or above it if
+/// there is no room below.
+///
+/// A read-only combo box shows the selected item and only changes by
+/// picking another one: with the list, the arrow keys while it is closed,
+/// or by typing the start of an item. An editable one lets the user type
+/// anything, showing the items starting with what was typed as the list;
+/// the selected item is the one whose text is in the field, if any.
+///
+/// F4 or a click on the button opens and closes the list. While it is open
+/// the arrow keys move through it, Enter picks the highlighted item and
+/// Escape closes it without picking one.
+pub struct ComboBox {
+    /// Only changed through `set_bounds`, which lays out the field too.
+    bounds: Rect,
+    /// The text field, used for typing into editable combo boxes. Its font
+    /// and colors are the ones of the whole combo box.
+    pub field: TextBox,
+    /// The most rows the list shows before it scrolls.
+    pub max_visible_items: usize,
+    items: Vec<String>,
+    selected: Option<usize>,
+    editable: bool,
+    list: Rc<RefCell<DropDown>>,
+    popup: Popup,
+    /// Where the list is while it is open, relative to the combo box.
+    list_rect: Rect,
+    on_selection_change: Option<Box<FnMut(Option<usize>, &mut InputContext)>>,
+    on_popup_error: Option<Box<FnMut(&PopupError, &mut InputContext)>>,
+    focused: bool,
+}
+
+impl ComboBox {
+    pub fn new() -> Self {
+        let field = TextBox::new();
+        let list = Rc::new(RefCell::new(DropDown {
+            font_builder: FontBuilder::new(),
+            style: field.style,
+            padding: Size::new(4, 1),
+            matches: Vec::new(),
+            rows: Vec::new(),
+            state: ListState::new(SelectionMode::Single),
+            size: Size::default(),
+        }));
+        ComboBox {
+            bounds: Rect::default(),
+            field: field,
+            max_visible_items: 8,
+            items: Vec::new(),
+            selected: None,
+            editable: false,
+            popup: Popup::new(list.clone()),
+            list: list,
+            list_rect: Rect::default(),
+            on_selection_change: None,
+            on_popup_error: None,
+            focused: false,
+        }
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Replaces the items, keeping the selection if it still fits.
+    pub fn set_items(&mut self, items: Vec<String>) -> &mut Self {
+        self.items = items;
+        if self.selected.map_or(false, |i| i >= self.items.len()) {
+            self.selected = None;
+        }
+        self.popup.close();
+        self
+    }
+
+    pub fn add_item(&mut self, item: &str) -> &mut Self {
+        self.items.push(item.to_string());
+        self
+    }
+
+    pub fn is_editable(&self) -> bool {
+        self.editable
+    }
+
+    pub fn set_editable(&mut self, editable: bool) -> &mut Self {
+        self.editable = editable;
+        self
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Selects an item, putting its text into the field, without calling
+    /// the selection callback.
+    pub fn set_selected(&mut self, index: Option<usize>) -> &mut Self {
+        let index = index.and_then(|i| if i < self.items.len() { Some(i) } else { None });
+        self.selected = index;
+        match index {
+            Some(index) => {
+                let text = self.items[index].clone();
+                self.field.set_text(&text);
+            },
+            None => {
+                self.field.set_text("");
+            },
+        }
+        self
+    }
+
+    /// What the combo box shows: the selected item, or what was typed into
+    /// an editable one.
+    pub fn text(&self) -> String {
+        if self.editable {
+            self.field.text()
+        } else {
+            self.selected.map_or(String::new(), |i| self.items[i].clone())
+        }
+    }
+
+    /// Replaces the text of an editable combo box, selecting the item with
+    /// that text if there is one, without calling the selection callback.
+    pub fn set_text(&mut self, text: &str) -> &mut Self {
+        if self.editable {
+            self.field.set_text(text);
+            self.selected = self.items.iter().position(|item| item == text);
+        }
+        self
+    }
+
+    /// Calls `f` with the newly selected item whenever the user changes it.
+    pub fn set_on_selection_change<F: FnMut(Option<usize>, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_selection_change = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` when the list couldn't be opened, in which case it stays
+    /// closed.
+    pub fn set_on_popup_error<F: FnMut(&PopupError, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_popup_error = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.popup.is_open()
+    }
+
+    /// The item highlighted in the list while it is open.
+    pub fn highlighted(&self) -> Option<usize> {
+        if !self.popup.is_open() {
+            return None;
+        }
+        let list = self.list.borrow();
+        list.state.focus().and_then(|row| list.matches.get(row).cloned())
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    fn button_rect(&self) -> Rect {
+        Rect::new(self.bounds.right - BUTTON_WIDTH, self.bounds.top, self.bounds.right, self.bounds.bottom)
+    }
+
+    /// The items starting with the text of the field, or all of them for a
+    /// read-only combo box or an empty field.
+    fn matching_items(&self) -> Vec<usize> {
+        let text = self.field.text().to_lowercase();
+        if !self.editable || text.is_empty() {
+            return (0..self.items.len()).collect();
+        }
+        (0..self.items.len()).filter(|&i| self.items[i].to_lowercase().starts_with(&text)).collect()
+    }
+
+    /// Shows the list with `matches`, highlighting `highlight`.
+    fn open(&mut self, matches: Vec<usize>, highlight: Option<usize>, context: &mut InputContext)
+            -> Result<(), PopupError> {
+        if matches.is_empty() {
+            self.close(context);
+            return Ok(());
+        }
+        let height = {
+            let mut list = self.list.borrow_mut();
+            list.font_builder = self.field.font_builder.clone();
+            list.style = self.field.style;
+            list.rows = matches.iter().map(|&i| self.items[i].clone()).collect();
+            list.matches = matches;
+            list.row_height() * min(list.rows.len(), max(1, self.max_visible_items)) as i32 + 2
+        };
+
+        let anchor = Rect::from_origin_size(context.origin, self.bounds.size());
+        let rect = Popup::place(context.window, &anchor, Size::new(self.bounds.width(), height));
+        self.list_rect = rect.offset(-context.origin.x, -context.origin.y);
+        {
+            let mut list = self.list.borrow_mut();
+            list.size = rect.size();
+            list.state = ListState::new(SelectionMode::Single);
+            match highlight.and_then(|item| list.matches.iter().position(|&i| i == item)) {
+                Some(row) => list.highlight(row),
+                None => { },
+            }
+        }
+        context.invalidate();
+        self.popup.open(context.window, context.id, rect)
+    }
+
+    fn close(&mut self, context: &mut InputContext) {
+        if self.popup.is_open() {
+            self.popup.close();
+            context.invalidate();
+        }
+    }
+
+    /// Hands the error of opening the list to the popup error callback.
+    fn report(&mut self, result: Result<(), PopupError>, context: &mut InputContext) {
+        if let Err(e) = result {
+            if let Some(ref mut on_popup_error) = self.on_popup_error {
+                on_popup_error(&e, context);
+            }
+        }
+    }
+
+    /// Makes `index` the selected item on behalf of the user.
+    fn select_item(&mut self, index: Option<usize>, context: &mut InputContext) {
+        if let Some(index) = index {
+            let text = self.items[index].clone();
+            self.field.set_text(&text);
+            self.field.editor.select_all();
+        }
+        context.invalidate();
+        if index == self.selected {
+            return;
+        }
+        self.selected = index;
+        if let Some(ref mut on_selection_change) = self.on_selection_change {
+            on_selection_change(index, context);
+        }
+    }
+
+    /// Picks the item in `row` of the list and closes it.
+    fn pick(&mut self, row: usize, context: &mut InputContext) {
+        let index = self.list.borrow().matches.get(row).cloned();
+        self.close(context);
+        if index.is_some() {
+            self.select_item(index, context);
+        }
+    }
+
+    /// Filters the list after the text of an editable combo box changed,
+    /// and selects the item with that text, even if the list couldn't be
+    /// opened.
+    fn text_changed(&mut self, context: &mut InputContext) -> Result<(), PopupError> {
+        let text = self.field.text();
+        let opened = if text.is_empty() {
+            self.close(context);
+            Ok(())
+        } else {
+            let matches = self.matching_items();
+            let first = matches.first().cloned();
+            self.open(matches, first, context)
+        };
+
+        let index = self.items.iter().position(|item| *item == text);
+        if index != self.selected {
+            self.selected = index;
+            if let Some(ref mut on_selection_change) = self.on_selection_change {
+                on_selection_change(index, context);
+            }
+        }
+        opened
+    }
+
+    fn toggle(&mut self, context: &mut InputContext) -> Result<(), PopupError> {
+        if self.popup.is_open() {
+            self.close(context);
+            Ok(())
+        } else {
+            let matches = self.matching_items();
+            let selected = self.selected;
+            self.open(matches, selected, context)
+        }
+    }
+
+    /// Handles the keys that move through the items, returning whether it
+    /// was one of them.
+    fn navigate(&mut self, key: u32, context: &mut InputContext) -> bool {
+        let vk = key as i32;
+        if self.popup.is_open() {
+            let moved = {
+                let mut list = self.list.borrow_mut();
+                let count = list.rows.len();
+                let page = list.page_rows();
+                list.state.key(key, Modifiers::empty(), count, page).is_some()
+            };
+            if moved {
+                self.popup.invalidate();
+            }
+            return moved;
+        }
+
+        // Closed, the keys go through the items one at a time, apart from
+        // the ones an editable field needs itself.
+        let count = self.items.len();
+        if count == 0 {
+            return false;
+        }
+        let last = count - 1;
+        let current = self.selected;
+        let target = if vk == VK_UP {
+            current.map_or(0, |i| i.saturating_sub(1))
+        } else if vk == VK_DOWN {
+            current.map_or(0, |i| min(i + 1, last))
+        } else if self.editable {
+            return false;
+        } else if vk == VK_PRIOR {
+            current.map_or(0, |i| i.saturating_sub(max(1, self.max_visible_items) - 1))
+        } else if vk == VK_NEXT {
+            current.map_or(0, |i| min(i + max(1, self.max_visible_items) - 1, last))
+        } else if vk == VK_HOME {
+            0
+        } else if vk == VK_END {
+            last
+        } else {
+            return false;
+        };
+        self.select_item(Some(target), context);
+        true
+    }
+}
+
+impl Paintable for ComboBox {
+    fn paint(&self, context: &PaintContext) {
+        let style = &self.field.style;
+        if self.editable {
+            self.field.paint(context);
+        } else {
+            context.fill_rect(&self.bounds, style.background);
+            let border = if self.focused { style.focus_border } else { style.border };
+            draw_frame(context, &self.bounds, border);
+            let padding = self.field.padding;
+            let text = Rect::new(self.bounds.left + padding.width, self.bounds.top + padding.height,
+                                 self.bounds.right - BUTTON_WIDTH, self.bounds.bottom - padding.height);
+            let color = if self.focused && !self.popup.is_open() {
+                context.fill_rect(&text.inset(-2, 0), style.selection_background);
+                style.selection_foreground
+            } else {
+                style.foreground
+            };
+            if let (Some(index), Ok(font)) = (self.selected, self.field.font_builder.build()) {
+                context.draw_text(&self.items[index], &font, color, &text,
+                                  DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX);
+            }
+        }
+
+        let button = self.button_rect();
+        context.fill_rect(&button.inset(1, 1), style.background);
+        draw_frame(context, &button, style.border);
+        // A small triangle pointing down, drawn row by row.
+        let center = (button.left + button.right) / 2;
+        let top = (button.top + button.bottom) / 2 - 2;
+        for dy in 0..4 {
+            context.draw_line(Point::new(center - 3 + dy, top + dy), Point::new(center + 4 - dy, top + dy),
+                              style.foreground);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for ComboBox {
+    /// Wide enough for the widest item and the button.
+    fn measure(&self, available: Size) -> Size {
+        let field = self.field.measure(available);
+        let widest = self.items.iter()
+            .map(|item| self.field.font_builder.text_extent(item).map_or(0, |size| size.width))
+            .max()
+            .unwrap_or(0);
+        Size::new(max(field.width, widest + 2 * self.field.padding.width) + BUTTON_WIDTH, field.height)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.field.set_bounds(Rect::new(bounds.left, bounds.top, bounds.right - BUTTON_WIDTH + 1, bounds.bottom));
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        let size = self.bounds.size();
+        let list = if self.popup.is_open() { self.list_rect } else { Rect::default() };
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                self.field.handle_event(event, context);
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.field.handle_event(event, context);
+                self.close(context);
+                context.invalidate();
+                true
+            },
+
+            // Input on the list, passed on by the popup.
+            Event::MouseMove { position, .. } if list.contains(position) => {
+                let y = position.y - self.list_rect.top;
+                let row = self.list.borrow().row_at(y);
+                if let Some(row) = row {
+                    if self.list.borrow().state.focus() != Some(row) {
+                        self.list.borrow_mut().highlight(row);
+                        self.popup.invalidate();
+                    }
+                }
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } if list.contains(position) => true,
+            Event::MouseUp { button: MouseButton::Left, position, .. } if list.contains(position) => {
+                let y = position.y - self.list_rect.top;
+                let row = self.list.borrow().row_at(y);
+                if let Some(row) = row {
+                    self.pick(row, context);
+                }
+                true
+            },
+            Event::MouseWheel { delta, horizontal: false, .. } if self.popup.is_open() => {
+                {
+                    let mut list = self.list.borrow_mut();
+                    let count = list.rows.len();
+                    let page = list.page_rows();
+                    list.state.scroll_by(-(delta / WHEEL_DELTA as i32) as isize * WHEEL_ROWS, count, page);
+                }
+                self.popup.invalidate();
+                true
+            },
+
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                let on_button = position.x >= size.width - BUTTON_WIDTH && position.y < size.height;
+                if self.editable && !on_button {
+                    self.close(context);
+                    self.field.handle_event(event, context)
+                } else {
+                    let result = self.toggle(context);
+                    self.report(result, context);
+                    true
+                }
+            },
+            Event::MouseMove { .. } | Event::MouseUp { .. } if self.editable => self.field.handle_event(event, context),
+
+            Event::KeyDown { key, .. } => {
+                let vk = key as i32;
+                if vk == VK_F4 {
+                    let result = self.toggle(context);
+                    self.report(result, context);
+                    return true;
+                }
+                if self.popup.is_open() && vk == VK_ESCAPE {
+                    self.close(context);
+                    return true;
+                }
+                if self.popup.is_open() && vk == VK_RETURN {
+                    let row = self.list.borrow().state.focus();
+                    match row {
+                        Some(row) => self.pick(row, context),
+                        None => self.close(context),
+                    }
+                    return true;
+                }
+                if self.navigate(key, context) {
+                    return true;
+                }
+                if !self.editable {
+                    return false;
+                }
+                let before = self.field.text();
+                let handled = self.field.handle_event(event, context);
+                if self.field.text() != before {
+                    let result = self.text_changed(context);
+                    self.report(result, context);
+                }
+                handled
+            },
+            _ => false,
+        }
+    }
+
+    /// Typing goes into an editable field, or jumps to the next item
+    /// starting with what was typed in a read-only one.
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        if self.editable {
+            let before = self.field.text();
+            let handled = self.field.handle_char(c, context);
+            if self.field.text() != before {
+                let result = self.text_changed(context);
+                self.report(result, context);
+            }
+            return handled;
+        }
+        if c.is_control() {
+            return true;
+        }
+
+        let now = backend::current().tick_count();
+        let open = self.popup.is_open();
+        let found = {
+            let mut list = self.list.borrow_mut();
+            if !open {
+                // Closed, the search starts from the selected item.
+                list.matches = (0..self.items.len()).collect();
+                list.rows = self.items.clone();
+                match self.selected {
+                    Some(index) => list.state.select(index),
+                    None => list.state = ListState::new(SelectionMode::Single),
+                }
+            }
+            let count = list.rows.len();
+            let page = list.page_rows();
+            let items = &self.items;
+            let matches = list.matches.clone();
+            list.state.type_ahead(c, now, count, page, |row| items[matches[row]].clone())
+                .and_then(|_| list.state.focus())
+                .and_then(|row| matches.get(row).cloned())
+        };
+        match found {
+            Some(_) if open => self.popup.invalidate(),
+            Some(index) => self.select_item(Some(index), context),
+            None => { },
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::testing::{ self, open_window };
+
+    /// The height of a row of the list with the default font.
+    const ROW: i32 = 14;
+
+    /// A combo box at (10, 10) to (160, 30) in a new window, and the
+    /// selections the user made.
+    fn window_with(editable: bool) -> (Rc<HeadlessBackend>, HWND, Rc<RefCell<Vec<Option<usize>>>>) {
+        let (backend, mut handle) = open_window(300, 200);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut combo_box = ComboBox::new();
+        combo_box.set_items(["Apple", "Apricot", "Banana", "Blueberry", "Cherry"].iter().map(|s| s.to_string()).collect())
+            .set_editable(editable);
+        {
+            let changes = changes.clone();
+            combo_box.set_on_selection_change(move |index, _| changes.borrow_mut().push(index));
+        }
+        combo_box.set_bounds(Rect::new(10, 10, 160, 30));
+        testing::core(&mut handle).add_control(Box::new(combo_box));
+        (backend, handle, changes)
+    }
+
+    /// The rows of the open list, and the highlighted one.
+    fn list(backend: &HeadlessBackend, handle: HWND) -> Option<(Vec<String>, Option<String>)> {
+        let overlay = match backend.overlays(handle).first() {
+            Some(&overlay) => overlay,
+            None => return None,
+        };
+        let highlight = TextBoxStyle::default().selection_foreground;
+        let mut rows = Vec::new();
+        let mut highlighted = None;
+        for call in backend.paint(overlay) {
+            if let DrawCall::Text { text, color, .. } = call {
+                if color == highlight {
+                    highlighted = Some(text.clone());
+                }
+                rows.push(text);
+            }
+        }
+        Some((rows, highlighted))
+    }
+
+    fn rows(backend: &HeadlessBackend, handle: HWND) -> Vec<String> {
+        list(backend, handle).map_or(Vec::new(), |(rows, _)| rows)
+    }
+
+    fn type_text(backend: &HeadlessBackend, handle: HWND, text: &str) {
+        for c in text.chars() {
+            testing::type_char(backend, handle, c);
+        }
+    }
+
+    #[test]
+    fn the_button_opens_the_list_below() {
+        let (backend, handle, changes) = window_with(false);
+        testing::click(&backend, handle, 150, 20);
+        let overlay = backend.overlays(handle)[0];
+        assert_eq!(backend.screen_rect(overlay), Some(Rect::new(10, 30, 160, 32 + 5 * ROW)));
+        assert_eq!(rows(&backend, handle), vec!["Apple", "Apricot", "Banana", "Blueberry", "Cherry"]);
+
+        testing::click(&backend, handle, 150, 20);
+        assert!(backend.overlays(handle).is_empty());
+        assert!(changes.borrow().is_empty());
+    }
+
+    #[test]
+    fn clicks_on_the_list_pick_the_row_under_them() {
+        let (backend, handle, changes) = window_with(false);
+        testing::click(&backend, handle, 150, 20);
+        // Hovering highlights, in the client coordinates of the window.
+        testing::mouse_move(&backend, handle, 50, 31 + 2 * ROW + 5);
+        assert_eq!(list(&backend, handle).unwrap().1, Some("Banana".to_string()));
+
+        testing::click(&backend, handle, 50, 31 + 3 * ROW + 5);
+        assert_eq!(*changes.borrow(), vec![Some(3)]);
+        assert!(backend.overlays(handle).is_empty());
+        let texts = backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::Text { text, .. } => Some(text),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Blueberry"]);
+    }
+
+    #[test]
+    fn keys_move_through_the_open_list() {
+        let (backend, handle, changes) = window_with(false);
+        testing::click(&backend, handle, 150, 20);
+        testing::key(&backend, handle, VK_ESCAPE);
+        assert!(backend.overlays(handle).is_empty());
+
+        testing::key(&backend, handle, VK_F4);
+        testing::key(&backend, handle, VK_DOWN);
+        testing::key(&backend, handle, VK_DOWN);
+        assert_eq!(list(&backend, handle).unwrap().1, Some("Apricot".to_string()));
+        testing::key(&backend, handle, VK_UP);
+        assert_eq!(list(&backend, handle).unwrap().1, Some("Apple".to_string()));
+        testing::key(&backend, handle, VK_DOWN);
+        testing::key(&backend, handle, VK_RETURN);
+        assert!(backend.overlays(handle).is_empty());
+        assert_eq!(*changes.borrow(), vec![Some(1)]);
+
+        // Reopened, the selected item is highlighted, and Escape picks
+        // nothing.
+        testing::key(&backend, handle, VK_F4);
+        assert_eq!(list(&backend, handle).unwrap().1, Some("Apricot".to_string()));
+        testing::key(&backend, handle, VK_DOWN);
+        testing::key(&backend, handle, VK_ESCAPE);
+        assert_eq!(*changes.borrow(), vec![Some(1)]);
+
+        // Closed, the arrows select the next item right away.
+        testing::key(&backend, handle, VK_DOWN);
+        assert!(backend.overlays(handle).is_empty());
+        assert_eq!(*changes.borrow(), vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn typing_filters_by_prefix() {
+        let (backend, handle, changes) = window_with(true);
+        testing::click(&backend, handle, 20, 20);
+        type_text(&backend, handle, "a");
+        assert_eq!(list(&backend, handle).unwrap(), (vec!["Apple".to_string(), "Apricot".to_string()],
+                                                       Some("Apple".to_string())));
+        type_text(&backend, handle, "pr");
+        assert_eq!(rows(&backend, handle), vec!["Apricot"]);
+
+        // Nothing starts with it, so there is no list.
+        type_text(&backend, handle, "x");
+        assert!(backend.overlays(handle).is_empty());
+        testing::key(&backend, handle, VK_BACK);
+        assert_eq!(rows(&backend, handle), vec!["Apricot"]);
+        assert!(changes.borrow().is_empty());
+
+        testing::key(&backend, handle, VK_RETURN);
+        assert_eq!(*changes.borrow(), vec![Some(1)]);
+    }
+
+    #[test]
+    fn typing_an_item_selects_it() {
+        let (backend, handle, changes) = window_with(true);
+        testing::click(&backend, handle, 20, 20);
+        // The list ignores case, the selection doesn't.
+        type_text(&backend, handle, "cherry");
+        assert_eq!(rows(&backend, handle), vec!["Cherry"]);
+        assert!(changes.borrow().is_empty());
+
+        testing::key(&backend, handle, VK_ESCAPE);
+        testing::key(&backend, handle, VK_HOME);
+        testing::key(&backend, handle, VK_DELETE);
+        type_text(&backend, handle, "C");
+        assert_eq!(*changes.borrow(), vec![Some(4)]);
+        testing::key(&backend, handle, VK_BACK);
+        assert_eq!(*changes.borrow(), vec![Some(4), None]);
+    }
+}
@@ -0,0 +1,50 @@
+use ::colors::Color;
+use ::geometry::{ Rect, Size };
+use ::layout::Panel;
+use ::ml::{ Paintable, PaintContext };
+
+use super::Control;
+
+/// A control that only exists to lay out its children with a panel.
+pub struct Container<P: Panel> {
+    pub bounds: Rect,
+    pub panel: P,
+    pub background: Option<Color>,
+}
+
+impl<P: Panel> Container<P> {
+    pub fn new(panel: P) -> Self {
+        Container {
+            bounds: Rect::default(),
+            panel: panel,
+            background: None,
+        }
+    }
+}
+
+impl<P: Panel> Paintable for Container<P> {
+    fn paint(&self, context: &PaintContext) {
+        if let Some(color) = self.background {
+            context.fill_rect(&self.bounds, color);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl<P: Panel> Control for Container<P> {
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        Size::default()
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn panel(&self) -> Option<&Panel> {
+        Some(&self.panel)
+    }
+}
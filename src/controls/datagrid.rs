@@ -0,0 +1,961 @@
+use winapi::*;
+
+use ::backend;
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, LIGHT_GRAY, WHITE };
+use ::event::{ Event, MouseButton, MODIFIER_SHIFT };
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::layout::Alignment;
+
+use super::{ Control, InputContext, draw_frame };
+use super::listview::{ ListState, SelectionMode };
+
+use std::cmp::{ max, min, Ordering };
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// How many rows one notch of the mouse wheel scrolls.
+const WHEEL_ROWS: isize = 3;
+
+/// How close to the edge between two headers dragging resizes the column.
+const RESIZE_MARGIN: i32 = 4;
+
+/// Columns don't get any narrower than this by dragging.
+pub const MIN_COLUMN_WIDTH: i32 = 16;
+
+/// What is in a cell, before it is formatted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CellValue {
+    Empty,
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl CellValue {
+    /// The order rows are sorted in. Empty cells come first and numbers
+    /// before text; numbers compare by value whatever their kind, text
+    /// ignoring case.
+    pub fn compare(&self, other: &CellValue) -> Ordering {
+        use self::CellValue::*;
+        match (self, other) {
+            (&Integer(a), &Integer(b)) => a.cmp(&b),
+            (&Text(ref a), &Text(ref b)) => match a.to_lowercase().cmp(&b.to_lowercase()) {
+                Ordering::Equal => a.cmp(b),
+                ordering => ordering,
+            },
+            (&Bool(a), &Bool(b)) => a.cmp(&b),
+            (&Empty, &Empty) => Ordering::Equal,
+            _ => match (self.number(), other.number()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => self.rank().cmp(&other.rank()),
+            },
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            CellValue::Integer(value) => Some(value as f64),
+            CellValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Where the kind of value goes relative to the other kinds.
+    fn rank(&self) -> u8 {
+        match *self {
+            CellValue::Empty => 0,
+            CellValue::Bool(_) => 1,
+            CellValue::Integer(_) | CellValue::Float(_) => 2,
+            CellValue::Text(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for CellValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CellValue::Empty => Ok(()),
+            CellValue::Text(ref text) => write!(f, "{}", text),
+            CellValue::Integer(value) => write!(f, "{}", value),
+            CellValue::Float(value) => write!(f, "{}", value),
+            CellValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Where the rows of a grid come from, one cell at a time.
+pub trait GridSource {
+    fn row_count(&self) -> usize;
+    fn cell(&self, row: usize, field: usize) -> CellValue;
+}
+
+impl GridSource for Vec<Vec<CellValue>> {
+    fn row_count(&self) -> usize {
+        self.len()
+    }
+
+    fn cell(&self, row: usize, field: usize) -> CellValue {
+        self[row].get(field).cloned().unwrap_or(CellValue::Empty)
+    }
+}
+
+/// A column of a `DataGrid`.
+pub struct Column {
+    pub header: String,
+    /// Which cell of every row the column shows.
+    pub field: usize,
+    pub width: i32,
+    /// How the cells are aligned horizontally. `Stretch` is the same as
+    /// `Start`.
+    pub alignment: Alignment,
+    /// Whether clicking the header sorts the rows by the column.
+    pub sortable: bool,
+    formatter: Option<Box<Fn(&CellValue) -> String>>,
+}
+
+impl Column {
+    pub fn new(header: &str, field: usize, width: i32) -> Self {
+        Column {
+            header: header.to_string(),
+            field: field,
+            width: width,
+            alignment: Alignment::Start,
+            sortable: true,
+            formatter: None,
+        }
+    }
+
+    pub fn set_alignment(&mut self, alignment: Alignment) -> &mut Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn set_sortable(&mut self, sortable: bool) -> &mut Self {
+        self.sortable = sortable;
+        self
+    }
+
+    /// Turns cell values into text with `f` instead of `Display`. Sorting
+    /// still goes by the values.
+    pub fn set_formatter<F: Fn(&CellValue) -> String + 'static>(&mut self, f: F) -> &mut Self {
+        self.formatter = Some(Box::new(f));
+        self
+    }
+
+    pub fn format(&self, value: &CellValue) -> String {
+        match self.formatter {
+            Some(ref formatter) => formatter(value),
+            None => value.to_string(),
+        }
+    }
+
+    fn text_format(&self) -> UINT {
+        let alignment = match self.alignment {
+            Alignment::Start | Alignment::Stretch => DT_LEFT,
+            Alignment::Center => DT_CENTER,
+            Alignment::End => DT_RIGHT,
+        };
+        alignment | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX | DT_END_ELLIPSIS
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// The colors a grid is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridStyle {
+    pub foreground: Color,
+    pub background: Color,
+    pub header_foreground: Color,
+    pub header_background: Color,
+    pub grid_lines: Color,
+    pub selection_foreground: Color,
+    pub selection_background: Color,
+    /// The selection while the grid doesn't have the focus.
+    pub inactive_selection_background: Color,
+    pub border: Color,
+    pub focus: Color,
+}
+
+impl Default for GridStyle {
+    fn default() -> Self {
+        GridStyle {
+            foreground: BLACK,
+            background: WHITE,
+            header_foreground: BLACK,
+            header_background: LIGHT_GRAY,
+            grid_lines: LIGHT_GRAY,
+            selection_foreground: WHITE,
+            selection_background: BLUE,
+            inactive_selection_background: LIGHT_GRAY,
+            border: GRAY,
+            focus: GRAY,
+        }
+    }
+}
+
+/// What the left mouse button is doing in the header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HeaderDrag {
+    /// Pressed on the header of a column, which gets sorted by if the
+    /// button is released there too.
+    Pressed(usize),
+    /// Dragging the right edge of a column, which started out `width` wide
+    /// with the mouse at `x`.
+    Resizing { column: usize, x: i32, width: i32 },
+}
+
+/// Rows of a `GridSource` in columns under a header.
+///
+/// Like a `ListView`, the grid only asks for the rows in view and keeps its
+/// selection, keyboard focus and scrolling in a `ListState`, counting rows
+/// in the order they are shown. Clicking a header sorts by the column,
+/// clicking it again reverses the order; dragging the edge between two
+/// headers resizes the column on the left. Selections and callbacks talk
+/// about rows of the source.
+pub struct DataGrid {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub style: GridStyle,
+    /// Space around the text of every cell.
+    pub padding: Size,
+    columns: Vec<Column>,
+    source: Box<GridSource>,
+    state: ListState,
+    /// The source rows in the order they are shown.
+    order: Vec<usize>,
+    sort: Option<(usize, SortOrder)>,
+    /// How far the columns are scrolled to the left, in pixels.
+    scroll_x: i32,
+    drag: Option<HeaderDrag>,
+    on_selection_change: Option<Box<FnMut(&[usize], &mut InputContext)>>,
+    on_sort: Option<Box<FnMut(usize, SortOrder, &mut InputContext)>>,
+    on_activate: Option<Box<FnMut(usize, &mut InputContext)>>,
+    focused: bool,
+}
+
+impl DataGrid {
+    pub fn new(source: Box<GridSource>) -> Self {
+        let order = (0..source.row_count()).collect();
+        DataGrid {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            style: GridStyle::default(),
+            padding: Size::new(4, 1),
+            columns: Vec::new(),
+            source: source,
+            state: ListState::new(SelectionMode::Single),
+            order: order,
+            sort: None,
+            scroll_x: 0,
+            drag: None,
+            on_selection_change: None,
+            on_sort: None,
+            on_activate: None,
+            focused: false,
+        }
+    }
+
+    pub fn add_column(&mut self, column: Column) -> &mut Self {
+        self.columns.push(column);
+        self
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn column_mut(&mut self, index: usize) -> Option<&mut Column> {
+        self.columns.get_mut(index)
+    }
+
+    pub fn source(&self) -> &GridSource {
+        &*self.source
+    }
+
+    /// Replaces the rows, dropping the selection but keeping the sort
+    /// order.
+    pub fn set_source(&mut self, source: Box<GridSource>) -> &mut Self {
+        self.source = source;
+        self.state = ListState::new(self.state.selection_mode());
+        self.order = (0..self.source.row_count()).collect();
+        self.sort_rows();
+        self
+    }
+
+    /// Catches up with a source whose rows changed behind the grid's back,
+    /// sorting them again.
+    pub fn rows_changed(&mut self) {
+        let selected = self.selected_rows();
+        let focus = self.focused_row();
+        self.order = (0..self.source.row_count()).collect();
+        self.sort_rows();
+        self.restore_selection(&selected, focus);
+    }
+
+    pub fn state(&self) -> &ListState {
+        &self.state
+    }
+
+    pub fn set_selection_mode(&mut self, mode: SelectionMode) -> &mut Self {
+        self.state.set_selection_mode(mode);
+        self
+    }
+
+    /// The source row shown at `index`, counting from the top.
+    pub fn source_row(&self, index: usize) -> Option<usize> {
+        self.order.get(index).cloned()
+    }
+
+    /// The selected source rows, in the order they are shown.
+    pub fn selected_rows(&self) -> Vec<usize> {
+        self.state.selected().iter().filter_map(|&i| self.source_row(i)).collect()
+    }
+
+    /// The source row with the keyboard focus.
+    pub fn focused_row(&self) -> Option<usize> {
+        self.state.focus().and_then(|i| self.source_row(i))
+    }
+
+    /// Selects and focuses the source row `row` and scrolls it into view,
+    /// without calling the change callback.
+    pub fn select_row(&mut self, row: usize) -> &mut Self {
+        if let Some(index) = self.order.iter().position(|&r| r == row) {
+            self.state.select(index);
+            let page = self.page_rows();
+            self.state.ensure_visible(index, page);
+        }
+        self
+    }
+
+    pub fn sort_order(&self) -> Option<(usize, SortOrder)> {
+        self.sort
+    }
+
+    /// Sorts by `column`, or shows the rows in source order, without
+    /// calling the sort callback. The selection stays with its rows.
+    pub fn sort_by(&mut self, sort: Option<(usize, SortOrder)>) -> &mut Self {
+        let selected = self.selected_rows();
+        let focus = self.focused_row();
+        self.sort = sort;
+        if sort.is_none() {
+            self.order = (0..self.source.row_count()).collect();
+        }
+        self.sort_rows();
+        self.restore_selection(&selected, focus);
+        self
+    }
+
+    /// Calls `f` with the selected source rows whenever the user changes
+    /// the selection.
+    pub fn set_on_selection_change<F: FnMut(&[usize], &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_selection_change = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the column and the order whenever the user sorts.
+    pub fn set_on_sort<F: FnMut(usize, SortOrder, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_sort = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the source row activated with Enter or a double-click.
+    pub fn set_on_activate<F: FnMut(usize, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_activate = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn scroll_x(&self) -> i32 {
+        self.scroll_x
+    }
+
+    /// The first row in view and the one after the last, partly visible
+    /// ones included.
+    pub fn visible_range(&self) -> (usize, usize) {
+        let height = max(1, self.row_height());
+        let rows = (self.body().height() + height - 1) / height;
+        let first = self.state.scroll();
+        (first, min(first + max(0, rows) as usize, self.order.len()))
+    }
+
+    pub fn scroll_by(&mut self, rows: isize) {
+        let count = self.order.len();
+        let page = self.page_rows();
+        self.state.scroll_by(rows, count, page);
+    }
+
+    /// Scrolls the columns horizontally by `dx` pixels, within their total
+    /// width.
+    pub fn scroll_x_by(&mut self, dx: i32) {
+        let total = self.columns.iter().map(|c| c.width).sum::<i32>();
+        let width = self.content().width();
+        self.scroll_x = max(0, min(self.scroll_x + dx, total - width));
+    }
+
+    fn content(&self) -> Rect {
+        self.bounds.inset(1, 1)
+    }
+
+    fn row_height(&self) -> i32 {
+        let text = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        text.height + 2 * self.padding.height
+    }
+
+    fn header(&self) -> Rect {
+        let content = self.content();
+        Rect::new(content.left, content.top, content.right, min(content.top + self.row_height(), content.bottom))
+    }
+
+    /// Where the rows go, below the header.
+    fn body(&self) -> Rect {
+        let content = self.content();
+        Rect::new(content.left, self.header().bottom, content.right, content.bottom)
+    }
+
+    /// How many whole rows fit, but at least one.
+    fn page_rows(&self) -> usize {
+        let height = self.row_height();
+        if height <= 0 { 1 } else { max(1, self.body().height() / height) as usize }
+    }
+
+    /// The left and right edge of every column, scrolling included.
+    fn column_edges(&self) -> Vec<(i32, i32)> {
+        let mut left = self.content().left - self.scroll_x;
+        self.columns.iter().map(|column| {
+            let edges = (left, left + column.width);
+            left += column.width;
+            edges
+        }).collect()
+    }
+
+    /// Sorts `order` by the sort column, keeping rows that compare equal in
+    /// source order.
+    fn sort_rows(&mut self) {
+        let (column, order) = match self.sort {
+            Some(sort) => sort,
+            None => return,
+        };
+        let field = match self.columns.get(column) {
+            Some(column) => column.field,
+            None => return,
+        };
+        let keys = (0..self.source.row_count()).map(|row| self.source.cell(row, field)).collect::<Vec<_>>();
+        self.order = (0..keys.len()).collect();
+        self.order.sort_by(|&a, &b| match order {
+            SortOrder::Ascending => keys[a].compare(&keys[b]),
+            SortOrder::Descending => keys[b].compare(&keys[a]),
+        });
+    }
+
+    /// Selects the source rows `rows` again after the order changed.
+    fn restore_selection(&mut self, rows: &[usize], focus: Option<usize>) {
+        let mut positions = vec![None; self.source.row_count()];
+        for (index, &row) in self.order.iter().enumerate() {
+            positions[row] = Some(index);
+        }
+        let position = |row: usize| positions.get(row).cloned().and_then(|p| p);
+        let selected = rows.iter().filter_map(|&row| position(row)).collect::<BTreeSet<_>>();
+        let focus = focus.and_then(|row| position(row));
+        self.state.set_selection(selected, focus);
+        if let Some(focus) = focus {
+            let page = self.page_rows();
+            self.state.ensure_visible(focus, page);
+        }
+        let count = self.order.len();
+        self.state.clamp(count);
+    }
+
+    /// The column whose header is at `x`, and whether `x` is on its right
+    /// edge, where dragging resizes it.
+    fn header_at(&self, x: i32) -> Option<(usize, bool)> {
+        let x = x + self.bounds.left;
+        let edges = self.column_edges();
+        if let Some(column) = edges.iter().position(|&(_, right)| (x - right).abs() <= RESIZE_MARGIN) {
+            return Some((column, true));
+        }
+        edges.iter().position(|&(left, right)| x >= left && x < right).map(|column| (column, false))
+    }
+
+    /// The row shown at `y`, if there is one.
+    fn row_at(&self, y: i32) -> Option<usize> {
+        let y = y + self.bounds.top - self.body().top;
+        let height = self.row_height();
+        if y < 0 || height <= 0 {
+            return None;
+        }
+        let index = self.state.scroll() + (y / height) as usize;
+        if index < self.order.len() { Some(index) } else { None }
+    }
+
+    fn in_header(&self, y: i32) -> bool {
+        let y = y + self.bounds.top;
+        let header = self.header();
+        y >= header.top && y < header.bottom
+    }
+
+    fn selection_changed(&mut self, context: &mut InputContext) {
+        let rows = self.selected_rows();
+        if let Some(ref mut on_selection_change) = self.on_selection_change {
+            on_selection_change(&rows, context);
+        }
+    }
+
+    fn activate(&mut self, context: &mut InputContext) {
+        if let Some(row) = self.focused_row() {
+            if let Some(ref mut on_activate) = self.on_activate {
+                on_activate(row, context);
+            }
+        }
+    }
+
+    /// Sorts by `column` on behalf of the user: ascending at first, then
+    /// the other way round every time.
+    fn header_clicked(&mut self, column: usize, context: &mut InputContext) {
+        if !self.columns[column].sortable {
+            return;
+        }
+        let order = match self.sort {
+            Some((sorted, SortOrder::Ascending)) if sorted == column => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        };
+        self.sort_by(Some((column, order)));
+        context.invalidate();
+        if let Some(ref mut on_sort) = self.on_sort {
+            on_sort(column, order, context);
+        }
+    }
+
+    /// Draws `text` into `rect`, clipped to `clip`.
+    fn draw_cell(&self, context: &PaintContext, font: &Font, text: &str, color: Color, rect: Rect, clip: &Rect,
+                 format: UINT) {
+        if let Some(visible) = rect.inset(self.padding.width, 0).intersection(clip) {
+            context.draw_text(text, font, color, &visible, format);
+        }
+    }
+
+    /// A small triangle pointing up for ascending order and down for
+    /// descending, centered on `center`.
+    fn draw_sort_glyph(&self, context: &PaintContext, center: Point, order: SortOrder) {
+        for row in 0..4 {
+            let half = match order {
+                SortOrder::Ascending => row,
+                SortOrder::Descending => 3 - row,
+            };
+            let y = center.y - 2 + row;
+            context.draw_line(Point::new(center.x - half, y), Point::new(center.x + half + 1, y),
+                              self.style.header_foreground);
+        }
+    }
+}
+
+impl Paintable for DataGrid {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        draw_frame(context, &self.bounds, self.style.border);
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let edges = self.column_edges();
+
+        let header = self.header();
+        context.fill_rect(&header, self.style.header_background);
+        for (index, column) in self.columns.iter().enumerate() {
+            let (left, right) = edges[index];
+            if right <= header.left || left >= header.right {
+                continue;
+            }
+            let mut rect = Rect::new(left, header.top, right, header.bottom);
+            if let Some((sorted, order)) = self.sort {
+                if sorted == index {
+                    let center = Point::new(right - self.padding.width - 4, (header.top + header.bottom) / 2);
+                    if center.x - 4 >= header.left && center.x + 4 < header.right {
+                        self.draw_sort_glyph(context, center, order);
+                    }
+                    rect.right -= 12;
+                }
+            }
+            self.draw_cell(context, &font, &column.header, self.style.header_foreground, rect, &header,
+                           column.text_format());
+            if right - 1 >= header.left && right - 1 < header.right {
+                context.draw_line(Point::new(right - 1, header.top), Point::new(right - 1, header.bottom),
+                                  self.style.grid_lines);
+            }
+        }
+
+        let body = self.body();
+        let height = self.row_height();
+        let (first, last) = self.visible_range();
+        let selection = if self.focused { self.style.selection_background } else { self.style.inactive_selection_background };
+        for index in first..last {
+            let top = body.top + (index - first) as i32 * height;
+            let row = Rect::new(body.left, top, body.right, min(top + height, body.bottom));
+            let color = if self.state.is_selected(index) {
+                context.fill_rect(&row, selection);
+                if self.focused { self.style.selection_foreground } else { self.style.foreground }
+            } else {
+                self.style.foreground
+            };
+            let source_row = self.order[index];
+            for (column_index, column) in self.columns.iter().enumerate() {
+                let (left, right) = edges[column_index];
+                if right <= body.left || left >= body.right {
+                    continue;
+                }
+                let text = column.format(&self.source.cell(source_row, column.field));
+                self.draw_cell(context, &font, &text, color, Rect::new(left, top, right, top + height), &row,
+                               column.text_format());
+            }
+            if self.focused && self.state.focus() == Some(index) {
+                draw_frame(context, &row, self.style.focus);
+            }
+        }
+
+        for &(_, right) in edges.iter() {
+            if right - 1 >= body.left && right - 1 < body.right {
+                context.draw_line(Point::new(right - 1, body.top), Point::new(right - 1, body.bottom),
+                                  self.style.grid_lines);
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for DataGrid {
+    /// All columns and a few rows.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let width = self.columns.iter().map(|c| c.width).sum::<i32>();
+        Size::new(width + 2, 9 * self.row_height() + 2)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.scroll_x_by(0);
+        self.scroll_by(0);
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.drag = None;
+                context.invalidate();
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, modifiers } |
+            Event::DoubleClick { button: MouseButton::Left, position, modifiers } => {
+                let double = match *event { Event::DoubleClick { .. } => true, _ => false };
+                if self.in_header(position.y) {
+                    self.drag = self.header_at(position.x).map(|(column, edge)| if edge {
+                        HeaderDrag::Resizing { column: column, x: position.x, width: self.columns[column].width }
+                    } else {
+                        HeaderDrag::Pressed(column)
+                    });
+                } else if let Some(index) = self.row_at(position.y) {
+                    if double {
+                        self.activate(context);
+                    } else {
+                        let page = self.page_rows();
+                        let changed = self.state.click(index, modifiers);
+                        self.state.ensure_visible(index, page);
+                        if changed {
+                            self.selection_changed(context);
+                        }
+                        context.invalidate();
+                    }
+                }
+                true
+            },
+            Event::MouseMove { position, .. } => match self.drag {
+                Some(HeaderDrag::Resizing { column, x, width }) => {
+                    self.columns[column].width = max(MIN_COLUMN_WIDTH, width + position.x - x);
+                    context.invalidate();
+                    true
+                },
+                _ => false,
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                match self.drag.take() {
+                    Some(HeaderDrag::Pressed(column)) => {
+                        if self.in_header(position.y) && self.header_at(position.x) == Some((column, false)) {
+                            self.header_clicked(column, context);
+                        }
+                        true
+                    },
+                    Some(HeaderDrag::Resizing { .. }) => {
+                        self.scroll_x_by(0);
+                        context.invalidate();
+                        true
+                    },
+                    None => false,
+                }
+            },
+            Event::MouseWheel { delta, horizontal, .. } => {
+                let notches = delta / WHEEL_DELTA as i32;
+                if horizontal || context.modifiers.contains(MODIFIER_SHIFT) {
+                    let step = self.row_height();
+                    self.scroll_x_by(-notches * step * WHEEL_ROWS as i32);
+                } else {
+                    self.scroll_by(-notches as isize * WHEEL_ROWS);
+                }
+                context.invalidate();
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_RETURN as u32 => {
+                self.activate(context);
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_LEFT as u32 || key == VK_RIGHT as u32 => {
+                let step = self.row_height();
+                self.scroll_x_by(if key == VK_LEFT as u32 { -step } else { step });
+                context.invalidate();
+                true
+            },
+            Event::KeyDown { key, .. } => {
+                let count = self.order.len();
+                let page = self.page_rows();
+                match self.state.key(key, context.modifiers, count, page) {
+                    Some(changed) => {
+                        if changed {
+                            self.selection_changed(context);
+                        }
+                        context.invalidate();
+                        true
+                    },
+                    None => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Type-ahead, on the sort column or else the first one.
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        if c.is_control() || c == ' ' {
+            return true;
+        }
+        let now = backend::current().tick_count();
+        let count = self.order.len();
+        let page = self.page_rows();
+        let result = match self.columns.get(self.sort.map_or(0, |(column, _)| column)) {
+            Some(column) => {
+                let (source, order) = (&self.source, &self.order);
+                let row = |i: usize| column.format(&source.cell(order[i], column.field));
+                self.state.type_ahead(c, now, count, page, row)
+            },
+            None => None,
+        };
+        if let Some(changed) = result {
+            if changed {
+                self.selection_changed(context);
+            }
+            context.invalidate();
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::event::{ Modifiers, MODIFIER_CONTROL };
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// What the callbacks of a grid were called with.
+    #[derive(Default)]
+    struct Calls {
+        sorts: Vec<(usize, SortOrder)>,
+        selections: Vec<Vec<usize>>,
+    }
+
+    /// A grid at (10, 10) to (310, 200) in a new window, with a 100 pixel
+    /// wide "Name" column and an 80 pixel wide "Size" column. With the
+    /// headless font rows are 14 pixels high, so the header goes from 11 to
+    /// 25 and the first row from 25 to 39.
+    fn window_with(mode: SelectionMode) -> (Rc<HeadlessBackend>, HWND, Rc<RefCell<Calls>>) {
+        let (backend, mut handle) = open_window(400, 300);
+        let rows = vec![
+            vec![CellValue::Text("b".to_string()), CellValue::Integer(2)],
+            vec![CellValue::Text("a".to_string()), CellValue::Integer(3)],
+            vec![CellValue::Text("c".to_string()), CellValue::Integer(1)],
+        ];
+        let calls = Rc::new(RefCell::new(Calls::default()));
+        let mut grid = DataGrid::new(Box::new(rows));
+        grid.add_column(Column::new("Name", 0, 100))
+            .add_column(Column::new("Size", 1, 80))
+            .set_selection_mode(mode);
+        {
+            let calls = calls.clone();
+            grid.set_on_sort(move |column, order, _| calls.borrow_mut().sorts.push((column, order)));
+        }
+        {
+            let calls = calls.clone();
+            grid.set_on_selection_change(move |rows, _| calls.borrow_mut().selections.push(rows.to_vec()));
+        }
+        grid.set_bounds(Rect::new(10, 10, 310, 200));
+        testing::core(&mut handle).add_control(Box::new(grid));
+        backend.paint(handle);
+        (backend, handle, calls)
+    }
+
+    /// The text of the cells, row by row, leaving out the header.
+    fn cells(backend: &HeadlessBackend, handle: HWND) -> Vec<String> {
+        backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::Text { text, rect, .. } if rect.top >= 25 => Some(text),
+            _ => None,
+        }).collect()
+    }
+
+    /// The columns of the vertical grid lines in the header.
+    fn header_lines(backend: &HeadlessBackend, handle: HWND) -> Vec<i32> {
+        backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::Line { from, to, color } if color == LIGHT_GRAY && from.y == 11 && to.y == 25 => Some(from.x),
+            _ => None,
+        }).collect()
+    }
+
+    fn row_y(index: i32) -> i32 {
+        25 + index * 14 + 7
+    }
+
+    fn click_with(backend: &HeadlessBackend, handle: HWND, x: i32, y: i32, modifiers: Modifiers) {
+        testing::mouse_move(backend, handle, x, y);
+        testing::send(backend, handle, Event::MouseDown {
+            button: MouseButton::Left,
+            position: Point::new(x, y),
+            modifiers: modifiers,
+        });
+        testing::mouse_up(backend, handle, x, y);
+    }
+
+    #[test]
+    fn clicking_a_header_sorts() {
+        let (backend, handle, calls) = window_with(SelectionMode::Single);
+        assert_eq!(cells(&backend, handle), vec!["b", "2", "a", "3", "c", "1"]);
+
+        testing::click(&backend, handle, 50, 18);
+        assert_eq!(cells(&backend, handle), vec!["a", "3", "b", "2", "c", "1"]);
+        testing::click(&backend, handle, 50, 18);
+        assert_eq!(cells(&backend, handle), vec!["c", "1", "b", "2", "a", "3"]);
+        testing::click(&backend, handle, 150, 18);
+        assert_eq!(cells(&backend, handle), vec!["c", "1", "b", "2", "a", "3"]);
+        assert_eq!(calls.borrow().sorts, vec![(0, SortOrder::Ascending), (0, SortOrder::Descending),
+                                              (1, SortOrder::Ascending)]);
+    }
+
+    #[test]
+    fn releasing_elsewhere_does_not_sort() {
+        let (backend, handle, calls) = window_with(SelectionMode::Single);
+        // On another header, then below the header.
+        testing::mouse_down(&backend, handle, 50, 18);
+        testing::mouse_move(&backend, handle, 150, 18);
+        testing::mouse_up(&backend, handle, 150, 18);
+        testing::mouse_down(&backend, handle, 50, 18);
+        testing::mouse_move(&backend, handle, 50, row_y(1));
+        testing::mouse_up(&backend, handle, 50, row_y(1));
+        assert!(calls.borrow().sorts.is_empty());
+        assert_eq!(cells(&backend, handle), vec!["b", "2", "a", "3", "c", "1"]);
+    }
+
+    #[test]
+    fn columns_that_are_not_sortable_ignore_clicks() {
+        let (backend, mut handle) = open_window(400, 300);
+        let mut grid = DataGrid::new(Box::new(vec![vec![CellValue::Integer(2)], vec![CellValue::Integer(1)]]));
+        grid.add_column(Column::new("Size", 0, 80));
+        grid.column_mut(0).unwrap().set_sortable(false);
+        grid.set_bounds(Rect::new(10, 10, 310, 200));
+        testing::core(&mut handle).add_control(Box::new(grid));
+        testing::click(&backend, handle, 50, 18);
+        assert_eq!(cells(&backend, handle), vec!["2", "1"]);
+    }
+
+    #[test]
+    fn dragging_a_header_edge_resizes_the_column() {
+        let (backend, handle, calls) = window_with(SelectionMode::Single);
+        assert_eq!(header_lines(&backend, handle), vec![110, 190]);
+
+        // The edge of the first column is at 111; grabbing it a little off
+        // still resizes.
+        testing::mouse_down(&backend, handle, 113, 18);
+        testing::mouse_move(&backend, handle, 143, 18);
+        assert_eq!(header_lines(&backend, handle), vec![140, 220]);
+        // Far to the left, outside the grid: the column keeps its minimum
+        // width.
+        testing::mouse_move(&backend, handle, 0, 100);
+        assert_eq!(header_lines(&backend, handle), vec![10 + MIN_COLUMN_WIDTH, 10 + MIN_COLUMN_WIDTH + 80]);
+        testing::mouse_move(&backend, handle, 63, 18);
+        testing::mouse_up(&backend, handle, 63, 18);
+        assert_eq!(header_lines(&backend, handle), vec![60, 140]);
+
+        // Moving after the release changes nothing, and resizing didn't
+        // sort.
+        testing::mouse_move(&backend, handle, 200, 18);
+        assert_eq!(header_lines(&backend, handle), vec![60, 140]);
+        assert!(calls.borrow().sorts.is_empty());
+        assert_eq!(cells(&backend, handle), vec!["b", "2", "a", "3", "c", "1"]);
+    }
+
+    #[test]
+    fn clicking_rows_selects_them() {
+        let (backend, handle, calls) = window_with(SelectionMode::Single);
+        testing::click(&backend, handle, 50, row_y(1));
+        testing::click(&backend, handle, 150, row_y(2));
+        // Clicking the selected row again, or below the last one, changes
+        // nothing.
+        testing::click(&backend, handle, 150, row_y(2));
+        testing::click(&backend, handle, 50, row_y(5));
+        assert_eq!(calls.borrow().selections, vec![vec![1], vec![2]]);
+
+        let selected = DrawCall::FillRect { rect: Rect::new(11, 53, 309, 67), color: BLUE };
+        assert!(backend.paint(handle).contains(&selected));
+    }
+
+    #[test]
+    fn selection_follows_its_rows_when_sorting() {
+        let (backend, handle, calls) = window_with(SelectionMode::Multiple);
+        testing::click(&backend, handle, 50, row_y(0));
+        click_with(&backend, handle, 50, row_y(2), MODIFIER_CONTROL);
+        assert_eq!(calls.borrow().selections, vec![vec![0], vec![0, 2]]);
+
+        // Sorted by name "b" and "c" are the last two rows, still selected.
+        testing::click(&backend, handle, 50, 18);
+        let paint = backend.paint(handle);
+        for &top in &[39, 53] {
+            assert!(paint.contains(&DrawCall::FillRect { rect: Rect::new(11, top, 309, top + 14), color: BLUE }));
+        }
+        assert!(!paint.contains(&DrawCall::FillRect { rect: Rect::new(11, 25, 309, 39), color: BLUE }));
+
+        // Shift-clicking the first row now selects from "c" up to "a".
+        click_with(&backend, handle, 50, row_y(0), MODIFIER_SHIFT);
+        assert_eq!(calls.borrow().selections.last(), Some(&vec![1, 0, 2]));
+        assert_eq!(calls.borrow().sorts, vec![(0, SortOrder::Ascending)]);
+    }
+}
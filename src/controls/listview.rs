@@ -0,0 +1,701 @@
+use winapi::*;
+
+use ::backend;
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, LIGHT_GRAY, WHITE };
+use ::event::{ Event, Modifiers, MouseButton, MODIFIER_CONTROL, MODIFIER_SHIFT };
+use ::font::FontBuilder;
+use ::geometry::{ Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+
+use std::cmp::{ max, min };
+use std::collections::BTreeSet;
+
+/// How many rows one notch of the mouse wheel scrolls.
+const WHEEL_ROWS: isize = 3;
+
+/// How long after the last typed character type-ahead starts over, in
+/// milliseconds.
+pub const TYPE_AHEAD_TIMEOUT: u32 = 1000;
+
+/// Where the rows of a list come from.
+///
+/// Lists only ask for the rows they show, so the rows can be made up on
+/// the fly instead of being kept around.
+pub trait ListSource {
+    fn row_count(&self) -> usize;
+    fn row(&self, index: usize) -> String;
+}
+
+impl ListSource for Vec<String> {
+    fn row_count(&self) -> usize {
+        self.len()
+    }
+
+    fn row(&self, index: usize) -> String {
+        self[index].clone()
+    }
+}
+
+/// How many rows can be selected at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SelectionMode {
+    None,
+    Single,
+    /// Any number, with Ctrl and Shift as in Explorer.
+    Multiple,
+}
+
+/// Selection, keyboard focus and scrolling of a list of rows, apart from
+/// what is in the rows or how they look.
+///
+/// The focused row is the one the keyboard acts on; with single selection
+/// it is the selected one. The anchor is where Shift starts range
+/// selections from.
+#[derive(Clone, Debug)]
+pub struct ListState {
+    mode: SelectionMode,
+    selected: BTreeSet<usize>,
+    focus: Option<usize>,
+    anchor: Option<usize>,
+    /// The first row in view.
+    scroll: usize,
+    type_ahead: String,
+    /// When the last character was typed, in backend ticks.
+    typed_at: u32,
+}
+
+impl ListState {
+    pub fn new(mode: SelectionMode) -> Self {
+        ListState {
+            mode: mode,
+            selected: BTreeSet::new(),
+            focus: None,
+            anchor: None,
+            scroll: 0,
+            type_ahead: String::new(),
+            typed_at: 0,
+        }
+    }
+
+    pub fn selection_mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    /// Changes the selection mode, dropping what no longer fits into it.
+    pub fn set_selection_mode(&mut self, mode: SelectionMode) {
+        self.mode = mode;
+        match mode {
+            SelectionMode::None => self.selected.clear(),
+            SelectionMode::Single => {
+                let focus = self.focus;
+                self.selected.retain(|&i| Some(i) == focus);
+            },
+            SelectionMode::Multiple => { },
+        }
+    }
+
+    pub fn selected(&self) -> &BTreeSet<usize> {
+        &self.selected
+    }
+
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.selected.contains(&index)
+    }
+
+    pub fn focus(&self) -> Option<usize> {
+        self.focus
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Makes `index` the only selected row, and the focused one.
+    pub fn select(&mut self, index: usize) {
+        self.selected.clear();
+        if self.mode != SelectionMode::None {
+            self.selected.insert(index);
+        }
+        self.focus = Some(index);
+        self.anchor = Some(index);
+    }
+
+    /// Replaces the selection and the focused row, e.g. after the rows
+    /// were put in a different order.
+    pub fn set_selection(&mut self, selected: BTreeSet<usize>, focus: Option<usize>) {
+        self.selected = if self.mode == SelectionMode::None { BTreeSet::new() } else { selected };
+        self.focus = focus;
+        self.anchor = focus;
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected.clear();
+    }
+
+    pub fn select_all(&mut self, count: usize) {
+        if self.mode == SelectionMode::Multiple {
+            self.selected = (0..count).collect();
+        }
+    }
+
+    /// Forgets about rows past `count`, after the rows changed.
+    pub fn clamp(&mut self, count: usize) {
+        self.selected = self.selected.iter().cloned().filter(|&i| i < count).collect();
+        let last = if count > 0 { Some(count - 1) } else { None };
+        self.focus = self.focus.and_then(|f| last.map(|l| min(f, l)));
+        self.anchor = self.anchor.and_then(|a| last.map(|l| min(a, l)));
+        self.scroll = min(self.scroll, count.saturating_sub(1));
+    }
+
+    /// Moves the focus to `index` the way a click or a key press with
+    /// `modifiers` would, returning whether the selection changed.
+    pub fn move_to(&mut self, index: usize, modifiers: Modifiers) -> bool {
+        let before = self.selected.clone();
+        let extend = modifiers.contains(MODIFIER_SHIFT);
+        let keep = modifiers.contains(MODIFIER_CONTROL);
+        match self.mode {
+            SelectionMode::Multiple if extend => {
+                let anchor = self.anchor.unwrap_or(index);
+                if !keep {
+                    self.selected.clear();
+                }
+                for i in min(anchor, index)..max(anchor, index) + 1 {
+                    self.selected.insert(i);
+                }
+                self.focus = Some(index);
+            },
+            SelectionMode::Multiple if keep => {
+                self.focus = Some(index);
+            },
+            _ => self.select(index),
+        }
+        self.selected != before
+    }
+
+    /// Selects or unselects the focused row, as Ctrl+Space and Ctrl+click
+    /// do. Returns whether the selection changed.
+    pub fn toggle(&mut self, index: usize) -> bool {
+        self.focus = Some(index);
+        self.anchor = Some(index);
+        match self.mode {
+            SelectionMode::Multiple => {
+                if !self.selected.remove(&index) {
+                    self.selected.insert(index);
+                }
+                true
+            },
+            SelectionMode::Single if !self.selected.contains(&index) => {
+                self.select(index);
+                true
+            },
+            SelectionMode::Single | SelectionMode::None => false,
+        }
+    }
+
+    /// A click on `index`, returning whether the selection changed.
+    pub fn click(&mut self, index: usize, modifiers: Modifiers) -> bool {
+        if self.mode == SelectionMode::Multiple && modifiers.contains(MODIFIER_CONTROL)
+                && !modifiers.contains(MODIFIER_SHIFT) {
+            self.toggle(index)
+        } else {
+            self.move_to(index, modifiers)
+        }
+    }
+
+    /// Handles the arrow keys, Page Up and Down, Home, End, Space and
+    /// Ctrl+A for `count` rows with `page` of them in view. Returns `None`
+    /// for other keys, otherwise whether the selection changed.
+    pub fn key(&mut self, key: u32, modifiers: Modifiers, count: usize, page: usize) -> Option<bool> {
+        if count == 0 {
+            return None;
+        }
+        let key = key as i32;
+        let last = count - 1;
+        let page = max(1, page);
+        let current = self.focus;
+        let target = if key == VK_UP {
+            current.map_or(0, |f| f.saturating_sub(1))
+        } else if key == VK_DOWN {
+            current.map_or(0, |f| min(f + 1, last))
+        } else if key == VK_PRIOR {
+            current.map_or(0, |f| f.saturating_sub(page - 1))
+        } else if key == VK_NEXT {
+            current.map_or(0, |f| min(f + page - 1, last))
+        } else if key == VK_HOME {
+            0
+        } else if key == VK_END {
+            last
+        } else if key == VK_SPACE {
+            let focus = current.unwrap_or(0);
+            return Some(if modifiers.contains(MODIFIER_CONTROL) {
+                self.toggle(focus)
+            } else {
+                self.move_to(focus, Modifiers::empty())
+            });
+        } else if key == 'A' as i32 && modifiers.contains(MODIFIER_CONTROL) {
+            let before = self.selected.len();
+            self.select_all(count);
+            return Some(self.selected.len() != before);
+        } else {
+            return None;
+        };
+        let changed = self.move_to(target, modifiers);
+        self.ensure_visible(target, page);
+        Some(changed)
+    }
+
+    /// Searches for the next row starting with what was typed recently,
+    /// `c` included, and moves there. Typing the same letter over and over
+    /// goes through the rows starting with it. Returns `None` if no row
+    /// matched, otherwise whether the selection changed.
+    pub fn type_ahead<F>(&mut self, c: char, now: u32, count: usize, page: usize, row: F) -> Option<bool>
+            where F: Fn(usize) -> String {
+        if now.wrapping_sub(self.typed_at) > TYPE_AHEAD_TIMEOUT {
+            self.type_ahead.clear();
+        }
+        self.typed_at = now;
+        self.type_ahead.extend(c.to_lowercase());
+        if count == 0 {
+            return None;
+        }
+
+        let first = self.type_ahead.chars().next().unwrap();
+        let repeated = self.type_ahead.chars().all(|c| c == first);
+        let (prefix, skip) = if repeated {
+            (first.to_string(), 1)
+        } else {
+            (self.type_ahead.clone(), 0)
+        };
+        let start = self.focus.map_or(0, |f| f + skip);
+        let found = (0..count)
+            .map(|n| (start + n) % count)
+            .find(|&i| row(i).to_lowercase().starts_with(&prefix));
+        found.map(|index| {
+            let changed = self.move_to(index, Modifiers::empty());
+            self.ensure_visible(index, page);
+            changed
+        })
+    }
+
+    /// Scrolls just far enough to have `index` in view.
+    pub fn ensure_visible(&mut self, index: usize, page: usize) {
+        let page = max(1, page);
+        if index < self.scroll {
+            self.scroll = index;
+        } else if index >= self.scroll + page {
+            self.scroll = index + 1 - page;
+        }
+    }
+
+    /// Scrolls by `rows`, but not past the last page.
+    pub fn scroll_by(&mut self, rows: isize, count: usize, page: usize) {
+        let scroll = max(0, self.scroll as isize + rows) as usize;
+        self.scroll_to(scroll, count, page);
+    }
+
+    pub fn scroll_to(&mut self, row: usize, count: usize, page: usize) {
+        self.scroll = min(row, count.saturating_sub(max(1, page)));
+    }
+}
+
+/// The colors a list is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListStyle {
+    pub foreground: Color,
+    pub background: Color,
+    pub selection_foreground: Color,
+    pub selection_background: Color,
+    /// The selection while the list doesn't have the focus.
+    pub inactive_selection_background: Color,
+    pub border: Color,
+    pub focus: Color,
+}
+
+impl Default for ListStyle {
+    fn default() -> Self {
+        ListStyle {
+            foreground: BLACK,
+            background: WHITE,
+            selection_foreground: WHITE,
+            selection_background: BLUE,
+            inactive_selection_background: LIGHT_GRAY,
+            border: GRAY,
+            focus: GRAY,
+        }
+    }
+}
+
+/// A scrolling list of text rows from a `ListSource`.
+///
+/// All rows are as high as one line of text, so only the rows in view are
+/// ever asked for, measured or painted, however many there are. A
+/// `Vec<String>` makes a simple list box.
+///
+/// Besides the keys `ListState` knows, Enter and double-clicks activate the
+/// focused row and typing jumps to the next row starting with what was
+/// typed.
+pub struct ListView {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub style: ListStyle,
+    /// Space around the text of every row.
+    pub padding: Size,
+    source: Box<ListSource>,
+    state: ListState,
+    on_selection_change: Option<Box<FnMut(&BTreeSet<usize>, &mut InputContext)>>,
+    on_activate: Option<Box<FnMut(usize, &mut InputContext)>>,
+    focused: bool,
+}
+
+impl ListView {
+    pub fn new(source: Box<ListSource>) -> Self {
+        ListView {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            style: ListStyle::default(),
+            padding: Size::new(4, 1),
+            source: source,
+            state: ListState::new(SelectionMode::Single),
+            on_selection_change: None,
+            on_activate: None,
+            focused: false,
+        }
+    }
+
+    pub fn source(&self) -> &ListSource {
+        &*self.source
+    }
+
+    /// Replaces the rows, dropping the selection.
+    pub fn set_source(&mut self, source: Box<ListSource>) -> &mut Self {
+        self.source = source;
+        self.state = ListState::new(self.state.selection_mode());
+        self
+    }
+
+    /// Catches up with a source whose rows changed behind the list's back.
+    pub fn rows_changed(&mut self) {
+        let count = self.source.row_count();
+        self.state.clamp(count);
+    }
+
+    pub fn state(&self) -> &ListState {
+        &self.state
+    }
+
+    pub fn set_selection_mode(&mut self, mode: SelectionMode) -> &mut Self {
+        self.state.set_selection_mode(mode);
+        self
+    }
+
+    /// Selects and focuses the row at `index` and scrolls it into view,
+    /// without calling the change callback.
+    pub fn select(&mut self, index: usize) -> &mut Self {
+        if index < self.source.row_count() {
+            self.state.select(index);
+            let page = self.page_rows();
+            self.state.ensure_visible(index, page);
+        }
+        self
+    }
+
+    /// Calls `f` with the selected rows whenever the user changes the
+    /// selection.
+    pub fn set_on_selection_change<F>(&mut self, f: F) -> &mut Self
+            where F: FnMut(&BTreeSet<usize>, &mut InputContext) + 'static {
+        self.on_selection_change = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the row activated with Enter or a double-click.
+    pub fn set_on_activate<F: FnMut(usize, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_activate = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The first row in view and the one after the last, partly visible
+    /// ones included.
+    pub fn visible_range(&self) -> (usize, usize) {
+        let height = max(1, self.row_height());
+        let rows = (self.content().height() + height - 1) / height;
+        let first = self.state.scroll();
+        (first, min(first + max(0, rows) as usize, self.source.row_count()))
+    }
+
+    /// Scrolls so that `row` is the first one in view, as far as possible.
+    pub fn scroll_to(&mut self, row: usize) {
+        let count = self.source.row_count();
+        let page = self.page_rows();
+        self.state.scroll_to(row, count, page);
+    }
+
+    pub fn scroll_by(&mut self, rows: isize) {
+        let count = self.source.row_count();
+        let page = self.page_rows();
+        self.state.scroll_by(rows, count, page);
+    }
+
+    fn content(&self) -> Rect {
+        self.bounds.inset(1, 1)
+    }
+
+    fn row_height(&self) -> i32 {
+        let text = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        text.height + 2 * self.padding.height
+    }
+
+    /// How many whole rows fit, but at least one.
+    fn page_rows(&self) -> usize {
+        let height = self.row_height();
+        if height <= 0 { 1 } else { max(1, self.content().height() / height) as usize }
+    }
+
+    /// The row at `y`, relative to the list, if there is one.
+    fn row_at(&self, y: i32) -> Option<usize> {
+        let y = y - (self.content().top - self.bounds.top);
+        let height = self.row_height();
+        if y < 0 || height <= 0 {
+            return None;
+        }
+        let index = self.state.scroll() + (y / height) as usize;
+        if index < self.source.row_count() { Some(index) } else { None }
+    }
+
+    fn selection_changed(&mut self, context: &mut InputContext) {
+        if let Some(ref mut on_selection_change) = self.on_selection_change {
+            on_selection_change(self.state.selected(), context);
+        }
+    }
+
+    fn activate(&mut self, context: &mut InputContext) {
+        if let Some(index) = self.state.focus() {
+            if let Some(ref mut on_activate) = self.on_activate {
+                on_activate(index, context);
+            }
+        }
+    }
+}
+
+impl Paintable for ListView {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        draw_frame(context, &self.bounds, self.style.border);
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+
+        let content = self.content();
+        let height = self.row_height();
+        let (first, last) = self.visible_range();
+        let selection = if self.focused { self.style.selection_background } else { self.style.inactive_selection_background };
+        for index in first..last {
+            let top = content.top + (index - first) as i32 * height;
+            let row = Rect::new(content.left, top, content.right, min(top + height, content.bottom));
+            let color = if self.state.is_selected(index) {
+                context.fill_rect(&row, selection);
+                if self.focused { self.style.selection_foreground } else { self.style.foreground }
+            } else {
+                self.style.foreground
+            };
+            let text = Rect::new(row.left + self.padding.width, top + self.padding.height, row.right, row.bottom);
+            context.draw_text(&self.source.row(index), &font, color, &text,
+                              DT_LEFT | DT_TOP | DT_SINGLELINE | DT_NOPREFIX | DT_END_ELLIPSIS);
+            if self.focused && self.state.focus() == Some(index) {
+                draw_frame(context, &row, self.style.focus);
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for ListView {
+    /// A few rows of a few words. The rows themselves aren't measured,
+    /// there may be far too many of them.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let line = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        Size::new(10 * line.width + 2 * self.padding.width + 2, 8 * self.row_height() + 2)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        let scroll = self.state.scroll();
+        self.scroll_to(scroll);
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        let count = self.source.row_count();
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                context.invalidate();
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, modifiers } => {
+                if let Some(index) = self.row_at(position.y) {
+                    let page = self.page_rows();
+                    let changed = self.state.click(index, modifiers);
+                    self.state.ensure_visible(index, page);
+                    if changed {
+                        self.selection_changed(context);
+                    }
+                    context.invalidate();
+                }
+                true
+            },
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                if self.row_at(position.y).is_some() {
+                    self.activate(context);
+                }
+                true
+            },
+            Event::MouseWheel { delta, horizontal: false, .. } => {
+                self.scroll_by(-(delta / WHEEL_DELTA as i32) as isize * WHEEL_ROWS);
+                context.invalidate();
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_RETURN as u32 => {
+                self.activate(context);
+                true
+            },
+            Event::KeyDown { key, .. } => {
+                let page = self.page_rows();
+                match self.state.key(key, context.modifiers, count, page) {
+                    Some(changed) => {
+                        if changed {
+                            self.selection_changed(context);
+                        }
+                        context.invalidate();
+                        true
+                    },
+                    None => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        // Space was taken care of as a key, and so were Enter and friends.
+        if c.is_control() || (c == ' ' && self.state.type_ahead.is_empty()) {
+            return true;
+        }
+        let now = backend::current().tick_count();
+        let count = self.source.row_count();
+        let page = self.page_rows();
+        let source = &self.source;
+        if let Some(changed) = self.state.type_ahead(c, now, count, page, |i| source.row(i)) {
+            if changed {
+                self.selection_changed(context);
+            }
+            context.invalidate();
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::testing::{ self, open_window };
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    /// Rows made up on the fly, counting how many were asked for.
+    struct Entries {
+        count: usize,
+        asked: Rc<Cell<usize>>,
+    }
+
+    impl ListSource for Entries {
+        fn row_count(&self) -> usize {
+            self.count
+        }
+
+        fn row(&self, index: usize) -> String {
+            self.asked.set(self.asked.get() + 1);
+            format!("Entry {}", index)
+        }
+    }
+
+    /// A window with a list of `count` rows, ten of which fit, with the
+    /// mouse over it.
+    fn window_with(count: usize) -> (Rc<HeadlessBackend>, HWND, Rc<Cell<usize>>) {
+        let (backend, mut handle) = open_window(300, 200);
+        let asked = Rc::new(Cell::new(0));
+        let mut list = ListView::new(Box::new(Entries { count: count, asked: asked.clone() }));
+        list.set_bounds(Rect::new(0, 0, 200, 10 * 14 + 2));
+        testing::core(&mut handle).add_control(Box::new(list));
+        testing::mouse_move(&backend, handle, 50, 50);
+        (backend, handle, asked)
+    }
+
+    fn texts(calls: &[DrawCall]) -> Vec<String> {
+        calls.iter().filter_map(|call| match *call {
+            DrawCall::Text { ref text, .. } => Some(text.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn only_visible_rows_are_asked_for() {
+        let (backend, handle, asked) = window_with(1000000);
+        let shown = texts(&backend.paint(handle));
+        assert_eq!(shown.len(), 10);
+        assert_eq!(shown[0], "Entry 0");
+        assert_eq!(asked.get(), 10);
+
+        testing::wheel(&backend, handle, -2);
+        let shown = texts(&backend.paint(handle));
+        assert_eq!((&shown[0][..], &shown[9][..]), ("Entry 6", "Entry 15"));
+        assert_eq!(asked.get(), 20);
+
+        // Scrolling stops with the last row at the bottom.
+        let (backend, handle, _) = window_with(25);
+        testing::wheel(&backend, handle, -10);
+        let shown = texts(&backend.paint(handle));
+        assert_eq!((&shown[0][..], &shown[9][..]), ("Entry 15", "Entry 24"));
+    }
+
+    /// Scrolls through a million rows a notch at a time, repainting after
+    /// every notch. Run with `cargo test -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn scrolling_a_large_list() {
+        const NOTCHES: u32 = 20000;
+        let (backend, handle, asked) = window_with(1000000);
+        backend.paint(handle);
+        let start = Instant::now();
+        for _ in 0..NOTCHES {
+            testing::wheel(&backend, handle, -1);
+            backend.paint(handle);
+        }
+        let elapsed = start.elapsed();
+        let nanos = elapsed.as_secs() * 1000000000 + elapsed.subsec_nanos() as u64;
+        println!("{} notches in {} ms, {} us per notch, {} rows asked for",
+                 NOTCHES, nanos / 1000000, nanos / 1000 / NOTCHES as u64, asked.get());
+        assert_eq!(asked.get(), 10 * (NOTCHES as usize + 1));
+    }
+}
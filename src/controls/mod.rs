@@ -1,7 +1,127 @@
+pub mod button;
+pub mod checkbox;
+pub mod combobox;
+pub mod container;
+pub mod datagrid;
 pub mod label;
+pub mod listview;
+pub mod popup;
+pub mod progressbar;
+pub mod radio;
+pub mod range;
+pub mod slider;
+pub mod spinbox;
+pub mod textarea;
+pub mod textbox;
+pub mod treeview;
 
-use ::winapi::winuser::NMHDR;
+use ::winapi::*;
+use ::colors::Color;
+use ::event::{ Event, Modifiers };
+use ::geometry::{ Point, Rect, Size };
+use ::layout::Panel;
+use ::message::{ MessageError, MessageQueue, WindowSender };
+use ::ml::{ Paintable, PaintContext };
 
-pub trait Control {
-    fn handle_notify(&mut self, info: *const NMHDR);
+use std::marker::Reflect;
+use std::sync::Arc;
+
+/// What a control handling input knows about its window, and can ask of it.
+pub struct InputContext {
+    /// The id of the control in the window's control tree.
+    pub id: i32,
+    pub window: HWND,
+    /// The modifier keys held down, as far as the window has seen.
+    pub modifiers: Modifiers,
+    /// The control's top left corner in client coordinates.
+    pub origin: Point,
+    queue: Arc<MessageQueue>,
+    invalidated: bool,
+}
+
+impl InputContext {
+    pub fn new(id: i32, window: HWND, queue: Arc<MessageQueue>) -> Self {
+        InputContext {
+            id: id,
+            window: window,
+            modifiers: Modifiers::empty(),
+            origin: Point::default(),
+            queue: queue,
+            invalidated: false,
+        }
+    }
+
+    /// Has the control repainted once it is done with the event.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    pub fn is_invalidated(&self) -> bool {
+        self.invalidated
+    }
+
+    /// Posts a typed message to the window, to be handled after the event.
+    pub fn post<T: Reflect + Send + 'static>(&self, msg: T) -> Result<(), MessageError> {
+        WindowSender::new(self.window, self.queue.clone()).send(msg)
+    }
+}
+
+/// Something that lives in a window's control tree.
+pub trait Control : Paintable {
+    #[allow(unused_variables)]
+    fn handle_notify(&mut self, info: *const NMHDR) { }
+
+    /// How large the contents would like to be, given the space available
+    /// inside the padding. Defaults to the current size.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        self.bounds().map_or(Size::default(), |b| b.size())
+    }
+
+    /// Called by the layout pass with the final bounds, relative to the
+    /// parent. Controls that don't store their bounds ignore it.
+    #[allow(unused_variables)]
+    fn set_bounds(&mut self, bounds: Rect) { }
+
+    /// Handles mouse and keyboard input, with positions relative to the
+    /// control's top left corner, returning whether it did. Unhandled events
+    /// go on to the parent.
+    ///
+    /// Mouse events go to the control under the mouse, or the one that got
+    /// the button press until the button is released; keyboard events and
+    /// `Focus`/`Blur` to the focused control. `MouseLeave` arrives when the
+    /// mouse moves off the control.
+    #[allow(unused_variables)]
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        false
+    }
+
+    /// Handles a typed character while focused.
+    #[allow(unused_variables)]
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        false
+    }
+
+    /// Whether clicking or tabbing to the control gives it the keyboard focus.
+    fn focusable(&self) -> bool {
+        false
+    }
+
+    /// Containers return how they arrange their children.
+    fn panel(&self) -> Option<&Panel> {
+        None
+    }
+}
+
+/// Draws the one pixel wide outline just inside `rect`.
+pub fn draw_frame(context: &PaintContext, rect: &Rect, color: Color) {
+    if rect.is_empty() {
+        return;
+    }
+    let right = rect.right - 1;
+    let bottom = rect.bottom - 1;
+    context.draw_line(Point::new(rect.left, rect.top), Point::new(right, rect.top), color);
+    context.draw_line(Point::new(right, rect.top), Point::new(right, bottom), color);
+    context.draw_line(Point::new(right, bottom), Point::new(rect.left, bottom), color);
+    context.draw_line(Point::new(rect.left, bottom), Point::new(rect.left, rect.top), color);
 }
@@ -0,0 +1,307 @@
+use winapi::*;
+
+use ::backend;
+use ::ml::{ Paintable, PaintContext };
+use ::event::Event;
+use ::geometry::{ Point, Rect, Size };
+use ::layout::Layout;
+use ::window::{ self, Window, WindowBuilder, WindowClass, WindowClassBuilder, WindowCore };
+
+use super::{ Control, InputContext };
+
+use std::cell::RefCell;
+use std::cmp::{ max, min };
+use std::fmt;
+use std::ptr;
+use std::rc::Rc;
+
+/// Why a popup couldn't be opened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PopupError {
+    /// Creating the window failed with this error code.
+    Create(DWORD),
+}
+
+impl fmt::Display for PopupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PopupError::Create(code) => write!(f, "creating the popup window failed with error {}", code),
+        }
+    }
+}
+
+impl From<DWORD> for PopupError {
+    fn from(code: DWORD) -> Self {
+        PopupError::Create(code)
+    }
+}
+
+/// The window a `Popup` shows up in.
+struct PopupWindow {
+    core: Option<WindowCore>,
+}
+
+impl Window for PopupWindow {
+    fn init_handle(&mut self, handle: HWND) {
+        self.core = Some(WindowCore::from_handle(handle));
+    }
+
+    fn get_core<'a>(&'a self) -> &'a WindowCore {
+        self.core.as_ref().unwrap()
+    }
+
+    fn get_core_mut<'a>(&'a mut self) -> &'a mut WindowCore {
+        self.core.as_mut().unwrap()
+    }
+
+    fn get_debug_name(&self) -> String {
+        "PopupWindow".to_string()
+    }
+
+    fn quits_on_destroy(&self) -> bool {
+        false
+    }
+}
+
+impl Paintable for PopupWindow {
+    fn paint(&self, context: &PaintContext) {
+        self.get_core().paint(context);
+    }
+}
+
+impl WindowClass for PopupWindow {
+    fn class_name() -> &'static str { "popup_wnd_class" }
+    fn default_title() -> &'static str { "" }
+    /// Stays on top without taking the focus away from the owner.
+    fn default_extended_style() -> DWORD { WS_EX_TOOLWINDOW | WS_EX_TOPMOST | WS_EX_NOACTIVATE }
+    fn default_style() -> UINT { WS_POPUP }
+    fn default_class_style() -> UINT { CS_DROPSHADOW | CS_SAVEBITS }
+
+    fn new() -> Box<Window> {
+        Box::new(PopupWindow {
+            core: None,
+        })
+    }
+}
+
+/// Fills a popup window, painting its content and passing the input on to
+/// the control that opened it.
+struct PopupSurface {
+    bounds: Rect,
+    content: Rc<RefCell<Paintable>>,
+    owner: HWND,
+    control: i32,
+}
+
+impl Paintable for PopupSurface {
+    fn paint(&self, context: &PaintContext) {
+        self.content.borrow().paint(context);
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for PopupSurface {
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::MouseMove { .. } | Event::MouseDown { .. } | Event::MouseUp { .. } |
+            Event::DoubleClick { .. } | Event::MouseWheel { .. } => { },
+            _ => return false,
+        }
+        let backend = backend::current();
+        if backend.user_data(self.owner) == 0 {
+            return false;
+        }
+        // Into the client coordinates of the owner.
+        let offset = backend.client_to_screen(context.window, Point::default())
+            - backend.client_to_screen(self.owner, Point::default());
+        let event = event.relative_to(Point::default() - offset);
+        let mut owner = self.owner;
+        window::get_window_from_handle_mut(&mut owner).get_core_mut().send_event(self.control, &event)
+    }
+}
+
+/// Something a control shows outside of its bounds, like the list of a
+/// combo box, in a window of its own.
+///
+/// The popup only paints `content`, in its own client coordinates. Mouse
+/// input on it goes on to the control that opened it, in that control's
+/// coordinates, so the control handles it like input on itself; the
+/// keyboard focus stays where it was. With the Win32 backend the popup is
+/// a `WS_POPUP` window owned by the control's window, which the headless
+/// backend turns into an overlay on top of it.
+///
+/// Controls must not open a popup while handling input that came from it,
+/// as moving the window lays it out again underneath them.
+pub struct Popup {
+    content: Rc<RefCell<Paintable>>,
+    handle: Option<HWND>,
+    owner: HWND,
+    control: i32,
+    /// Where the popup is, in the client coordinates of the owner.
+    rect: Rect,
+    open: bool,
+}
+
+impl Popup {
+    pub fn new(content: Rc<RefCell<Paintable>>) -> Self {
+        Popup {
+            content: content,
+            handle: None,
+            owner: ptr::null_mut(),
+            control: 0,
+            rect: Rect::default(),
+            open: false,
+        }
+    }
+
+    /// Where a popup of `size` goes next to `anchor`, both in the client
+    /// coordinates of `owner`: below it if it fits on the screen there,
+    /// otherwise wherever there is more room, and no higher than that.
+    pub fn place(owner: HWND, anchor: &Rect, size: Size) -> Rect {
+        let backend = backend::current();
+        let origin = backend.client_to_screen(owner, Point::default());
+        let area = backend.work_area(owner).offset(-origin.x, -origin.y);
+        let below = area.bottom - anchor.bottom;
+        let above = anchor.top - area.top;
+        let left = max(area.left, min(anchor.left, area.right - size.width));
+        if size.height <= below || below >= above {
+            Rect::new(left, anchor.bottom, left + size.width, anchor.bottom + min(size.height, max(0, below)))
+        } else {
+            Rect::new(left, anchor.top - min(size.height, above), left + size.width, anchor.top)
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Where the popup is, or was last, in the client coordinates of the
+    /// window that opened it.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// The popup window, once it was opened.
+    pub fn handle(&self) -> Option<HWND> {
+        self.handle
+    }
+
+    /// Shows the popup at `rect` in the client coordinates of `owner`, for
+    /// the control `control` of it. The window is created the first time
+    /// and moved after that; if that fails the popup stays closed.
+    pub fn open(&mut self, owner: HWND, control: i32, rect: Rect) -> Result<(), PopupError> {
+        let backend = backend::current();
+        if self.handle.is_some() && (self.owner != owner || self.control != control) {
+            self.destroy();
+        }
+        let origin = backend.client_to_screen(owner, Point::default());
+        let screen = rect.offset(origin.x, origin.y);
+        let handle = match self.handle {
+            Some(handle) => {
+                backend.move_window(handle, &screen);
+                handle
+            },
+            None => {
+                WindowClassBuilder::<PopupWindow>::new().register(ptr::null_mut());
+                let mut handle = try!(WindowBuilder::<PopupWindow>::new(ptr::null_mut())
+                    .set_parent(owner)
+                    .set_position(screen.left, screen.top)
+                    .set_width(screen.width())
+                    .set_height(screen.height())
+                    .build());
+                {
+                    let core = window::get_window_from_handle_mut(&mut handle).get_core_mut();
+                    let id = core.add_control(Box::new(PopupSurface {
+                        bounds: Rect::default(),
+                        content: self.content.clone(),
+                        owner: owner,
+                        control: control,
+                    }));
+                    core.set_layout(id, Layout::new()).unwrap();
+                    // Nothing constrains the surface, so it always fits.
+                    core.update_layout().unwrap();
+                }
+                self.handle = Some(handle);
+                self.owner = owner;
+                self.control = control;
+                handle
+            },
+        };
+        self.rect = rect;
+        self.open = true;
+        backend.show_window(handle, SW_SHOWNOACTIVATE);
+        backend.invalidate_rect(handle, None);
+        Ok(())
+    }
+
+    /// Hides the popup, keeping the window around for the next time.
+    pub fn close(&mut self) {
+        if let (true, Some(handle)) = (self.open, self.handle) {
+            backend::current().show_window(handle, SW_HIDE);
+        }
+        self.open = false;
+    }
+
+    /// Has the content painted again.
+    pub fn invalidate(&self) {
+        if let (true, Some(handle)) = (self.open, self.handle) {
+            backend::current().invalidate_rect(handle, None);
+        }
+    }
+
+    fn destroy(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            backend::current().destroy_window(handle);
+        }
+        self.open = false;
+    }
+}
+
+impl Drop for Popup {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::testing::open_window;
+
+    #[test]
+    fn popups_go_below_the_anchor_if_they_fit() {
+        let (_backend, handle) = open_window(300, 200);
+        let anchor = Rect::new(10, 10, 160, 30);
+        assert_eq!(Popup::place(handle, &anchor, Size::new(150, 72)), Rect::new(10, 30, 160, 102));
+        // Until the bottom of the work area.
+        assert_eq!(Popup::place(handle, &anchor, Size::new(150, 300)), Rect::new(10, 30, 160, 200));
+    }
+
+    #[test]
+    fn popups_go_above_the_anchor_when_there_is_more_room() {
+        let (_backend, handle) = open_window(300, 200);
+        let anchor = Rect::new(10, 170, 160, 190);
+        assert_eq!(Popup::place(handle, &anchor, Size::new(150, 72)), Rect::new(10, 98, 160, 170));
+        assert_eq!(Popup::place(handle, &anchor, Size::new(150, 300)), Rect::new(10, 0, 160, 170));
+
+        // More room below still wins over not fitting above.
+        let anchor = Rect::new(10, 80, 160, 100);
+        assert_eq!(Popup::place(handle, &anchor, Size::new(150, 150)), Rect::new(10, 100, 160, 200));
+    }
+
+    #[test]
+    fn popups_stay_inside_the_work_area_sideways() {
+        let (_backend, handle) = open_window(300, 200);
+        let anchor = Rect::new(250, 10, 290, 30);
+        assert_eq!(Popup::place(handle, &anchor, Size::new(100, 50)), Rect::new(200, 30, 300, 80));
+        let anchor = Rect::new(-20, 10, 20, 30);
+        assert_eq!(Popup::place(handle, &anchor, Size::new(100, 50)), Rect::new(0, 30, 100, 80));
+    }
+}
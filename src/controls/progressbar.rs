@@ -0,0 +1,218 @@
+use ::backend;
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLUE, GRAY, WHITE };
+use ::geometry::{ Rect, Size };
+use ::timer::TimerId;
+use ::window::WindowCore;
+
+use super::{ Control, draw_frame };
+use super::range::ValueRange;
+
+use std::cmp::{ max, min };
+use std::time::Duration;
+
+/// How long the block of an indeterminate progress bar takes to cross it,
+/// in milliseconds.
+pub const MARQUEE_PERIOD: u32 = 2000;
+
+/// How often `ProgressBar::animate` repaints, in milliseconds.
+const MARQUEE_INTERVAL: u64 = 30;
+
+/// The colors a progress bar is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressStyle {
+    pub background: Color,
+    pub border: Color,
+    pub bar: Color,
+}
+
+impl Default for ProgressStyle {
+    fn default() -> Self {
+        ProgressStyle {
+            background: WHITE,
+            border: GRAY,
+            bar: BLUE,
+        }
+    }
+}
+
+/// A bar filling up from the left as some work gets done.
+///
+/// An indeterminate progress bar shows that something is going on without
+/// knowing how far along it is: a block keeps moving across it. The block's
+/// position comes from the time it is painted at, so it only moves while
+/// something repaints the bar, like `ProgressBar::animate`.
+///
+/// Only the program changes a progress bar, so its change callback gets no
+/// `InputContext`; it is called from `set_value` and `set_range`.
+pub struct ProgressBar {
+    pub bounds: Rect,
+    pub style: ProgressStyle,
+    /// The width of the moving block of an indeterminate progress bar, as a
+    /// fraction of the whole bar.
+    pub marquee_width: f64,
+    range: ValueRange,
+    indeterminate: bool,
+    on_change: Option<Box<FnMut(f64)>>,
+}
+
+impl ProgressBar {
+    /// A determinate progress bar going from 0 to 100.
+    pub fn new() -> Self {
+        ProgressBar {
+            bounds: Rect::default(),
+            style: ProgressStyle::default(),
+            marquee_width: 0.25,
+            range: ValueRange::new(0.0, 100.0, 0.0),
+            indeterminate: false,
+            on_change: None,
+        }
+    }
+
+    pub fn range(&self) -> &ValueRange {
+        &self.range
+    }
+
+    pub fn value(&self) -> f64 {
+        self.range.value()
+    }
+
+    /// How much of the bar is filled, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        self.range.fraction()
+    }
+
+    pub fn set_value(&mut self, value: f64) -> &mut Self {
+        if self.range.set_value(value) {
+            self.notify();
+        }
+        self
+    }
+
+    pub fn set_range(&mut self, min: f64, max: f64) -> &mut Self {
+        let before = self.range.value();
+        self.range.set_range(min, max);
+        if self.range.value() != before {
+            self.notify();
+        }
+        self
+    }
+
+    pub fn is_indeterminate(&self) -> bool {
+        self.indeterminate
+    }
+
+    pub fn set_indeterminate(&mut self, indeterminate: bool) -> &mut Self {
+        self.indeterminate = indeterminate;
+        self
+    }
+
+    /// Calls `f` with the new value whenever it changes.
+    pub fn set_on_change<F: FnMut(f64) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    /// Keeps repainting the control `id` of `core`, for the block of an
+    /// indeterminate progress bar to move. Cancel the timer once it is no
+    /// longer needed.
+    pub fn animate(core: &mut WindowCore, id: i32) -> TimerId {
+        core.set_timer(Duration::from_millis(MARQUEE_INTERVAL), true, move |core| core.invalidate(id))
+    }
+
+    fn notify(&mut self) {
+        let value = self.range.value();
+        if let Some(ref mut on_change) = self.on_change {
+            on_change(value);
+        }
+    }
+
+    /// The filled part of the inside of the bar.
+    fn bar_rect(&self, inside: &Rect) -> Rect {
+        let width = inside.width();
+        if !self.indeterminate {
+            let filled = (self.range.fraction() * width as f64).round() as i32;
+            return Rect::new(inside.left, inside.top, inside.left + filled, inside.bottom);
+        }
+        // The block comes in at the left and leaves at the right.
+        let block = max(1, (self.marquee_width * width as f64).round() as i32);
+        let phase = backend::current().tick_count() % MARQUEE_PERIOD;
+        let left = inside.left - block + ((width + block) as u64 * phase as u64 / MARQUEE_PERIOD as u64) as i32;
+        Rect::new(max(inside.left, left), inside.top, min(inside.right, left + block), inside.bottom)
+    }
+}
+
+impl Paintable for ProgressBar {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        draw_frame(context, &self.bounds, self.style.border);
+        let bar = self.bar_rect(&self.bounds.inset(1, 1));
+        if !bar.is_empty() {
+            context.fill_rect(&bar, self.style.bar);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for ProgressBar {
+    /// As wide as there is room for, and a little taller than a line.
+    fn measure(&self, available: Size) -> Size {
+        Size::new(max(100, available.width), 18)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::DrawCall;
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn bar_with_changes() -> (ProgressBar, Rc<RefCell<Vec<f64>>>) {
+        let mut bar = ProgressBar::new();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        {
+            let changes = changes.clone();
+            bar.set_on_change(move |value| changes.borrow_mut().push(value));
+        }
+        (bar, changes)
+    }
+
+    #[test]
+    fn values_are_clamped_and_reported_when_they_change() {
+        let (mut bar, changes) = bar_with_changes();
+        bar.set_value(40.0).set_value(150.0).set_value(100.0).set_value(-3.0);
+        assert_eq!(*changes.borrow(), vec![40.0, 100.0, 0.0]);
+
+        bar.set_value(80.0);
+        bar.set_range(0.0, 50.0);
+        bar.set_range(0.0, 200.0);
+        assert_eq!(*changes.borrow(), vec![40.0, 100.0, 0.0, 80.0, 50.0]);
+        assert_eq!(bar.fraction(), 0.25);
+    }
+
+    #[test]
+    fn the_bar_fills_the_fraction_of_the_inside() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut bar = ProgressBar::new();
+        bar.set_range(0.0, 50.0);
+        bar.set_value(20.0);
+        bar.set_bounds(Rect::new(10, 10, 112, 30));
+        testing::core(&mut handle).add_control(Box::new(bar));
+        let color = ProgressStyle::default().bar;
+        let filled = backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::FillRect { rect, color: c } if c == color => Some(rect),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(filled, vec![Rect::new(11, 11, 51, 29)]);
+    }
+}
@@ -0,0 +1,639 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::Color;
+use ::event::{ Event, MouseButton };
+use ::font::FontBuilder;
+use ::geometry::{ Point, Rect, Size };
+use ::layout::Orientation;
+
+use super::{ Control, InputContext };
+use super::checkbox::{ MARK_SIZE, ToggleStyle, mark_rect, measure_toggle, paint_toggle_text };
+
+use std::cmp::max;
+
+/// Fills the circle of `diameter` pixels whose bounding square starts at
+/// `origin`, one row at a time.
+fn fill_circle(context: &PaintContext, origin: Point, diameter: i32, color: Color) {
+    // Pixel centers in doubled coordinates, so the circle's center is at
+    // zero for even and odd diameters alike.
+    for row in 0..diameter {
+        let dy = 2 * row + 1 - diameter;
+        let inset = (0..diameter)
+            .find(|&column| {
+                let dx = 2 * column + 1 - diameter;
+                dx * dx + dy * dy <= diameter * diameter
+            })
+            .unwrap_or(diameter);
+        if 2 * inset < diameter {
+            let top = origin.y + row;
+            context.fill_rect(&Rect::new(origin.x + inset, top, origin.x + diameter - inset, top + 1), color);
+        }
+    }
+}
+
+/// One choice out of several, with a text next to it.
+///
+/// On its own a radio button can only be checked by clicking it, not
+/// unchecked; put the choices into a `RadioGroup` to have checking one
+/// uncheck the others.
+pub struct RadioButton {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub text: String,
+    pub enabled: bool,
+    pub style: ToggleStyle,
+    checked: bool,
+    on_change: Option<Box<FnMut(bool, &mut InputContext)>>,
+    hovered: bool,
+    /// The left mouse button went down over the radio button and is still
+    /// down.
+    mouse_pressed: bool,
+    /// Space is held down.
+    key_pressed: bool,
+    focused: bool,
+}
+
+impl RadioButton {
+    pub fn new(text: &str) -> Self {
+        RadioButton {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            text: text.to_string(),
+            enabled: true,
+            style: ToggleStyle::default(),
+            checked: false,
+            on_change: None,
+            hovered: false,
+            mouse_pressed: false,
+            key_pressed: false,
+            focused: false,
+        }
+    }
+
+    pub fn is_checked(&self) -> bool {
+        self.checked
+    }
+
+    /// Checks or unchecks the radio button without calling the change
+    /// callback.
+    pub fn set_checked(&mut self, checked: bool) -> &mut Self {
+        self.checked = checked;
+        self
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        if !enabled {
+            self.mouse_pressed = false;
+            self.key_pressed = false;
+        }
+        self
+    }
+
+    /// Calls `f` with whether the radio button is checked whenever the user
+    /// changes it.
+    pub fn set_on_change<F: FnMut(bool, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    fn check(&mut self, context: &mut InputContext) {
+        context.invalidate();
+        if self.checked {
+            return;
+        }
+        self.checked = true;
+        if let Some(ref mut on_change) = self.on_change {
+            on_change(true, context);
+        }
+    }
+
+    /// Whether a position relative to the radio button is on it.
+    fn contains(&self, position: Point) -> bool {
+        Rect::from_origin_size(Point::default(), self.bounds.size()).contains(position)
+    }
+}
+
+impl Paintable for RadioButton {
+    fn paint(&self, context: &PaintContext) {
+        let mark = mark_rect(&self.bounds);
+        let pressed = self.key_pressed || (self.mouse_pressed && self.hovered);
+        let border = if self.enabled && (self.hovered || pressed) { self.style.hover_border } else { self.style.border };
+        let width = if pressed { 2 } else { 1 };
+        fill_circle(context, mark.origin(), MARK_SIZE, border);
+        fill_circle(context, mark.origin().offset(width, width), MARK_SIZE - 2 * width, self.style.background);
+        if self.checked {
+            let color = if self.enabled { self.style.mark } else { self.style.disabled_foreground };
+            fill_circle(context, mark.origin().offset(4, 4), MARK_SIZE - 8, color);
+        }
+
+        paint_toggle_text(context, &self.font_builder, &self.text, &self.bounds, &self.style,
+                          self.enabled, self.focused);
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for RadioButton {
+    /// The circle and the text next to it.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        measure_toggle(&self.font_builder, &self.text)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                return true;
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.key_pressed = false;
+                context.invalidate();
+                return true;
+            },
+            Event::MouseLeave => {
+                if self.hovered {
+                    self.hovered = false;
+                    context.invalidate();
+                }
+                return true;
+            },
+            _ => { },
+        }
+        if !self.enabled {
+            return false;
+        }
+
+        match *event {
+            Event::MouseMove { position, .. } => {
+                let inside = self.contains(position);
+                if inside != self.hovered {
+                    self.hovered = inside;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                self.mouse_pressed = true;
+                self.hovered = self.contains(position);
+                context.invalidate();
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                if !self.mouse_pressed {
+                    return false;
+                }
+                self.mouse_pressed = false;
+                context.invalidate();
+                if self.contains(position) {
+                    self.check(context);
+                }
+                true
+            },
+            Event::KeyDown { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    self.key_pressed = true;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::KeyUp { key, .. } if key == VK_SPACE as u32 => {
+                if !self.key_pressed {
+                    return false;
+                }
+                self.key_pressed = false;
+                self.check(context);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Radio buttons of which exactly one can be checked.
+///
+/// The group owns its buttons and lays them out one after the other. It
+/// takes the focus as a whole: the arrow keys check the previous or next
+/// enabled button, wrapping around, and Tab moves on past the group.
+pub struct RadioGroup {
+    pub bounds: Rect,
+    pub orientation: Orientation,
+    /// Space between the buttons.
+    pub spacing: i32,
+    buttons: Vec<RadioButton>,
+    selected: Option<usize>,
+    on_change: Option<Box<FnMut(usize, &mut InputContext)>>,
+    /// The button the left mouse button went down on.
+    pressed: Option<usize>,
+    focused: bool,
+}
+
+impl RadioGroup {
+    pub fn new() -> Self {
+        RadioGroup {
+            bounds: Rect::default(),
+            orientation: Orientation::Vertical,
+            spacing: 4,
+            buttons: Vec::new(),
+            selected: None,
+            on_change: None,
+            pressed: None,
+            focused: false,
+        }
+    }
+
+    /// Adds a button with `text` at the end.
+    pub fn add(&mut self, text: &str) -> &mut Self {
+        self.buttons.push(RadioButton::new(text));
+        let bounds = self.bounds;
+        self.arrange(bounds);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.buttons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buttons.is_empty()
+    }
+
+    pub fn button(&self, index: usize) -> Option<&RadioButton> {
+        self.buttons.get(index)
+    }
+
+    /// A button to change the text, font or style of. Its bounds are the
+    /// group's business.
+    pub fn button_mut(&mut self, index: usize) -> Option<&mut RadioButton> {
+        self.buttons.get_mut(index)
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Checks the button at `index`, or none, without calling the change
+    /// callback.
+    pub fn set_selected(&mut self, index: Option<usize>) -> &mut Self {
+        let index = index.and_then(|i| if i < self.buttons.len() { Some(i) } else { None });
+        for (i, button) in self.buttons.iter_mut().enumerate() {
+            button.checked = Some(i) == index;
+        }
+        self.selected = index;
+        self.update_focus();
+        self
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) -> &mut Self {
+        self.orientation = orientation;
+        let bounds = self.bounds;
+        self.arrange(bounds);
+        self
+    }
+
+    /// Calls `f` with the index of the checked button whenever the user
+    /// checks another one.
+    pub fn set_on_change<F: FnMut(usize, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Checks the button at `index` on behalf of the user.
+    fn select(&mut self, index: usize, context: &mut InputContext) {
+        context.invalidate();
+        if self.selected == Some(index) {
+            return;
+        }
+        self.set_selected(Some(index));
+        if let Some(ref mut on_change) = self.on_change {
+            on_change(index, context);
+        }
+    }
+
+    /// The button drawn with the focus: the checked one, or the first
+    /// enabled one while none is checked.
+    fn focus_index(&self) -> Option<usize> {
+        self.selected.or_else(|| self.buttons.iter().position(|b| b.enabled))
+    }
+
+    fn update_focus(&mut self) {
+        let index = if self.focused { self.focus_index() } else { None };
+        for (i, button) in self.buttons.iter_mut().enumerate() {
+            button.focused = Some(i) == index;
+        }
+    }
+
+    /// The next enabled button after or before the focused one, wrapping
+    /// around.
+    fn step(&self, forward: bool) -> Option<usize> {
+        let count = self.buttons.len();
+        let start = match self.focus_index() {
+            Some(index) => index,
+            None => return None,
+        };
+        (1..count + 1)
+            .map(|n| if forward { (start + n) % count } else { (start + count - n) % count })
+            .find(|&i| self.buttons[i].enabled)
+    }
+
+    /// The button at a position relative to the group.
+    fn button_at(&self, position: Point) -> Option<usize> {
+        let position = position + self.bounds.origin();
+        self.buttons.iter().position(|b| b.bounds.contains(position))
+    }
+
+    /// Updates which button the mouse is over, returning whether that
+    /// changed.
+    fn set_hovered(&mut self, hovered: Option<usize>) -> bool {
+        let mut changed = false;
+        for (i, button) in self.buttons.iter_mut().enumerate() {
+            let hover = Some(i) == hovered && button.enabled;
+            changed |= button.hovered != hover;
+            button.hovered = hover;
+        }
+        changed
+    }
+
+    /// Stacks the buttons inside `bounds`, each as large as it wants to be
+    /// along the orientation.
+    fn arrange(&mut self, bounds: Rect) {
+        let mut offset = 0;
+        for button in self.buttons.iter_mut() {
+            let size = button.measure(bounds.size());
+            button.bounds = match self.orientation {
+                Orientation::Vertical =>
+                    Rect::new(bounds.left, bounds.top + offset, bounds.right, bounds.top + offset + size.height),
+                Orientation::Horizontal =>
+                    Rect::new(bounds.left + offset, bounds.top, bounds.left + offset + size.width, bounds.bottom),
+            };
+            offset += self.spacing + match self.orientation {
+                Orientation::Vertical => size.height,
+                Orientation::Horizontal => size.width,
+            };
+        }
+    }
+}
+
+impl Paintable for RadioGroup {
+    fn paint(&self, context: &PaintContext) {
+        for button in self.buttons.iter() {
+            button.paint(context);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for RadioGroup {
+    /// The buttons one after the other.
+    fn measure(&self, available: Size) -> Size {
+        let gaps = self.spacing * max(0, self.buttons.len() as i32 - 1);
+        let sizes = self.buttons.iter().map(|b| b.measure(available)).collect::<Vec<_>>();
+        match self.orientation {
+            Orientation::Vertical => Size::new(sizes.iter().map(|s| s.width).max().unwrap_or(0),
+                                               sizes.iter().map(|s| s.height).sum::<i32>() + gaps),
+            Orientation::Horizontal => Size::new(sizes.iter().map(|s| s.width).sum::<i32>() + gaps,
+                                                 sizes.iter().map(|s| s.height).max().unwrap_or(0)),
+        }
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.arrange(bounds);
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                self.update_focus();
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.pressed = None;
+                self.update_focus();
+                context.invalidate();
+                true
+            },
+            Event::MouseLeave => {
+                if self.set_hovered(None) {
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseMove { position, .. } => {
+                let hit = self.button_at(position);
+                if self.set_hovered(hit) {
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                self.pressed = self.button_at(position).and_then(|i| if self.buttons[i].enabled { Some(i) } else { None });
+                if let Some(index) = self.pressed {
+                    self.buttons[index].mouse_pressed = true;
+                    context.invalidate();
+                }
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                let pressed = match self.pressed.take() {
+                    Some(pressed) => pressed,
+                    None => return false,
+                };
+                self.buttons[pressed].mouse_pressed = false;
+                context.invalidate();
+                if self.button_at(position) == Some(pressed) {
+                    self.select(pressed, context);
+                }
+                true
+            },
+            Event::KeyDown { key, .. } => {
+                let key = key as i32;
+                let target = if key == VK_UP || key == VK_LEFT {
+                    self.step(false)
+                } else if key == VK_DOWN || key == VK_RIGHT {
+                    self.step(true)
+                } else if key == VK_SPACE {
+                    self.focus_index()
+                } else {
+                    return false;
+                };
+                if let Some(index) = target {
+                    self.select(index, context);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.buttons.iter().any(|b| b.enabled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::event::Modifiers;
+    use ::message::MessageQueue;
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::ptr;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    /// The height of a button with the default font, and the space below it.
+    const ROW: i32 = MARK_SIZE + 4;
+
+    /// A group of three buttons at the origin, and the indices it reports
+    /// as checked.
+    fn group() -> (RadioGroup, Rc<RefCell<Vec<usize>>>) {
+        let mut group = RadioGroup::new();
+        group.add("One").add("Two").add("Three");
+        group.set_bounds(Rect::new(0, 0, 100, 3 * ROW));
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        {
+            let changes = changes.clone();
+            group.set_on_change(move |index, _| changes.borrow_mut().push(index));
+        }
+        (group, changes)
+    }
+
+    fn context() -> InputContext {
+        InputContext::new(1, ptr::null_mut(), Arc::new(MessageQueue::new()))
+    }
+
+    fn click(group: &mut RadioGroup, x: i32, y: i32) {
+        let position = Point::new(x, y);
+        group.handle_event(&Event::MouseDown { button: MouseButton::Left, position: position, modifiers: Modifiers::empty() },
+                           &mut context());
+        group.handle_event(&Event::MouseUp { button: MouseButton::Left, position: position, modifiers: Modifiers::empty() },
+                           &mut context());
+    }
+
+    fn press(group: &mut RadioGroup, vk: i32) {
+        group.handle_event(&Event::KeyDown { key: vk as u32, system: false, flags: Default::default() }, &mut context());
+    }
+
+    fn checked(group: &RadioGroup) -> Vec<bool> {
+        (0..group.len()).map(|i| group.button(i).unwrap().is_checked()).collect()
+    }
+
+    #[test]
+    fn buttons_are_stacked() {
+        let (group, _) = group();
+        for i in 0..3 {
+            assert_eq!(group.button(i).unwrap().bounds, Rect::new(0, i as i32 * ROW, 100, i as i32 * ROW + MARK_SIZE));
+        }
+    }
+
+    #[test]
+    fn only_one_button_is_checked() {
+        let (mut group, changes) = group();
+        assert_eq!(checked(&group), vec![false, false, false]);
+
+        click(&mut group, 5, ROW + 5);
+        assert_eq!(group.selected(), Some(1));
+        assert_eq!(checked(&group), vec![false, true, false]);
+        click(&mut group, 5, 2 * ROW + 5);
+        assert_eq!(checked(&group), vec![false, false, true]);
+
+        // Clicking the checked one again or between the buttons changes
+        // nothing.
+        click(&mut group, 5, 2 * ROW + 5);
+        click(&mut group, 5, ROW - 2);
+        assert_eq!(checked(&group), vec![false, false, true]);
+        assert_eq!(*changes.borrow(), vec![1, 2]);
+
+        group.set_selected(Some(0));
+        assert_eq!(checked(&group), vec![true, false, false]);
+        group.set_selected(Some(7));
+        assert_eq!(checked(&group), vec![false, false, false]);
+        assert_eq!(changes.borrow().len(), 2);
+    }
+
+    #[test]
+    fn arrow_keys_wrap_around() {
+        let (mut group, changes) = group();
+        group.set_selected(Some(2));
+        group.handle_event(&Event::Focus { previous: ptr::null_mut() }, &mut context());
+        assert!(group.button(2).unwrap().is_focused());
+
+        press(&mut group, VK_DOWN);
+        assert_eq!(group.selected(), Some(0));
+        assert!(group.button(0).unwrap().is_focused());
+        assert!(!group.button(2).unwrap().is_focused());
+        press(&mut group, VK_UP);
+        assert_eq!(group.selected(), Some(2));
+        press(&mut group, VK_LEFT);
+        press(&mut group, VK_RIGHT);
+        press(&mut group, VK_RIGHT);
+        assert_eq!(*changes.borrow(), vec![0, 2, 1, 2, 0]);
+        assert_eq!(checked(&group), vec![true, false, false]);
+    }
+
+    #[test]
+    fn arrow_keys_skip_disabled_buttons() {
+        let (mut group, _) = group();
+        group.button_mut(0).unwrap().set_enabled(false);
+        group.handle_event(&Event::Focus { previous: ptr::null_mut() }, &mut context());
+
+        // Without a checked button, Space checks the first enabled one.
+        press(&mut group, VK_SPACE);
+        assert_eq!(group.selected(), Some(1));
+        press(&mut group, VK_DOWN);
+        press(&mut group, VK_DOWN);
+        assert_eq!(group.selected(), Some(1));
+        press(&mut group, VK_UP);
+        assert_eq!(group.selected(), Some(2));
+
+        click(&mut group, 5, 5);
+        assert_eq!(group.selected(), Some(2));
+    }
+
+    #[test]
+    fn clicks_reach_the_group_in_a_window() {
+        let (backend, mut handle) = open_window(200, 100);
+        let (mut group, changes) = group();
+        group.set_bounds(Rect::new(20, 10, 120, 10 + 3 * ROW));
+        let id = testing::core(&mut handle).add_control(Box::new(group));
+        testing::click(&backend, handle, 25, 10 + ROW + 5);
+        testing::click(&backend, handle, 25, 10 + 5);
+        assert_eq!(*changes.borrow(), vec![1, 0]);
+
+        assert!(testing::core(&mut handle).focus(Some(id)));
+        testing::key(&backend, handle, VK_UP);
+        assert_eq!(*changes.borrow(), vec![1, 0, 2]);
+    }
+}
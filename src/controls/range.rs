@@ -0,0 +1,182 @@
+/// A number between a minimum and a maximum, kept on whole steps from the
+/// minimum, as used by sliders, progress bars and spin boxes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValueRange {
+    min: f64,
+    max: f64,
+    /// The value only takes multiples of this from `min`; zero for any value.
+    step: f64,
+    value: f64,
+}
+
+impl ValueRange {
+    /// A range starting out at `min`. A `max` below `min` is taken as `min`.
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        let mut range = ValueRange {
+            min: 0.0,
+            max: 0.0,
+            step: 0.0,
+            value: min,
+        };
+        range.set_range(min, max);
+        range.set_step(step);
+        range
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Changes the bounds, moving the value into them.
+    pub fn set_range(&mut self, min: f64, max: f64) {
+        self.min = min;
+        self.max = if max < min { min } else { max };
+        let value = self.value;
+        self.set_value(value);
+    }
+
+    /// Changes the step, moving the value onto one. Negative steps count as
+    /// zero.
+    pub fn set_step(&mut self, step: f64) {
+        self.step = if step > 0.0 { step } else { 0.0 };
+        let value = self.value;
+        self.set_value(value);
+    }
+
+    /// `value` within the bounds and on the nearest step.
+    pub fn constrain(&self, value: f64) -> f64 {
+        let mut value = if value < self.min { self.min } else if value > self.max { self.max } else { value };
+        if self.step > 0.0 {
+            value = self.min + ((value - self.min) / self.step).round() * self.step;
+            // The last step may overshoot a maximum that isn't on a step.
+            if value > self.max {
+                value -= self.step;
+            }
+        }
+        value
+    }
+
+    /// Sets the value, constrained, returning whether it changed.
+    pub fn set_value(&mut self, value: f64) -> bool {
+        let value = self.constrain(value);
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+
+    /// Moves the value by `steps` steps, or by hundredths of the range if
+    /// there is no step. With `wrap` going past one end comes back in at
+    /// the other, otherwise the value stops there. Returns whether the
+    /// value changed.
+    pub fn step_by(&mut self, steps: f64, wrap: bool) -> bool {
+        let step = if self.step > 0.0 { self.step } else { (self.max - self.min) / 100.0 };
+        let target = self.value + steps * step;
+        // The highest value there is, short of a maximum not on a step.
+        let last = self.constrain(self.max);
+        if wrap && target > last && self.value >= last {
+            let min = self.min;
+            self.set_value(min)
+        } else if wrap && target < self.min && self.value <= self.min {
+            let max = self.max;
+            self.set_value(max)
+        } else {
+            self.set_value(target)
+        }
+    }
+
+    /// How far along the range the value is, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.max > self.min { (self.value - self.min) / (self.max - self.min) } else { 0.0 }
+    }
+
+    /// Sets the value to the one `fraction` of the way along the range,
+    /// returning whether it changed.
+    pub fn set_fraction(&mut self, fraction: f64) -> bool {
+        let value = self.min + fraction * (self.max - self.min);
+        self.set_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_stay_within_the_bounds() {
+        let mut range = ValueRange::new(0.0, 10.0, 0.0);
+        assert!(range.set_value(12.0));
+        assert_eq!(range.value(), 10.0);
+        assert!(range.set_value(-1.0));
+        assert_eq!(range.value(), 0.0);
+        assert!(!range.set_value(-5.0));
+
+        // Narrowing the bounds moves the value along.
+        range.set_value(8.0);
+        range.set_range(0.0, 5.0);
+        assert_eq!(range.value(), 5.0);
+        range.set_range(6.0, 2.0);
+        assert_eq!((range.min(), range.max(), range.value()), (6.0, 6.0, 6.0));
+    }
+
+    #[test]
+    fn values_snap_to_steps_from_the_minimum() {
+        let mut range = ValueRange::new(1.0, 10.0, 2.0);
+        range.set_value(4.2);
+        assert_eq!(range.value(), 5.0);
+        // The nearest step past a maximum that isn't on one is the last
+        // step below it.
+        assert_eq!(range.constrain(10.0), 9.0);
+
+        range.set_step(-1.0);
+        assert_eq!(range.step(), 0.0);
+        range.set_value(4.2);
+        assert_eq!(range.value(), 4.2);
+    }
+
+    #[test]
+    fn stepping_stops_or_wraps_at_the_ends() {
+        let mut range = ValueRange::new(0.0, 10.0, 3.0);
+        assert!(range.step_by(2.0, false));
+        assert_eq!(range.value(), 6.0);
+        assert!(range.step_by(5.0, false));
+        assert_eq!(range.value(), 9.0);
+        assert!(!range.step_by(1.0, false));
+
+        // Only stepping on from the end wraps around.
+        range.set_value(6.0);
+        range.step_by(5.0, true);
+        assert_eq!(range.value(), 9.0);
+        range.step_by(1.0, true);
+        assert_eq!(range.value(), 0.0);
+        range.step_by(-1.0, true);
+        assert_eq!(range.value(), 9.0);
+
+        // Without a step, a step is a hundredth of the range.
+        let mut range = ValueRange::new(0.0, 50.0, 0.0);
+        range.step_by(3.0, false);
+        assert_eq!(range.value(), 1.5);
+    }
+
+    #[test]
+    fn fractions_go_from_the_minimum_to_the_maximum() {
+        let mut range = ValueRange::new(-10.0, 30.0, 0.0);
+        assert_eq!(range.fraction(), 0.0);
+        range.set_fraction(0.25);
+        assert_eq!((range.value(), range.fraction()), (0.0, 0.25));
+        range.set_fraction(2.0);
+        assert_eq!(range.fraction(), 1.0);
+        assert_eq!(ValueRange::new(5.0, 5.0, 0.0).fraction(), 0.0);
+    }
+}
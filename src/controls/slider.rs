@@ -0,0 +1,517 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, LIGHT_GRAY, WHITE };
+use ::event::{ Event, MouseButton };
+use ::geometry::{ Point, Rect, Size };
+use ::layout::Orientation;
+
+use super::{ Control, InputContext, draw_frame };
+use super::range::ValueRange;
+
+/// The size of the thumb along the track.
+pub const THUMB_LENGTH: i32 = 11;
+
+/// The size of the thumb across the track.
+pub const THUMB_THICKNESS: i32 = 19;
+
+/// How thick the track is.
+const TRACK_THICKNESS: i32 = 4;
+
+/// How long tick marks are, and their distance from the thumb.
+const TICK_LENGTH: i32 = 4;
+
+/// The colors a slider is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SliderStyle {
+    pub track: Color,
+    /// The part of the track between the minimum and the thumb.
+    pub fill: Color,
+    pub thumb: Color,
+    pub thumb_border: Color,
+    pub hover_thumb_border: Color,
+    pub ticks: Color,
+    pub disabled: Color,
+    pub focus: Color,
+}
+
+impl Default for SliderStyle {
+    fn default() -> Self {
+        SliderStyle {
+            track: LIGHT_GRAY,
+            fill: BLUE,
+            thumb: WHITE,
+            thumb_border: GRAY,
+            hover_thumb_border: BLUE,
+            ticks: GRAY,
+            disabled: LIGHT_GRAY,
+            focus: BLACK,
+        }
+    }
+}
+
+/// A thumb moved along a track to pick a value from a range.
+///
+/// The value goes up to the right, or upwards for a vertical slider. The
+/// arrow keys move it by one step, Page Up and Page Down by `page_step`,
+/// Home and End to either end; so does the mouse wheel by one step per
+/// notch. The thumb can be dragged, and clicking the track elsewhere moves
+/// it a page towards the click.
+pub struct Slider {
+    pub bounds: Rect,
+    pub orientation: Orientation,
+    /// The distance between tick marks, in values; zero for none.
+    pub tick_frequency: f64,
+    /// How far Page Up, Page Down and clicks on the track move the value.
+    pub page_step: f64,
+    pub enabled: bool,
+    pub style: SliderStyle,
+    range: ValueRange,
+    on_change: Option<Box<FnMut(f64, &mut InputContext)>>,
+    /// Where on the thumb it was grabbed, along the track, while dragging.
+    drag: Option<i32>,
+    hovered: bool,
+    focused: bool,
+}
+
+impl Slider {
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        Slider {
+            bounds: Rect::default(),
+            orientation: Orientation::Horizontal,
+            tick_frequency: 0.0,
+            page_step: (max - min) / 10.0,
+            enabled: true,
+            style: SliderStyle::default(),
+            range: ValueRange::new(min, max, step),
+            on_change: None,
+            drag: None,
+            hovered: false,
+            focused: false,
+        }
+    }
+
+    pub fn range(&self) -> &ValueRange {
+        &self.range
+    }
+
+    pub fn value(&self) -> f64 {
+        self.range.value()
+    }
+
+    /// Changes the value, within the range and on a step, without calling
+    /// the change callback.
+    pub fn set_value(&mut self, value: f64) -> &mut Self {
+        self.range.set_value(value);
+        self
+    }
+
+    pub fn set_range(&mut self, min: f64, max: f64) -> &mut Self {
+        self.range.set_range(min, max);
+        self
+    }
+
+    pub fn set_step(&mut self, step: f64) -> &mut Self {
+        self.range.set_step(step);
+        self
+    }
+
+    pub fn set_page_step(&mut self, page_step: f64) -> &mut Self {
+        self.page_step = page_step;
+        self
+    }
+
+    pub fn set_tick_frequency(&mut self, tick_frequency: f64) -> &mut Self {
+        self.tick_frequency = tick_frequency;
+        self
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) -> &mut Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        if !enabled {
+            self.drag = None;
+        }
+        self
+    }
+
+    /// Calls `f` with the new value whenever the user changes it.
+    pub fn set_on_change<F: FnMut(f64, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    fn horizontal(&self) -> bool {
+        self.orientation == Orientation::Horizontal
+    }
+
+    /// How far the center of the thumb can travel.
+    fn travel(&self) -> i32 {
+        let length = if self.horizontal() { self.bounds.width() } else { self.bounds.height() };
+        ::std::cmp::max(0, length - THUMB_LENGTH)
+    }
+
+    /// The distance of the thumb's center from its position at the minimum.
+    fn offset_of(&self, fraction: f64) -> i32 {
+        (fraction * self.travel() as f64).round() as i32
+    }
+
+    /// Where `fraction` of the way along the range is on the track, relative
+    /// to the slider: an x for horizontal sliders, a y for vertical ones.
+    fn position_of(&self, fraction: f64) -> i32 {
+        if self.horizontal() {
+            THUMB_LENGTH / 2 + self.offset_of(fraction)
+        } else {
+            self.bounds.height() - 1 - THUMB_LENGTH / 2 - self.offset_of(fraction)
+        }
+    }
+
+    /// The fraction of the range at the position `along` the track.
+    fn fraction_at(&self, along: i32) -> f64 {
+        let travel = self.travel();
+        if travel == 0 {
+            return 0.0;
+        }
+        let offset = if self.horizontal() {
+            along - THUMB_LENGTH / 2
+        } else {
+            self.bounds.height() - 1 - THUMB_LENGTH / 2 - along
+        };
+        offset as f64 / travel as f64
+    }
+
+    /// The middle of the slider across the track, relative to it.
+    fn across(&self) -> i32 {
+        if self.horizontal() { self.bounds.height() / 2 } else { self.bounds.width() / 2 }
+    }
+
+    /// Where the thumb is, relative to the slider.
+    fn thumb_rect(&self) -> Rect {
+        let along = self.position_of(self.range.fraction()) - THUMB_LENGTH / 2;
+        let across = self.across() - THUMB_THICKNESS / 2;
+        if self.horizontal() {
+            Rect::new(along, across, along + THUMB_LENGTH, across + THUMB_THICKNESS)
+        } else {
+            Rect::new(across, along, across + THUMB_THICKNESS, along + THUMB_LENGTH)
+        }
+    }
+
+    /// A rect covering `from` to `to` along the track, both included, and
+    /// `thickness` from `start` across it, relative to the slider.
+    fn band(&self, from: i32, to: i32, start: i32, thickness: i32) -> Rect {
+        let (from, to) = if from <= to { (from, to + 1) } else { (to, from + 1) };
+        if self.horizontal() {
+            Rect::new(from, start, to, start + thickness)
+        } else {
+            Rect::new(start, from, start + thickness, to)
+        }
+    }
+
+    fn along(&self, position: Point) -> i32 {
+        if self.horizontal() { position.x } else { position.y }
+    }
+
+    /// Changes the value on behalf of the user, letting the callback know.
+    fn change(&mut self, value: f64, context: &mut InputContext) {
+        if self.range.set_value(value) {
+            context.invalidate();
+            let value = self.range.value();
+            if let Some(ref mut on_change) = self.on_change {
+                on_change(value, context);
+            }
+        }
+    }
+
+    fn step(&mut self, steps: f64, context: &mut InputContext) {
+        let mut range = self.range;
+        if range.step_by(steps, false) {
+            self.change(range.value(), context);
+        }
+    }
+}
+
+impl Paintable for Slider {
+    fn paint(&self, context: &PaintContext) {
+        let origin = self.bounds.origin();
+        let context = context.translate(origin);
+        let start = self.position_of(0.0);
+        let end = self.position_of(1.0);
+        let value = self.position_of(self.range.fraction());
+        let track_start = self.across() - TRACK_THICKNESS / 2;
+
+        context.fill_rect(&self.band(start, end, track_start, TRACK_THICKNESS), self.style.track);
+        if self.enabled {
+            context.fill_rect(&self.band(start, value, track_start, TRACK_THICKNESS), self.style.fill);
+        }
+
+        // Tick marks on both sides of the thumb, and always at the ends.
+        if self.tick_frequency > 0.0 && self.range.max() > self.range.min() {
+            let span = self.range.max() - self.range.min();
+            let mut fractions = Vec::new();
+            let mut tick = 0.0;
+            while tick < span {
+                fractions.push(tick / span);
+                tick += self.tick_frequency;
+            }
+            fractions.push(1.0);
+            let near = self.across() - THUMB_THICKNESS / 2 - TICK_LENGTH - 1;
+            let far = self.across() + (THUMB_THICKNESS + 1) / 2 + 1;
+            for &fraction in fractions.iter() {
+                let at = self.position_of(fraction);
+                for &from in [near, far].iter() {
+                    let (a, b) = if self.horizontal() {
+                        (Point::new(at, from), Point::new(at, from + TICK_LENGTH))
+                    } else {
+                        (Point::new(from, at), Point::new(from + TICK_LENGTH, at))
+                    };
+                    context.draw_line(a, b, self.style.ticks);
+                }
+            }
+        }
+
+        let thumb = self.thumb_rect();
+        let border = if !self.enabled {
+            self.style.disabled
+        } else if self.hovered || self.drag.is_some() {
+            self.style.hover_thumb_border
+        } else {
+            self.style.thumb_border
+        };
+        context.fill_rect(&thumb, self.style.thumb);
+        draw_frame(&context, &thumb, border);
+        if self.focused {
+            draw_frame(&context, &Rect::from_origin_size(Point::default(), self.bounds.size()), self.style.focus);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for Slider {
+    /// Room for the thumb and the tick marks across, and whatever there is
+    /// along the track.
+    fn measure(&self, available: Size) -> Size {
+        let across = THUMB_THICKNESS + 2 * (TICK_LENGTH + 2);
+        let along = THUMB_LENGTH * 10;
+        if self.horizontal() {
+            Size::new(::std::cmp::max(along, available.width), across)
+        } else {
+            Size::new(across, ::std::cmp::max(along, available.height))
+        }
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                return true;
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.drag = None;
+                context.invalidate();
+                return true;
+            },
+            Event::MouseLeave => {
+                if self.hovered {
+                    self.hovered = false;
+                    context.invalidate();
+                }
+                return true;
+            },
+            _ => { },
+        }
+        if !self.enabled {
+            return false;
+        }
+
+        match *event {
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                let thumb = self.thumb_rect();
+                let along = self.along(position);
+                if thumb.contains(position) {
+                    let center = self.position_of(self.range.fraction());
+                    self.drag = Some(along - center);
+                    context.invalidate();
+                } else {
+                    // A page towards the click.
+                    let value = self.range.value();
+                    let clicked = self.range.min() + self.fraction_at(along) * (self.range.max() - self.range.min());
+                    let page = if clicked > value { self.page_step } else { -self.page_step };
+                    let target = value + page;
+                    let target = if (page > 0.0 && target > clicked) || (page < 0.0 && target < clicked) {
+                        clicked
+                    } else {
+                        target
+                    };
+                    self.change(target, context);
+                }
+                true
+            },
+            Event::MouseMove { position, .. } => {
+                if let Some(grab) = self.drag {
+                    let fraction = self.fraction_at(self.along(position) - grab);
+                    let value = self.range.min() + fraction * (self.range.max() - self.range.min());
+                    self.change(value, context);
+                } else {
+                    let inside = self.thumb_rect().contains(position);
+                    if inside != self.hovered {
+                        self.hovered = inside;
+                        context.invalidate();
+                    }
+                }
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, position, .. } => {
+                if self.drag.take().is_none() {
+                    return false;
+                }
+                self.hovered = self.thumb_rect().contains(position);
+                context.invalidate();
+                true
+            },
+            Event::MouseWheel { delta, .. } => {
+                self.step((delta / WHEEL_DELTA as i32) as f64, context);
+                true
+            },
+            Event::KeyDown { key, .. } => {
+                let vk = key as i32;
+                let value = self.range.value();
+                if vk == VK_RIGHT || vk == VK_UP {
+                    self.step(1.0, context);
+                } else if vk == VK_LEFT || vk == VK_DOWN {
+                    self.step(-1.0, context);
+                } else if vk == VK_PRIOR {
+                    let page = self.page_step;
+                    self.change(value + page, context);
+                } else if vk == VK_NEXT {
+                    let page = self.page_step;
+                    self.change(value - page, context);
+                } else if vk == VK_HOME {
+                    let min = self.range.min();
+                    self.change(min, context);
+                } else if vk == VK_END {
+                    let max = self.range.max();
+                    self.change(max, context);
+                } else {
+                    return false;
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// The client x of the thumb's center at the minimum. The thumb travels
+    /// 100 pixels, one for every value from 0 to 100.
+    const START: i32 = 10 + THUMB_LENGTH / 2;
+
+    /// The client y of the middle of the track.
+    const MIDDLE: i32 = 25;
+
+    /// A focused slider from 0 to 100 at (10, 10) to (121, 40) in a new
+    /// window, and the values the user changed it to.
+    fn window_with(slider: Slider) -> (Rc<HeadlessBackend>, HWND, Rc<RefCell<Vec<f64>>>) {
+        let (backend, mut handle) = open_window(200, 100);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut slider = slider;
+        {
+            let changes = changes.clone();
+            slider.set_on_change(move |value, _| changes.borrow_mut().push(value));
+        }
+        slider.set_bounds(Rect::new(10, 10, 111 + THUMB_LENGTH - 1, 40));
+        let thumb = START + slider.value() as i32;
+        testing::core(&mut handle).add_control(Box::new(slider));
+        // Grabbing the thumb without moving it changes nothing.
+        testing::click(&backend, handle, thumb, MIDDLE);
+        (backend, handle, changes)
+    }
+
+    #[test]
+    fn keys_step_and_stop_at_the_ends() {
+        let (backend, handle, changes) = window_with(Slider::new(0.0, 100.0, 1.0));
+        for &vk in [VK_RIGHT, VK_LEFT, VK_LEFT, VK_PRIOR, VK_END, VK_UP, VK_NEXT, VK_HOME].iter() {
+            testing::key(&backend, handle, vk);
+        }
+        assert_eq!(*changes.borrow(), vec![1.0, 0.0, 10.0, 100.0, 90.0, 0.0]);
+    }
+
+    #[test]
+    fn values_stay_on_steps() {
+        let mut slider = Slider::new(0.0, 100.0, 5.0);
+        slider.set_value(12.0);
+        assert_eq!(slider.value(), 10.0);
+        slider.set_page_step(12.0);
+        let (backend, handle, changes) = window_with(slider);
+        testing::key(&backend, handle, VK_RIGHT);
+        testing::key(&backend, handle, VK_PRIOR);
+        assert_eq!(*changes.borrow(), vec![15.0, 25.0]);
+    }
+
+    #[test]
+    fn clicks_on_the_track_move_a_page_towards_them() {
+        let (backend, handle, changes) = window_with(Slider::new(0.0, 100.0, 1.0));
+        testing::click(&backend, handle, START + 80, MIDDLE);
+        testing::click(&backend, handle, START + 80, MIDDLE);
+        // Never past the click.
+        testing::click(&backend, handle, START + 2, MIDDLE);
+        testing::click(&backend, handle, START + 2, MIDDLE);
+        assert_eq!(*changes.borrow(), vec![10.0, 20.0, 10.0, 2.0]);
+    }
+
+    #[test]
+    fn dragging_the_thumb_follows_the_mouse() {
+        let (backend, handle, changes) = window_with(Slider::new(0.0, 100.0, 1.0));
+        // Where the thumb was grabbed stays under the mouse.
+        testing::mouse_down(&backend, handle, START + 2, MIDDLE);
+        testing::mouse_move(&backend, handle, START + 52, MIDDLE);
+        // The window keeps the mouse while it is outside the slider.
+        testing::mouse_move(&backend, handle, 190, 90);
+        testing::mouse_move(&backend, handle, 0, MIDDLE);
+        testing::mouse_up(&backend, handle, 0, MIDDLE);
+        testing::mouse_move(&backend, handle, START + 30, MIDDLE);
+        assert_eq!(*changes.borrow(), vec![50.0, 100.0, 0.0]);
+    }
+
+    #[test]
+    fn the_wheel_steps() {
+        let (backend, handle, changes) = window_with(Slider::new(0.0, 100.0, 1.0));
+        testing::wheel(&backend, handle, 3);
+        testing::wheel(&backend, handle, -1);
+        assert_eq!(*changes.borrow(), vec![3.0, 2.0]);
+    }
+}
@@ -0,0 +1,441 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, RED };
+use ::event::{ Event, MouseButton };
+use ::geometry::{ Point, Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+use super::range::ValueRange;
+use super::textbox::TextBox;
+
+/// The width of the up and down buttons.
+pub const BUTTON_WIDTH: i32 = 15;
+
+/// How many steps Page Up and Page Down move the value.
+const PAGE_STEPS: f64 = 10.0;
+
+/// Which of the two buttons of a spin box.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SpinButton {
+    Up,
+    Down,
+}
+
+/// A text field for a number, with buttons next to it to step it up and
+/// down.
+///
+/// Only digits, a minus sign and, with `decimals`, a decimal point can be
+/// typed. The typed text takes effect on Enter or when the field loses the
+/// focus; if it is no number within the range the field goes back to the
+/// value, and while typing it is framed in `invalid_border`. Escape throws
+/// the typed text away.
+///
+/// The buttons, the Up and Down keys and the mouse wheel move the value by
+/// one step, Page Up and Page Down by ten. With `wrap` stepping past one end
+/// of the range comes back in at the other.
+pub struct SpinBox {
+    /// Only changed through `set_bounds`, which lays out the field too.
+    bounds: Rect,
+    /// The text field, whose font and colors are the ones of the whole spin
+    /// box.
+    pub field: TextBox,
+    pub wrap: bool,
+    /// How many digits are shown after the decimal point.
+    pub decimals: usize,
+    /// Frames the field while the text isn't a valid value.
+    pub invalid_border: Color,
+    range: ValueRange,
+    on_change: Option<Box<FnMut(f64, &mut InputContext)>>,
+    /// The button held down with the mouse.
+    pressed: Option<SpinButton>,
+    focused: bool,
+}
+
+impl SpinBox {
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        let mut spin_box = SpinBox {
+            bounds: Rect::default(),
+            field: TextBox::new(),
+            wrap: false,
+            decimals: 0,
+            invalid_border: RED,
+            range: ValueRange::new(min, max, step),
+            on_change: None,
+            pressed: None,
+            focused: false,
+        };
+        spin_box.show_value();
+        spin_box
+    }
+
+    pub fn range(&self) -> &ValueRange {
+        &self.range
+    }
+
+    pub fn value(&self) -> f64 {
+        self.range.value()
+    }
+
+    /// Changes the value, within the range and on a step, without calling
+    /// the change callback. Anything typed is replaced.
+    pub fn set_value(&mut self, value: f64) -> &mut Self {
+        self.range.set_value(value);
+        self.show_value();
+        self
+    }
+
+    pub fn set_range(&mut self, min: f64, max: f64) -> &mut Self {
+        self.range.set_range(min, max);
+        self.show_value();
+        self
+    }
+
+    pub fn set_step(&mut self, step: f64) -> &mut Self {
+        self.range.set_step(step);
+        self.show_value();
+        self
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) -> &mut Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn set_decimals(&mut self, decimals: usize) -> &mut Self {
+        self.decimals = decimals;
+        self.show_value();
+        self
+    }
+
+    /// Calls `f` with the new value whenever the user changes it.
+    pub fn set_on_change<F: FnMut(f64, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The value the text in the field stands for, if it is a valid one.
+    pub fn parse(&self) -> Option<f64> {
+        match self.field.text().trim().parse::<f64>() {
+            Ok(value) if value >= self.range.min() && value <= self.range.max() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Whether the text in the field is a valid value.
+    pub fn is_valid(&self) -> bool {
+        self.parse().is_some()
+    }
+
+    fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.decimals, value)
+    }
+
+    /// Puts the value into the field, replacing whatever was typed.
+    fn show_value(&mut self) {
+        let text = self.format(self.range.value());
+        self.field.set_text(&text);
+        if self.focused {
+            self.field.editor.select_all();
+        }
+    }
+
+    /// Changes the value on behalf of the user, letting the callback know.
+    fn change(&mut self, value: f64, context: &mut InputContext) {
+        let changed = self.range.set_value(value);
+        self.show_value();
+        context.invalidate();
+        if changed {
+            let value = self.range.value();
+            if let Some(ref mut on_change) = self.on_change {
+                on_change(value, context);
+            }
+        }
+    }
+
+    /// Takes the typed text as the value, or goes back to the value if it
+    /// isn't a valid one.
+    fn commit(&mut self, context: &mut InputContext) {
+        let value = self.parse().unwrap_or(self.range.value());
+        self.change(value, context);
+    }
+
+    /// Steps from the typed value if it is a valid one, from the value
+    /// otherwise.
+    fn step(&mut self, steps: f64, context: &mut InputContext) {
+        let mut range = self.range;
+        if let Some(value) = self.parse() {
+            range.set_value(value);
+        }
+        range.step_by(steps, self.wrap);
+        self.change(range.value(), context);
+    }
+
+    fn button_rect(&self, button: SpinButton) -> Rect {
+        let middle = (self.bounds.top + self.bounds.bottom) / 2;
+        let left = self.bounds.right - BUTTON_WIDTH;
+        match button {
+            SpinButton::Up => Rect::new(left, self.bounds.top, self.bounds.right, middle + 1),
+            SpinButton::Down => Rect::new(left, middle, self.bounds.right, self.bounds.bottom),
+        }
+    }
+
+    /// The button at `position`, relative to the spin box.
+    fn button_at(&self, position: Point) -> Option<SpinButton> {
+        let position = position + self.bounds.origin();
+        if self.button_rect(SpinButton::Up).contains(position) {
+            Some(SpinButton::Up)
+        } else if self.button_rect(SpinButton::Down).contains(position) {
+            Some(SpinButton::Down)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `c` can go into the text of a number.
+    fn accepts(&self, c: char) -> bool {
+        c.is_digit(10) || (c == '-' && self.range.min() < 0.0) || (c == '.' && self.decimals > 0)
+    }
+}
+
+impl Paintable for SpinBox {
+    fn paint(&self, context: &PaintContext) {
+        self.field.paint(context);
+        if !self.is_valid() {
+            draw_frame(context, &self.field.bounds, self.invalid_border);
+        }
+
+        let style = &self.field.style;
+        for &button in [SpinButton::Up, SpinButton::Down].iter() {
+            let rect = self.button_rect(button);
+            let background = if self.pressed == Some(button) { style.selection_background } else { style.background };
+            context.fill_rect(&rect.inset(1, 1), background);
+            draw_frame(context, &rect, style.border);
+            // A small triangle pointing away from the other button, drawn
+            // row by row.
+            let center = (rect.left + rect.right) / 2;
+            let middle = (rect.top + rect.bottom) / 2;
+            for row in 0..3 {
+                let (y, half) = match button {
+                    SpinButton::Up => (middle - 1 + row, row),
+                    SpinButton::Down => (middle + 1 - row, row),
+                };
+                context.draw_line(Point::new(center - half, y), Point::new(center + half + 1, y), style.foreground);
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for SpinBox {
+    /// Room for the widest value the range allows and the buttons.
+    fn measure(&self, available: Size) -> Size {
+        let field = self.field.measure(available);
+        let widest = [self.range.min(), self.range.max()].iter()
+            .map(|&value| self.field.font_builder.text_extent(&self.format(value)).map_or(0, |size| size.width))
+            .max()
+            .unwrap_or(0);
+        Size::new(::std::cmp::max(field.width, widest + 2 * self.field.padding.width) + BUTTON_WIDTH, field.height)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.field.set_bounds(Rect::new(bounds.left, bounds.top, bounds.right - BUTTON_WIDTH + 1, bounds.bottom));
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                self.field.handle_event(event, context);
+                self.field.editor.select_all();
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.pressed = None;
+                self.field.handle_event(event, context);
+                self.commit(context);
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                match self.button_at(position) {
+                    Some(button) => {
+                        self.pressed = Some(button);
+                        self.step(if button == SpinButton::Up { 1.0 } else { -1.0 }, context);
+                        true
+                    },
+                    None => self.field.handle_event(event, context),
+                }
+            },
+            Event::MouseUp { button: MouseButton::Left, .. } if self.pressed.is_some() => {
+                self.pressed = None;
+                context.invalidate();
+                true
+            },
+            Event::MouseMove { .. } | Event::MouseUp { .. } => self.field.handle_event(event, context),
+            Event::MouseWheel { delta, horizontal: false, .. } => {
+                self.step((delta / WHEEL_DELTA as i32) as f64, context);
+                true
+            },
+            Event::KeyDown { key, .. } => {
+                let vk = key as i32;
+                if vk == VK_UP {
+                    self.step(1.0, context);
+                } else if vk == VK_DOWN {
+                    self.step(-1.0, context);
+                } else if vk == VK_PRIOR {
+                    self.step(PAGE_STEPS, context);
+                } else if vk == VK_NEXT {
+                    self.step(-PAGE_STEPS, context);
+                } else if vk == VK_RETURN {
+                    self.commit(context);
+                } else if vk == VK_ESCAPE {
+                    self.show_value();
+                    context.invalidate();
+                } else {
+                    return self.field.handle_event(event, context);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Typing goes into the field, as long as it could be part of a number.
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        if c.is_control() || self.accepts(c) {
+            self.field.handle_char(c, context)
+        } else {
+            true
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A focused spin box at (10, 10) to (110, 30) in a new window, and the
+    /// values the user changed it to.
+    fn window_with(spin_box: SpinBox) -> (Rc<HeadlessBackend>, HWND, Rc<RefCell<Vec<f64>>>) {
+        let (backend, mut handle) = open_window(200, 100);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut spin_box = spin_box;
+        {
+            let changes = changes.clone();
+            spin_box.set_on_change(move |value, _| changes.borrow_mut().push(value));
+        }
+        spin_box.set_bounds(Rect::new(10, 10, 110, 30));
+        testing::core(&mut handle).add_control(Box::new(spin_box));
+        testing::click(&backend, handle, 20, 20);
+        (backend, handle, changes)
+    }
+
+    /// Selects everything in the field and types `text` over it.
+    fn retype(backend: &HeadlessBackend, handle: HWND, text: &str) {
+        testing::key(backend, handle, VK_HOME);
+        for _ in 0..10 {
+            testing::key(backend, handle, VK_DELETE);
+        }
+        for c in text.chars() {
+            testing::type_char(backend, handle, c);
+        }
+    }
+
+    /// The text shown, and whether it is framed as invalid.
+    fn shown(backend: &HeadlessBackend, handle: HWND) -> (String, bool) {
+        let calls = backend.paint(handle);
+        let text = calls.iter().filter_map(|call| match *call {
+            DrawCall::Text { ref text, .. } => Some(text.clone()),
+            _ => None,
+        }).collect::<Vec<_>>().concat();
+        let invalid = calls.iter().any(|call| match *call {
+            DrawCall::Line { color, .. } => color == RED,
+            _ => false,
+        });
+        (text, invalid)
+    }
+
+    #[test]
+    fn typed_numbers_take_effect_on_enter() {
+        let (backend, handle, changes) = window_with(SpinBox::new(0.0, 100.0, 1.0));
+        retype(&backend, handle, "42");
+        assert!(changes.borrow().is_empty());
+        testing::key(&backend, handle, VK_RETURN);
+        assert_eq!(*changes.borrow(), vec![42.0]);
+        assert_eq!(shown(&backend, handle), ("42".to_string(), false));
+    }
+
+    #[test]
+    fn invalid_text_goes_back_to_the_value() {
+        let (backend, handle, changes) = window_with(SpinBox::new(0.0, 100.0, 1.0));
+        // Letters, and a minus sign with no negative values, can't be typed.
+        retype(&backend, handle, "-4x2");
+        assert_eq!(shown(&backend, handle), ("42".to_string(), false));
+
+        retype(&backend, handle, "500");
+        assert_eq!(shown(&backend, handle), ("500".to_string(), true));
+        testing::key(&backend, handle, VK_RETURN);
+        assert_eq!(shown(&backend, handle), ("0".to_string(), false));
+
+        retype(&backend, handle, "");
+        assert_eq!(shown(&backend, handle), (String::new(), true));
+        testing::key(&backend, handle, VK_ESCAPE);
+        assert_eq!(shown(&backend, handle), ("0".to_string(), false));
+        assert!(changes.borrow().is_empty());
+    }
+
+    #[test]
+    fn keys_step_from_the_typed_value() {
+        let (backend, handle, changes) = window_with(SpinBox::new(0.0, 100.0, 1.0));
+        testing::key(&backend, handle, VK_UP);
+        testing::key(&backend, handle, VK_DOWN);
+        testing::key(&backend, handle, VK_DOWN);
+        retype(&backend, handle, "30");
+        testing::key(&backend, handle, VK_UP);
+        testing::key(&backend, handle, VK_NEXT);
+        for _ in 0..10 {
+            testing::key(&backend, handle, VK_PRIOR);
+        }
+        assert_eq!(*changes.borrow(), vec![1.0, 0.0, 31.0, 21.0, 31.0, 41.0, 51.0, 61.0, 71.0, 81.0, 91.0, 100.0]);
+        assert_eq!(shown(&backend, handle).0, "100");
+    }
+
+    #[test]
+    fn the_buttons_step_and_wrap() {
+        let mut spin_box = SpinBox::new(-1.0, 1.0, 0.5);
+        spin_box.set_decimals(1).set_wrap(true);
+        let (backend, handle, changes) = window_with(spin_box);
+        assert_eq!(shown(&backend, handle).0, "-1.0");
+        testing::click(&backend, handle, 105, 27);
+        testing::click(&backend, handle, 105, 12);
+        testing::click(&backend, handle, 105, 12);
+        // Stepping on from a typed maximum wraps too.
+        retype(&backend, handle, "1");
+        testing::click(&backend, handle, 105, 12);
+        assert_eq!(*changes.borrow(), vec![1.0, -1.0, -0.5, -1.0]);
+
+        retype(&backend, handle, "0.5");
+        testing::key(&backend, handle, VK_RETURN);
+        assert_eq!(shown(&backend, handle).0, "0.5");
+    }
+}
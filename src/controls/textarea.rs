@@ -0,0 +1,1018 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::event::{ Event, MouseButton, MODIFIER_CONTROL, MODIFIER_SHIFT };
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::text::{ Command, LineChange, Movement, TextEditor };
+
+use super::{ Control, InputContext, draw_frame };
+use super::textbox::TextBoxStyle;
+
+use std::cell::RefCell;
+use std::cmp::{ max, min };
+use std::collections::HashMap;
+
+/// How many rows one notch of the mouse wheel scrolls.
+const WHEEL_ROWS: i32 = 3;
+
+/// The parts of a font that change how wide the text is.
+struct FontKey {
+    face: String,
+    height: i32,
+    width: i32,
+    weight: i32,
+    italic: bool,
+}
+
+impl FontKey {
+    fn new(builder: &FontBuilder) -> Self {
+        FontKey {
+            face: builder.face().to_string(),
+            height: builder.height(),
+            width: builder.width(),
+            weight: builder.weight().to_int(),
+            italic: builder.italic(),
+        }
+    }
+
+    fn matches(&self, builder: &FontBuilder) -> bool {
+        self.face == builder.face() && self.height == builder.height() && self.width == builder.width() &&
+            self.weight == builder.weight().to_int() && self.italic == builder.italic()
+    }
+}
+
+/// Where the rows of every line start, and what it took to find out.
+///
+/// Lines are only broken into rows when they are needed, and again after
+/// they changed. Until then they count as a single row, so a large document
+/// is never wrapped all at once and a keystroke only measures its own line.
+struct Layout {
+    /// The font and wrap width the rows were made for.
+    font: Option<FontKey>,
+    wrap_width: Option<i32>,
+    line_height: i32,
+    tab_width: i32,
+    widths: HashMap<char, i32>,
+    /// The start of every row of a line, relative to the line.
+    rows: Vec<Option<Vec<usize>>>,
+    /// A Fenwick tree over the number of rows of every line, to find the
+    /// rows before a line, and the line of a row, without counting them all.
+    counts: Vec<usize>,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Layout {
+            font: None,
+            wrap_width: None,
+            line_height: 0,
+            tab_width: 0,
+            widths: HashMap::new(),
+            rows: Vec::new(),
+            counts: Vec::new(),
+        }
+    }
+
+    /// Where `c` ends when it starts at `x`. Tabs go to the next tab stop.
+    fn advance(&mut self, font: &Font, c: char, x: i32) -> i32 {
+        if c == '\t' {
+            return if self.tab_width > 0 { (x / self.tab_width + 1) * self.tab_width } else { x };
+        }
+        if let Some(&width) = self.widths.get(&c) {
+            return x + width;
+        }
+        let width = font.text_extent(&c.to_string()).width;
+        self.widths.insert(c, width);
+        x + width
+    }
+
+    /// Breaks `line` into rows, after the last space that still fits or
+    /// wherever a word is too long for a row of its own.
+    fn wrap(&mut self, font: &Font, editor: &TextEditor, line: usize) -> Vec<usize> {
+        let mut rows = vec![0];
+        let width = match self.wrap_width {
+            Some(width) => width,
+            None => return rows,
+        };
+        let start = editor.line_start(line);
+        let len = editor.line_end(line) - start;
+        let mut row = 0;
+        let mut last_space = None;
+        let mut x = 0;
+        let mut i = 0;
+        while i < len {
+            let c = editor.char_at(start + i);
+            let next = self.advance(font, c, x);
+            if next > width && i > row {
+                row = match last_space {
+                    Some(space) if space > row => space,
+                    _ => i,
+                };
+                rows.push(row);
+                last_space = None;
+                x = 0;
+                i = row;
+                continue;
+            }
+            if c == ' ' || c == '\t' {
+                last_space = Some(i + 1);
+            }
+            x = next;
+            i += 1;
+        }
+        rows
+    }
+
+    /// Breaks `line` into rows unless it already is.
+    fn lay_out(&mut self, font: &Font, editor: &TextEditor, line: usize) {
+        if self.rows[line].is_none() {
+            let rows = self.wrap(font, editor, line);
+            let count = rows.len();
+            self.rows[line] = Some(rows);
+            self.add(line, 1, count);
+        }
+    }
+
+    /// The number of rows of `line`, one until it is laid out.
+    fn count(&self, line: usize) -> usize {
+        self.rows[line].as_ref().map_or(1, |rows| rows.len())
+    }
+
+    /// Forgets the rows of all `lines` lines.
+    fn reset(&mut self, lines: usize) {
+        self.rows = vec![None; lines];
+        self.rebuild();
+    }
+
+    /// Counts the rows of every line again.
+    fn rebuild(&mut self) {
+        let len = self.rows.len();
+        self.counts = (0..len).map(|line| self.count(line)).collect();
+        for i in 1..len + 1 {
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= len {
+                self.counts[parent - 1] += self.counts[i - 1];
+            }
+        }
+    }
+
+    /// Tells the tree that `line` went from `old` to `new` rows.
+    fn add(&mut self, line: usize, old: usize, new: usize) {
+        let mut i = line + 1;
+        while i <= self.counts.len() {
+            self.counts[i - 1] = self.counts[i - 1] - old + new;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// The number of rows of the lines before `line`.
+    fn rows_before(&self, line: usize) -> usize {
+        let mut sum = 0;
+        let mut i = line;
+        while i > 0 {
+            sum += self.counts[i - 1];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    fn row_count(&self) -> usize {
+        self.rows_before(self.rows.len())
+    }
+
+    /// The line of `row` and the row within it, or the last row there is.
+    fn find(&self, row: usize) -> (usize, usize) {
+        let len = self.rows.len();
+        if row >= self.row_count() {
+            return (len - 1, self.count(len - 1) - 1);
+        }
+        let mut step = 1;
+        while step * 2 <= len {
+            step *= 2;
+        }
+        let mut line = 0;
+        let mut rest = row;
+        while step > 0 {
+            if line + step <= len && self.counts[line + step - 1] <= rest {
+                line += step;
+                rest -= self.counts[line - 1];
+            }
+            step /= 2;
+        }
+        (line, rest)
+    }
+
+    /// Drops the rows of changed lines, making room for the new ones. A
+    /// change that doesn't fit leaves the wrong number of lines behind,
+    /// which has everything laid out again.
+    fn apply(&mut self, change: LineChange) {
+        if change.removed == change.inserted && change.first + change.removed <= self.rows.len() {
+            for line in change.first..change.first + change.removed {
+                let count = self.count(line);
+                self.rows[line] = None;
+                self.add(line, count, 1);
+            }
+            return;
+        }
+        let moved = self.rows.split_off(min(change.first + change.removed, self.rows.len()));
+        self.rows.truncate(change.first);
+        for _ in 0..change.inserted {
+            self.rows.push(None);
+        }
+        self.rows.extend(moved);
+        self.rebuild();
+    }
+}
+
+/// Multiple lines of editable text, wrapped at the edge unless `wrap` is
+/// turned off.
+///
+/// The text lives in a `TextEditor`. The text area breaks its lines into
+/// rows, measuring them with the font, and moves between rows for the
+/// arrow keys, Page Up and Page Down, and Home and End. Tab inserts a tab
+/// character unless `accepts_tab` is off, in which case it moves the focus
+/// as usual.
+pub struct TextArea {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    editor: TextEditor,
+    pub wrap: bool,
+    /// The distance between tab stops, in spaces.
+    pub tab_width: usize,
+    pub accepts_tab: bool,
+    pub style: TextBoxStyle,
+    /// Space between the text and the edges.
+    pub padding: Size,
+    on_change: Option<Box<FnMut(&TextEditor, &mut InputContext)>>,
+    focused: bool,
+    /// Whether the mouse is extending the selection.
+    selecting: bool,
+    /// The first row in view.
+    scroll_row: usize,
+    /// How far the text is scrolled to the left, in pixels. Always zero
+    /// while wrapping.
+    scroll_x: i32,
+    /// Where moving up and down tries to keep the caret, in pixels from the
+    /// start of the row.
+    preferred_x: Option<i32>,
+    layout: RefCell<Layout>,
+}
+
+impl TextArea {
+    pub fn new() -> Self {
+        TextArea {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            editor: TextEditor::new(),
+            wrap: true,
+            tab_width: 4,
+            accepts_tab: true,
+            style: TextBoxStyle::default(),
+            padding: Size::new(4, 3),
+            on_change: None,
+            focused: false,
+            selecting: false,
+            scroll_row: 0,
+            scroll_x: 0,
+            preferred_x: None,
+            layout: RefCell::new(Layout::new()),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.editor.text()
+    }
+
+    pub fn editor(&self) -> &TextEditor {
+        &self.editor
+    }
+
+    /// The editor, to change the text or the selection directly. The whole
+    /// text gets laid out again afterwards.
+    pub fn editor_mut(&mut self) -> &mut TextEditor {
+        self.layout.borrow_mut().rows.clear();
+        &mut self.editor
+    }
+
+    /// Replaces the text without calling the change callback.
+    pub fn set_text(&mut self, text: &str) -> &mut Self {
+        self.editor.set_text(text);
+        self.scroll_row = 0;
+        self.scroll_x = 0;
+        self.scroll_to_caret();
+        self
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) -> &mut Self {
+        self.wrap = wrap;
+        self.scroll_x = 0;
+        self.scroll_to_caret();
+        self
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) -> &mut Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn set_accepts_tab(&mut self, accepts_tab: bool) -> &mut Self {
+        self.accepts_tab = accepts_tab;
+        self
+    }
+
+    /// Calls `f` with the editor whenever the user changes the text.
+    pub fn set_on_change<F: FnMut(&TextEditor, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The first row in view.
+    pub fn scroll_row(&self) -> usize {
+        self.scroll_row
+    }
+
+    pub fn scroll_x(&self) -> i32 {
+        self.scroll_x
+    }
+
+    /// The number of rows the text takes up.
+    pub fn row_count(&self) -> usize {
+        match self.font_builder.build() {
+            Ok(font) => self.row_count_with(&font),
+            Err(_) => self.editor.line_count(),
+        }
+    }
+
+    /// The row the caret is on.
+    pub fn caret_row(&self) -> usize {
+        match self.font_builder.build() {
+            Ok(font) => self.locate(&font, self.editor.caret()),
+            Err(_) => self.editor.line_of(self.editor.caret()),
+        }
+    }
+
+    fn content(&self) -> Rect {
+        self.bounds.inset(self.padding.width, self.padding.height)
+    }
+
+    /// Starts the layout over when the font, the width or the number of
+    /// lines changed. Lines are laid out as they are needed.
+    fn update_layout(&self, font: &Font) {
+        let mut layout = self.layout.borrow_mut();
+        let same_font = layout.font.as_ref().map_or(false, |key| key.matches(&self.font_builder));
+        if !same_font {
+            layout.widths.clear();
+            layout.line_height = font.text_extent("Xg").height;
+        }
+        let width = self.content().width() - 1;
+        let wrap_width = if self.wrap && width > 0 { Some(width) } else { None };
+        let tab_width = layout.advance(font, ' ', 0) * self.tab_width as i32;
+        let stale = layout.rows.len() != self.editor.line_count();
+        if stale || !same_font || layout.wrap_width != wrap_width || layout.tab_width != tab_width {
+            if !same_font {
+                layout.font = Some(FontKey::new(&self.font_builder));
+            }
+            layout.wrap_width = wrap_width;
+            layout.tab_width = tab_width;
+            layout.reset(self.editor.line_count());
+        }
+    }
+
+    /// The line and the row within it of row `row`, counting from the top.
+    fn find_row(&self, font: &Font, row: usize) -> (usize, usize) {
+        self.update_layout(font);
+        let mut layout = self.layout.borrow_mut();
+        loop {
+            // Laying out the line may give it more rows, and the row a
+            // different line.
+            let (line, index) = layout.find(row);
+            if layout.rows[line].is_some() {
+                return (line, index);
+            }
+            layout.lay_out(font, &self.editor, line);
+        }
+    }
+
+    /// The range of characters on the row `index` of `line`, without the
+    /// line break.
+    fn row_range(&self, line: usize, index: usize) -> (usize, usize) {
+        let layout = self.layout.borrow();
+        let rows = layout.rows[line].as_ref().expect("row range of a line not laid out");
+        let start = self.editor.line_start(line);
+        let end = if index + 1 < rows.len() { start + rows[index + 1] } else { self.editor.line_end(line) };
+        (start + rows[index], end)
+    }
+
+    /// Whether the row `index` of `line` is the last one of the line.
+    fn is_last_row(&self, line: usize, index: usize) -> bool {
+        let layout = self.layout.borrow();
+        layout.rows[line].as_ref().map_or(true, |rows| index + 1 >= rows.len())
+    }
+
+    /// The row `index` is on. A position where a line wraps belongs to the
+    /// row after.
+    fn locate(&self, font: &Font, index: usize) -> usize {
+        let (line, row) = self.locate_in_line(font, index);
+        self.layout.borrow().rows_before(line) + row
+    }
+
+    /// The line of `index` and the row within it.
+    fn locate_in_line(&self, font: &Font, index: usize) -> (usize, usize) {
+        self.update_layout(font);
+        let mut layout = self.layout.borrow_mut();
+        let line = self.editor.line_of(index);
+        layout.lay_out(font, &self.editor, line);
+        let offset = index - self.editor.line_start(line);
+        let rows = layout.rows[line].as_ref().unwrap();
+        (line, rows.iter().rposition(|&start| start <= offset).unwrap_or(0))
+    }
+
+    /// The x offset of `index` from the start of its row.
+    fn x_of(&self, font: &Font, index: usize) -> i32 {
+        let (line, row) = self.locate_in_line(font, index);
+        let (start, _) = self.row_range(line, row);
+        let mut layout = self.layout.borrow_mut();
+        (start..index).fold(0, |x, i| layout.advance(font, self.editor.char_at(i), x))
+    }
+
+    /// The position on `row` closest to `x`. Only the last row of a line
+    /// can be left at its very end, as that is where the next row starts.
+    fn index_at_x(&self, font: &Font, row: usize, x: i32) -> usize {
+        let (line, index) = self.find_row(font, row);
+        let (start, end) = self.row_range(line, index);
+        let end = if !self.is_last_row(line, index) && end > start { end - 1 } else { end };
+        let mut layout = self.layout.borrow_mut();
+        let mut best = start;
+        let mut left = 0;
+        for i in start..end {
+            let right = layout.advance(font, self.editor.char_at(i), left);
+            if x < (left + right) / 2 {
+                return best;
+            }
+            best = i + 1;
+            left = right;
+        }
+        best
+    }
+
+    /// The position closest to `position`, relative to the text area.
+    fn index_at(&self, position: Point) -> usize {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return 0,
+        };
+        self.update_layout(&font);
+        let line_height = max(1, self.layout.borrow().line_height);
+        let y = position.y - self.padding.height;
+        let row = if y < 0 { self.scroll_row.saturating_sub(1) } else { self.scroll_row + (y / line_height) as usize };
+        let row = min(row, self.row_count_with(&font) - 1);
+        self.index_at_x(&font, row, position.x - self.padding.width + self.scroll_x)
+    }
+
+    fn row_count_with(&self, font: &Font) -> usize {
+        self.update_layout(font);
+        self.layout.borrow().row_count()
+    }
+
+    /// How many whole rows fit into the text area, but at least one.
+    fn page_rows(&self, font: &Font) -> usize {
+        self.update_layout(font);
+        let line_height = self.layout.borrow().line_height;
+        if line_height <= 0 { 1 } else { max(1, self.content().height() / line_height) as usize }
+    }
+
+    /// Scrolls just far enough to have the caret in view.
+    fn scroll_to_caret(&mut self) {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        if let Some(change) = self.editor.take_change() {
+            self.layout.borrow_mut().apply(change);
+        }
+        let row = self.locate(&font, self.editor.caret());
+        let page = self.page_rows(&font);
+        if row < self.scroll_row {
+            self.scroll_row = row;
+        }
+        if row >= self.scroll_row + page {
+            self.scroll_row = row + 1 - page;
+        }
+        self.clamp_scroll(&font);
+
+        if self.wrap {
+            self.scroll_x = 0;
+        } else {
+            let x = self.x_of(&font, self.editor.caret());
+            let width = max(0, self.content().width() - 1);
+            if x - self.scroll_x > width {
+                self.scroll_x = x - width;
+            }
+            if x < self.scroll_x {
+                self.scroll_x = x;
+            }
+        }
+    }
+
+    /// Keeps from scrolling past the last page.
+    fn clamp_scroll(&mut self, font: &Font) {
+        let last_page = self.row_count_with(font).saturating_sub(self.page_rows(font));
+        self.scroll_row = min(self.scroll_row, last_page);
+        self.scroll_x = max(0, self.scroll_x);
+    }
+
+    /// Moves the caret by `rows` rows, staying at the same x offset for as
+    /// long as the caret keeps moving up and down.
+    fn move_rows(&mut self, rows: isize, select: bool) {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let caret = self.editor.caret();
+        let x = match self.preferred_x {
+            Some(x) => x,
+            None => self.x_of(&font, caret),
+        };
+        let row = self.locate(&font, caret) as isize;
+        let last = self.row_count_with(&font) as isize - 1;
+        let target = row + rows;
+        let index = if target < 0 {
+            0
+        } else if target > last {
+            self.editor.len()
+        } else {
+            self.index_at_x(&font, target as usize, x)
+        };
+        self.editor.set_caret(index, select);
+        self.preferred_x = Some(x);
+    }
+
+    /// Moves the caret to the start or the end of its row.
+    fn move_row_edge(&mut self, end: bool, select: bool) {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let (line, index) = self.locate_in_line(&font, self.editor.caret());
+        let (start, stop) = self.row_range(line, index);
+        let target = if !end {
+            start
+        } else if self.is_last_row(line, index) || stop == start {
+            stop
+        } else {
+            stop - 1
+        };
+        self.editor.set_caret(target, select);
+    }
+
+    /// Scrolls by `rows` rows without moving the caret.
+    pub fn scroll_by(&mut self, rows: isize) {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        self.scroll_row = max(0, self.scroll_row as isize + rows) as usize;
+        self.clamp_scroll(&font);
+    }
+
+    /// Applies a command from the user, notifying about changes.
+    fn edit(&mut self, command: Command, context: &mut InputContext) {
+        let changed = self.editor.apply(command);
+        self.preferred_x = None;
+        self.scroll_to_caret();
+        context.invalidate();
+        if changed {
+            if let Some(ref mut on_change) = self.on_change {
+                on_change(&self.editor, context);
+            }
+        }
+    }
+
+    /// Handles a key press, returning whether it was one for the text area.
+    fn key(&mut self, key: u32, context: &mut InputContext) -> bool {
+        let select = context.modifiers.contains(MODIFIER_SHIFT);
+        let control = context.modifiers.contains(MODIFIER_CONTROL);
+        let key = key as i32;
+        let page = match self.font_builder.build() {
+            Ok(font) => self.page_rows(&font) as isize,
+            Err(_) => 1,
+        };
+        let command = if key == VK_LEFT {
+            Command::Move(if control { Movement::WordLeft } else { Movement::Left }, select)
+        } else if key == VK_RIGHT {
+            Command::Move(if control { Movement::WordRight } else { Movement::Right }, select)
+        } else if key == VK_BACK {
+            Command::Delete(if control { Movement::WordLeft } else { Movement::Left })
+        } else if key == VK_DELETE {
+            Command::Delete(if control { Movement::WordRight } else { Movement::Right })
+        } else if key == VK_RETURN {
+            Command::Insert("\n".to_string())
+        } else if key == VK_TAB && self.accepts_tab && !select && !control {
+            Command::Insert("\t".to_string())
+        } else if key == 'A' as i32 && control {
+            Command::SelectAll
+        } else {
+            if key == VK_UP || key == VK_DOWN || key == VK_PRIOR || key == VK_NEXT {
+                let rows = if key == VK_UP || key == VK_PRIOR { -1 } else { 1 };
+                let paging = key == VK_PRIOR || key == VK_NEXT;
+                if paging {
+                    self.scroll_by(rows * page);
+                }
+                self.move_rows(if paging { rows * page } else { rows }, select);
+            } else if (key == VK_HOME || key == VK_END) && control {
+                let target = if key == VK_HOME { 0 } else { self.editor.len() };
+                self.editor.set_caret(target, select);
+                self.preferred_x = None;
+            } else if key == VK_HOME || key == VK_END {
+                self.move_row_edge(key == VK_END, select);
+                self.preferred_x = None;
+            } else {
+                return false;
+            }
+            self.scroll_to_caret();
+            context.invalidate();
+            return true;
+        };
+        self.edit(command, context);
+        true
+    }
+}
+
+impl Paintable for TextArea {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        let border = if self.focused { self.style.focus_border } else { self.style.border };
+        draw_frame(context, &self.bounds, border);
+
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        self.update_layout(&font);
+        let content = self.content();
+        let line_height = self.layout.borrow().line_height;
+        if line_height <= 0 {
+            return;
+        }
+        let format = DT_LEFT | DT_TOP | DT_SINGLELINE | DT_NOPREFIX;
+        let (selection_start, selection_end) = self.editor.selection();
+
+        let mut row = self.scroll_row;
+        let mut top = content.top;
+        while row < self.row_count_with(&font) && top < content.bottom {
+            let bottom = min(top + line_height, content.bottom);
+            let (line, index) = self.find_row(&font, row);
+            let (start, end) = self.row_range(line, index);
+            let last_row = self.is_last_row(line, index);
+
+            // Runs of whole characters in view with the same selection
+            // state. Tabs only take up space.
+            let mut layout = self.layout.borrow_mut();
+            let mut runs: Vec<(i32, i32, bool, String)> = Vec::new();
+            let mut x = 0;
+            for i in start..end {
+                let c = self.editor.char_at(i);
+                let next = layout.advance(&font, c, x);
+                let visible = x >= self.scroll_x && next - self.scroll_x <= content.width();
+                let selected = i >= selection_start && i < selection_end;
+                if visible {
+                    let join = match runs.last() {
+                        Some(&(_, right, run_selected, ref text)) =>
+                            right == x && run_selected == selected && c != '\t' && !text.is_empty(),
+                        None => false,
+                    };
+                    if join {
+                        let run = runs.last_mut().unwrap();
+                        run.1 = next;
+                        run.3.push(c);
+                    } else {
+                        let text = if c == '\t' { String::new() } else { c.to_string() };
+                        runs.push((x, next, selected, text));
+                    }
+                }
+                x = next;
+            }
+            // A selected line break shows as a space at the end of the row.
+            if last_row && end >= selection_start && end < selection_end && end < self.editor.len() {
+                let next = layout.advance(&font, ' ', x);
+                runs.push((x, next, true, String::new()));
+            }
+            drop(layout);
+
+            for &(left, right, selected, ref text) in runs.iter() {
+                let rect = Rect::new(content.left + left - self.scroll_x, top,
+                                     min(content.left + right - self.scroll_x, content.right), bottom);
+                let color = if selected {
+                    context.fill_rect(&rect, self.style.selection_background);
+                    self.style.selection_foreground
+                } else {
+                    self.style.foreground
+                };
+                if !text.is_empty() {
+                    context.draw_text(text, &font, color, &rect, format);
+                }
+            }
+
+            let caret = self.editor.caret();
+            let on_row = caret >= start && (caret < end || (caret == end && last_row));
+            if self.focused && on_row {
+                let x = content.left + self.x_of(&font, caret) - self.scroll_x;
+                if x >= content.left && x < content.right {
+                    context.draw_line(Point::new(x, top), Point::new(x, bottom - 1), self.style.foreground);
+                }
+            }
+
+            row += 1;
+            top += line_height;
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for TextArea {
+    /// Room for a few lines of a few words each.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let line = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        Size::new(10 * line.width + 2 * self.padding.width, 5 * line.height + 2 * self.padding.height)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.scroll_to_caret();
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.selecting = false;
+                context.invalidate();
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } => {
+                let index = self.index_at(position);
+                let select = context.modifiers.contains(MODIFIER_SHIFT);
+                self.editor.set_caret(index, select);
+                self.preferred_x = None;
+                self.selecting = true;
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                let index = self.index_at(position);
+                self.editor.select_word(index);
+                self.preferred_x = None;
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::MouseMove { position, .. } if self.selecting => {
+                let index = self.index_at(position);
+                self.editor.set_caret(index, true);
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, .. } => {
+                self.selecting = false;
+                true
+            },
+            Event::MouseWheel { delta, horizontal, .. } => {
+                let notches = delta / WHEEL_DELTA as i32;
+                if horizontal || context.modifiers.contains(MODIFIER_SHIFT) {
+                    if !self.wrap {
+                        let step = self.font_builder.text_extent("x").map(|size| size.width).unwrap_or(8);
+                        self.scroll_x = max(0, self.scroll_x - notches * step * WHEEL_ROWS);
+                    }
+                } else {
+                    self.scroll_by((-notches * WHEEL_ROWS) as isize);
+                }
+                context.invalidate();
+                true
+            },
+            Event::KeyDown { key, .. } => self.key(key, context),
+            _ => false,
+        }
+    }
+
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        // Enter, Tab, Backspace and friends were taken care of as keys.
+        if c.is_control() {
+            return true;
+        }
+        self.edit(Command::Insert(c.to_string()), context);
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::event::Modifiers;
+    use ::message::MessageQueue;
+
+    use std::ptr;
+    use std::sync::Arc;
+
+    /// A text area with room for five rows of ten characters of the
+    /// headless font, which is 9 by 12 pixels a character.
+    fn text_area(text: &str) -> TextArea {
+        HeadlessBackend::install();
+        let mut area = TextArea::new();
+        area.set_bounds(Rect::new(0, 0, 99, 66));
+        area.set_text(text);
+        area
+    }
+
+    fn context() -> InputContext {
+        InputContext::new(1, ptr::null_mut(), Arc::new(MessageQueue::new()))
+    }
+
+    fn press(area: &mut TextArea, vk: i32) {
+        area.handle_event(&Event::KeyDown { key: vk as u32, system: false, flags: Default::default() }, &mut context());
+    }
+
+    fn type_text(area: &mut TextArea, text: &str) {
+        for c in text.chars() {
+            area.handle_char(c, &mut context());
+        }
+    }
+
+    fn set_caret(area: &mut TextArea, index: usize) {
+        area.editor_mut().set_caret(index, false);
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (0..count).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn lines_wrap_at_the_content_width() {
+        // Ten characters fit, so the space after "two" ends the first row.
+        let mut area = text_area("one two three four");
+        assert_eq!(area.row_count(), 2);
+        set_caret(&mut area, 8);
+        assert_eq!(area.caret_row(), 1);
+
+        // A word too long for a row is broken where it no longer fits.
+        area.set_text("abcdefghijklmnopqrstuvwxy");
+        assert_eq!(area.row_count(), 3);
+
+        area.set_wrap(false);
+        assert_eq!(area.row_count(), 1);
+    }
+
+    #[test]
+    fn home_and_end_stay_on_the_visual_row() {
+        let mut area = text_area("one two three four");
+        set_caret(&mut area, 2);
+        press(&mut area, VK_END);
+        // Right after the space would already be on the next row.
+        assert_eq!(area.editor().caret(), 7);
+        press(&mut area, VK_HOME);
+        assert_eq!(area.editor().caret(), 0);
+
+        set_caret(&mut area, 12);
+        press(&mut area, VK_HOME);
+        assert_eq!(area.editor().caret(), 8);
+        press(&mut area, VK_END);
+        assert_eq!(area.editor().caret(), 18);
+    }
+
+    #[test]
+    fn page_keys_move_by_the_rows_in_view() {
+        let mut area = text_area(&numbered_lines(20));
+        press(&mut area, VK_RIGHT);
+        press(&mut area, VK_NEXT);
+        assert_eq!((area.caret_row(), area.scroll_row()), (5, 5));
+        // The caret keeps its column.
+        assert_eq!(area.editor().caret(), area.editor().line_start(5) + 1);
+        press(&mut area, VK_NEXT);
+        assert_eq!((area.caret_row(), area.scroll_row()), (10, 10));
+        press(&mut area, VK_PRIOR);
+        assert_eq!((area.caret_row(), area.scroll_row()), (5, 5));
+
+        // The last page doesn't scroll past the end.
+        for _ in 0..5 {
+            press(&mut area, VK_NEXT);
+        }
+        assert_eq!((area.caret_row(), area.scroll_row()), (19, 15));
+    }
+
+    #[test]
+    fn tabs_go_to_the_next_tab_stop() {
+        let mut area = text_area("");
+        type_text(&mut area, "a");
+        press(&mut area, VK_TAB);
+        type_text(&mut area, "b");
+        assert_eq!(area.text(), "a\tb");
+        let font = area.font_builder.build().unwrap();
+        assert_eq!(area.x_of(&font, 2), 36);
+        assert_eq!(area.x_of(&font, 3), 45);
+
+        area.set_tab_width(2);
+        assert_eq!(area.x_of(&font, 2), 18);
+
+        // Without accepting tabs, Tab is left to move the focus.
+        area.set_accepts_tab(false);
+        press(&mut area, VK_TAB);
+        assert_eq!(area.text(), "a\tb");
+    }
+
+    #[test]
+    fn typing_past_the_bottom_scrolls_to_the_caret() {
+        let mut area = text_area("");
+        for _ in 0..6 {
+            type_text(&mut area, "x");
+            press(&mut area, VK_RETURN);
+        }
+        assert_eq!(area.caret_row(), 6);
+        assert_eq!(area.scroll_row(), 2);
+
+        for _ in 0..5 {
+            press(&mut area, VK_UP);
+        }
+        assert_eq!(area.scroll_row(), 1);
+
+        // Without wrapping, long lines scroll sideways instead.
+        area.set_wrap(false);
+        type_text(&mut area, "abcdefghijklmno");
+        assert_eq!(area.scroll_x(), 15 * 9 - 90);
+    }
+
+    #[test]
+    fn only_the_lines_in_use_are_laid_out() {
+        let mut area = text_area(&numbered_lines(10000));
+        let font = area.font_builder.build().unwrap();
+        set_caret(&mut area, 0);
+        area.handle_event(&Event::MouseDown { button: MouseButton::Left, position: Point::new(20, 20),
+                                              modifiers: Modifiers::empty() }, &mut context());
+        type_text(&mut area, "a long line to wrap");
+        let laid_out = area.layout.borrow().rows.iter().filter(|rows| rows.is_some()).count();
+        assert!(laid_out < 10, "{} lines laid out", laid_out);
+        assert_eq!(area.row_count(), 10002);
+
+        // Far away lines are laid out when they are reached.
+        let mut context = context();
+        context.modifiers = MODIFIER_CONTROL;
+        area.handle_event(&Event::KeyDown { key: VK_END as u32, system: false, flags: Default::default() }, &mut context);
+        let end = area.editor().len();
+        type_text(&mut area, " and another long one");
+        assert_eq!(area.locate(&font, end), 10001);
+        // "line 9999 ", "and ", "another " and "long one".
+        assert_eq!(area.row_count(), 10005);
+        assert_eq!(area.scroll_row(), 10000);
+    }
+
+    #[test]
+    fn the_row_index_follows_edits() {
+        let mut area = text_area("a\nb b b b b b b b\nc\nd d d d d d d d d d d\ne");
+        let font = area.font_builder.build().unwrap();
+        for row in 0..area.row_count() {
+            area.find_row(&font, row);
+        }
+        let counts = |area: &TextArea| -> Vec<usize> {
+            let layout = area.layout.borrow();
+            (0..layout.rows.len()).map(|line| layout.rows_before(line)).collect()
+        };
+        assert_eq!(counts(&area), vec![0, 1, 3, 4, 7]);
+        assert_eq!(area.find_row(&font, 5), (3, 1));
+
+        set_caret(&mut area, 2);
+        press(&mut area, VK_END);
+        press(&mut area, VK_END);
+        type_text(&mut area, " b b b b b b");
+        press(&mut area, VK_RETURN);
+        assert_eq!(area.text().lines().count(), 6);
+        for row in 0..area.row_count() {
+            area.find_row(&font, row);
+        }
+        let mut expected = Vec::new();
+        let mut sum = 0;
+        for line in 0..6 {
+            expected.push(sum);
+            sum += area.layout.borrow().count(line);
+        }
+        assert_eq!(counts(&area), expected);
+    }
+}
@@ -0,0 +1,452 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, WHITE };
+use ::event::{ Event, MouseButton, MODIFIER_CONTROL, MODIFIER_SHIFT };
+use ::font::{ Font, FontBuilder };
+use ::geometry::{ Point, Rect, Size };
+use ::text::{ Command, LineEditor, Movement };
+
+use super::{ Control, InputContext, draw_frame };
+
+use std::cmp::{ max, min };
+
+/// The colors a text box is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextBoxStyle {
+    pub foreground: Color,
+    pub background: Color,
+    pub placeholder: Color,
+    pub selection_foreground: Color,
+    pub selection_background: Color,
+    pub border: Color,
+    pub focus_border: Color,
+}
+
+impl Default for TextBoxStyle {
+    fn default() -> Self {
+        TextBoxStyle {
+            foreground: BLACK,
+            background: WHITE,
+            placeholder: GRAY,
+            selection_foreground: WHITE,
+            selection_background: BLUE,
+            border: GRAY,
+            focus_border: BLUE,
+        }
+    }
+}
+
+/// A single line of editable text.
+///
+/// The editing itself happens in a `LineEditor`; this only turns input into
+/// editing commands and draws the result, scrolled so the caret stays in
+/// view.
+pub struct TextBox {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub editor: LineEditor,
+    /// Shown in place of the text while there is none.
+    pub placeholder: String,
+    /// Shows every character as `mask` instead of itself.
+    pub password: bool,
+    pub mask: char,
+    pub style: TextBoxStyle,
+    /// Space between the text and the edges.
+    pub padding: Size,
+    on_change: Option<Box<FnMut(&str, &mut InputContext)>>,
+    focused: bool,
+    /// Whether the mouse is extending the selection.
+    selecting: bool,
+    /// How far the text is scrolled to the left, in pixels.
+    scroll: i32,
+}
+
+impl TextBox {
+    pub fn new() -> Self {
+        TextBox {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            editor: LineEditor::new(),
+            placeholder: String::new(),
+            password: false,
+            mask: '*',
+            style: TextBoxStyle::default(),
+            padding: Size::new(4, 3),
+            on_change: None,
+            focused: false,
+            selecting: false,
+            scroll: 0,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.editor.text()
+    }
+
+    /// Replaces the text without calling the change callback.
+    pub fn set_text(&mut self, text: &str) -> &mut Self {
+        self.editor.set_text(text);
+        self.scroll_to_caret();
+        self
+    }
+
+    pub fn set_placeholder(&mut self, placeholder: &str) -> &mut Self {
+        self.placeholder = placeholder.to_string();
+        self
+    }
+
+    pub fn set_password(&mut self, password: bool) -> &mut Self {
+        self.password = password;
+        self
+    }
+
+    pub fn set_max_length(&mut self, max_length: Option<usize>) -> &mut Self {
+        self.editor.set_max_length(max_length);
+        self
+    }
+
+    /// Calls `f` with the new text whenever the user changes it.
+    pub fn set_on_change<F: FnMut(&str, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The text as it is drawn.
+    fn display_text(&self) -> String {
+        if self.password {
+            ::std::iter::repeat(self.mask).take(self.editor.len()).collect()
+        } else {
+            self.editor.text()
+        }
+    }
+
+    /// The x offset of every caret position in the displayed text.
+    fn offsets(&self, font: &Font) -> Vec<i32> {
+        let text = self.display_text();
+        let mut offsets = vec![0];
+        let mut prefix = String::new();
+        for c in text.chars() {
+            prefix.push(c);
+            offsets.push(font.text_extent(&prefix).width);
+        }
+        offsets
+    }
+
+    fn content(&self) -> Rect {
+        self.bounds.inset(self.padding.width, self.padding.height)
+    }
+
+    /// The caret position closest to `x`, relative to the text box.
+    fn index_at(&self, x: i32) -> usize {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return 0,
+        };
+        let x = x - self.padding.width + self.scroll;
+        let offsets = self.offsets(&font);
+        let mut best = 0;
+        for (i, &offset) in offsets.iter().enumerate() {
+            if (offset - x).abs() < (offsets[best] - x).abs() {
+                best = i;
+            }
+        }
+        best
+    }
+
+    /// Scrolls just far enough to have the caret in view.
+    fn scroll_to_caret(&mut self) {
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let offsets = self.offsets(&font);
+        let width = max(0, self.content().width() - 1);
+        let caret = offsets[self.editor.caret()];
+        let total = offsets[offsets.len() - 1];
+        if caret - self.scroll > width {
+            self.scroll = caret - width;
+        }
+        if caret < self.scroll {
+            self.scroll = caret;
+        }
+        self.scroll = max(0, min(self.scroll, total - width));
+    }
+
+    /// Applies a command from the user, notifying about changes.
+    fn edit(&mut self, command: Command, context: &mut InputContext) {
+        let changed = self.editor.apply(command);
+        self.scroll_to_caret();
+        context.invalidate();
+        if changed {
+            let text = self.editor.text();
+            if let Some(ref mut on_change) = self.on_change {
+                on_change(&text, context);
+            }
+        }
+    }
+
+    /// What a key press does, if anything.
+    fn command(key: u32, context: &InputContext) -> Option<Command> {
+        let select = context.modifiers.contains(MODIFIER_SHIFT);
+        let word = context.modifiers.contains(MODIFIER_CONTROL);
+        let key = key as i32;
+        let command = if key == VK_LEFT {
+            Command::Move(if word { Movement::WordLeft } else { Movement::Left }, select)
+        } else if key == VK_RIGHT {
+            Command::Move(if word { Movement::WordRight } else { Movement::Right }, select)
+        } else if key == VK_HOME {
+            Command::Move(Movement::Home, select)
+        } else if key == VK_END {
+            Command::Move(Movement::End, select)
+        } else if key == VK_BACK {
+            Command::Delete(if word { Movement::WordLeft } else { Movement::Left })
+        } else if key == VK_DELETE {
+            Command::Delete(if word { Movement::WordRight } else { Movement::Right })
+        } else if key == 'A' as i32 && word {
+            Command::SelectAll
+        } else {
+            return None;
+        };
+        Some(command)
+    }
+}
+
+impl Paintable for TextBox {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        let border = if self.focused { self.style.focus_border } else { self.style.border };
+        draw_frame(context, &self.bounds, border);
+
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+        let content = self.content();
+        let format = DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX;
+        if self.editor.is_empty() && !self.placeholder.is_empty() {
+            context.draw_text(&self.placeholder, &font, self.style.placeholder, &content, format);
+        }
+
+        // Only whole characters that fit are drawn, in up to three runs so
+        // the selection can have colors of its own.
+        let chars = self.display_text().chars().collect::<Vec<_>>();
+        let offsets = self.offsets(&font);
+        let visible = |i: usize| offsets[i] >= self.scroll && offsets[i] - self.scroll <= content.width();
+        let (start, end) = self.editor.selection();
+        let runs = [(0, start, false), (start, end, true), (end, chars.len(), false)];
+        for &(from, to, selected) in runs.iter() {
+            let first = (from..to + 1).find(|&i| visible(i)).unwrap_or(to);
+            let last = (first..to + 1).rev().find(|&i| visible(i)).unwrap_or(first);
+            if first >= last {
+                continue;
+            }
+            let rect = Rect::new(content.left + offsets[first] - self.scroll, content.top,
+                                 content.left + offsets[last] - self.scroll, content.bottom);
+            let color = if selected {
+                context.fill_rect(&rect, self.style.selection_background);
+                self.style.selection_foreground
+            } else {
+                self.style.foreground
+            };
+            let run = chars[first..last].iter().cloned().collect::<String>();
+            context.draw_text(&run, &font, color, &rect, format);
+        }
+
+        if self.focused {
+            let x = content.left + offsets[self.editor.caret()] - self.scroll;
+            context.draw_line(Point::new(x, content.top), Point::new(x, content.bottom - 1), self.style.foreground);
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for TextBox {
+    /// One line of the text or the placeholder, but never too narrow to
+    /// type into.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let text = if self.editor.is_empty() { self.placeholder.clone() } else { self.display_text() };
+        let extent = self.font_builder.text_extent(&text).unwrap_or(Size::default());
+        let line = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        Size::new(max(extent.width, 4 * line.width) + 2 * self.padding.width,
+                  line.height + 2 * self.padding.height)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.scroll_to_caret();
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                self.selecting = false;
+                context.invalidate();
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } => {
+                let index = self.index_at(position.x);
+                let select = context.modifiers.contains(MODIFIER_SHIFT);
+                self.editor.set_caret(index, select);
+                self.selecting = true;
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                let index = self.index_at(position.x);
+                self.editor.select_word(index);
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::MouseMove { position, .. } if self.selecting => {
+                let index = self.index_at(position.x);
+                self.editor.set_caret(index, true);
+                self.scroll_to_caret();
+                context.invalidate();
+                true
+            },
+            Event::MouseUp { button: MouseButton::Left, .. } => {
+                self.selecting = false;
+                true
+            },
+            Event::KeyDown { key, .. } => match TextBox::command(key, context) {
+                Some(command) => {
+                    self.edit(command, context);
+                    true
+                },
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        // Backspace, Ctrl+A and friends arrive as characters too, but were
+        // already taken care of as keys.
+        if c.is_control() {
+            return true;
+        }
+        self.edit(Command::Insert(c.to_string()), context);
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::event::KeyFlags;
+    use ::testing::{ self, open_window };
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A focused, empty text box at (10, 10) to (210, 30) in a new window,
+    /// and the texts it changes to.
+    fn window_with(text_box: TextBox) -> (Rc<HeadlessBackend>, HWND, Rc<RefCell<Vec<String>>>) {
+        let (backend, mut handle) = open_window(300, 100);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut text_box = text_box;
+        {
+            let changes = changes.clone();
+            text_box.set_on_change(move |text, _| changes.borrow_mut().push(text.to_string()));
+        }
+        text_box.set_bounds(Rect::new(10, 10, 210, 30));
+        testing::core(&mut handle).add_control(Box::new(text_box));
+        testing::click(&backend, handle, 12, 20);
+        (backend, handle, changes)
+    }
+
+    fn type_text(backend: &HeadlessBackend, handle: HWND, text: &str) {
+        for c in text.chars() {
+            testing::type_char(backend, handle, c);
+        }
+    }
+
+    /// Presses `vk` with Shift held down.
+    fn shift_key(backend: &HeadlessBackend, handle: HWND, vk: i32) {
+        testing::send(backend, handle, Event::KeyDown { key: VK_SHIFT as u32, system: false, flags: KeyFlags::default() });
+        testing::key(backend, handle, vk);
+        testing::send(backend, handle, Event::KeyUp { key: VK_SHIFT as u32, system: false, flags: KeyFlags::default() });
+    }
+
+    fn last(changes: &RefCell<Vec<String>>) -> String {
+        changes.borrow().last().cloned().unwrap_or(String::new())
+    }
+
+    #[test]
+    fn typing_inserts_at_the_caret() {
+        let (backend, handle, changes) = window_with(TextBox::new());
+        type_text(&backend, handle, "hlo");
+        testing::key(&backend, handle, VK_LEFT);
+        testing::key(&backend, handle, VK_LEFT);
+        type_text(&backend, handle, "el");
+        assert_eq!(*changes.borrow(), vec!["h", "hl", "hlo", "helo", "hello"]);
+        // Control characters were handled as keys already.
+        testing::type_char(&backend, handle, '\u{8}');
+        assert_eq!(changes.borrow().len(), 5);
+    }
+
+    #[test]
+    fn shift_and_arrows_select() {
+        let (backend, handle, changes) = window_with(TextBox::new());
+        type_text(&backend, handle, "hello");
+        shift_key(&backend, handle, VK_LEFT);
+        shift_key(&backend, handle, VK_LEFT);
+        type_text(&backend, handle, "p");
+        assert_eq!(last(&changes), "help");
+
+        // Without Shift, the selection collapses instead of being replaced.
+        shift_key(&backend, handle, VK_HOME);
+        testing::key(&backend, handle, VK_RIGHT);
+        type_text(&backend, handle, "!");
+        assert_eq!(last(&changes), "help!");
+    }
+
+    #[test]
+    fn backspace_and_delete_remove_characters() {
+        let (backend, handle, changes) = window_with(TextBox::new());
+        type_text(&backend, handle, "hello");
+        testing::key(&backend, handle, VK_BACK);
+        assert_eq!(last(&changes), "hell");
+        testing::key(&backend, handle, VK_HOME);
+        testing::key(&backend, handle, VK_DELETE);
+        assert_eq!(last(&changes), "ell");
+
+        // Nothing to delete at the end, and no change reported.
+        testing::key(&backend, handle, VK_END);
+        testing::key(&backend, handle, VK_DELETE);
+        shift_key(&backend, handle, VK_LEFT);
+        testing::key(&backend, handle, VK_BACK);
+        assert_eq!(*changes.borrow(), vec!["h", "he", "hel", "hell", "hello", "hell", "ell", "el"]);
+    }
+
+    #[test]
+    fn typing_stops_at_the_maximum_length() {
+        let mut text_box = TextBox::new();
+        text_box.set_max_length(Some(3));
+        let (backend, handle, changes) = window_with(text_box);
+        type_text(&backend, handle, "abcd");
+        assert_eq!(*changes.borrow(), vec!["a", "ab", "abc"]);
+    }
+}
@@ -0,0 +1,826 @@
+use winapi::*;
+
+use ::ml::{ Paintable, PaintContext };
+use ::colors::{ Color, BLACK, BLUE, GRAY, LIGHT_GRAY, WHITE };
+use ::event::{ Event, MouseButton };
+use ::font::FontBuilder;
+use ::geometry::{ Point, Rect, Size };
+
+use super::{ Control, InputContext, draw_frame };
+
+use std::cmp::{ max, min };
+
+/// How many rows one notch of the mouse wheel scrolls.
+const WHEEL_ROWS: isize = 3;
+
+/// How far every level is indented.
+pub const INDENT: i32 = 16;
+
+/// The width and height of the box with the plus or minus in it.
+const EXPANDER_SIZE: i32 = 9;
+
+/// A node as the provider describes it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TreeItem {
+    /// What the provider knows the node by.
+    pub key: String,
+    pub text: String,
+    /// Whether the node can be expanded. Its children are only asked for
+    /// once it is.
+    pub has_children: bool,
+}
+
+impl TreeItem {
+    pub fn new(key: &str, text: &str, has_children: bool) -> Self {
+        TreeItem {
+            key: key.to_string(),
+            text: text.to_string(),
+            has_children: has_children,
+        }
+    }
+}
+
+/// Where the nodes of a tree come from.
+pub trait TreeProvider {
+    /// The children of `parent`, or the roots without one. Only called when
+    /// a node gets expanded for the first time, or again after `reload`.
+    fn children(&self, parent: Option<&TreeItem>) -> Vec<TreeItem>;
+}
+
+/// Identifies a node of a `TreeView` for as long as its parent's children
+/// aren't reloaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct Node {
+    item: TreeItem,
+    parent: Option<usize>,
+    depth: usize,
+    expanded: bool,
+    /// `None` until the provider was asked.
+    children: Option<Vec<usize>>,
+}
+
+/// The colors a tree is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeStyle {
+    pub foreground: Color,
+    pub background: Color,
+    pub selection_foreground: Color,
+    pub selection_background: Color,
+    /// The selection while the tree doesn't have the focus.
+    pub inactive_selection_background: Color,
+    pub expander: Color,
+    pub border: Color,
+}
+
+impl Default for TreeStyle {
+    fn default() -> Self {
+        TreeStyle {
+            foreground: BLACK,
+            background: WHITE,
+            selection_foreground: WHITE,
+            selection_background: BLUE,
+            inactive_selection_background: LIGHT_GRAY,
+            expander: GRAY,
+            border: GRAY,
+        }
+    }
+}
+
+/// A tree of text nodes whose children are loaded as they are expanded.
+///
+/// The nodes that are in view, that is whose ancestors are all expanded,
+/// make up the rows. The keyboard works like in Explorer: Up and Down go
+/// through the rows, Right expands a node or goes to its first child, Left
+/// collapses a node or goes to its parent, `+` and `-` expand and collapse,
+/// and `*` expands everything below the selected node.
+pub struct TreeView {
+    pub bounds: Rect,
+    pub font_builder: FontBuilder,
+    pub style: TreeStyle,
+    /// Space around the text of every row.
+    pub padding: Size,
+    provider: Box<TreeProvider>,
+    /// Every node ever loaded. Nodes dropped by `reload` leave holes.
+    nodes: Vec<Option<Node>>,
+    roots: Vec<usize>,
+    /// The nodes in view, top to bottom.
+    rows: Vec<usize>,
+    selected: Option<usize>,
+    /// The first row in view.
+    scroll: usize,
+    on_select: Option<Box<FnMut(&TreeItem, &mut InputContext)>>,
+    on_expand: Option<Box<FnMut(&TreeItem, bool, &mut InputContext)>>,
+    on_activate: Option<Box<FnMut(&TreeItem, &mut InputContext)>>,
+    focused: bool,
+}
+
+impl TreeView {
+    pub fn new(provider: Box<TreeProvider>) -> Self {
+        let mut tree = TreeView {
+            bounds: Rect::default(),
+            font_builder: FontBuilder::new(),
+            style: TreeStyle::default(),
+            padding: Size::new(2, 1),
+            provider: provider,
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            rows: Vec::new(),
+            selected: None,
+            scroll: 0,
+            on_select: None,
+            on_expand: None,
+            on_activate: None,
+            focused: false,
+        };
+        tree.reload_roots();
+        tree
+    }
+
+    pub fn roots(&self) -> Vec<NodeId> {
+        self.roots.iter().map(|&id| NodeId(id)).collect()
+    }
+
+    pub fn item(&self, id: NodeId) -> Option<&TreeItem> {
+        self.node(id.0).map(|node| &node.item)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id.0).and_then(|node| node.parent).map(NodeId)
+    }
+
+    /// The children of `id`, if they were loaded already.
+    pub fn children(&self, id: NodeId) -> Option<Vec<NodeId>> {
+        self.node(id.0).and_then(|node| node.children.as_ref())
+            .map(|children| children.iter().map(|&child| NodeId(child)).collect())
+    }
+
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.node(id.0).map_or(0, |node| node.depth)
+    }
+
+    pub fn is_expanded(&self, id: NodeId) -> bool {
+        self.node(id.0).map_or(false, |node| node.expanded)
+    }
+
+    /// Expands or collapses `id`, loading its children if need be, without
+    /// calling the expand callback.
+    pub fn set_expanded(&mut self, id: NodeId, expanded: bool) -> &mut Self {
+        self.expand_node(id.0, expanded);
+        self
+    }
+
+    /// Expands `id` and everything below it, without calling the expand
+    /// callback.
+    pub fn expand_all(&mut self, id: NodeId) -> &mut Self {
+        let mut pending = vec![id.0];
+        while let Some(node) = pending.pop() {
+            self.expand_node(node, true);
+            if let Some(children) = self.node(node).and_then(|n| n.children.clone()) {
+                pending.extend(children);
+            }
+        }
+        self
+    }
+
+    /// Asks the provider for the children of `id` again, or the roots
+    /// without one. The nodes below are gone, and with them the selection
+    /// if it was one of them.
+    pub fn reload(&mut self, id: Option<NodeId>) {
+        match id {
+            Some(id) => {
+                let children = self.node_mut(id.0).and_then(|node| node.children.take());
+                if let Some(children) = children {
+                    self.remove_nodes(children);
+                }
+                if self.is_expanded(id) {
+                    self.load_children(id.0);
+                }
+            },
+            None => self.reload_roots(),
+        }
+        self.update_rows();
+    }
+
+    pub fn selected(&self) -> Option<NodeId> {
+        self.selected.map(NodeId)
+    }
+
+    pub fn selected_item(&self) -> Option<&TreeItem> {
+        self.selected.and_then(|id| self.node(id)).map(|node| &node.item)
+    }
+
+    /// Selects `id`, expanding its ancestors and scrolling it into view,
+    /// without calling the select callback.
+    pub fn select(&mut self, id: NodeId) -> &mut Self {
+        if self.node(id.0).is_none() {
+            return self;
+        }
+        let mut ancestor = self.node(id.0).and_then(|node| node.parent);
+        while let Some(parent) = ancestor {
+            self.expand_node(parent, true);
+            ancestor = self.node(parent).and_then(|node| node.parent);
+        }
+        self.selected = Some(id.0);
+        self.scroll_to_selection();
+        self
+    }
+
+    /// The number of rows, one for every node in view.
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn node_at_row(&self, row: usize) -> Option<NodeId> {
+        self.rows.get(row).map(|&id| NodeId(id))
+    }
+
+    /// The first row in view.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    pub fn scroll_by(&mut self, rows: isize) {
+        let last = self.rows.len().saturating_sub(self.page_rows());
+        self.scroll = min(max(0, self.scroll as isize + rows) as usize, last);
+    }
+
+    /// Calls `f` with the item the user selected.
+    pub fn set_on_select<F: FnMut(&TreeItem, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_select = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the item the user expanded or collapsed, and whether
+    /// it is expanded now.
+    pub fn set_on_expand<F: FnMut(&TreeItem, bool, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_expand = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the item activated with Enter or a double-click on a
+    /// node without children.
+    pub fn set_on_activate<F: FnMut(&TreeItem, &mut InputContext) + 'static>(&mut self, f: F) -> &mut Self {
+        self.on_activate = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    fn node(&self, id: usize) -> Option<&Node> {
+        self.nodes.get(id).and_then(|node| node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: usize) -> Option<&mut Node> {
+        self.nodes.get_mut(id).and_then(|node| node.as_mut())
+    }
+
+    fn add_nodes(&mut self, items: Vec<TreeItem>, parent: Option<usize>) -> Vec<usize> {
+        let depth = parent.and_then(|p| self.node(p)).map_or(0, |node| node.depth + 1);
+        items.into_iter().map(|item| {
+            self.nodes.push(Some(Node { item: item, parent: parent, depth: depth, expanded: false, children: None }));
+            self.nodes.len() - 1
+        }).collect()
+    }
+
+    /// Drops `ids` and everything below them.
+    fn remove_nodes(&mut self, ids: Vec<usize>) {
+        let mut pending = ids;
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.nodes.get_mut(id).and_then(|node| node.take()) {
+                pending.extend(node.children.unwrap_or(Vec::new()));
+            }
+            if self.selected == Some(id) {
+                self.selected = None;
+            }
+        }
+    }
+
+    fn reload_roots(&mut self) {
+        let roots = ::std::mem::replace(&mut self.roots, Vec::new());
+        self.remove_nodes(roots);
+        let items = self.provider.children(None);
+        self.roots = self.add_nodes(items, None);
+        self.update_rows();
+    }
+
+    fn load_children(&mut self, id: usize) {
+        let items = match self.node(id) {
+            Some(node) if node.children.is_none() => self.provider.children(Some(&node.item)),
+            _ => return,
+        };
+        let children = self.add_nodes(items, Some(id));
+        if let Some(node) = self.node_mut(id) {
+            node.children = Some(children);
+        }
+    }
+
+    /// Returns whether anything changed.
+    fn expand_node(&mut self, id: usize, expanded: bool) -> bool {
+        match self.node(id) {
+            Some(node) if node.item.has_children && node.expanded != expanded => { },
+            _ => return false,
+        }
+        if expanded {
+            self.load_children(id);
+        }
+        if let Some(node) = self.node_mut(id) {
+            node.expanded = expanded;
+        }
+        self.update_rows();
+        true
+    }
+
+    /// Lists the nodes in view again, after something was expanded or
+    /// collapsed. A selection that got hidden moves up to its ancestor in
+    /// view.
+    fn update_rows(&mut self) {
+        let mut rows = Vec::new();
+        let mut pending = self.roots.iter().rev().cloned().collect::<Vec<_>>();
+        while let Some(id) = pending.pop() {
+            rows.push(id);
+            if let Some(node) = self.node(id) {
+                if node.expanded {
+                    if let Some(ref children) = node.children {
+                        pending.extend(children.iter().rev().cloned());
+                    }
+                }
+            }
+        }
+        self.rows = rows;
+
+        while let Some(selected) = self.selected {
+            if self.rows.contains(&selected) {
+                break;
+            }
+            self.selected = self.node(selected).and_then(|node| node.parent);
+        }
+        self.scroll_by(0);
+    }
+
+    fn selected_row(&self) -> Option<usize> {
+        self.selected.and_then(|id| self.rows.iter().position(|&row| row == id))
+    }
+
+    fn row_height(&self) -> i32 {
+        let text = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        max(text.height, EXPANDER_SIZE) + 2 * self.padding.height
+    }
+
+    fn content(&self) -> Rect {
+        self.bounds.inset(1, 1)
+    }
+
+    /// How many whole rows fit, but at least one.
+    fn page_rows(&self) -> usize {
+        let height = self.row_height();
+        if height <= 0 { 1 } else { max(1, self.content().height() / height) as usize }
+    }
+
+    fn scroll_to_selection(&mut self) {
+        if let Some(row) = self.selected_row() {
+            let page = self.page_rows();
+            if row < self.scroll {
+                self.scroll = row;
+            } else if row >= self.scroll + page {
+                self.scroll = row + 1 - page;
+            }
+        }
+    }
+
+    /// The row at `y`, relative to the tree, if there is one.
+    fn row_at(&self, y: i32) -> Option<usize> {
+        let y = y - (self.content().top - self.bounds.top);
+        let height = self.row_height();
+        if y < 0 || height <= 0 {
+            return None;
+        }
+        let row = self.scroll + (y / height) as usize;
+        if row < self.rows.len() { Some(row) } else { None }
+    }
+
+    /// The box with the plus or minus of a node at `depth`, in a row whose
+    /// top left corner is `origin`.
+    fn expander_rect(&self, origin: Point, depth: usize) -> Rect {
+        let left = origin.x + depth as i32 * INDENT + (INDENT - EXPANDER_SIZE) / 2;
+        let top = origin.y + (self.row_height() - EXPANDER_SIZE) / 2;
+        Rect::new(left, top, left + EXPANDER_SIZE, top + EXPANDER_SIZE)
+    }
+
+    /// Selects `id` on behalf of the user.
+    fn select_node(&mut self, id: usize, context: &mut InputContext) {
+        context.invalidate();
+        if self.selected == Some(id) {
+            return;
+        }
+        self.selected = Some(id);
+        self.scroll_to_selection();
+        let item = match self.node(id) {
+            Some(node) => node.item.clone(),
+            None => return,
+        };
+        if let Some(ref mut on_select) = self.on_select {
+            on_select(&item, context);
+        }
+    }
+
+    /// Expands or collapses `id` on behalf of the user.
+    fn expand_with_callback(&mut self, id: usize, expanded: bool, context: &mut InputContext) {
+        let selected = self.selected;
+        if !self.expand_node(id, expanded) {
+            return;
+        }
+        context.invalidate();
+        let item = match self.node(id) {
+            Some(node) => node.item.clone(),
+            None => return,
+        };
+        if let Some(ref mut on_expand) = self.on_expand {
+            on_expand(&item, expanded, context);
+        }
+        // Collapsing may have moved the selection up to this node.
+        if self.selected != selected {
+            if let Some(ref mut on_select) = self.on_select {
+                on_select(&item, context);
+            }
+        }
+    }
+
+    fn activate(&mut self, context: &mut InputContext) {
+        let item = match self.selected.and_then(|id| self.node(id)) {
+            Some(node) => node.item.clone(),
+            None => return,
+        };
+        if let Some(ref mut on_activate) = self.on_activate {
+            on_activate(&item, context);
+        }
+    }
+
+    /// Handles a navigation key, returning whether it was one.
+    fn key(&mut self, key: u32, context: &mut InputContext) -> bool {
+        let key = key as i32;
+        let count = self.rows.len();
+        if count == 0 {
+            return false;
+        }
+        let current = self.selected_row();
+        let page = self.page_rows();
+        let selected = self.selected.and_then(|id| self.node(id).map(|node| (id, node.expanded, node.item.has_children, node.parent)));
+        let target = if key == VK_UP {
+            current.map_or(0, |row| row.saturating_sub(1))
+        } else if key == VK_DOWN {
+            current.map_or(0, |row| min(row + 1, count - 1))
+        } else if key == VK_PRIOR {
+            current.map_or(0, |row| row.saturating_sub(page - 1))
+        } else if key == VK_NEXT {
+            current.map_or(0, |row| min(row + page - 1, count - 1))
+        } else if key == VK_HOME {
+            0
+        } else if key == VK_END {
+            count - 1
+        } else if key == VK_RIGHT {
+            match selected {
+                Some((id, false, true, _)) => {
+                    self.expand_with_callback(id, true, context);
+                    return true;
+                },
+                Some((_, true, _, _)) => current.map_or(0, |row| min(row + 1, count - 1)),
+                _ => return true,
+            }
+        } else if key == VK_LEFT {
+            match selected {
+                Some((id, true, _, _)) => {
+                    self.expand_with_callback(id, false, context);
+                    return true;
+                },
+                Some((_, false, _, Some(parent))) => match self.rows.iter().position(|&row| row == parent) {
+                    Some(row) => row,
+                    None => return true,
+                },
+                _ => return true,
+            }
+        } else if key == VK_RETURN {
+            self.activate(context);
+            return true;
+        } else {
+            return false;
+        };
+        let id = self.rows[target];
+        self.select_node(id, context);
+        true
+    }
+}
+
+impl Paintable for TreeView {
+    fn paint(&self, context: &PaintContext) {
+        context.fill_rect(&self.bounds, self.style.background);
+        draw_frame(context, &self.bounds, self.style.border);
+        let font = match self.font_builder.build() {
+            Ok(font) => font,
+            Err(_) => return,
+        };
+
+        let content = self.content();
+        let height = self.row_height();
+        let selection = if self.focused { self.style.selection_background } else { self.style.inactive_selection_background };
+        let mut top = content.top;
+        for &id in self.rows.iter().skip(self.scroll) {
+            if top >= content.bottom {
+                break;
+            }
+            let node = match self.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let bottom = min(top + height, content.bottom);
+
+            let expander = self.expander_rect(Point::new(content.left, top), node.depth);
+            if node.item.has_children && expander.bottom <= content.bottom {
+                draw_frame(context, &expander, self.style.expander);
+                let middle = (expander.top + expander.bottom) / 2;
+                let center = (expander.left + expander.right) / 2;
+                context.draw_line(Point::new(expander.left + 2, middle), Point::new(expander.right - 2, middle),
+                                  self.style.foreground);
+                if !node.expanded {
+                    context.draw_line(Point::new(center, expander.top + 2), Point::new(center, expander.bottom - 2),
+                                      self.style.foreground);
+                }
+            }
+
+            let left = content.left + (node.depth as i32 + 1) * INDENT;
+            let text_width = font.text_extent(&node.item.text).width + 2 * self.padding.width;
+            let row = Rect::new(left, top, min(left + text_width, content.right), bottom);
+            let color = if self.selected == Some(id) {
+                context.fill_rect(&row, selection);
+                if self.focused { self.style.selection_foreground } else { self.style.foreground }
+            } else {
+                self.style.foreground
+            };
+            context.draw_text(&node.item.text, &font, color, &row.inset(self.padding.width, 0),
+                              DT_LEFT | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX);
+            top += height;
+        }
+    }
+
+    fn bounds(&self) -> Option<Rect> {
+        Some(self.bounds)
+    }
+}
+
+impl Control for TreeView {
+    /// A few rows of a few words, the rows that may be loaded later
+    /// notwithstanding.
+    #[allow(unused_variables)]
+    fn measure(&self, available: Size) -> Size {
+        let line = self.font_builder.text_extent("Xg").unwrap_or(Size::default());
+        Size::new(10 * line.width + 3 * INDENT + 2, 10 * self.row_height() + 2)
+    }
+
+    fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+        self.scroll_by(0);
+    }
+
+    fn handle_event(&mut self, event: &Event, context: &mut InputContext) -> bool {
+        match *event {
+            Event::Focus { .. } => {
+                self.focused = true;
+                context.invalidate();
+                true
+            },
+            Event::Blur { .. } => {
+                self.focused = false;
+                context.invalidate();
+                true
+            },
+            Event::MouseDown { button: MouseButton::Left, position, .. } |
+            Event::DoubleClick { button: MouseButton::Left, position, .. } => {
+                let id = match self.row_at(position.y) {
+                    Some(row) => self.rows[row],
+                    None => return true,
+                };
+                let (depth, expanded, has_children) = match self.node(id) {
+                    Some(node) => (node.depth, node.expanded, node.item.has_children),
+                    None => return true,
+                };
+                let row = self.row_at(position.y).unwrap() - self.scroll;
+                let origin = Point::new(1, 1 + row as i32 * self.row_height());
+                let on_expander = self.expander_rect(origin, depth).inset(-2, -2).contains(position);
+                let double = match *event { Event::DoubleClick { .. } => true, _ => false };
+                if has_children && (on_expander || double) {
+                    self.expand_with_callback(id, !expanded, context);
+                }
+                if !on_expander {
+                    self.select_node(id, context);
+                    if double && !has_children {
+                        self.activate(context);
+                    }
+                }
+                true
+            },
+            Event::MouseWheel { delta, horizontal: false, .. } => {
+                self.scroll_by(-(delta / WHEEL_DELTA as i32) as isize * WHEEL_ROWS);
+                context.invalidate();
+                true
+            },
+            Event::KeyDown { key, .. } => self.key(key, context),
+            _ => false,
+        }
+    }
+
+    /// `+`, `-` and `*` come in as characters, from the keypad or not.
+    fn handle_char(&mut self, c: char, context: &mut InputContext) -> bool {
+        let id = match self.selected {
+            Some(id) => id,
+            None => return false,
+        };
+        match c {
+            '+' => self.expand_with_callback(id, true, context),
+            '-' => self.expand_with_callback(id, false, context),
+            '*' => {
+                self.expand_all(NodeId(id));
+                context.invalidate();
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::event::Modifiers;
+    use ::message::MessageQueue;
+
+    use std::cell::RefCell;
+    use std::ptr;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    /// The height of a row with the default font.
+    const ROW: i32 = 14;
+
+    /// Two roots with children and a leaf, recording what it was asked for.
+    struct Provider {
+        asked: Rc<RefCell<Vec<Option<String>>>>,
+    }
+
+    impl TreeProvider for Provider {
+        fn children(&self, parent: Option<&TreeItem>) -> Vec<TreeItem> {
+            let key = parent.map(|item| item.key.clone());
+            self.asked.borrow_mut().push(key.clone());
+            let children: &[(&str, bool)] = match key.as_ref().map(|key| &key[..]) {
+                None => &[("a", true), ("b", true), ("c", false)],
+                Some("a") => &[("a1", true), ("a2", false)],
+                Some("a1") => &[("a1x", false)],
+                Some("b") => &[("b1", false)],
+                _ => &[],
+            };
+            children.iter().map(|&(key, has_children)| TreeItem::new(key, key, has_children)).collect()
+        }
+    }
+
+    /// A tree at the origin, what its provider was asked for, and what the
+    /// user selected and expanded.
+    fn tree() -> (TreeView, Rc<RefCell<Vec<Option<String>>>>, Rc<RefCell<Vec<String>>>) {
+        HeadlessBackend::install();
+        let asked = Rc::new(RefCell::new(Vec::new()));
+        let mut tree = TreeView::new(Box::new(Provider { asked: asked.clone() }));
+        tree.set_bounds(Rect::new(0, 0, 200, 200));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        {
+            let events = events.clone();
+            tree.set_on_select(move |item, _| events.borrow_mut().push(format!("select {}", item.key)));
+        }
+        {
+            let events = events.clone();
+            tree.set_on_expand(move |item, expanded, _| {
+                events.borrow_mut().push(format!("{} {}", if expanded { "expand" } else { "collapse" }, item.key));
+            });
+        }
+        (tree, asked, events)
+    }
+
+    fn context() -> InputContext {
+        InputContext::new(1, ptr::null_mut(), Arc::new(MessageQueue::new()))
+    }
+
+    fn press(tree: &mut TreeView, vk: i32) {
+        tree.handle_event(&Event::KeyDown { key: vk as u32, system: false, flags: Default::default() }, &mut context());
+    }
+
+    fn click(tree: &mut TreeView, x: i32, y: i32) {
+        let position = Point::new(x, y);
+        tree.handle_event(&Event::MouseDown { button: MouseButton::Left, position: position, modifiers: Modifiers::empty() },
+                          &mut context());
+        tree.handle_event(&Event::MouseUp { button: MouseButton::Left, position: position, modifiers: Modifiers::empty() },
+                          &mut context());
+    }
+
+    /// Clicks the expander of the node in `row`, `depth` levels down.
+    fn click_expander(tree: &mut TreeView, row: i32, depth: i32) {
+        click(tree, 1 + depth * INDENT + INDENT / 2, 1 + row * ROW + ROW / 2);
+    }
+
+    fn keys(tree: &TreeView) -> Vec<String> {
+        (0..tree.row_count()).map(|row| tree.item(tree.node_at_row(row).unwrap()).unwrap().key.clone()).collect()
+    }
+
+    fn selected(tree: &TreeView) -> Option<String> {
+        tree.selected_item().map(|item| item.key.clone())
+    }
+
+    #[test]
+    fn children_are_loaded_on_first_expand_only() {
+        let (mut tree, asked, _) = tree();
+        assert_eq!(*asked.borrow(), vec![None]);
+        assert_eq!(tree.children(tree.roots()[0]), None);
+
+        click_expander(&mut tree, 0, 0);
+        assert_eq!(keys(&tree), vec!["a", "a1", "a2", "b", "c"]);
+        click_expander(&mut tree, 0, 0);
+        click_expander(&mut tree, 0, 0);
+        assert_eq!(keys(&tree), vec!["a", "a1", "a2", "b", "c"]);
+        assert_eq!(*asked.borrow(), vec![None, Some("a".to_string())]);
+
+        // Reloading asks again, but only for what is expanded.
+        tree.reload(Some(tree.roots()[0]));
+        tree.reload(Some(tree.roots()[1]));
+        assert_eq!(*asked.borrow(), vec![None, Some("a".to_string()), Some("a".to_string())]);
+    }
+
+    #[test]
+    fn arrow_keys_walk_the_tree() {
+        let (mut tree, _, events) = tree();
+        press(&mut tree, VK_DOWN);
+        assert_eq!(selected(&tree), Some("a".to_string()));
+
+        // Right expands, then goes to the first child.
+        press(&mut tree, VK_RIGHT);
+        assert_eq!((selected(&tree), tree.row_count()), (Some("a".to_string()), 5));
+        press(&mut tree, VK_RIGHT);
+        press(&mut tree, VK_RIGHT);
+        press(&mut tree, VK_DOWN);
+        assert_eq!(selected(&tree), Some("a1x".to_string()));
+        // Right on a leaf does nothing.
+        press(&mut tree, VK_RIGHT);
+        assert_eq!(selected(&tree), Some("a1x".to_string()));
+
+        // Left goes to the parent, then collapses it.
+        press(&mut tree, VK_LEFT);
+        assert_eq!(selected(&tree), Some("a1".to_string()));
+        press(&mut tree, VK_LEFT);
+        assert_eq!(keys(&tree), vec!["a", "a1", "a2", "b", "c"]);
+        press(&mut tree, VK_DOWN);
+        press(&mut tree, VK_DOWN);
+        assert_eq!(selected(&tree), Some("b".to_string()));
+        press(&mut tree, VK_UP);
+        press(&mut tree, VK_LEFT);
+        press(&mut tree, VK_LEFT);
+        assert_eq!(selected(&tree), Some("a".to_string()));
+        assert_eq!(keys(&tree), vec!["a", "b", "c"]);
+
+        press(&mut tree, VK_END);
+        press(&mut tree, VK_LEFT);
+        assert_eq!(selected(&tree), Some("c".to_string()));
+
+        assert_eq!(*events.borrow(), vec![
+            "select a", "expand a", "select a1", "expand a1", "select a1x", "select a1", "collapse a1",
+            "select a2", "select b", "select a2", "select a", "collapse a", "select c",
+        ]);
+    }
+
+    #[test]
+    fn the_expander_toggles_without_selecting() {
+        let (mut tree, _, events) = tree();
+        click_expander(&mut tree, 1, 0);
+        assert_eq!(keys(&tree), vec!["a", "b", "b1", "c"]);
+        assert_eq!(selected(&tree), None);
+
+        // The rest of the row selects.
+        click(&mut tree, 60, 1 + 2 * ROW + ROW / 2);
+        assert_eq!(selected(&tree), Some("b1".to_string()));
+        click(&mut tree, 60, 1 + 3 * ROW + ROW / 2);
+        click(&mut tree, 60, 1 + 2 * ROW + ROW / 2);
+
+        // Collapsing the parent moves the selection up to it.
+        click_expander(&mut tree, 1, 0);
+        assert_eq!(keys(&tree), vec!["a", "b", "c"]);
+        assert_eq!(selected(&tree), Some("b".to_string()));
+
+        // Leaves have no expander to click.
+        click_expander(&mut tree, 2, 0);
+        assert_eq!(selected(&tree), Some("b".to_string()));
+
+        assert_eq!(*events.borrow(), vec!["expand b", "select b1", "select c", "select b1", "collapse b", "select b"]);
+    }
+}
@@ -0,0 +1,506 @@
+//! Raw window messages decoded into something typed.
+//!
+//! `Event::decode` and `Event::encode` only shuffle bits around, so they
+//! work without any window and on every platform.
+
+use winapi::*;
+
+use ::geometry::{ Point, Size };
+
+// Not in every winapi 0.2 release.
+const XBUTTON1: WORD = 0x0001;
+const XBUTTON2: WORD = 0x0002;
+const WM_XBUTTONDOWN: UINT = 0x020B;
+const WM_XBUTTONUP: UINT = 0x020C;
+const WM_XBUTTONDBLCLK: UINT = 0x020D;
+const WM_MOUSEHWHEEL: UINT = 0x020E;
+const WM_MOUSELEAVE: UINT = 0x02A3;
+const WA_INACTIVE: WORD = 0;
+const WA_ACTIVE: WORD = 1;
+const WA_CLICKACTIVE: WORD = 2;
+
+bitflags! {
+    /// The keys and buttons held down during a mouse event, the `MK_*`
+    /// flags of the `WPARAM`.
+    flags Modifiers: u32 {
+        const MODIFIER_LEFT_BUTTON   = 0x0001,
+        const MODIFIER_RIGHT_BUTTON  = 0x0002,
+        const MODIFIER_SHIFT         = 0x0004,
+        const MODIFIER_CONTROL       = 0x0008,
+        const MODIFIER_MIDDLE_BUTTON = 0x0010,
+        const MODIFIER_X_BUTTON1     = 0x0020,
+        const MODIFIER_X_BUTTON2     = 0x0040,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    X1,
+    X2,
+}
+
+/// The details packed into the `LPARAM` of keyboard messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyFlags {
+    /// How many times the key got repeated because it was held down.
+    pub repeat_count: u16,
+    pub scan_code: u8,
+    pub extended: bool,
+    /// Whether ALT was held down.
+    pub context: bool,
+    /// Whether the key was already down before this message.
+    pub previously_down: bool,
+    /// Whether the key is being released.
+    pub released: bool,
+}
+
+impl KeyFlags {
+    pub fn from_lparam(l_param: LPARAM) -> Self {
+        let bits = l_param as u32;
+        KeyFlags {
+            repeat_count: (bits & 0xFFFF) as u16,
+            scan_code: ((bits >> 16) & 0xFF) as u8,
+            extended: bits & (1 << 24) != 0,
+            context: bits & (1 << 29) != 0,
+            previously_down: bits & (1 << 30) != 0,
+            released: bits & (1 << 31) != 0,
+        }
+    }
+
+    pub fn to_lparam(&self) -> LPARAM {
+        let mut bits = self.repeat_count as u32 | (self.scan_code as u32) << 16;
+        if self.extended { bits |= 1 << 24; }
+        if self.context { bits |= 1 << 29; }
+        if self.previously_down { bits |= 1 << 30; }
+        if self.released { bits |= 1 << 31; }
+        bits as i32 as LPARAM
+    }
+}
+
+/// Why a window got resized, the `SIZE_*` value of `WM_SIZE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SizeKind {
+    Restored,
+    Minimized,
+    Maximized,
+    /// Another window got restored.
+    MaxShow,
+    /// Another window got maximized.
+    MaxHide,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Activation {
+    Inactive,
+    Active,
+    /// Activated by a mouse click.
+    ClickActive,
+}
+
+/// A window message, decoded.
+///
+/// Mouse positions are in client coordinates, except for the wheel ones,
+/// which Windows reports in screen coordinates. Anything this doesn't know
+/// about ends up in `Other` untouched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    MouseMove { position: Point, modifiers: Modifiers },
+    MouseDown { button: MouseButton, position: Point, modifiers: Modifiers },
+    MouseUp { button: MouseButton, position: Point, modifiers: Modifiers },
+    DoubleClick { button: MouseButton, position: Point, modifiers: Modifiers },
+    /// `delta` is in multiples of `WHEEL_DELTA` per notch, positive away
+    /// from the user or to the right.
+    MouseWheel { delta: i32, horizontal: bool, position: Point, modifiers: Modifiers },
+    /// The mouse left the window, or the control it was over.
+    MouseLeave,
+    /// `system` is set for `WM_SYSKEYDOWN`, i.e. with ALT or F10.
+    KeyDown { key: u32, system: bool, flags: KeyFlags },
+    KeyUp { key: u32, system: bool, flags: KeyFlags },
+    /// A UTF-16 code unit; characters outside the BMP arrive as two of them.
+    Char { code: u16, system: bool, flags: KeyFlags },
+    Size { kind: SizeKind, size: Size },
+    /// The new position of the client area.
+    Move { position: Point },
+    /// Got the keyboard focus from `previous`, which may be null.
+    Focus { previous: HWND },
+    /// Lost the keyboard focus to `next`, which may be null.
+    Blur { next: HWND },
+    Activate { activation: Activation, minimized: bool, other: HWND },
+    Timer { id: usize },
+    /// A menu item, accelerator or control notification. `control` is null
+    /// for the first two.
+    Command { id: u16, code: u16, control: HWND },
+    Notify { id: usize, header: *const NMHDR },
+    Close,
+    Destroy,
+    Paint,
+    Other { message: UINT, w_param: WPARAM, l_param: LPARAM },
+}
+
+fn low_word(value: usize) -> WORD {
+    (value & 0xFFFF) as WORD
+}
+
+fn high_word(value: usize) -> WORD {
+    ((value >> 16) & 0xFFFF) as WORD
+}
+
+fn make_long(low: WORD, high: WORD) -> usize {
+    (low as u32 | (high as u32) << 16) as usize
+}
+
+/// Signed coordinates as in `GET_X_LPARAM`/`GET_Y_LPARAM`.
+fn point_from_lparam(l_param: LPARAM) -> Point {
+    let bits = l_param as usize;
+    Point::new(low_word(bits) as i16 as i32, high_word(bits) as i16 as i32)
+}
+
+fn point_to_lparam(point: Point) -> LPARAM {
+    make_long(point.x as i16 as WORD, point.y as i16 as WORD) as i32 as LPARAM
+}
+
+fn modifiers(w_param: WPARAM) -> Modifiers {
+    Modifiers::from_bits_truncate(low_word(w_param as usize) as u32)
+}
+
+fn button_message(button: MouseButton) -> (UINT, UINT, UINT) {
+    match button {
+        MouseButton::Left => (WM_LBUTTONDOWN, WM_LBUTTONUP, WM_LBUTTONDBLCLK),
+        MouseButton::Right => (WM_RBUTTONDOWN, WM_RBUTTONUP, WM_RBUTTONDBLCLK),
+        MouseButton::Middle => (WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MBUTTONDBLCLK),
+        MouseButton::X1 | MouseButton::X2 => (WM_XBUTTONDOWN, WM_XBUTTONUP, WM_XBUTTONDBLCLK),
+    }
+}
+
+fn button_wparam(button: MouseButton, modifiers: Modifiers) -> WPARAM {
+    let x = match button {
+        MouseButton::X1 => XBUTTON1,
+        MouseButton::X2 => XBUTTON2,
+        _ => 0,
+    };
+    make_long(modifiers.bits() as WORD, x) as WPARAM
+}
+
+impl Event {
+    pub fn decode(message: UINT, w_param: WPARAM, l_param: LPARAM) -> Event {
+        let key_flags = KeyFlags::from_lparam(l_param);
+
+        match message {
+            WM_MOUSEMOVE => Event::MouseMove { position: point_from_lparam(l_param), modifiers: modifiers(w_param) },
+            WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN |
+            WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP |
+            WM_LBUTTONDBLCLK | WM_RBUTTONDBLCLK | WM_MBUTTONDBLCLK | WM_XBUTTONDBLCLK => {
+                let button = match message {
+                    WM_LBUTTONDOWN | WM_LBUTTONUP | WM_LBUTTONDBLCLK => MouseButton::Left,
+                    WM_RBUTTONDOWN | WM_RBUTTONUP | WM_RBUTTONDBLCLK => MouseButton::Right,
+                    WM_MBUTTONDOWN | WM_MBUTTONUP | WM_MBUTTONDBLCLK => MouseButton::Middle,
+                    _ if high_word(w_param as usize) == XBUTTON2 => MouseButton::X2,
+                    _ => MouseButton::X1,
+                };
+                let position = point_from_lparam(l_param);
+                let modifiers = modifiers(w_param);
+                match message {
+                    WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN =>
+                        Event::MouseDown { button: button, position: position, modifiers: modifiers },
+                    WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP =>
+                        Event::MouseUp { button: button, position: position, modifiers: modifiers },
+                    _ => Event::DoubleClick { button: button, position: position, modifiers: modifiers },
+                }
+            },
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL => Event::MouseWheel {
+                delta: high_word(w_param as usize) as i16 as i32,
+                horizontal: message == WM_MOUSEHWHEEL,
+                position: point_from_lparam(l_param),
+                modifiers: modifiers(w_param),
+            },
+            WM_MOUSELEAVE => Event::MouseLeave,
+            WM_KEYDOWN | WM_SYSKEYDOWN => Event::KeyDown {
+                key: w_param as u32,
+                system: message == WM_SYSKEYDOWN,
+                flags: key_flags,
+            },
+            WM_KEYUP | WM_SYSKEYUP => Event::KeyUp {
+                key: w_param as u32,
+                system: message == WM_SYSKEYUP,
+                flags: key_flags,
+            },
+            WM_CHAR | WM_SYSCHAR => Event::Char {
+                code: w_param as u16,
+                system: message == WM_SYSCHAR,
+                flags: key_flags,
+            },
+            WM_SIZE => {
+                let kind = match w_param as UINT {
+                    SIZE_MINIMIZED => SizeKind::Minimized,
+                    SIZE_MAXIMIZED => SizeKind::Maximized,
+                    SIZE_MAXSHOW => SizeKind::MaxShow,
+                    SIZE_MAXHIDE => SizeKind::MaxHide,
+                    _ => SizeKind::Restored,
+                };
+                let bits = l_param as usize;
+                Event::Size {
+                    kind: kind,
+                    size: Size::new(low_word(bits) as i32, high_word(bits) as i32),
+                }
+            },
+            WM_MOVE => Event::Move { position: point_from_lparam(l_param) },
+            WM_SETFOCUS => Event::Focus { previous: w_param as HWND },
+            WM_KILLFOCUS => Event::Blur { next: w_param as HWND },
+            WM_ACTIVATE => Event::Activate {
+                activation: match low_word(w_param as usize) {
+                    WA_INACTIVE => Activation::Inactive,
+                    WA_CLICKACTIVE => Activation::ClickActive,
+                    _ => Activation::Active,
+                },
+                minimized: high_word(w_param as usize) != 0,
+                other: l_param as HWND,
+            },
+            WM_TIMER => Event::Timer { id: w_param as usize },
+            WM_COMMAND => Event::Command {
+                id: low_word(w_param as usize),
+                code: high_word(w_param as usize),
+                control: l_param as HWND,
+            },
+            WM_NOTIFY => Event::Notify { id: w_param as usize, header: l_param as *const NMHDR },
+            WM_CLOSE => Event::Close,
+            WM_DESTROY => Event::Destroy,
+            WM_PAINT => Event::Paint,
+            _ => Event::Other { message: message, w_param: w_param, l_param: l_param },
+        }
+    }
+
+    /// The same event with client positions made relative to `origin`, e.g.
+    /// the top left corner of a control. Wheel positions stay on screen.
+    pub fn relative_to(&self, origin: Point) -> Event {
+        match *self {
+            Event::MouseMove { position, modifiers } =>
+                Event::MouseMove { position: position - origin, modifiers: modifiers },
+            Event::MouseDown { button, position, modifiers } =>
+                Event::MouseDown { button: button, position: position - origin, modifiers: modifiers },
+            Event::MouseUp { button, position, modifiers } =>
+                Event::MouseUp { button: button, position: position - origin, modifiers: modifiers },
+            Event::DoubleClick { button, position, modifiers } =>
+                Event::DoubleClick { button: button, position: position - origin, modifiers: modifiers },
+            event => event,
+        }
+    }
+
+    /// The message this was decoded from, or would be sent as.
+    pub fn encode(&self) -> (UINT, WPARAM, LPARAM) {
+        match *self {
+            Event::MouseMove { position, modifiers } =>
+                (WM_MOUSEMOVE, modifiers.bits() as WPARAM, point_to_lparam(position)),
+            Event::MouseDown { button, position, modifiers } =>
+                (button_message(button).0, button_wparam(button, modifiers), point_to_lparam(position)),
+            Event::MouseUp { button, position, modifiers } =>
+                (button_message(button).1, button_wparam(button, modifiers), point_to_lparam(position)),
+            Event::DoubleClick { button, position, modifiers } =>
+                (button_message(button).2, button_wparam(button, modifiers), point_to_lparam(position)),
+            Event::MouseWheel { delta, horizontal, position, modifiers } => (
+                if horizontal { WM_MOUSEHWHEEL } else { WM_MOUSEWHEEL },
+                make_long(modifiers.bits() as WORD, delta as i16 as WORD) as WPARAM,
+                point_to_lparam(position)),
+            Event::MouseLeave => (WM_MOUSELEAVE, 0, 0),
+            Event::KeyDown { key, system, flags } =>
+                (if system { WM_SYSKEYDOWN } else { WM_KEYDOWN }, key as WPARAM, flags.to_lparam()),
+            Event::KeyUp { key, system, flags } =>
+                (if system { WM_SYSKEYUP } else { WM_KEYUP }, key as WPARAM, flags.to_lparam()),
+            Event::Char { code, system, flags } =>
+                (if system { WM_SYSCHAR } else { WM_CHAR }, code as WPARAM, flags.to_lparam()),
+            Event::Size { kind, size } => {
+                let kind = match kind {
+                    SizeKind::Restored => SIZE_RESTORED,
+                    SizeKind::Minimized => SIZE_MINIMIZED,
+                    SizeKind::Maximized => SIZE_MAXIMIZED,
+                    SizeKind::MaxShow => SIZE_MAXSHOW,
+                    SizeKind::MaxHide => SIZE_MAXHIDE,
+                };
+                (WM_SIZE, kind as WPARAM, make_long(size.width as WORD, size.height as WORD) as LPARAM)
+            },
+            Event::Move { position } => (WM_MOVE, 0, point_to_lparam(position)),
+            Event::Focus { previous } => (WM_SETFOCUS, previous as WPARAM, 0),
+            Event::Blur { next } => (WM_KILLFOCUS, next as WPARAM, 0),
+            Event::Activate { activation, minimized, other } => {
+                let state = match activation {
+                    Activation::Inactive => WA_INACTIVE,
+                    Activation::Active => WA_ACTIVE,
+                    Activation::ClickActive => WA_CLICKACTIVE,
+                };
+                (WM_ACTIVATE, make_long(state, minimized as WORD) as WPARAM, other as LPARAM)
+            },
+            Event::Timer { id } => (WM_TIMER, id as WPARAM, 0),
+            Event::Command { id, code, control } =>
+                (WM_COMMAND, make_long(id, code) as WPARAM, control as LPARAM),
+            Event::Notify { id, header } => (WM_NOTIFY, id as WPARAM, header as LPARAM),
+            Event::Close => (WM_CLOSE, 0, 0),
+            Event::Destroy => (WM_DESTROY, 0, 0),
+            Event::Paint => (WM_PAINT, 0, 0),
+            Event::Other { message, w_param, l_param } => (message, w_param, l_param),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ptr;
+
+    /// An `LPARAM` from its 32 bits, sign extended like Windows does.
+    fn bits(value: u32) -> LPARAM {
+        value as i32 as LPARAM
+    }
+
+    fn key_flags(repeat_count: u16, scan_code: u8) -> KeyFlags {
+        KeyFlags { repeat_count: repeat_count, scan_code: scan_code, ..KeyFlags::default() }
+    }
+
+    fn table() -> Vec<(UINT, WPARAM, LPARAM, Event)> {
+        vec![
+            (WM_MOUSEMOVE, 0x0009, bits(0x001E_0014), Event::MouseMove {
+                position: Point::new(20, 30),
+                modifiers: MODIFIER_LEFT_BUTTON | MODIFIER_CONTROL,
+            }),
+            (WM_LBUTTONDOWN, 0x0001, bits(0xFFF6_FFFB), Event::MouseDown {
+                button: MouseButton::Left,
+                position: Point::new(-5, -10),
+                modifiers: MODIFIER_LEFT_BUTTON,
+            }),
+            (WM_RBUTTONUP, 0, 0, Event::MouseUp {
+                button: MouseButton::Right,
+                position: Point::new(0, 0),
+                modifiers: Modifiers::empty(),
+            }),
+            (WM_MBUTTONDBLCLK, 0x0010, bits(0x0002_0001), Event::DoubleClick {
+                button: MouseButton::Middle,
+                position: Point::new(1, 2),
+                modifiers: MODIFIER_MIDDLE_BUTTON,
+            }),
+            (WM_XBUTTONDOWN, 0x0002_0040, bits(0x0002_0001), Event::MouseDown {
+                button: MouseButton::X2,
+                position: Point::new(1, 2),
+                modifiers: MODIFIER_X_BUTTON2,
+            }),
+            (WM_XBUTTONUP, 0x0001_0000, bits(0x0002_0001), Event::MouseUp {
+                button: MouseButton::X1,
+                position: Point::new(1, 2),
+                modifiers: Modifiers::empty(),
+            }),
+            // Towards the user, on a screen left of and above the primary one.
+            (WM_MOUSEWHEEL, 0xFF88_0004, bits(0xFF38_FED4), Event::MouseWheel {
+                delta: -(WHEEL_DELTA as i32),
+                horizontal: false,
+                position: Point::new(-300, -200),
+                modifiers: MODIFIER_SHIFT,
+            }),
+            (WM_MOUSEHWHEEL, 0x00F0_0000, bits(0x0002_0001), Event::MouseWheel {
+                delta: 2 * WHEEL_DELTA as i32,
+                horizontal: true,
+                position: Point::new(1, 2),
+                modifiers: Modifiers::empty(),
+            }),
+            (WM_MOUSELEAVE, 0, 0, Event::MouseLeave),
+            (WM_KEYDOWN, VK_RETURN as WPARAM, bits(0x001C_0001), Event::KeyDown {
+                key: VK_RETURN as u32,
+                system: false,
+                flags: key_flags(1, 0x1C),
+            }),
+            (WM_SYSKEYDOWN, 'F' as WPARAM, bits(0x2021_0001), Event::KeyDown {
+                key: 'F' as u32,
+                system: true,
+                flags: KeyFlags { context: true, ..key_flags(1, 0x21) },
+            }),
+            (WM_KEYUP, VK_RIGHT as WPARAM, bits(0xC14D_0001), Event::KeyUp {
+                key: VK_RIGHT as u32,
+                system: false,
+                flags: KeyFlags { extended: true, previously_down: true, released: true, ..key_flags(1, 0x4D) },
+            }),
+            (WM_CHAR, 'a' as WPARAM, bits(0x001E_0003), Event::Char {
+                code: 'a' as u16,
+                system: false,
+                flags: key_flags(3, 0x1E),
+            }),
+            (WM_SIZE, SIZE_MAXIMIZED as WPARAM, bits(0x0258_0320), Event::Size {
+                kind: SizeKind::Maximized,
+                size: Size::new(800, 600),
+            }),
+            (WM_MOVE, 0, bits(0x0028_F880), Event::Move { position: Point::new(-1920, 40) }),
+            (WM_SETFOCUS, 0, 0, Event::Focus { previous: ptr::null_mut() }),
+            (WM_KILLFOCUS, 0x42, 0, Event::Blur { next: 0x42 as HWND }),
+            (WM_ACTIVATE, 0x0001_0002, 0, Event::Activate {
+                activation: Activation::ClickActive,
+                minimized: true,
+                other: ptr::null_mut(),
+            }),
+            (WM_TIMER, 7, 0, Event::Timer { id: 7 }),
+            (WM_COMMAND, 0x0300_0065, 0x1234, Event::Command { id: 0x65, code: 0x300, control: 0x1234 as HWND }),
+            (WM_CLOSE, 0, 0, Event::Close),
+            (WM_DESTROY, 0, 0, Event::Destroy),
+            (WM_PAINT, 0, 0, Event::Paint),
+            (WM_USER, 1, -1, Event::Other { message: WM_USER, w_param: 1, l_param: -1 }),
+        ]
+    }
+
+    #[test]
+    fn messages_decode_to_events() {
+        for (message, w_param, l_param, event) in table() {
+            assert_eq!(Event::decode(message, w_param, l_param), event, "message {:#x}", message);
+        }
+    }
+
+    #[test]
+    fn events_encode_to_messages() {
+        for (message, w_param, l_param, event) in table() {
+            assert_eq!(event.encode(), (message, w_param, l_param), "{:?}", event);
+            let (message, w_param, l_param) = event.encode();
+            assert_eq!(Event::decode(message, w_param, l_param), event);
+        }
+    }
+
+    #[test]
+    fn extreme_values_survive_a_round_trip() {
+        let events = [
+            Event::MouseMove { position: Point::new(-32768, 32767), modifiers: Modifiers::all() },
+            Event::MouseDown { button: MouseButton::X1, position: Point::new(32767, -32768), modifiers: Modifiers::all() },
+            Event::MouseWheel { delta: -32768, horizontal: false, position: Point::new(-1, -1), modifiers: Modifiers::empty() },
+            Event::MouseWheel { delta: 32767, horizontal: true, position: Point::new(0, -1), modifiers: MODIFIER_CONTROL },
+            Event::KeyUp {
+                key: 0xFF,
+                system: true,
+                flags: KeyFlags {
+                    repeat_count: 0xFFFF,
+                    scan_code: 0xFF,
+                    extended: true,
+                    context: true,
+                    previously_down: true,
+                    released: true,
+                },
+            },
+            Event::Size { kind: SizeKind::MaxHide, size: Size::new(65535, 65535) },
+            Event::Char { code: 0xD83D, system: false, flags: KeyFlags::default() },
+        ];
+        for event in events.iter() {
+            let (message, w_param, l_param) = event.encode();
+            assert_eq!(Event::decode(message, w_param, l_param), *event);
+        }
+    }
+
+    #[test]
+    fn decoding_ignores_unknown_modifier_bits() {
+        let event = Event::decode(WM_MOUSEMOVE, 0xFF80 | 0x0004, 0);
+        assert_eq!(event, Event::MouseMove { position: Point::new(0, 0), modifiers: MODIFIER_SHIFT });
+    }
+
+    #[test]
+    fn relative_positions_leave_the_wheel_on_screen() {
+        let origin = Point::new(10, 20);
+        let down = Event::MouseDown { button: MouseButton::Left, position: Point::new(5, 5), modifiers: Modifiers::empty() };
+        assert_eq!(down.relative_to(origin),
+                   Event::MouseDown { button: MouseButton::Left, position: Point::new(-5, -15), modifiers: Modifiers::empty() });
+        let wheel = Event::MouseWheel { delta: 120, horizontal: false, position: Point::new(5, 5), modifiers: Modifiers::empty() };
+        assert_eq!(wheel.relative_to(origin), wheel);
+    }
+}
@@ -0,0 +1,272 @@
+//! Running the message loop.
+//!
+//! An `EventLoop` pulls messages out of an `EventSource` and has them
+//! delivered until somebody posts a quit message. The backend of the calling
+//! thread is the default source, so the same loop drives real windows and
+//! headless ones.
+
+use ::backend::{ self, Backend };
+
+use std::mem;
+use std::rc::Rc;
+
+/// What a single step of an event source did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// A message was delivered.
+    Dispatched,
+    /// There was nothing to deliver.
+    Empty,
+    /// The quit message arrived, with this exit code.
+    Quit(i32),
+}
+
+/// Somewhere messages come from.
+pub trait EventSource {
+    /// Delivers the next message. With `wait` set this blocks until there
+    /// is one, otherwise it returns `Empty` straight away.
+    fn dispatch_next(&self, wait: bool) -> Dispatch;
+}
+
+impl EventSource for Rc<Backend> {
+    fn dispatch_next(&self, wait: bool) -> Dispatch {
+        (**self).dispatch_next(wait)
+    }
+}
+
+/// Delivers messages until the application quits.
+///
+/// Idle callbacks run whenever the source runs dry. One returning `true`
+/// wants to be called again and keeps the loop from blocking in the
+/// meantime; one returning `false` is done and gets dropped.
+pub struct EventLoop {
+    source: Box<EventSource>,
+    idle: Vec<Box<FnMut() -> bool>>,
+    exit_code: Option<i32>,
+}
+
+impl EventLoop {
+    /// A loop over the messages of the calling thread's backend.
+    pub fn new() -> Self {
+        EventLoop::with_source(Box::new(backend::current()))
+    }
+
+    pub fn with_source(source: Box<EventSource>) -> Self {
+        EventLoop {
+            source: source,
+            idle: Vec::new(),
+            exit_code: None,
+        }
+    }
+
+    pub fn add_idle<F: FnMut() -> bool + 'static>(&mut self, callback: F) -> &mut Self {
+        self.idle.push(Box::new(callback));
+        self
+    }
+
+    /// Asks the loop of the calling thread to stop with `code`, once the
+    /// messages before it are delivered.
+    pub fn quit(code: i32) {
+        backend::current().post_quit_message(code);
+    }
+
+    /// The exit code the loop stopped with, if it did.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Runs until the quit message arrives and returns its exit code.
+    pub fn run(&mut self) -> i32 {
+        loop {
+            if let Some(code) = self.step() {
+                return code;
+            }
+        }
+    }
+
+    /// Runs until `done` returns `true`, which is checked before every
+    /// message. Returns the exit code if the quit message came first.
+    pub fn run_until<F: FnMut() -> bool>(&mut self, mut done: F) -> Option<i32> {
+        while !done() {
+            if let Some(code) = self.step() {
+                return Some(code);
+            }
+        }
+        None
+    }
+
+    /// Delivers whatever is there without waiting for more, then gives the
+    /// idle callbacks a turn. Returns the exit code if the quit message was
+    /// among the messages.
+    pub fn pump_pending(&mut self) -> Option<i32> {
+        loop {
+            match self.source.dispatch_next(false) {
+                Dispatch::Dispatched => { },
+                Dispatch::Empty => {
+                    self.run_idle();
+                    return None;
+                },
+                Dispatch::Quit(code) => return Some(self.stop(code)),
+            }
+        }
+    }
+
+    /// Delivers one message, if need be after running the idle callbacks
+    /// and waiting for it.
+    fn step(&mut self) -> Option<i32> {
+        match self.source.dispatch_next(false) {
+            Dispatch::Dispatched => None,
+            Dispatch::Quit(code) => Some(self.stop(code)),
+            Dispatch::Empty => {
+                let again = self.run_idle();
+                match self.source.dispatch_next(!again) {
+                    Dispatch::Quit(code) => Some(self.stop(code)),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// Calls every idle callback once, returning whether any of them wants
+    /// to be called again.
+    fn run_idle(&mut self) -> bool {
+        for mut callback in mem::replace(&mut self.idle, Vec::new()) {
+            if callback() {
+                self.idle.push(callback);
+            }
+        }
+        !self.idle.is_empty()
+    }
+
+    fn stop(&mut self, code: i32) -> i32 {
+        self.exit_code = Some(code);
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::message::{ self, MessageHandler, MessageHandlerBase };
+    use ::testing::{ self, open_window };
+    use ::window::WindowCore;
+
+    use std::cell::{ Cell, RefCell };
+    use std::rc::Rc;
+
+    struct Ping(usize);
+
+    thread_local!(static PINGS: RefCell<Vec<usize>> = RefCell::new(Vec::new()));
+
+    impl MessageHandler<Ping> for WindowCore {
+        fn handle_message(&mut self, msg: &Ping) {
+            PINGS.with(|p| p.borrow_mut().push(msg.0));
+        }
+    }
+
+    fn pings() -> Vec<usize> {
+        PINGS.with(|p| p.borrow().clone())
+    }
+
+    /// Counts its calls, wanting to be called again while `again` is set.
+    fn counter(again: bool) -> (Rc<Cell<usize>>, Box<FnMut() -> bool>) {
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        (calls, Box::new(move || {
+            counted.set(counted.get() + 1);
+            again
+        }))
+    }
+
+    #[test]
+    fn quitting_waits_for_the_messages_posted_before() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Ping>();
+        message::post_message(handle, Ping(1)).unwrap();
+        message::post_message(handle, Ping(2)).unwrap();
+        EventLoop::quit(3);
+        assert_eq!(backend.pending_messages(), 1);
+
+        let mut event_loop = EventLoop::new();
+        assert_eq!(event_loop.run(), 3);
+        assert_eq!(event_loop.exit_code(), Some(3));
+        assert_eq!(pings(), vec![1, 2]);
+        assert_eq!(backend.pending_messages(), 0);
+    }
+
+    #[test]
+    fn idle_callbacks_run_once_the_queue_is_empty() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Ping>();
+        let (once, callback) = counter(false);
+        let repeated = Rc::new(Cell::new(0));
+        let mut event_loop = EventLoop::new();
+        event_loop.add_idle(callback);
+        {
+            let repeated = repeated.clone();
+            let backend = backend.clone();
+            event_loop.add_idle(move || {
+                // Nothing is left to deliver by the time this runs.
+                assert_eq!(backend.pending_messages(), 0);
+                repeated.set(repeated.get() + 1);
+                if repeated.get() == 3 {
+                    EventLoop::quit(7);
+                }
+                true
+            });
+        }
+        message::post_message(handle, Ping(1)).unwrap();
+
+        assert_eq!(event_loop.run(), 7);
+        assert_eq!(pings(), vec![1]);
+        // Done after the first call, the other one kept going until it quit.
+        assert_eq!(once.get(), 1);
+        assert_eq!(repeated.get(), 3);
+        assert!(backend.invalid_region(handle).is_empty());
+    }
+
+    #[test]
+    fn run_until_stops_when_done() {
+        let (backend, _handle) = open_window(100, 100);
+        let (calls, callback) = counter(true);
+        let mut event_loop = EventLoop::new();
+        event_loop.add_idle(callback);
+
+        let counted = calls.clone();
+        assert_eq!(event_loop.run_until(move || counted.get() >= 2), None);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(event_loop.exit_code(), None);
+        assert_eq!(backend.quit_code(), None);
+    }
+
+    #[test]
+    fn run_until_returns_the_exit_code_of_an_earlier_quit() {
+        let (_backend, _handle) = open_window(100, 100);
+        EventLoop::quit(5);
+        let mut event_loop = EventLoop::new();
+        assert_eq!(event_loop.run_until(|| false), Some(5));
+        assert_eq!(event_loop.exit_code(), Some(5));
+    }
+
+    #[test]
+    fn pumping_delivers_what_is_there_without_waiting() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Ping>();
+        let (calls, callback) = counter(false);
+        let mut event_loop = EventLoop::new();
+        event_loop.add_idle(callback);
+
+        message::post_message(handle, Ping(1)).unwrap();
+        assert_eq!(event_loop.pump_pending(), None);
+        assert_eq!(pings(), vec![1]);
+        assert!(backend.invalid_region(handle).is_empty());
+        assert_eq!(event_loop.pump_pending(), None);
+        assert_eq!(calls.get(), 1);
+
+        message::post_message(handle, Ping(2)).unwrap();
+        EventLoop::quit(2);
+        assert_eq!(event_loop.pump_pending(), Some(2));
+        assert_eq!(pings(), vec![1, 2]);
+        assert_eq!(event_loop.exit_code(), Some(2));
+    }
+}
@@ -1,9 +1,10 @@
 use winapi::*;
-use gdi32::*;
-use kernel32::*;
-use wio::wide::*;
+
+use ::backend::{ self, Backend };
+use ::geometry::Size;
 
 use std::ops::Deref;
+use std::rc::Rc;
 
 #[derive(Clone, Debug)]
 pub enum FontWeight {
@@ -48,6 +49,7 @@ impl FontWeight {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum CharSet {
     Ansi,
     Baltic,
@@ -96,6 +98,7 @@ impl CharSet {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum OutputPrecision {
     Character,
     Default,
@@ -126,6 +129,7 @@ impl OutputPrecision {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum ClipPrecision {
     Character,
     Default,
@@ -156,6 +160,7 @@ impl ClipPrecision {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum FontQuality {
     AntiAliased,
     ClearType,
@@ -178,6 +183,7 @@ impl FontQuality {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum FontPitch {
     Decorative,
     DontCare,
@@ -204,6 +210,7 @@ impl FontPitch {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct FontBuilder {
     height: i32,
     width: i32,
@@ -242,36 +249,32 @@ impl FontBuilder {
     }
 
     pub fn build(&self) -> Result<Font, DWORD> {
-        unsafe {
-            let mut face_wide = self.face.to_wide_null();
-            let italic = if self.italic { TRUE as DWORD } else { FALSE as DWORD };
-            let underline = if self.underline { TRUE as DWORD } else { FALSE as DWORD };
-            let strikeout = if self.strikeout { TRUE as DWORD } else { FALSE as DWORD };
-
-            let handle = CreateFontW(
-                self.height,
-                self.width,
-                self.escapement,
-                self.orientation,
-                self.weight.to_int(),
-                italic,
-                underline,
-                strikeout,
-                self.charset.to_uint(),
-                self.output_precision.to_uint(),
-                self.clip_precision.to_uint(),
-                self.quality.to_uint(),
-                self.pitch.to_uint(),
-                face_wide.as_mut_ptr());
-
-            if handle.is_null() {
-                Err(GetLastError())
-            } else {
-                Ok(Font::new(handle))
-            }
-        }
+        let backend = backend::current();
+        let handle = try!(backend.create_font(self));
+        Ok(Font::new(handle, self.height, self.width, backend))
     }
 
+    /// Builds the font just to measure `text` with it.
+    pub fn text_extent(&self, text: &str) -> Result<Size, DWORD> {
+        let font = try!(self.build());
+        Ok(font.text_extent(text))
+    }
+
+    pub fn height(&self) -> i32 { self.height }
+    pub fn width(&self) -> i32 { self.width }
+    pub fn escapement(&self) -> i32 { self.escapement }
+    pub fn orientation(&self) -> i32 { self.orientation }
+    pub fn weight(&self) -> &FontWeight { &self.weight }
+    pub fn italic(&self) -> bool { self.italic }
+    pub fn underline(&self) -> bool { self.underline }
+    pub fn strikeout(&self) -> bool { self.strikeout }
+    pub fn charset(&self) -> &CharSet { &self.charset }
+    pub fn output_precision(&self) -> &OutputPrecision { &self.output_precision }
+    pub fn clip_precision(&self) -> &ClipPrecision { &self.clip_precision }
+    pub fn quality(&self) -> &FontQuality { &self.quality }
+    pub fn pitch(&self) -> &FontPitch { &self.pitch }
+    pub fn face(&self) -> &str { &self.face }
+
     pub fn set_height(&mut self, height: i32) -> &mut Self {
         self.height = height;
         self
@@ -343,11 +346,34 @@ impl FontBuilder {
     }
 }
 
-pub struct Font(HFONT);
+/// A font created by a backend, released through it again on drop.
+pub struct Font {
+    handle: HFONT,
+    height: i32,
+    width: i32,
+    backend: Rc<Backend>,
+}
 
 impl Font {
-    pub fn new(handle: HFONT) -> Self {
-        Font(handle)
+    pub fn new(handle: HFONT, height: i32, width: i32, backend: Rc<Backend>) -> Self {
+        Font {
+            handle: handle,
+            height: height,
+            width: width,
+            backend: backend,
+        }
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn text_extent(&self, text: &str) -> Size {
+        self.backend.text_extent(self, text)
     }
 }
 
@@ -355,16 +381,12 @@ impl Deref for Font {
     type Target = HFONT;
 
     fn deref<'a>(&'a self) -> &'a Self::Target {
-        let &Font(ref handle) = self;
-        handle
+        &self.handle
     }
 }
 
 impl Drop for Font {
     fn drop(&mut self) {
-        let &mut Font(ref handle) = self;
-        unsafe {
-            DeleteObject(*handle as *mut c_void);
-        }
+        self.backend.delete_font(self.handle);
     }
 }
@@ -0,0 +1,329 @@
+//! Points, sizes and rectangles in signed client coordinates.
+//!
+//! `Rect` follows the `RECT` convention: `right` and `bottom` are exclusive,
+//! so a rectangle with `right <= left` or `bottom <= top` is empty.
+
+use winapi::{ POINT, RECT };
+
+use std::cmp::{ max, min };
+use std::ops::{ Add, Sub };
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Self {
+        Point { x: x, y: y }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Self {
+        Point::new(self.x + dx, self.y + dy)
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl From<POINT> for Point {
+    fn from(p: POINT) -> Self {
+        Point::new(p.x, p.y)
+    }
+}
+
+impl From<Point> for POINT {
+    fn from(p: Point) -> Self {
+        POINT { x: p.x, y: p.y }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Size {
+    pub fn new(width: i32, height: i32) -> Self {
+        Size { width: width, height: height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Rect { left: left, top: top, right: right, bottom: bottom }
+    }
+
+    pub fn from_origin_size(origin: Point, size: Size) -> Self {
+        Rect::new(origin.x, origin.y, origin.x + size.width, origin.y + size.height)
+    }
+
+    pub fn origin(&self) -> Point {
+        Point::new(self.left, self.top)
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+
+    /// Saturates at `i32::MAX` for rectangles wider than that.
+    pub fn width(&self) -> i32 {
+        self.right.saturating_sub(self.left)
+    }
+
+    /// Saturates at `i32::MAX` for rectangles taller than that.
+    pub fn height(&self) -> i32 {
+        self.bottom.saturating_sub(self.top)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.right <= self.left || self.bottom <= self.top
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.left && p.x < self.right && p.y >= self.top && p.y < self.bottom
+    }
+
+    /// Whether `other` lies completely within `self`. Empty rectangles are
+    /// contained in everything.
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.is_empty() || (!self.is_empty()
+            && other.left >= self.left && other.right <= self.right
+            && other.top >= self.top && other.bottom <= self.bottom)
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The overlapping area, or `None` if the rectangles do not overlap.
+    /// Rectangles that merely touch do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let r = Rect::new(
+            max(self.left, other.left),
+            max(self.top, other.top),
+            min(self.right, other.right),
+            min(self.bottom, other.bottom));
+        if r.is_empty() { None } else { Some(r) }
+    }
+
+    /// The smallest rectangle covering both. Empty rectangles are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Rect::new(
+            min(self.left, other.left),
+            min(self.top, other.top),
+            max(self.right, other.right),
+            max(self.bottom, other.bottom))
+    }
+
+    /// Shrinks the rectangle by `dx` on the left and right and `dy` on the
+    /// top and bottom; negative values grow it.
+    pub fn inset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(self.left + dx, self.top + dy, self.right - dx, self.bottom - dy)
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(self.left + dx, self.top + dy, self.right + dx, self.bottom + dy)
+    }
+}
+
+impl From<RECT> for Rect {
+    fn from(r: RECT) -> Self {
+        Rect::new(r.left, r.top, r.right, r.bottom)
+    }
+}
+
+impl From<Rect> for RECT {
+    fn from(r: Rect) -> Self {
+        RECT { left: r.left, top: r.top, right: r.right, bottom: r.bottom }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winapi::RECT;
+
+    use std::i32;
+
+    /// Coordinates worth trying: around zero, and at the ends of `i32`.
+    const EDGES: [i32; 9] = [i32::MIN, i32::MIN + 1, -100, -1, 0, 1, 100, i32::MAX - 1, i32::MAX];
+
+    /// A xorshift generator, so the random cases are the same on every run.
+    struct Cases(u32);
+
+    impl Cases {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        /// Mostly small coordinates, so rectangles overlap often, with the
+        /// edge values mixed in.
+        fn coordinate(&mut self) -> i32 {
+            match self.next() % 8 {
+                0 => EDGES[(self.next() % EDGES.len() as u32) as usize],
+                _ => (self.next() % 41) as i32 - 20,
+            }
+        }
+
+        fn rect(&mut self) -> Rect {
+            Rect::new(self.coordinate(), self.coordinate(), self.coordinate(), self.coordinate())
+        }
+    }
+
+    fn rects() -> Vec<Rect> {
+        let mut cases = Cases(0x2545_F491);
+        let mut rects = (0..500).map(|_| cases.rect()).collect::<Vec<_>>();
+        rects.push(Rect::default());
+        rects.push(Rect::new(i32::MIN, i32::MIN, i32::MAX, i32::MAX));
+        rects.push(Rect::new(5, 5, -5, -5));
+        rects
+    }
+
+    #[test]
+    fn empty_rects() {
+        assert!(Rect::default().is_empty());
+        assert!(Rect::new(0, 0, 0, 10).is_empty());
+        assert!(Rect::new(0, 0, 10, 0).is_empty());
+        assert!(Rect::new(10, 10, 0, 20).is_empty());
+        assert!(!Rect::new(-10, -10, -9, -9).is_empty());
+        assert!(!Rect::new(5, 5, 5, 5).contains(Point::new(5, 5)));
+        assert!(Size::new(-1, 10).is_empty());
+    }
+
+    #[test]
+    fn sizes_of_huge_rects_saturate() {
+        let everything = Rect::new(i32::MIN, i32::MIN, i32::MAX, i32::MAX);
+        assert_eq!(everything.size(), Size::new(i32::MAX, i32::MAX));
+        assert!(!everything.is_empty());
+        assert!(everything.contains(Point::new(i32::MIN, i32::MAX - 1)));
+        assert!(!everything.contains(Point::new(0, i32::MAX)));
+        assert_eq!(Rect::new(-3, -4, 7, 1).size(), Size::new(10, 5));
+        assert_eq!(Rect::new(10, 10, 0, 0).size(), Size::new(-10, -10));
+    }
+
+    #[test]
+    fn intersection_is_symmetric_and_inside_both() {
+        let rects = rects();
+        for a in &rects {
+            for b in rects.iter().take(60) {
+                let ab = a.intersection(b);
+                assert_eq!(ab, b.intersection(a), "{:?} {:?}", a, b);
+                assert_eq!(ab.is_some(), a.intersects(b));
+                if let Some(r) = ab {
+                    assert!(!r.is_empty());
+                    assert!(a.contains_rect(&r) && b.contains_rect(&r), "{:?} {:?} -> {:?}", a, b, r);
+                    assert!(a.contains(r.origin()) && b.contains(r.origin()));
+                }
+            }
+            assert_eq!(a.intersection(a), if a.is_empty() { None } else { Some(*a) });
+            assert_eq!(a.intersection(&Rect::default()), None);
+        }
+    }
+
+    #[test]
+    fn union_is_symmetric_and_covers_both() {
+        let rects = rects();
+        for a in &rects {
+            for b in rects.iter().take(60) {
+                let ab = a.union(b);
+                if !a.is_empty() && !b.is_empty() {
+                    assert_eq!(ab, b.union(a));
+                }
+                assert!(ab.contains_rect(a) && ab.contains_rect(b), "{:?} {:?} -> {:?}", a, b, ab);
+                if let Some(r) = a.intersection(b) {
+                    assert!(ab.contains_rect(&r));
+                }
+            }
+            assert_eq!(a.union(a), *a);
+            if !a.is_empty() {
+                assert_eq!(a.union(&Rect::default()), *a);
+                assert_eq!(Rect::default().union(a), *a);
+            }
+        }
+    }
+
+    #[test]
+    fn points_in_the_intersection_are_in_both() {
+        let rects = rects();
+        let mut cases = Cases(7);
+        for pair in rects.chunks(2) {
+            if pair.len() < 2 {
+                continue;
+            }
+            let (a, b) = (pair[0], pair[1]);
+            for _ in 0..20 {
+                let p = Point::new(cases.coordinate(), cases.coordinate());
+                let inside = a.intersection(&b).map_or(false, |r| r.contains(p));
+                assert_eq!(inside, a.contains(p) && b.contains(p), "{:?} {:?} {:?}", a, b, p);
+            }
+        }
+    }
+
+    #[test]
+    fn touching_rects_do_not_intersect() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert!(!a.intersects(&Rect::new(10, 0, 20, 10)));
+        assert!(!a.intersects(&Rect::new(0, 10, 10, 20)));
+        assert!(a.intersects(&Rect::new(9, 9, 20, 20)));
+        assert_eq!(a.union(&Rect::new(10, 0, 20, 10)), Rect::new(0, 0, 20, 10));
+    }
+
+    #[test]
+    fn inset_and_offset() {
+        let r = Rect::new(-5, 0, 5, 20);
+        assert_eq!(r.inset(2, 3), Rect::new(-3, 3, 3, 17));
+        assert_eq!(r.inset(-2, -3), Rect::new(-7, -3, 7, 23));
+        assert!(r.inset(5, 0).is_empty());
+        assert_eq!(r.offset(-10, 4), Rect::new(-15, 4, -5, 24));
+        assert_eq!(r.offset(3, 3).size(), r.size());
+        assert_eq!(Rect::from_origin_size(Point::new(-4, 2), Size::new(3, 5)), Rect::new(-4, 2, -1, 7));
+    }
+
+    #[test]
+    fn rects_and_points_survive_the_win32_types() {
+        for r in rects() {
+            let raw: RECT = r.into();
+            assert_eq!((raw.left, raw.top, raw.right, raw.bottom), (r.left, r.top, r.right, r.bottom));
+            assert_eq!(Rect::from(raw), r);
+            let p: ::winapi::POINT = r.origin().into();
+            assert_eq!(Point::from(p), r.origin());
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use ::geometry::{ Rect, Size };
+
+use super::{ Layout, Panel, grow, shrink };
+
+use std::cmp::{ max, min };
+
+/// The edge of a dock panel a child sticks to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dock {
+    Left,
+    Top,
+    Right,
+    Bottom,
+}
+
+/// Stacks children against the edges of the panel in order, each taking
+/// the space it asks for from what the ones before it left over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DockPanel {
+    /// Whether the last child gets all the remaining space, regardless of
+    /// its dock side.
+    pub last_child_fill: bool,
+}
+
+impl DockPanel {
+    pub fn new() -> Self {
+        DockPanel {
+            last_child_fill: true,
+        }
+    }
+
+    pub fn set_last_child_fill(&mut self, fill: bool) -> &mut Self {
+        self.last_child_fill = fill;
+        self
+    }
+}
+
+impl Panel for DockPanel {
+    fn measure(&self, children: &[&Layout], available: Size,
+               measure_child: &mut FnMut(usize, Size) -> Size) -> Size {
+        // The space taken up by the children docked so far, and the size
+        // needed across them.
+        let mut used = Size::default();
+        let mut needed = Size::default();
+        for (i, child) in children.iter().enumerate() {
+            let remaining = Size::new(shrink(available.width, used.width), shrink(available.height, used.height));
+            let desired = measure_child(i, remaining);
+            match child.dock {
+                Dock::Left | Dock::Right => {
+                    needed.height = max(needed.height, grow(used.height, desired.height));
+                    used.width = grow(used.width, desired.width);
+                },
+                Dock::Top | Dock::Bottom => {
+                    needed.width = max(needed.width, grow(used.width, desired.width));
+                    used.height = grow(used.height, desired.height);
+                },
+            }
+        }
+        Size::new(max(needed.width, used.width), max(needed.height, used.height))
+    }
+
+    fn arrange(&self, children: &[&Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let mut remaining = content;
+        let mut slots = Vec::with_capacity(children.len());
+        for (i, (child, d)) in children.iter().zip(desired).enumerate() {
+            if self.last_child_fill && i + 1 == children.len() {
+                slots.push(remaining);
+                break;
+            }
+
+            let width = min(d.width, remaining.width());
+            let height = min(d.height, remaining.height());
+            let r = remaining;
+            slots.push(match child.dock {
+                Dock::Left => {
+                    remaining.left += width;
+                    Rect::new(r.left, r.top, r.left + width, r.bottom)
+                },
+                Dock::Right => {
+                    remaining.right -= width;
+                    Rect::new(r.right - width, r.top, r.right, r.bottom)
+                },
+                Dock::Top => {
+                    remaining.top += height;
+                    Rect::new(r.left, r.top, r.right, r.top + height)
+                },
+                Dock::Bottom => {
+                    remaining.bottom -= height;
+                    Rect::new(r.left, r.bottom - height, r.right, r.bottom)
+                },
+            });
+        }
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn docked(sides: &[Dock]) -> Vec<Layout> {
+        sides.iter().map(|&side| {
+            let mut layout = Layout::new();
+            layout.set_dock(side);
+            layout
+        }).collect()
+    }
+
+    const SIDES: [Dock; 4] = [Dock::Top, Dock::Left, Dock::Right, Dock::Bottom];
+    const DESIRED: [Size; 4] = [Size { width: 10, height: 20 }, Size { width: 30, height: 10 },
+                                Size { width: 15, height: 10 }, Size { width: 5, height: 5 }];
+
+    #[test]
+    fn children_take_space_from_the_edges_in_order() {
+        let layouts = docked(&SIDES);
+        let children = layouts.iter().collect::<Vec<_>>();
+        let content = Rect::new(0, 0, 100, 100);
+        let mut panel = DockPanel::new();
+        assert_eq!(panel.arrange(&children, &DESIRED, content), vec![
+            Rect::new(0, 0, 100, 20),
+            Rect::new(0, 20, 30, 100),
+            Rect::new(85, 20, 100, 100),
+            // The last child fills what is left, whatever its side.
+            Rect::new(30, 20, 85, 100),
+        ]);
+
+        panel.set_last_child_fill(false);
+        assert_eq!(panel.arrange(&children, &DESIRED, content)[3], Rect::new(30, 95, 85, 100));
+    }
+
+    #[test]
+    fn measuring_adds_up_the_docked_children() {
+        let layouts = docked(&SIDES);
+        let children = layouts.iter().collect::<Vec<_>>();
+        let mut given = Vec::new();
+        let size = DockPanel::new().measure(&children, Size::new(100, 100), &mut |i, available| {
+            given.push(available);
+            DESIRED[i]
+        });
+        assert_eq!(given, vec![Size::new(100, 100), Size::new(100, 80), Size::new(70, 80), Size::new(55, 80)]);
+        assert_eq!(size, Size::new(50, 30));
+    }
+
+    #[test]
+    fn children_get_no_more_than_is_left() {
+        let layouts = docked(&[Dock::Left, Dock::Left, Dock::Top]);
+        let children = layouts.iter().collect::<Vec<_>>();
+        let desired = [Size::new(60, 10), Size::new(60, 10), Size::new(10, 10)];
+        assert_eq!(DockPanel::new().arrange(&children, &desired, Rect::new(0, 0, 100, 50)),
+                   vec![Rect::new(0, 0, 60, 50), Rect::new(60, 0, 100, 50), Rect::new(100, 0, 100, 50)]);
+    }
+}
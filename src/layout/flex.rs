@@ -0,0 +1,509 @@
+use ::geometry::{ Rect, Size };
+
+use super::{ Alignment, Layout, Orientation, Panel, UNBOUNDED, grow };
+
+use std::cmp::{ max, min };
+
+/// How the free space along a flex line is spread around its items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Justify {
+    Start,
+    End,
+    Center,
+    /// Between the items, none at the ends.
+    SpaceBetween,
+    /// Around every item, so the ends get half as much as the gaps.
+    SpaceAround,
+    /// Evenly between the items and the ends.
+    SpaceEvenly,
+}
+
+/// Lays children out in lines the way CSS flexbox does.
+///
+/// Items start out at their `Layout::basis`, or the size they were measured
+/// at, and then grow or shrink along the line by their flex factors to fill
+/// it, within their min/max size. With `wrap` set, items that don't fit
+/// start a new line. Lines are as thick as their thickest item, with any
+/// remaining space shared between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlexPanel {
+    pub direction: Orientation,
+    pub wrap: bool,
+    pub justify_content: Justify,
+    /// Where items go across their line. Only `Stretch` makes them as thick
+    /// as the line.
+    pub align_items: Alignment,
+    /// Space between neighbouring items and between lines.
+    pub gap: i32,
+}
+
+impl FlexPanel {
+    pub fn new(direction: Orientation) -> Self {
+        FlexPanel {
+            direction: direction,
+            wrap: false,
+            justify_content: Justify::Start,
+            align_items: Alignment::Stretch,
+            gap: 0,
+        }
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) -> &mut Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn set_justify_content(&mut self, justify: Justify) -> &mut Self {
+        self.justify_content = justify;
+        self
+    }
+
+    pub fn set_align_items(&mut self, alignment: Alignment) -> &mut Self {
+        self.align_items = alignment;
+        self
+    }
+
+    pub fn set_gap(&mut self, gap: i32) -> &mut Self {
+        self.gap = gap;
+        self
+    }
+
+    fn main(&self, size: Size) -> i32 {
+        match self.direction {
+            Orientation::Horizontal => size.width,
+            Orientation::Vertical => size.height,
+        }
+    }
+
+    fn cross(&self, size: Size) -> i32 {
+        match self.direction {
+            Orientation::Horizontal => size.height,
+            Orientation::Vertical => size.width,
+        }
+    }
+
+    fn size(&self, main: i32, cross: i32) -> Size {
+        match self.direction {
+            Orientation::Horizontal => Size::new(main, cross),
+            Orientation::Vertical => Size::new(cross, main),
+        }
+    }
+
+    fn item(&self, layout: &Layout, measured: i32) -> Item {
+        let (min_size, max_size) = layout.limits();
+        let margin = match self.direction {
+            Orientation::Horizontal => layout.margin.horizontal(),
+            Orientation::Vertical => layout.margin.vertical(),
+        };
+        let min_main = grow(self.main(min_size), margin);
+        let max_main = grow(self.main(max_size), margin);
+        let base = match layout.basis {
+            Some(basis) => grow(max(0, basis), margin),
+            None => measured,
+        };
+        Item {
+            base: max(min(base, max_main), min_main),
+            min: min_main,
+            max: max_main,
+            grow: layout.grow.max(0.0),
+            shrink: layout.shrink.max(0.0),
+        }
+    }
+
+    /// Splits the items into lines, as ranges of indices.
+    fn lines(&self, items: &[Item], available: i32) -> Vec<(usize, usize)> {
+        if !self.wrap || available == UNBOUNDED {
+            return vec![(0, items.len())];
+        }
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut used = 0;
+        for (i, item) in items.iter().enumerate() {
+            if i > start && grow(grow(used, self.gap), item.base) > available {
+                lines.push((start, i));
+                start = i;
+                used = 0;
+            }
+            used = if i == start { item.base } else { grow(grow(used, self.gap), item.base) };
+        }
+        lines.push((start, items.len()));
+        lines
+    }
+
+    fn gaps(&self, count: usize) -> i32 {
+        if count == 0 { 0 } else { self.gap.saturating_mul(count as i32 - 1) }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Item {
+    /// The hypothetical main size, margin included.
+    base: i32,
+    min: i32,
+    max: i32,
+    grow: f32,
+    shrink: f32,
+}
+
+/// Grows or shrinks the items of a line to fill `space`, following the
+/// "resolve flexible lengths" steps of the CSS spec: distribute the free
+/// space by flex factor, clamp to min/max, freeze the items that got
+/// clamped and start over with the rest.
+fn resolve(items: &[Item], space: i32) -> Vec<i32> {
+    if space == UNBOUNDED {
+        return items.iter().map(|i| i.base).collect();
+    }
+
+    let used = items.iter().fold(0i64, |acc, i| acc + i.base as i64);
+    let growing = used < space as i64;
+    let mut sizes = items.iter().map(|i| i.base as f32).collect::<Vec<_>>();
+    let mut frozen = items.iter().map(|i| {
+        let factor = if growing { i.grow } else { i.shrink };
+        factor == 0.0 || (growing && i.base >= i.max) || (!growing && i.base <= i.min)
+    }).collect::<Vec<_>>();
+    let initial_free = space as f32 - used as f32;
+
+    while frozen.iter().any(|&f| !f) {
+        let taken = (0..items.len()).fold(0.0f32, |acc, i| {
+            acc + if frozen[i] { sizes[i] } else { items[i].base as f32 }
+        });
+        let mut free = space as f32 - taken;
+        let factors = (0..items.len()).filter(|&i| !frozen[i])
+            .fold(0.0f32, |acc, i| acc + if growing { items[i].grow } else { items[i].shrink });
+        // Factors adding up to less than one only hand out that fraction.
+        if factors < 1.0 {
+            let limited = initial_free * factors;
+            if limited.abs() < free.abs() {
+                free = limited;
+            }
+        }
+
+        let scaled_total = (0..items.len()).filter(|&i| !frozen[i])
+            .fold(0.0f32, |acc, i| acc + items[i].shrink * items[i].base as f32);
+        let mut violation = 0.0f32;
+        let mut clamped = vec![0i8; items.len()];
+        for i in 0..items.len() {
+            if frozen[i] {
+                continue;
+            }
+            let item = &items[i];
+            let target = if growing {
+                item.base as f32 + free * item.grow / factors
+            } else if scaled_total > 0.0 {
+                item.base as f32 + free * item.shrink * item.base as f32 / scaled_total
+            } else {
+                item.base as f32
+            };
+            let limited = target.max(item.min as f32).min(item.max as f32).max(0.0);
+            violation += limited - target;
+            clamped[i] = if limited > target { 1 } else if limited < target { -1 } else { 0 };
+            sizes[i] = limited;
+        }
+
+        for i in 0..items.len() {
+            if frozen[i] {
+                continue;
+            }
+            frozen[i] = if violation == 0.0 {
+                true
+            } else if violation > 0.0 {
+                clamped[i] > 0
+            } else {
+                clamped[i] < 0
+            };
+        }
+    }
+    round(&sizes)
+}
+
+/// Rounds sizes so that their sum is the rounded sum of the originals.
+fn round(sizes: &[f32]) -> Vec<i32> {
+    let mut total = 0.0f32;
+    let mut previous = 0;
+    sizes.iter().map(|&s| {
+        total += s;
+        let edge = total.round() as i32;
+        let size = edge - previous;
+        previous = edge;
+        size
+    }).collect()
+}
+
+/// Where the items of a line start and how much space goes between them.
+fn justify(justify: Justify, free: i32, count: usize) -> (i32, i32) {
+    let count = count as i32;
+    if free < 0 {
+        return match justify {
+            Justify::End => (free, 0),
+            Justify::Center => (free / 2, 0),
+            _ => (0, 0),
+        };
+    }
+    match justify {
+        Justify::Start => (0, 0),
+        Justify::End => (free, 0),
+        Justify::Center => (free / 2, 0),
+        Justify::SpaceBetween if count > 1 => (0, free / (count - 1)),
+        Justify::SpaceBetween => (0, 0),
+        Justify::SpaceAround if count > 0 => (free / count / 2, free / count),
+        Justify::SpaceAround => (0, 0),
+        Justify::SpaceEvenly => (free / (count + 1), free / (count + 1)),
+    }
+}
+
+impl Panel for FlexPanel {
+    fn measure(&self, children: &[&Layout], available: Size,
+               measure_child: &mut FnMut(usize, Size) -> Size) -> Size {
+        let main_space = self.main(available);
+        let cross_space = self.cross(available);
+        let items = children.iter().enumerate().map(|(i, layout)| {
+            let measured = measure_child(i, self.size(UNBOUNDED, cross_space));
+            self.item(layout, self.main(measured))
+        }).collect::<Vec<_>>();
+
+        let mut longest = 0;
+        let mut thickness = 0;
+        let lines = self.lines(&items, main_space);
+        for (n, &(start, end)) in lines.iter().enumerate() {
+            let gaps = self.gaps(end - start);
+            let space = if main_space == UNBOUNDED { UNBOUNDED } else { max(0, main_space - gaps) };
+            let sizes = resolve(&items[start..end], space);
+
+            // Measure again at the final size, text might wrap differently.
+            let mut line_thickness = 0;
+            for (i, &size) in (start..end).zip(sizes.iter()) {
+                let desired = measure_child(i, self.size(size, cross_space));
+                line_thickness = max(line_thickness, self.cross(desired));
+            }
+            longest = max(longest, sizes.iter().fold(gaps, |acc, &s| grow(acc, s)));
+            if n > 0 {
+                thickness = grow(thickness, self.gap);
+            }
+            thickness = grow(thickness, line_thickness);
+        }
+        self.size(min(longest, main_space), min(thickness, cross_space))
+    }
+
+    fn arrange(&self, children: &[&Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let main_space = self.main(content.size());
+        let cross_space = self.cross(content.size());
+        let items = children.iter().zip(desired)
+            .map(|(layout, d)| self.item(layout, self.main(*d)))
+            .collect::<Vec<_>>();
+        let lines = self.lines(&items, main_space);
+
+        let mut thicknesses = lines.iter().map(|&(start, end)| {
+            desired[start..end].iter().fold(0, |acc, d| max(acc, self.cross(*d)))
+        }).collect::<Vec<_>>();
+        if lines.len() == 1 {
+            thicknesses[0] = cross_space;
+        } else {
+            let used = thicknesses.iter().fold(self.gaps(lines.len()), |acc, &t| acc + t);
+            let extra = round(&vec![max(0, cross_space - used) as f32 / lines.len() as f32; lines.len()]);
+            for (t, e) in thicknesses.iter_mut().zip(extra) {
+                *t += e;
+            }
+        }
+
+        let mut slots = Vec::with_capacity(children.len());
+        let mut line_start = 0;
+        for (&(start, end), &thickness) in lines.iter().zip(thicknesses.iter()) {
+            let gaps = self.gaps(end - start);
+            let sizes = resolve(&items[start..end], max(0, main_space - gaps));
+            let used = sizes.iter().fold(gaps, |acc, &s| acc + s);
+            let (mut position, spacing) = justify(self.justify_content, main_space - used, end - start);
+
+            for (i, &size) in (start..end).zip(sizes.iter()) {
+                let (cross_start, cross_size) = match self.align_items {
+                    Alignment::Stretch => (0, thickness),
+                    alignment => {
+                        let size = min(self.cross(desired[i]), thickness);
+                        match alignment {
+                            Alignment::End => (thickness - size, size),
+                            Alignment::Center => ((thickness - size) / 2, size),
+                            _ => (0, size),
+                        }
+                    },
+                };
+                let cross_start = line_start + cross_start;
+                slots.push(match self.direction {
+                    Orientation::Horizontal => Rect::new(
+                        content.left + position, content.top + cross_start,
+                        content.left + position + size, content.top + cross_start + cross_size),
+                    Orientation::Vertical => Rect::new(
+                        content.left + cross_start, content.top + position,
+                        content.left + cross_start + cross_size, content.top + position + size),
+                });
+                position += size + self.gap + spacing;
+            }
+            line_start += thickness + self.gap;
+        }
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::controls::container::Container;
+    use ::geometry::{ Rect, Size };
+    use ::layout::{ Alignment, Layout, Orientation, UNBOUNDED };
+    use ::testing::{ self, open_window };
+
+    const ALL: [Justify; 6] = [Justify::Start, Justify::End, Justify::Center,
+                               Justify::SpaceBetween, Justify::SpaceAround, Justify::SpaceEvenly];
+
+    fn row() -> FlexPanel {
+        FlexPanel::new(Orientation::Horizontal)
+    }
+
+    fn flex(grow: f32, shrink: f32) -> Layout {
+        let mut layout = Layout::new();
+        layout.set_flex(grow, shrink);
+        layout
+    }
+
+    /// Arranges children that were measured at `desired` in `content`.
+    fn arrange(panel: &FlexPanel, layouts: &[Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let children = layouts.iter().collect::<Vec<_>>();
+        panel.arrange(&children, desired, content)
+    }
+
+    fn widths(rects: &[Rect]) -> Vec<i32> {
+        rects.iter().map(|r| r.width()).collect()
+    }
+
+    #[test]
+    fn empty_panels_arrange_nothing() {
+        let content = Rect::new(0, 0, 100, 50);
+        for &justify in ALL.iter() {
+            let mut panel = row();
+            panel.set_justify_content(justify).set_gap(5);
+            assert_eq!(arrange(&panel, &[], &[], content), vec![], "{:?}", justify);
+            panel.set_wrap(true);
+            assert_eq!(arrange(&panel, &[], &[], content), vec![], "{:?}", justify);
+            let size = panel.measure(&[], Size::new(100, 50), &mut |_, _| unreachable!());
+            assert_eq!(size, Size::default());
+        }
+    }
+
+    #[test]
+    fn empty_containers_lay_out_in_a_window() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut panel = row();
+        panel.set_justify_content(Justify::SpaceAround);
+        let id = testing::core(&mut handle).add_control(Box::new(Container::new(panel)));
+        testing::core(&mut handle).set_layout(id, Layout::new()).unwrap();
+        backend.resize(handle, 150, 80);
+        let bounds = testing::core(&mut handle).controls().client_bounds(id);
+        assert_eq!(bounds, Some(Rect::new(0, 0, 150, 80)));
+    }
+
+    #[test]
+    fn a_single_child_is_justified_in_its_line() {
+        let content = Rect::new(10, 0, 110, 20);
+        let expected = [
+            (Justify::Start, 10),
+            (Justify::End, 80),
+            (Justify::Center, 45),
+            (Justify::SpaceBetween, 10),
+            (Justify::SpaceAround, 45),
+            (Justify::SpaceEvenly, 45),
+        ];
+        for &(justify, left) in expected.iter() {
+            let mut panel = row();
+            panel.set_justify_content(justify);
+            let rects = arrange(&panel, &[Layout::new()], &[Size::new(30, 10)], content);
+            assert_eq!(rects, vec![Rect::new(left, 0, left + 30, 20)], "{:?}", justify);
+        }
+    }
+
+    #[test]
+    fn free_space_is_spread_between_items() {
+        let layouts = [Layout::new(), Layout::new(), Layout::new()];
+        let desired = [Size::new(20, 10); 3];
+        let content = Rect::new(0, 0, 100, 10);
+        let expected = [
+            (Justify::SpaceBetween, [0, 40, 80]),
+            (Justify::SpaceAround, [6, 39, 72]),
+            (Justify::SpaceEvenly, [10, 40, 70]),
+        ];
+        for &(justify, lefts) in expected.iter() {
+            let mut panel = row();
+            panel.set_justify_content(justify);
+            let rects = arrange(&panel, &layouts, &desired, content);
+            assert_eq!(rects.iter().map(|r| r.left).collect::<Vec<_>>(), lefts.to_vec(), "{:?}", justify);
+        }
+    }
+
+    #[test]
+    fn items_that_dont_fit_wrap_onto_new_lines() {
+        let mut panel = row();
+        panel.set_wrap(true);
+        let layouts = [Layout::new(), Layout::new(), Layout::new()];
+        let desired = [Size::new(40, 10); 3];
+
+        // The lines share the space left over across them.
+        let rects = arrange(&panel, &layouts, &desired, Rect::new(0, 0, 100, 50));
+        assert_eq!(rects, vec![Rect::new(0, 0, 40, 25), Rect::new(40, 0, 80, 25), Rect::new(0, 25, 40, 50)]);
+
+        let size = panel.measure(&[&layouts[0], &layouts[1], &layouts[2]], Size::new(100, 50),
+                                 &mut |i, _| desired[i]);
+        assert_eq!(size, Size::new(80, 20));
+
+        // Without wrapping they squeeze into one line instead.
+        panel.set_wrap(false).set_align_items(Alignment::Start);
+        let rects = arrange(&panel, &layouts, &desired, Rect::new(0, 0, 90, 50));
+        assert_eq!(rects, vec![Rect::new(0, 0, 30, 10), Rect::new(30, 0, 60, 10), Rect::new(60, 0, 90, 10)]);
+    }
+
+    #[test]
+    fn wrapped_lines_keep_their_gaps() {
+        let mut panel = FlexPanel::new(Orientation::Vertical);
+        panel.set_wrap(true).set_gap(4).set_align_items(Alignment::Start);
+        let layouts = [Layout::new(), Layout::new(), Layout::new()];
+        let desired = [Size::new(10, 20); 3];
+        let rects = arrange(&panel, &layouts, &desired, Rect::new(0, 0, 40, 50));
+        assert_eq!(rects, vec![Rect::new(0, 0, 10, 20), Rect::new(0, 24, 10, 44), Rect::new(22, 0, 32, 20)]);
+    }
+
+    #[test]
+    fn items_grow_by_their_factors() {
+        let desired = [Size::new(20, 10); 2];
+        let content = Rect::new(0, 0, 100, 10);
+        assert_eq!(widths(&arrange(&row(), &[flex(1.0, 1.0), flex(3.0, 1.0)], &desired, content)), vec![35, 65]);
+        assert_eq!(widths(&arrange(&row(), &[flex(0.0, 1.0), flex(2.0, 1.0)], &desired, content)), vec![20, 80]);
+
+        // Factors adding up to less than one leave some of the space free.
+        assert_eq!(widths(&arrange(&row(), &[flex(0.5, 1.0), flex(0.0, 1.0)], &desired, content)), vec![50, 20]);
+    }
+
+    #[test]
+    fn growing_items_stop_at_their_maximum() {
+        let mut limited = flex(1.0, 1.0);
+        limited.set_max_size(30, UNBOUNDED);
+        let rects = arrange(&row(), &[limited, flex(1.0, 1.0)], &[Size::new(20, 10); 2], Rect::new(0, 0, 100, 10));
+        assert_eq!(rects, vec![Rect::new(0, 0, 30, 10), Rect::new(30, 0, 100, 10)]);
+    }
+
+    #[test]
+    fn items_shrink_by_factor_and_size() {
+        let desired = [Size::new(80, 10), Size::new(40, 10)];
+        let content = Rect::new(0, 0, 60, 10);
+        assert_eq!(widths(&arrange(&row(), &[flex(0.0, 1.0), flex(0.0, 1.0)], &desired, content)), vec![40, 20]);
+        assert_eq!(widths(&arrange(&row(), &[flex(0.0, 1.0), flex(0.0, 0.0)], &desired, content)), vec![20, 40]);
+
+        // Nothing shrinks below its minimum, the rest makes up for it.
+        let mut limited = flex(0.0, 1.0);
+        limited.set_min_size(70, 0);
+        assert_eq!(widths(&arrange(&row(), &[limited, flex(0.0, 1.0)], &desired, content)), vec![70, 0]);
+    }
+
+    #[test]
+    fn basis_overrides_the_measured_size() {
+        let mut based = Layout::new();
+        based.set_basis(50);
+        let rects = arrange(&row(), &[based, Layout::new()], &[Size::new(20, 10); 2], Rect::new(0, 0, 100, 10));
+        assert_eq!(widths(&rects), vec![50, 20]);
+    }
+}
@@ -0,0 +1,306 @@
+use ::geometry::{ Rect, Size };
+
+use super::{ Layout, Panel, UNBOUNDED, grow };
+
+use std::cmp::{ max, min };
+
+/// The size of a grid row or column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridLength {
+    /// Exactly this many pixels.
+    Fixed(i32),
+    /// As large as the largest child in it.
+    Auto,
+    /// A share of whatever the fixed and auto tracks leave over, weighted
+    /// against the other star tracks.
+    Star(f32),
+}
+
+/// Puts children into cells, using `Layout::row`, `column`, `row_span` and
+/// `column_span`. Without any rows or columns defined the grid has a
+/// single star sized one, so its children end up on top of each other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid {
+    pub rows: Vec<GridLength>,
+    pub columns: Vec<GridLength>,
+}
+
+impl Grid {
+    pub fn new() -> Self {
+        Grid {
+            rows: Vec::new(),
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn add_row(&mut self, height: GridLength) -> &mut Self {
+        self.rows.push(height);
+        self
+    }
+
+    pub fn add_column(&mut self, width: GridLength) -> &mut Self {
+        self.columns.push(width);
+        self
+    }
+}
+
+impl Panel for Grid {
+    fn measure(&self, children: &[&Layout], available: Size,
+               measure_child: &mut FnMut(usize, Size) -> Size) -> Size {
+        let rows = tracks(&self.rows);
+        let columns = tracks(&self.columns);
+        let row_spans = children.iter().map(|c| span(c.row, c.row_span, rows.len())).collect::<Vec<_>>();
+        let column_spans = children.iter().map(|c| span(c.column, c.column_span, columns.len())).collect::<Vec<_>>();
+
+        // Measure everything with a guess for the star tracks first, then
+        // once more for children in star tracks, now that we know how large
+        // those ended up.
+        let row_guess = estimate(&rows, available.height);
+        let column_guess = estimate(&columns, available.width);
+        let mut desired = (0..children.len()).map(|i| {
+            measure_child(i, Size::new(sum(&column_guess, column_spans[i]), sum(&row_guess, row_spans[i])))
+        }).collect::<Vec<_>>();
+
+        let row_sizes = resolve(&rows, &row_spans, &heights(&desired), available.height);
+        let column_sizes = resolve(&columns, &column_spans, &widths(&desired), available.width);
+        for i in 0..children.len() {
+            if has_star(&rows, row_spans[i]) || has_star(&columns, column_spans[i]) {
+                desired[i] = measure_child(i, Size::new(sum(&column_sizes, column_spans[i]), sum(&row_sizes, row_spans[i])));
+            }
+        }
+
+        // Report what the contents need rather than all that is available,
+        // star tracks get stretched again when arranging.
+        let row_sizes = resolve(&rows, &row_spans, &heights(&desired), UNBOUNDED);
+        let column_sizes = resolve(&columns, &column_spans, &widths(&desired), UNBOUNDED);
+        Size::new(
+            min(sum(&column_sizes, (0, columns.len())), available.width),
+            min(sum(&row_sizes, (0, rows.len())), available.height))
+    }
+
+    fn arrange(&self, children: &[&Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let rows = tracks(&self.rows);
+        let columns = tracks(&self.columns);
+        let row_spans = children.iter().map(|c| span(c.row, c.row_span, rows.len())).collect::<Vec<_>>();
+        let column_spans = children.iter().map(|c| span(c.column, c.column_span, columns.len())).collect::<Vec<_>>();
+        let row_sizes = resolve(&rows, &row_spans, &heights(desired), content.height());
+        let column_sizes = resolve(&columns, &column_spans, &widths(desired), content.width());
+
+        (0..children.len()).map(|i| {
+            let (row, rows) = row_spans[i];
+            let (column, columns) = column_spans[i];
+            let left = content.left + sum(&column_sizes, (0, column));
+            let top = content.top + sum(&row_sizes, (0, row));
+            Rect::new(left, top,
+                      left + sum(&column_sizes, (column, columns)),
+                      top + sum(&row_sizes, (row, rows)))
+        }).collect()
+    }
+}
+
+fn tracks(definitions: &[GridLength]) -> Vec<GridLength> {
+    if definitions.is_empty() {
+        vec![GridLength::Star(1.0)]
+    } else {
+        definitions.to_vec()
+    }
+}
+
+/// The first track and number of tracks a child covers, kept inside the
+/// grid.
+fn span(start: usize, count: usize, tracks: usize) -> (usize, usize) {
+    let start = min(start, tracks - 1);
+    (start, max(1, min(count, tracks - start)))
+}
+
+fn sum(sizes: &[i32], span: (usize, usize)) -> i32 {
+    sizes[span.0..span.0 + span.1].iter().fold(0, |acc, &s| grow(acc, s))
+}
+
+fn has_star(tracks: &[GridLength], span: (usize, usize)) -> bool {
+    tracks[span.0..span.0 + span.1].iter().any(|t| match *t { GridLength::Star(_) => true, _ => false })
+}
+
+fn widths(sizes: &[Size]) -> Vec<i32> {
+    sizes.iter().map(|s| s.width).collect()
+}
+
+fn heights(sizes: &[Size]) -> Vec<i32> {
+    sizes.iter().map(|s| s.height).collect()
+}
+
+/// Track sizes to measure with before anything is known about the children.
+fn estimate(tracks: &[GridLength], available: i32) -> Vec<i32> {
+    let fixed = tracks.iter().fold(0, |acc, t| match *t {
+        GridLength::Fixed(size) => grow(acc, max(0, size)),
+        _ => acc,
+    });
+    let mut sizes = tracks.iter().map(|t| match *t {
+        GridLength::Fixed(size) => max(0, size),
+        _ => UNBOUNDED,
+    }).collect::<Vec<_>>();
+    if available != UNBOUNDED {
+        share_stars(tracks, &mut sizes, max(0, available - fixed));
+    }
+    sizes
+}
+
+/// Works out the final track sizes along one axis from the sizes the
+/// children want along it.
+fn resolve(tracks: &[GridLength], spans: &[(usize, usize)], desired: &[i32], available: i32) -> Vec<i32> {
+    let mut sizes = tracks.iter().map(|t| match *t {
+        GridLength::Fixed(size) => max(0, size),
+        _ => 0,
+    }).collect::<Vec<_>>();
+
+    // Single cell children first, then spanning ones only grow the last
+    // auto track they cover by whatever they still lack.
+    for (&(start, count), &d) in spans.iter().zip(desired) {
+        if count != 1 {
+            continue;
+        }
+        match tracks[start] {
+            GridLength::Auto => sizes[start] = max(sizes[start], d),
+            GridLength::Star(_) if available == UNBOUNDED => sizes[start] = max(sizes[start], d),
+            _ => { },
+        }
+    }
+    for (&(start, count), &d) in spans.iter().zip(desired) {
+        if count == 1 || has_star(tracks, (start, count)) {
+            continue;
+        }
+        let last_auto = (start..start + count).rev().find(|&i| tracks[i] == GridLength::Auto);
+        if let Some(i) = last_auto {
+            let missing = d - sum(&sizes, (start, count));
+            if missing > 0 {
+                sizes[i] += missing;
+            }
+        }
+    }
+
+    if available == UNBOUNDED {
+        // Keep the proportions with the largest star track as the yardstick.
+        let unit = tracks.iter().zip(&sizes).fold(0.0f32, |acc, (t, &s)| match *t {
+            GridLength::Star(weight) if weight > 0.0 => acc.max(s as f32 / weight),
+            _ => acc,
+        });
+        for (t, s) in tracks.iter().zip(sizes.iter_mut()) {
+            if let GridLength::Star(weight) = *t {
+                *s = (unit * weight.max(0.0)).ceil() as i32;
+            }
+        }
+    } else {
+        let used = tracks.iter().zip(&sizes).fold(0, |acc, (t, &s)| match *t {
+            GridLength::Star(_) => acc,
+            _ => grow(acc, s),
+        });
+        share_stars(tracks, &mut sizes, max(0, available - used));
+    }
+    sizes
+}
+
+/// Splits `space` between the star tracks by weight, the last one getting
+/// whatever rounding left over.
+fn share_stars(tracks: &[GridLength], sizes: &mut [i32], space: i32) {
+    let total = tracks.iter().fold(0.0f32, |acc, t| match *t {
+        GridLength::Star(weight) => acc + weight.max(0.0),
+        _ => acc,
+    });
+    if total <= 0.0 {
+        for (t, s) in tracks.iter().zip(sizes.iter_mut()) {
+            if let GridLength::Star(_) = *t {
+                *s = 0;
+            }
+        }
+        return;
+    }
+
+    let mut left = space;
+    let mut last = None;
+    for (i, t) in tracks.iter().enumerate() {
+        if let GridLength::Star(weight) = *t {
+            let share = (space as f32 * weight.max(0.0) / total) as i32;
+            sizes[i] = share;
+            left -= share;
+            last = Some(i);
+        }
+    }
+    if let Some(i) = last {
+        sizes[i] += left;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::layout::UNBOUNDED;
+
+    fn cell(row: usize, column: usize, rows: usize, columns: usize) -> Layout {
+        let mut layout = Layout::new();
+        layout.set_cell(row, column).set_span(rows, columns);
+        layout
+    }
+
+    fn arrange(grid: &Grid, layouts: &[Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let children = layouts.iter().collect::<Vec<_>>();
+        grid.arrange(&children, desired, content)
+    }
+
+    /// A 50 pixel column, an auto one and two star columns weighted 1 and
+    /// 3, with a child in each of the last three.
+    fn columns() -> (Grid, Vec<Layout>) {
+        let mut grid = Grid::new();
+        grid.add_column(GridLength::Fixed(50)).add_column(GridLength::Auto)
+            .add_column(GridLength::Star(1.0)).add_column(GridLength::Star(3.0));
+        (grid, vec![cell(0, 1, 1, 1), cell(0, 2, 1, 1), cell(0, 3, 1, 1)])
+    }
+
+    #[test]
+    fn stars_share_what_fixed_and_auto_columns_leave() {
+        let (grid, layouts) = columns();
+        let desired = [Size::new(30, 10), Size::new(5, 5), Size::new(5, 5)];
+        // 170 pixels left, split 1:3 with the rounding going to the last.
+        assert_eq!(arrange(&grid, &layouts, &desired, Rect::new(0, 0, 250, 40)),
+                   vec![Rect::new(50, 0, 80, 40), Rect::new(80, 0, 122, 40), Rect::new(122, 0, 250, 40)]);
+    }
+
+    #[test]
+    fn star_children_are_measured_again_with_their_share() {
+        let (grid, layouts) = columns();
+        let children = layouts.iter().collect::<Vec<_>>();
+        let desired = [Size::new(30, 10), Size::new(5, 5), Size::new(5, 5)];
+        let mut given = vec![Vec::new(); 3];
+        let size = grid.measure(&children, Size::new(250, 40), &mut |i, available| {
+            given[i].push(available);
+            desired[i]
+        });
+        // The implicit row is a star one, so everybody is measured twice.
+        assert_eq!(given[0], vec![Size::new(UNBOUNDED, 40), Size::new(30, 40)]);
+        assert_eq!(given[1], vec![Size::new(50, 40), Size::new(42, 40)]);
+        assert_eq!(given[2], vec![Size::new(150, 40), Size::new(128, 40)]);
+        // What the contents need: the stars keep their proportions.
+        assert_eq!(size, Size::new(50 + 30 + 5 + 15, 10));
+    }
+
+    #[test]
+    fn spanning_children_grow_the_last_auto_track() {
+        let mut grid = Grid::new();
+        grid.add_column(GridLength::Auto).add_column(GridLength::Auto)
+            .add_row(GridLength::Auto).add_row(GridLength::Auto);
+        // The last one asks for cells outside the grid and ends up in the
+        // last one.
+        let layouts = [cell(0, 0, 1, 1), cell(1, 0, 1, 2), cell(5, 5, 3, 3)];
+        let desired = [Size::new(20, 10), Size::new(50, 10), Size::new(1, 1)];
+        assert_eq!(arrange(&grid, &layouts, &desired, Rect::new(0, 0, 100, 100)),
+                   vec![Rect::new(0, 0, 20, 10), Rect::new(0, 10, 50, 20), Rect::new(20, 10, 50, 20)]);
+    }
+
+    #[test]
+    fn grids_without_tracks_stack_their_children() {
+        let grid = Grid::new();
+        let layouts = [Layout::new(), Layout::new()];
+        let content = Rect::new(5, 5, 55, 25);
+        assert_eq!(arrange(&grid, &layouts, &[Size::new(10, 10), Size::new(20, 5)], content),
+                   vec![content, content]);
+    }
+}
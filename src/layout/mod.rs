@@ -0,0 +1,370 @@
+//! Measuring and arranging controls.
+//!
+//! Layout runs in two passes. The measure pass asks every control, children
+//! first, how much space it would like given what is available; the arrange
+//! pass then hands each control its final rectangle, parents first. Panels
+//! decide how the space of a container is split between its children.
+//!
+//! Nothing in here knows about windows, so it works the same on every
+//! backend.
+
+pub mod dock;
+pub mod flex;
+pub mod grid;
+pub mod stack;
+
+pub use self::dock::{ Dock, DockPanel };
+pub use self::flex::{ FlexPanel, Justify };
+pub use self::grid::{ Grid, GridLength };
+pub use self::stack::{ Orientation, StackPanel };
+
+use ::geometry::{ Point, Rect, Size };
+
+use std::cmp::{ max, min };
+
+/// Stands in for an infinite width or height, e.g. along the orientation
+/// of a stack panel.
+pub const UNBOUNDED: i32 = ::std::i32::MAX;
+
+/// Space around the four edges of a rectangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Thickness {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Thickness {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Thickness { left: left, top: top, right: right, bottom: bottom }
+    }
+
+    pub fn uniform(thickness: i32) -> Self {
+        Thickness::new(thickness, thickness, thickness, thickness)
+    }
+
+    pub fn horizontal(&self) -> i32 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> i32 {
+        self.top + self.bottom
+    }
+
+    /// `rect` with the thickness taken off its edges.
+    pub fn deflate(&self, rect: &Rect) -> Rect {
+        let left = rect.left + self.left;
+        let top = rect.top + self.top;
+        Rect::new(left, top, max(left, rect.right - self.right), max(top, rect.bottom - self.bottom))
+    }
+}
+
+/// Where a control goes inside the slot its parent gave it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+    /// Fills the slot unless the control has a fixed or maximum size.
+    Stretch,
+}
+
+/// How a control wants to be laid out.
+///
+/// Besides the control's own properties this holds the ones its parent
+/// panel looks at: the grid cell, the dock side and the flex factors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub margin: Thickness,
+    pub padding: Thickness,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub min_size: Size,
+    pub max_size: Size,
+    pub horizontal_alignment: Alignment,
+    pub vertical_alignment: Alignment,
+    pub row: usize,
+    pub column: usize,
+    pub row_span: usize,
+    pub column_span: usize,
+    pub dock: Dock,
+    /// How much of the free space in a flex line this takes, relative to
+    /// the other items.
+    pub grow: f32,
+    /// How much this gives up when a flex line overflows, relative to the
+    /// other items and weighted by its size.
+    pub shrink: f32,
+    /// The size along a flex line to start from, instead of the measured one.
+    pub basis: Option<i32>,
+}
+
+/// What controls without a layout of their own get.
+pub static DEFAULT_LAYOUT: Layout = Layout {
+    margin: Thickness { left: 0, top: 0, right: 0, bottom: 0 },
+    padding: Thickness { left: 0, top: 0, right: 0, bottom: 0 },
+    width: None,
+    height: None,
+    min_size: Size { width: 0, height: 0 },
+    max_size: Size { width: UNBOUNDED, height: UNBOUNDED },
+    horizontal_alignment: Alignment::Stretch,
+    vertical_alignment: Alignment::Stretch,
+    row: 0,
+    column: 0,
+    row_span: 1,
+    column_span: 1,
+    dock: Dock::Left,
+    grow: 0.0,
+    shrink: 1.0,
+    basis: None,
+};
+
+impl Layout {
+    pub fn new() -> Self {
+        DEFAULT_LAYOUT.clone()
+    }
+
+    pub fn set_margin(&mut self, margin: Thickness) -> &mut Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn set_padding(&mut self, padding: Thickness) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn set_width(&mut self, width: i32) -> &mut Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn set_height(&mut self, height: i32) -> &mut Self {
+        self.height = Some(height);
+        self
+    }
+
+    pub fn set_min_size(&mut self, width: i32, height: i32) -> &mut Self {
+        self.min_size = Size::new(width, height);
+        self
+    }
+
+    pub fn set_max_size(&mut self, width: i32, height: i32) -> &mut Self {
+        self.max_size = Size::new(width, height);
+        self
+    }
+
+    pub fn set_alignment(&mut self, horizontal: Alignment, vertical: Alignment) -> &mut Self {
+        self.horizontal_alignment = horizontal;
+        self.vertical_alignment = vertical;
+        self
+    }
+
+    pub fn set_cell(&mut self, row: usize, column: usize) -> &mut Self {
+        self.row = row;
+        self.column = column;
+        self
+    }
+
+    pub fn set_span(&mut self, rows: usize, columns: usize) -> &mut Self {
+        self.row_span = rows;
+        self.column_span = columns;
+        self
+    }
+
+    pub fn set_dock(&mut self, dock: Dock) -> &mut Self {
+        self.dock = dock;
+        self
+    }
+
+    pub fn set_flex(&mut self, grow: f32, shrink: f32) -> &mut Self {
+        self.grow = grow;
+        self.shrink = shrink;
+        self
+    }
+
+    pub fn set_basis(&mut self, basis: i32) -> &mut Self {
+        self.basis = Some(basis);
+        self
+    }
+
+    /// The smallest and largest size allowed inside the margin.
+    fn limits(&self) -> (Size, Size) {
+        let (min_width, max_width) = limit(self.width, self.min_size.width, self.max_size.width);
+        let (min_height, max_height) = limit(self.height, self.min_size.height, self.max_size.height);
+        (Size::new(min_width, min_height), Size::new(max_width, max_height))
+    }
+}
+
+fn limit(fixed: Option<i32>, min_value: i32, max_value: i32) -> (i32, i32) {
+    match fixed {
+        Some(value) => {
+            let value = max(min(value, max_value), min_value);
+            (value, value)
+        },
+        None => (min_value, max(min_value, max_value)),
+    }
+}
+
+/// How a container splits its space between its children.
+///
+/// Children are passed bottom to top, the way the control tree keeps them.
+pub trait Panel {
+    /// Returns the size the children need, measuring each of them with
+    /// `measure_child(index, available)`.
+    fn measure(&self, children: &[&Layout], available: Size,
+               measure_child: &mut FnMut(usize, Size) -> Size) -> Size;
+
+    /// Returns a slot within `content` for every child, given the sizes
+    /// they asked for when measured.
+    fn arrange(&self, children: &[&Layout], desired: &[Size], content: Rect) -> Vec<Rect>;
+}
+
+/// Subtracts, leaving `UNBOUNDED` alone and never going below zero.
+pub fn shrink(value: i32, by: i32) -> i32 {
+    if value == UNBOUNDED { value } else { max(0, value - by) }
+}
+
+/// Adds without overflowing past `UNBOUNDED`.
+pub fn grow(value: i32, by: i32) -> i32 {
+    value.saturating_add(by)
+}
+
+/// The measure pass for a single control: works out the space left inside
+/// margin, padding and size limits, asks `content` how much of it the
+/// contents need and returns the size the control wants, margin included.
+pub fn measure(layout: &Layout, available: Size, content: &mut FnMut(Size) -> Size) -> Size {
+    let (min_size, max_size) = layout.limits();
+    let inner = Size::new(
+        max(min(shrink(available.width, layout.margin.horizontal()), max_size.width), min_size.width),
+        max(min(shrink(available.height, layout.margin.vertical()), max_size.height), min_size.height));
+    let wanted = content(Size::new(
+        shrink(inner.width, layout.padding.horizontal()),
+        shrink(inner.height, layout.padding.vertical())));
+
+    let width = max(min(grow(wanted.width, layout.padding.horizontal()), max_size.width), min_size.width);
+    let height = max(min(grow(wanted.height, layout.padding.vertical()), max_size.height), min_size.height);
+    Size::new(
+        min(grow(width, layout.margin.horizontal()), available.width),
+        min(grow(height, layout.margin.vertical()), available.height))
+}
+
+/// The arrange pass for a single control: places it inside `slot` according
+/// to its alignment, given the size it asked for.
+pub fn arrange(layout: &Layout, desired: Size, slot: Rect) -> Rect {
+    let (min_size, max_size) = layout.limits();
+    let inner = layout.margin.deflate(&slot);
+    let (left, width) = place(layout.horizontal_alignment, layout.width.is_some(),
+                              shrink(desired.width, layout.margin.horizontal()),
+                              inner.left, inner.width(), min_size.width, max_size.width);
+    let (top, height) = place(layout.vertical_alignment, layout.height.is_some(),
+                              shrink(desired.height, layout.margin.vertical()),
+                              inner.top, inner.height(), min_size.height, max_size.height);
+    Rect::from_origin_size(Point::new(left, top), Size::new(width, height))
+}
+
+fn place(alignment: Alignment, fixed: bool, desired: i32, start: i32, space: i32,
+         min_value: i32, max_value: i32) -> (i32, i32) {
+    let size = if alignment == Alignment::Stretch && !fixed { space } else { min(desired, space) };
+    let size = max(min(size, max_value), min_value);
+    let offset = match alignment {
+        Alignment::Start => 0,
+        Alignment::End => space - size,
+        Alignment::Center | Alignment::Stretch => (space - size) / 2,
+    };
+    (start + offset, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Measures with `layout`, the contents asking for `wanted`, and returns
+    /// the desired size along with the space the contents were given.
+    fn measure_with(layout: &Layout, available: Size, wanted: Size) -> (Size, Size) {
+        let mut given = Size::default();
+        let desired = measure(layout, available, &mut |space| {
+            given = space;
+            wanted
+        });
+        (desired, given)
+    }
+
+    fn aligned(horizontal: Alignment, vertical: Alignment) -> Layout {
+        let mut layout = Layout::new();
+        layout.set_alignment(horizontal, vertical);
+        layout
+    }
+
+    #[test]
+    fn margin_and_padding_surround_the_contents() {
+        let mut layout = Layout::new();
+        layout.set_margin(Thickness::new(1, 2, 3, 4)).set_padding(Thickness::uniform(5));
+        let (desired, given) = measure_with(&layout, Size::new(100, 100), Size::new(20, 10));
+        assert_eq!(given, Size::new(86, 84));
+        assert_eq!(desired, Size::new(34, 26));
+
+        // Never more than is available.
+        let (desired, given) = measure_with(&layout, Size::new(20, 20), Size::new(20, 10));
+        assert_eq!(given, Size::new(6, 4));
+        assert_eq!(desired, Size::new(20, 20));
+        let (_, given) = measure_with(&layout, Size::new(UNBOUNDED, 5), Size::default());
+        assert_eq!(given, Size::new(UNBOUNDED, 0));
+
+        let slot = Rect::new(0, 0, 100, 100);
+        assert_eq!(arrange(&layout, Size::new(34, 26), slot), Rect::new(1, 2, 97, 96));
+        layout.set_alignment(Alignment::Start, Alignment::Start);
+        assert_eq!(arrange(&layout, Size::new(34, 26), slot), Rect::new(1, 2, 31, 22));
+    }
+
+    #[test]
+    fn sizes_are_clamped_to_the_limits() {
+        let mut layout = Layout::new();
+        layout.set_min_size(50, 10).set_max_size(60, 15);
+        let (desired, given) = measure_with(&layout, Size::new(200, 200), Size::new(100, 5));
+        assert_eq!(given, Size::new(60, 15));
+        assert_eq!(desired, Size::new(60, 10));
+        // Stretching stops at the maximum, centered in the slot.
+        assert_eq!(arrange(&layout, desired, Rect::new(0, 0, 200, 200)), Rect::new(70, 92, 130, 107));
+        // Even a small slot gets the minimum.
+        assert_eq!(arrange(&layout, desired, Rect::new(0, 0, 20, 5)).size(), Size::new(50, 10));
+
+        // A fixed size is clamped too.
+        layout.set_width(40).set_height(40);
+        let (desired, given) = measure_with(&layout, Size::new(200, 200), Size::new(100, 5));
+        assert_eq!((desired, given), (Size::new(50, 15), Size::new(50, 15)));
+    }
+
+    #[test]
+    fn alignment_places_the_control_in_its_slot() {
+        let slot = Rect::new(10, 10, 110, 60);
+        let desired = Size::new(20, 10);
+        let cases = [
+            (Alignment::Start, Alignment::Start, Rect::new(10, 10, 30, 20)),
+            (Alignment::Center, Alignment::End, Rect::new(50, 50, 70, 60)),
+            (Alignment::End, Alignment::Center, Rect::new(90, 30, 110, 40)),
+            (Alignment::Stretch, Alignment::Stretch, slot),
+        ];
+        for &(horizontal, vertical, expected) in cases.iter() {
+            assert_eq!(arrange(&aligned(horizontal, vertical), desired, slot), expected,
+                       "{:?} {:?}", horizontal, vertical);
+        }
+
+        // A fixed width isn't stretched, and nothing gets larger than the
+        // slot.
+        let mut fixed = Layout::new();
+        fixed.set_width(30);
+        assert_eq!(arrange(&fixed, Size::new(30, 10), slot), Rect::new(45, 10, 75, 60));
+        let start = aligned(Alignment::Start, Alignment::Start);
+        assert_eq!(arrange(&start, Size::new(200, 100), slot), slot);
+    }
+
+    #[test]
+    fn thickness_deflates_down_to_nothing() {
+        let thickness = Thickness::new(1, 2, 3, 4);
+        assert_eq!((thickness.horizontal(), thickness.vertical()), (4, 6));
+        assert_eq!(thickness.deflate(&Rect::new(0, 0, 10, 10)), Rect::new(1, 2, 7, 6));
+        assert_eq!(thickness.deflate(&Rect::new(0, 0, 3, 3)), Rect::new(1, 2, 1, 2));
+        assert_eq!((shrink(UNBOUNDED, 5), shrink(3, 5), grow(UNBOUNDED, 5)), (UNBOUNDED, 0, UNBOUNDED));
+    }
+}
@@ -0,0 +1,125 @@
+use ::geometry::{ Rect, Size };
+
+use super::{ Layout, Panel, UNBOUNDED, grow };
+
+use std::cmp::max;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+/// Lines children up one after the other, each getting as much room along
+/// the orientation as it asks for and all the room across it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackPanel {
+    pub orientation: Orientation,
+    /// Space between two neighbouring children.
+    pub spacing: i32,
+}
+
+impl StackPanel {
+    pub fn new(orientation: Orientation) -> Self {
+        StackPanel {
+            orientation: orientation,
+            spacing: 0,
+        }
+    }
+
+    pub fn set_spacing(&mut self, spacing: i32) -> &mut Self {
+        self.spacing = spacing;
+        self
+    }
+}
+
+impl Panel for StackPanel {
+    fn measure(&self, children: &[&Layout], available: Size,
+               measure_child: &mut FnMut(usize, Size) -> Size) -> Size {
+        let mut along = 0;
+        let mut across = 0;
+        for i in 0..children.len() {
+            let desired = match self.orientation {
+                Orientation::Horizontal => {
+                    let d = measure_child(i, Size::new(UNBOUNDED, available.height));
+                    Size::new(d.width, d.height)
+                },
+                Orientation::Vertical => {
+                    let d = measure_child(i, Size::new(available.width, UNBOUNDED));
+                    Size::new(d.height, d.width)
+                },
+            };
+            if i > 0 {
+                along = grow(along, self.spacing);
+            }
+            along = grow(along, desired.width);
+            across = max(across, desired.height);
+        }
+
+        match self.orientation {
+            Orientation::Horizontal => Size::new(along, across),
+            Orientation::Vertical => Size::new(across, along),
+        }
+    }
+
+    fn arrange(&self, _children: &[&Layout], desired: &[Size], content: Rect) -> Vec<Rect> {
+        let mut slots = Vec::with_capacity(desired.len());
+        let mut offset = 0;
+        for d in desired {
+            slots.push(match self.orientation {
+                Orientation::Horizontal => {
+                    let left = content.left + offset;
+                    offset += d.width + self.spacing;
+                    Rect::new(left, content.top, left + d.width, content.bottom)
+                },
+                Orientation::Vertical => {
+                    let top = content.top + offset;
+                    offset += d.height + self.spacing;
+                    Rect::new(content.left, top, content.right, top + d.height)
+                },
+            });
+        }
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children_line_up_with_spacing() {
+        let layouts = [Layout::new(), Layout::new()];
+        let children = layouts.iter().collect::<Vec<_>>();
+        let desired = [Size::new(10, 10), Size::new(20, 5)];
+
+        let mut vertical = StackPanel::new(Orientation::Vertical);
+        vertical.set_spacing(4);
+        let mut given = Vec::new();
+        let size = vertical.measure(&children, Size::new(50, 100), &mut |i, available| {
+            given.push(available);
+            desired[i]
+        });
+        assert_eq!(given, vec![Size::new(50, UNBOUNDED); 2]);
+        assert_eq!(size, Size::new(20, 19));
+        assert_eq!(vertical.arrange(&children, &desired, Rect::new(0, 0, 50, 100)),
+                   vec![Rect::new(0, 0, 50, 10), Rect::new(0, 14, 50, 19)]);
+
+        let mut horizontal = StackPanel::new(Orientation::Horizontal);
+        horizontal.set_spacing(4);
+        let size = horizontal.measure(&children, Size::new(50, 100), &mut |i, available| {
+            assert_eq!(available, Size::new(UNBOUNDED, 100));
+            desired[i]
+        });
+        assert_eq!(size, Size::new(34, 10));
+        assert_eq!(horizontal.arrange(&children, &desired, Rect::new(5, 5, 100, 30)),
+                   vec![Rect::new(5, 5, 15, 30), Rect::new(19, 5, 39, 30)]);
+    }
+
+    #[test]
+    fn empty_stacks_need_no_space() {
+        let panel = StackPanel::new(Orientation::Horizontal);
+        assert_eq!(panel.measure(&[], Size::new(50, 50), &mut |_, _| unreachable!()), Size::default());
+        assert_eq!(panel.arrange(&[], &[], Rect::new(0, 0, 50, 50)), vec![]);
+    }
+}
@@ -9,21 +9,36 @@ extern crate kernel32;
 extern crate wio;
 #[macro_use] extern crate bitflags;
 
+mod backend;
 mod ml;
 mod colors;
+mod constraints;
+mod event;
+mod event_loop;
 mod font;
+mod geometry;
+mod layout;
 mod controls;
 mod message;
+mod raster;
+mod region;
+mod snapshot;
+mod task;
+#[cfg(test)] mod testing;
+mod text;
+mod timer;
+mod tree;
 mod window;
 
 
 use winapi::*;
-use user32::*;
-use kernel32::*;
+#[cfg(windows)] use user32::*;
+#[cfg(windows)] use kernel32::*;
 
 use ml::*;
 use colors::*;
 use window::*;
+use event_loop::EventLoop;
 use controls::label::*;
 
 struct MyWindow {
@@ -73,6 +88,7 @@ impl WindowClass for MyWindow {
     }
 }
 
+#[cfg(windows)]
 pub fn main() {
     println!("common main :(");
     unsafe {
@@ -81,6 +97,26 @@ pub fn main() {
     }
 }
 
+/// Without user32 there is nothing to show, so the window only gets created
+/// and painted headlessly, the way the tests drive it.
+#[cfg(not(windows))]
+pub fn main() {
+    let backend = backend::headless::HeadlessBackend::install();
+    WindowClassBuilder::<MyWindow>::new().register(std::ptr::null_mut());
+    let hwnd = match WindowBuilder::<MyWindow>::new(std::ptr::null_mut())
+                .set_title("Test Window")
+                .set_width(400).set_height(400)
+                .build() {
+        Ok(handle) => handle,
+        Err(e) => {
+            println!("HWND is NULL! ({})", e);
+            return;
+        }
+    };
+    backend.paint(hwnd);
+}
+
+#[cfg(windows)]
 #[no_mangle] #[allow(non_snake_case, unused_variables)]
 pub unsafe extern "system" fn WinMain(hinstance: HINSTANCE,
                                       prevInstance: HINSTANCE,
@@ -103,56 +139,5 @@ pub unsafe extern "system" fn WinMain(hinstance: HINSTANCE,
     ShowWindow(hwnd, cmdShow);
     UpdateWindow(hwnd);
 
-    let mut msg: MSG = std::mem::zeroed();
-    while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
-        TranslateMessage(&mut msg);
-        DispatchMessageW(&mut msg);
-    };
-
-    return 0;
-}
-
-fn repaint_window(context: &PaintContext) {
-    let window: &Box<Window> = get_window_from_handle(context.window);
-    window.paint(context);
-}
-
-fn destroy_window(handle: HWND) {
-    let window = unsafe {
-        Box::from_raw(GetWindowLongPtrW(handle, GWLP_USERDATA) as *mut Box<Window>)
-    };
-    drop(window);
-}
-
-#[allow(non_snake_case, unused_variables)]
-unsafe extern "system" fn wndProc(mut hwnd: HWND, msg: UINT, wParam: WPARAM, lParam: LPARAM) -> LRESULT {
-    match msg {
-        // TODO: override *all* the messages.
-        WM_CREATE => {
-            let st = lParam as *mut CREATESTRUCTW;
-            if lParam == 0 || (*st).lpCreateParams.is_null() {
-                println!("Create params are null!");
-            }
-            let mut wnd = Box::from_raw((*st).lpCreateParams as *mut Box<Window>);
-            wnd.init_handle(hwnd);
-            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(wnd) as LONG_PTR);
-            get_window_from_handle_mut(&mut hwnd).on_create();
-            0
-        },
-        WM_CLOSE => {
-            println!("WM_CLOSE");
-            DestroyWindow(hwnd);
-            0
-        },
-        WM_DESTROY => {
-            destroy_window(hwnd);
-            PostQuitMessage(0);
-            0
-        },
-        WM_PAINT =>  {
-            repaint_window(&PaintContext::begin_paint(&hwnd));
-            0
-        },
-        msg => DefWindowProcW(hwnd, msg, wParam, lParam)
-    }
+    EventLoop::new().run() as u32
 }
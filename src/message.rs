@@ -1,11 +1,246 @@
+//! Typed messages between windows.
+//!
+//! Any `'static` value can be sent to a window. It travels in an `Envelope`
+//! that remembers its type, as the `LPARAM` of a message registered with the
+//! backend under `MESSAGE_NAME`, and ends up with whichever handler the
+//! window registered for that type with `MessageHandlerBase::register_message`.
+//!
+//! Posted messages don't travel with the message at all: they wait in the
+//! window's `MessageQueue`, and the typed message id only wakes the window up
+//! to empty it. Whatever is still queued when the window goes away is dropped
+//! along with the queue.
+
+use ::backend::Waker;
+
 use ::std::any::{ Any, TypeId };
-use ::std::marker::Reflect;
+use ::std::collections::VecDeque;
+use ::std::fmt;
+use ::std::marker::{ PhantomData, Reflect };
+use ::std::mem;
 use ::std::rc::Rc;
+use ::std::sync::{ Arc, Mutex };
 use ::winapi::*;
 
-pub fn send_message<T>(hwnd: HWND, msg: T) {
-    let msg = Box::into_raw(Box::new(msg)) as LPARAM;
-    unsafe {::user32::SendMessageW(hwnd, WM_APP, 0, msg)};
+/// The name typed messages are registered under.
+pub const MESSAGE_NAME: &'static str = "windows-test.TypedMessage";
+
+/// Registered message ids all lie in this range.
+const FIRST_REGISTERED: UINT = 0xC000;
+const LAST_REGISTERED: UINT = 0xFFFF;
+
+/// The `WPARAM` of a typed message that only says the queue has something.
+pub const POSTED: WPARAM = 1;
+
+/// What the receiving window returns for a typed message.
+const HANDLED: LRESULT = 1;
+const NO_HANDLER: LRESULT = 2;
+
+/// The id typed messages are sent with.
+pub fn message_id() -> UINT {
+    ::backend::current().register_message(MESSAGE_NAME)
+}
+
+/// Whether `msg` is the id of typed messages.
+pub fn is_message_id(msg: UINT) -> bool {
+    msg >= FIRST_REGISTERED && msg <= LAST_REGISTERED && msg == message_id()
+}
+
+/// Why a message didn't get handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageError {
+    /// There is no such window, or it isn't one of ours.
+    NotDelivered,
+    /// The window has no handler for messages of this type.
+    NoHandler(TypeId),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MessageError::NotDelivered => write!(f, "the message didn't reach a window"),
+            MessageError::NoHandler(t) => write!(f, "no handler registered for messages of type {:?}", t),
+        }
+    }
+}
+
+/// A message together with its type.
+///
+/// The envelope owns the message until a handler takes it out, so it gets
+/// dropped exactly once whether or not anybody handled it.
+pub struct Envelope {
+    type_id: TypeId,
+    payload: Option<Box<Any>>,
+}
+
+impl Envelope {
+    pub fn new<T: Reflect + 'static>(message: T) -> Self {
+        Envelope {
+            type_id: TypeId::of::<T>(),
+            payload: Some(Box::new(message)),
+        }
+    }
+
+    pub fn message_type(&self) -> TypeId {
+        self.type_id
+    }
+
+    /// Takes the message out, leaving the envelope empty.
+    pub fn take(&mut self) -> Option<Box<Any>> {
+        self.payload.take()
+    }
+}
+
+/// Sends `msg` to `hwnd` and waits for it to be handled.
+///
+/// The message only lives as long as the call, so `hwnd` has to belong to
+/// the calling thread.
+pub fn send_message<T: Reflect + 'static>(hwnd: HWND, msg: T) -> Result<(), MessageError> {
+    let mut envelope = Envelope::new(msg);
+    let result = ::backend::current().send_message(
+        hwnd, message_id(), 0, &mut envelope as *mut Envelope as LPARAM);
+    match result {
+        HANDLED => Ok(()),
+        NO_HANDLER => Err(MessageError::NoHandler(envelope.message_type())),
+        _ => Err(MessageError::NotDelivered),
+    }
+}
+
+/// Queues `msg` for `hwnd` and returns without waiting for it to be handled.
+pub fn post_message<T: Reflect + Send + 'static>(hwnd: HWND, msg: T) -> Result<(), MessageError> {
+    if ::backend::current().user_data(hwnd) == 0 {
+        return Err(MessageError::NotDelivered);
+    }
+    ::window::get_window_from_handle(&hwnd).get_core().sender::<T>().send(msg)
+}
+
+/// Hands the envelope `l_param` points to over to `handler`, returning what
+/// `send_message` expects back.
+pub unsafe fn receive<H: MessageHandlerBase>(handler: &mut H, l_param: LPARAM) -> LRESULT {
+    let envelope = &mut *(l_param as *mut Envelope);
+    match handler.handle_message(envelope) {
+        Ok(()) => HANDLED,
+        Err(MessageError::NoHandler(_)) => NO_HANDLER,
+        Err(MessageError::NotDelivered) => 0,
+    }
+}
+
+/// An envelope whose message may be handed to another thread.
+struct Posted(Envelope);
+
+// Only ever made from messages that are `Send` themselves.
+unsafe impl Send for Posted {}
+
+struct QueueState {
+    posted: VecDeque<Posted>,
+    closed: bool,
+    /// Whether the window has been woken up and not emptied the queue yet.
+    wake_pending: bool,
+}
+
+/// The messages posted to a window that it hasn't handled yet.
+pub struct MessageQueue {
+    state: Mutex<QueueState>,
+}
+
+impl MessageQueue {
+    pub fn new() -> Self {
+        MessageQueue {
+            state: Mutex::new(QueueState { posted: VecDeque::new(), closed: false, wake_pending: false }),
+        }
+    }
+
+    /// Adds a message at the back, returning whether the window needs to be
+    /// woken up for it, or `NotDelivered` once the queue has been closed.
+    fn push<T: Reflect + Send + 'static>(&self, msg: T) -> Result<bool, MessageError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(MessageError::NotDelivered);
+        }
+        state.posted.push_back(Posted(Envelope::new(msg)));
+        let wake = !state.wake_pending;
+        state.wake_pending = true;
+        Ok(wake)
+    }
+
+    /// Lets the next `push` wake the window again, after waking it failed.
+    fn wake_failed(&self) {
+        self.state.lock().unwrap().wake_pending = false;
+    }
+
+    /// Takes everything that is queued right now, oldest first. Anything
+    /// queued after this wakes the window again.
+    pub fn take_all(&self) -> Vec<Envelope> {
+        let mut state = self.state.lock().unwrap();
+        state.wake_pending = false;
+        mem::replace(&mut state.posted, VecDeque::new()).into_iter().map(|p| p.0).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().posted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the queued messages and turns away any new ones.
+    pub fn close(&self) {
+        let posted = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            mem::replace(&mut state.posted, VecDeque::new())
+        };
+        drop(posted);
+    }
+}
+
+/// Posts messages of type `T` to a window, from any thread.
+///
+/// Messages arrive in the order they were sent, also across clones of the
+/// same sender.
+pub struct WindowSender<T> {
+    handle: usize,
+    message: UINT,
+    queue: Arc<MessageQueue>,
+    waker: Arc<Waker>,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<T: Reflect + Send + 'static> WindowSender<T> {
+    /// Makes a sender for the window `hwnd` of the calling thread.
+    pub fn new(hwnd: HWND, queue: Arc<MessageQueue>) -> Self {
+        let backend = ::backend::current();
+        WindowSender {
+            handle: hwnd as usize,
+            message: backend.register_message(MESSAGE_NAME),
+            queue: queue,
+            waker: backend.waker(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Queues `msg`, failing with `NotDelivered` once the window is gone.
+    pub fn send(&self, msg: T) -> Result<(), MessageError> {
+        // One wakeup is enough until the window empties the queue, it takes
+        // all of the messages at once.
+        if try!(self.queue.push(msg)) && !self.waker.post(self.handle, self.message, POSTED, 0) {
+            self.queue.wake_failed();
+            return Err(MessageError::NotDelivered);
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for WindowSender<T> {
+    fn clone(&self) -> Self {
+        WindowSender {
+            handle: self.handle,
+            message: self.message,
+            queue: self.queue.clone(),
+            waker: self.waker.clone(),
+            _phantom: PhantomData,
+        }
+    }
 }
 
 pub trait MessageHandler<T> {
@@ -13,11 +248,189 @@ pub trait MessageHandler<T> {
 }
 
 pub trait MessageHandlerBase {
-    fn register_message<T: Reflect>(&mut self)
+    fn register_message<T: Reflect + 'static>(&mut self)
         where Self: MessageHandler<T>;
-    fn get_message_handler(&self, t: TypeId) ->Rc<Fn(&mut Self, Box<Any>)>;
-    fn handle_message(&mut self, msg: Box<Any>) {
-        let handler = self.get_message_handler(msg.get_type_id());
-        handler(self, msg);
+    /// The handler registered for messages of type `t`, if there is one.
+    fn get_message_handler(&self, t: TypeId) -> Option<Rc<Fn(&mut Self, Box<Any>)>>;
+    fn handle_message(&mut self, envelope: &mut Envelope) -> Result<(), MessageError> {
+        let handler = try!(self.get_message_handler(envelope.message_type())
+            .ok_or(MessageError::NoHandler(envelope.message_type())));
+        if let Some(msg) = envelope.take() {
+            handler(self, msg);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::{ Backend, Waker };
+    use ::testing::{ self, open_window };
+    use ::window::WindowCore;
+
+    use std::any::TypeId;
+    use std::cell::{ Cell, RefCell };
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicBool, Ordering };
+    use std::thread;
+
+    /// The `index`th message of producer `producer`.
+    struct Numbered {
+        producer: usize,
+        index: usize,
+    }
+
+    thread_local!(static RECEIVED: RefCell<Vec<(usize, usize)>> = RefCell::new(Vec::new()));
+
+    impl MessageHandler<Numbered> for WindowCore {
+        fn handle_message(&mut self, msg: &Numbered) {
+            RECEIVED.with(|r| r.borrow_mut().push((msg.producer, msg.index)));
+        }
+    }
+
+    fn received() -> Vec<(usize, usize)> {
+        RECEIVED.with(|r| r.borrow().clone())
+    }
+
+    /// Counts how often it was dropped.
+    struct Tracked(Rc<Cell<usize>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    /// Like `Tracked`, but nobody handles it.
+    struct Unhandled(Tracked);
+
+    thread_local!(static HANDLED_DROPS: Cell<Option<usize>> = Cell::new(None));
+
+    impl MessageHandler<Tracked> for WindowCore {
+        fn handle_message(&mut self, msg: &Tracked) {
+            HANDLED_DROPS.with(|h| h.set(Some(msg.0.get())));
+        }
+    }
+
+    /// Fails the first wakeup, then passes them on.
+    struct Flaky {
+        failed: AtomicBool,
+        inner: Arc<Waker>,
+    }
+
+    impl Waker for Flaky {
+        fn post(&self, handle: usize, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> bool {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                return false;
+            }
+            self.inner.post(handle, msg, w_param, l_param)
+        }
+    }
+
+    #[test]
+    fn messages_from_many_threads_arrive_in_order() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Numbered>();
+        let sender = testing::core(&mut handle).sender::<Numbered>();
+
+        let producers = (0..4).map(|producer| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for index in 0..100 {
+                    sender.send(Numbered { producer: producer, index: index }).unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        // All of them wait behind a single wakeup.
+        assert_eq!(backend.pending_messages(), 1);
+        backend.dispatch_pending();
+        let received = received();
+        assert_eq!(received.len(), 400);
+        for producer in 0..4 {
+            let indices = received.iter().filter(|m| m.0 == producer).map(|m| m.1).collect::<Vec<_>>();
+            assert_eq!(indices, (0..100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn emptying_the_queue_lets_the_next_message_wake_the_window() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Numbered>();
+        let sender = testing::core(&mut handle).sender::<Numbered>();
+
+        for index in 0..3 {
+            sender.send(Numbered { producer: 0, index: index }).unwrap();
+            assert_eq!(backend.pending_messages(), 1);
+            backend.dispatch_pending();
+            assert_eq!(received().len(), index + 1);
+        }
+    }
+
+    #[test]
+    fn a_failed_wakeup_is_tried_again() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Numbered>();
+        let sender = WindowSender {
+            waker: Arc::new(Flaky { failed: AtomicBool::new(false), inner: backend.waker() }),
+            ..testing::core(&mut handle).sender::<Numbered>()
+        };
+
+        assert_eq!(sender.send(Numbered { producer: 0, index: 0 }), Err(MessageError::NotDelivered));
+        assert_eq!(backend.pending_messages(), 0);
+        sender.send(Numbered { producer: 0, index: 1 }).unwrap();
+        assert_eq!(backend.pending_messages(), 1);
+        backend.dispatch_pending();
+        assert_eq!(received(), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn nothing_gets_through_once_the_window_is_gone() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Numbered>();
+        let sender = testing::core(&mut handle).sender::<Numbered>();
+        sender.send(Numbered { producer: 0, index: 0 }).unwrap();
+        backend.destroy_window(handle);
+
+        assert_eq!(sender.send(Numbered { producer: 0, index: 1 }), Err(MessageError::NotDelivered));
+        backend.dispatch_pending();
+        assert!(received().is_empty());
+        assert_eq!(post_message(handle, Numbered { producer: 0, index: 2 }), Err(MessageError::NotDelivered));
+    }
+
+    #[test]
+    fn sending_waits_for_the_handler() {
+        let (_backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Tracked>();
+        let drops = Rc::new(Cell::new(0));
+
+        assert_eq!(send_message(handle, Tracked(drops.clone())), Ok(()));
+        // Handled before `send_message` returned, and dropped right after.
+        assert_eq!(HANDLED_DROPS.with(|h| h.get()), Some(0));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(Rc::strong_count(&drops), 1);
+    }
+
+    #[test]
+    fn sending_without_a_handler_drops_the_message() {
+        let (backend, mut handle) = open_window(100, 100);
+        testing::core(&mut handle).register_message::<Tracked>();
+        let drops = Rc::new(Cell::new(0));
+
+        assert_eq!(send_message(handle, Unhandled(Tracked(drops.clone()))),
+                   Err(MessageError::NoHandler(TypeId::of::<Unhandled>())));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(Rc::strong_count(&drops), 1);
+        assert_eq!(HANDLED_DROPS.with(|h| h.get()), None);
+
+        backend.destroy_window(handle);
+        assert_eq!(send_message(handle, Tracked(drops.clone())), Err(MessageError::NotDelivered));
+        assert_eq!(drops.get(), 2);
+        assert_eq!(Rc::strong_count(&drops), 1);
     }
 }
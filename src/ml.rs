@@ -1,82 +1,108 @@
 #![allow(non_upper_case_globals)]
 
 use winapi::*;
-use user32::*;
 
-use std::mem;
+use ::backend::{ self, Surface };
+use ::colors::Color;
+use ::font::Font;
+use ::geometry::{ Point, Rect };
+
 use std::ops::{ Deref };
 
 pub struct DeviceContext<'a> {
     window: &'a HWND,
-    context: HDC,
+    surface: Box<Surface>,
 }
 
 impl<'a> DeviceContext<'a> {
     pub fn from_hwnd(hwnd: &'a HWND) -> Self {
-        unsafe {
-            DeviceContext {
-                window: hwnd,
-                context: GetDC(*hwnd),
-            }
-        }
-    }
-}
-
-impl<'a> Drop for DeviceContext<'a> {
-    fn drop(&mut self) {
-        unsafe {
-            ReleaseDC(*self.window, self.context);
+        DeviceContext {
+            window: hwnd,
+            surface: backend::current().get_dc(*hwnd),
         }
     }
 }
 
 impl<'a> Deref for DeviceContext<'a> {
-    type Target = HDC;
+    type Target = Surface;
 
     fn deref(&self) -> &Self::Target {
-        &self.context
+        &*self.surface
     }
 }
 
 pub struct PaintContext<'a> {
     pub window: &'a HWND,
-    pub context: HDC,
-    pub paintstruct: PAINTSTRUCT,
+    /// The area to repaint, in the coordinates of whatever is being painted.
+    pub paint_rect: Rect,
+    surface: Box<Surface + 'a>,
 }
 
 impl<'a> PaintContext<'a> {
     pub fn begin_paint(handle: &'a HWND) -> Self {
-        unsafe {
-            let mut ps = mem::zeroed();
-            let hdc = BeginPaint(*handle, &mut ps);
-            PaintContext {
-                window: handle,
-                context: hdc,
-                paintstruct: ps,
-            }
+        let (paint_rect, surface) = backend::current().begin_paint(*handle);
+        PaintContext::from_surface(handle, paint_rect, surface)
+    }
+
+    /// Paints onto an arbitrary surface instead of the window itself.
+    pub fn from_surface(handle: &'a HWND, paint_rect: Rect, surface: Box<Surface>) -> Self {
+        PaintContext {
+            window: handle,
+            paint_rect: paint_rect,
+            surface: surface,
         }
     }
-}
 
-impl<'a> Drop for PaintContext<'a> {
-    fn drop(&mut self) {
-        unsafe {
-            EndPaint(*self.window, &mut self.paintstruct);
+    /// A context whose origin is at `origin` in this one's coordinates, for
+    /// painting children in their parent's coordinate space.
+    pub fn translate<'b>(&'b self, origin: Point) -> PaintContext<'b> {
+        PaintContext {
+            window: self.window,
+            paint_rect: self.paint_rect.offset(-origin.x, -origin.y),
+            surface: Box::new(Translated { surface: &*self.surface, origin: origin }),
         }
     }
 }
 
 impl<'a> Deref for PaintContext<'a> {
-    type Target = HDC;
+    type Target = Surface + 'a;
 
     fn deref(&self) -> &Self::Target {
-        &self.context
+        &*self.surface
+    }
+}
+
+struct Translated<'a> {
+    surface: &'a Surface,
+    origin: Point,
+}
+
+impl<'a> Surface for Translated<'a> {
+    fn fill_rect(&self, rect: &Rect, color: Color) {
+        self.surface.fill_rect(&rect.offset(self.origin.x, self.origin.y), color);
+    }
+
+    fn draw_line(&self, from: Point, to: Point, color: Color) {
+        self.surface.draw_line(from + self.origin, to + self.origin, color);
+    }
+
+    fn draw_text(&self, text: &str, font: &Font, color: Color, rect: &Rect, format: UINT) {
+        self.surface.draw_text(text, font, color, &rect.offset(self.origin.x, self.origin.y), format);
     }
 }
 
 pub trait Paintable {
     fn paint(&self, c: &PaintContext);
-    fn needs_repaint(&self, _c: &PaintContext) -> bool {
-        true
+
+    /// The area this paints into, if it is known.
+    fn bounds(&self) -> Option<Rect> {
+        None
+    }
+
+    fn needs_repaint(&self, c: &PaintContext) -> bool {
+        match self.bounds() {
+            Some(bounds) => bounds.intersects(&c.paint_rect),
+            None => true,
+        }
     }
 }
@@ -0,0 +1,266 @@
+//! Software rendering into an in-memory RGBA image.
+//!
+//! `RasterSurface` implements `Surface` without any help from the platform,
+//! so anything `Paintable` can be drawn off-screen, e.g. for thumbnails or
+//! in CI. Text is drawn with the bitmap font from `glyphs`, scaled to the
+//! requested font size.
+
+pub mod glyphs;
+pub mod png;
+
+use winapi::*;
+
+use ::backend::Surface;
+use ::colors::Color;
+use ::font::Font;
+use ::geometry::{ Point, Rect };
+use ::ml::{ Paintable, PaintContext };
+use ::window::get_window_from_handle;
+
+use std::cell::RefCell;
+use std::cmp::{ max, min };
+use std::ptr;
+use std::rc::Rc;
+
+/// An RGBA image, four bytes per pixel, rows top to bottom.
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![0; byte_len(width, height)],
+        }
+    }
+
+    /// Creates an image filled with `color`.
+    pub fn filled(width: u32, height: u32, color: Color) -> Self {
+        let mut image = Image::new(width, height);
+        for pixel in image.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&rgba(color));
+        }
+        image
+    }
+
+    /// Wraps existing RGBA data, returning `None` if the size does not match.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<u8>) -> Option<Self> {
+        if pixels.len() == byte_len(width, height) {
+            Some(Image { width: width, height: height, pixels: pixels })
+        } else {
+            None
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.index(x, y);
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, value: [u8; 4]) {
+        let i = self.index(x, y);
+        self.pixels[i..i + 4].copy_from_slice(&value);
+    }
+
+    /// Sets a pixel given in signed coordinates, ignoring anything outside.
+    fn plot(&mut self, x: i32, y: i32, color: Color) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.set_pixel(x as u32, y as u32, rgba(color));
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel ({}, {}) out of bounds", x, y);
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
+
+/// How many bytes the pixels of an image this large take.
+fn byte_len(width: u32, height: u32) -> usize {
+    (width as usize).checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .expect("image too large")
+}
+
+/// Converts a `Color` to RGBA bytes.
+///
+/// Like GDI, the rasterizer draws everything opaque and ignores the alpha
+/// component of `Color`.
+pub fn rgba(color: Color) -> [u8; 4] {
+    let Color(_, r, g, b) = color;
+    [r, g, b, 255]
+}
+
+/// The size of a single character cell for a font of the given size.
+///
+/// A `width` of zero picks the bitmap font's own aspect ratio, a negative
+/// `height` is treated like its absolute value, same as `CreateFontW`.
+pub fn cell_size(height: i32, width: i32) -> (i32, i32) {
+    let height = max(height.abs(), 1);
+    let width = if width > 0 {
+        width
+    } else {
+        max(height * glyphs::CELL_WIDTH / glyphs::CELL_HEIGHT, 1)
+    };
+    (width, height)
+}
+
+/// The extent of `text` drawn with a font of the given size, honouring
+/// line breaks.
+pub fn text_extent(text: &str, height: i32, width: i32) -> (i32, i32) {
+    let (cell_width, cell_height) = cell_size(height, width);
+    let lines = text.split('\n').collect::<Vec<_>>();
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    (longest as i32 * cell_width, lines.len() as i32 * cell_height)
+}
+
+/// A surface drawing into a shared `Image`.
+pub struct RasterSurface {
+    target: Rc<RefCell<Image>>,
+}
+
+impl RasterSurface {
+    pub fn new(target: Rc<RefCell<Image>>) -> Self {
+        RasterSurface {
+            target: target,
+        }
+    }
+
+    fn draw_glyph(&self, c: char, x: i32, y: i32, cell: (i32, i32), clip: &Rect, color: Color) {
+        let (cell_width, cell_height) = cell;
+        let mut image = self.target.borrow_mut();
+        for dy in 0..cell_height {
+            let py = y + dy;
+            if py < clip.top || py >= clip.bottom {
+                continue;
+            }
+            let row = dy * glyphs::CELL_HEIGHT / cell_height;
+            for dx in 0..cell_width {
+                let px = x + dx;
+                if px < clip.left || px >= clip.right {
+                    continue;
+                }
+                let column = dx * glyphs::CELL_WIDTH / cell_width;
+                if glyphs::is_set(c, column, row) {
+                    image.plot(px, py, color);
+                }
+            }
+        }
+    }
+}
+
+impl Surface for RasterSurface {
+    fn fill_rect(&self, rect: &Rect, color: Color) {
+        let mut image = self.target.borrow_mut();
+        let left = max(rect.left, 0);
+        let top = max(rect.top, 0);
+        let right = min(rect.right, image.width as i32);
+        let bottom = min(rect.bottom, image.height as i32);
+        for y in top..bottom {
+            for x in left..right {
+                image.plot(x, y, color);
+            }
+        }
+    }
+
+    /// Bresenham, leaving out the last point just like `LineTo`.
+    fn draw_line(&self, from: Point, to: Point, color: Color) {
+        let mut image = self.target.borrow_mut();
+        let (mut x, mut y) = (from.x, from.y);
+        let dx = (to.x - from.x).abs();
+        let dy = -(to.y - from.y).abs();
+        let sx = if from.x < to.x { 1 } else { -1 };
+        let sy = if from.y < to.y { 1 } else { -1 };
+        let mut err = dx + dy;
+        while x != to.x || y != to.y {
+            image.plot(x, y, color);
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn draw_text(&self, text: &str, font: &Font, color: Color, rect: &Rect, format: UINT) {
+        let cell = cell_size(font.height(), font.width());
+        let (cell_width, cell_height) = cell;
+        let lines = if format & DT_SINGLELINE != 0 {
+            vec![text.to_string()]
+        } else {
+            text.split('\n').map(|l| l.to_string()).collect()
+        };
+
+        let clip = if format & DT_NOCLIP != 0 {
+            let image = self.target.borrow();
+            Rect::new(0, 0, image.width as i32, image.height as i32)
+        } else {
+            *rect
+        };
+
+        let total_height = lines.len() as i32 * cell_height;
+        let mut y = if format & DT_BOTTOM != 0 {
+            rect.bottom - total_height
+        } else if format & DT_VCENTER != 0 {
+            rect.top + (rect.bottom - rect.top - total_height) / 2
+        } else {
+            rect.top
+        };
+
+        for line in lines {
+            let line_width = line.chars().count() as i32 * cell_width;
+            let mut x = if format & DT_RIGHT != 0 {
+                rect.right - line_width
+            } else if format & DT_CENTER != 0 {
+                rect.left + (rect.right - rect.left - line_width) / 2
+            } else {
+                rect.left
+            };
+            for c in line.chars() {
+                self.draw_glyph(c, x, y, cell, &clip, color);
+                x += cell_width;
+            }
+            y += cell_height;
+        }
+    }
+}
+
+/// Paints `what` into a new image of the given size, cleared to `background`.
+pub fn render<P: Paintable + ?Sized>(what: &P, width: u32, height: u32, background: Color) -> Image {
+    let target = Rc::new(RefCell::new(Image::filled(width, height, background)));
+    {
+        let handle: HWND = ptr::null_mut();
+        let paint_rect = Rect::new(0, 0, width as i32, height as i32);
+        let surface = Box::new(RasterSurface::new(target.clone()));
+        let context = PaintContext::from_surface(&handle, paint_rect, surface);
+        what.paint(&context);
+    }
+    let image = target.borrow().clone();
+    image
+}
+
+/// Paints one of our windows off-screen, whichever backend it lives in.
+pub fn render_window(handle: &HWND, width: u32, height: u32, background: Color) -> Image {
+    render(&**get_window_from_handle(handle), width, height, background)
+}
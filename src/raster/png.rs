@@ -0,0 +1,523 @@
+//! Just enough PNG to store and load `Image`s.
+//!
+//! Encoding always writes 8-bit RGBA with uncompressed deflate blocks, which
+//! keeps the encoder trivial and the output deterministic. Decoding handles
+//! any 8-bit RGB or RGBA file, so goldens that went through an image
+//! optimizer still load.
+
+use super::Image;
+
+use std::fmt;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+const COLOR_RGB: u8 = 2;
+const COLOR_RGBA: u8 = 6;
+
+#[derive(Debug)]
+pub struct DecodeError(pub &'static str);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid png: {}", self.0)
+    }
+}
+
+pub fn encode(image: &Image) -> Vec<u8> {
+    let stride = image.width() as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * image.height() as usize);
+    for row in image.pixels().chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    push_u32(&mut header, image.width());
+    push_u32(&mut header, image.height());
+    header.extend_from_slice(&[8, COLOR_RGBA, 0, 0, 0]);
+
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if data.len() < 8 || data[..8] != SIGNATURE {
+        return Err(DecodeError("missing signature"));
+    }
+
+    let mut pos = 8;
+    let mut header = None;
+    let mut compressed = Vec::new();
+    while pos + 12 <= data.len() {
+        let length = read_u32(&data[pos..]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        if pos + 12 + length > data.len() {
+            return Err(DecodeError("truncated chunk"));
+        }
+        let body = &data[pos + 8..pos + 8 + length];
+        if read_u32(&data[pos + 8 + length..]) != crc32(&data[pos + 4..pos + 8 + length]) {
+            return Err(DecodeError("chunk checksum mismatch"));
+        }
+        match kind {
+            b"IHDR" => {
+                if length != 13 {
+                    return Err(DecodeError("bad header"));
+                }
+                header = Some((read_u32(body), read_u32(&body[4..]), body[8], body[9], body[12]));
+            },
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => { },
+        }
+        pos += 12 + length;
+    }
+
+    let (width, height, depth, color, interlace) = match header {
+        Some(header) => header,
+        None => return Err(DecodeError("missing header")),
+    };
+    if depth != 8 || interlace != 0 {
+        return Err(DecodeError("only 8-bit, non-interlaced images are supported"));
+    }
+    let channels = match color {
+        COLOR_RGB => 3,
+        COLOR_RGBA => 4,
+        _ => return Err(DecodeError("only RGB and RGBA images are supported")),
+    };
+
+    let raw = try!(zlib_decompress(&compressed));
+    let stride = width as usize * channels;
+    if raw.len() != (stride + 1) * height as usize {
+        return Err(DecodeError("image data has the wrong size"));
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    let mut previous = vec![0u8; stride];
+    for line in raw.chunks(stride + 1) {
+        let mut current = line[1..].to_vec();
+        try!(unfilter(line[0], &mut current, &previous, channels));
+        for px in current.chunks(channels) {
+            pixels.extend_from_slice(&px[..3]);
+            pixels.push(if channels == 4 { px[3] } else { 255 });
+        }
+        previous = current;
+    }
+
+    Image::from_pixels(width, height, pixels).ok_or(DecodeError("image data has the wrong size"))
+}
+
+fn unfilter(filter: u8, current: &mut [u8], previous: &[u8], bpp: usize) -> Result<(), DecodeError> {
+    for i in 0..current.len() {
+        let a = if i >= bpp { current[i - bpp] as i16 } else { 0 };
+        let b = previous[i] as i16;
+        let c = if i >= bpp { previous[i - bpp] as i16 } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => (a + b) / 2,
+            4 => {
+                let p = a + b - c;
+                let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+            },
+            _ => return Err(DecodeError("unknown filter type")),
+        };
+        current[i] = current[i].wrapping_add(predicted as u8);
+    }
+    Ok(())
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    push_u32(out, body.len() as u32);
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    let crc = crc32(&out[start..]);
+    push_u32(out, crc);
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// Wraps `data` in a zlib stream made of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = if blocks.peek().is_none() { 1 } else { 0 };
+        let len = block.len() as u16;
+        out.extend_from_slice(&[last, len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.extend_from_slice(block);
+    }
+    push_u32(&mut out, adler32(data));
+    out
+}
+
+fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < 6 || data[0] & 0x0F != 8 || ((data[0] as u16) << 8 | data[1] as u16) % 31 != 0 {
+        return Err(DecodeError("bad zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(DecodeError("preset zlib dictionaries are not supported"));
+    }
+    let out = try!(Inflater::new(&data[2..]).inflate());
+    if read_u32(&data[data.len() - 4..]) != adler32(&out) {
+        return Err(DecodeError("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// A canonical huffman code, decoded one bit at a time.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts: counts, symbols: symbols }
+    }
+}
+
+struct Inflater<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+    out: Vec<u8>,
+}
+
+impl<'a> Inflater<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Inflater { data: data, pos: 0, bit: 0, out: Vec::new() }
+    }
+
+    fn inflate(mut self) -> Result<Vec<u8>, DecodeError> {
+        loop {
+            let last = try!(self.bits(1));
+            match try!(self.bits(2)) {
+                0 => try!(self.stored()),
+                1 => {
+                    let (literals, distances) = fixed_codes();
+                    try!(self.codes(&literals, &distances));
+                },
+                2 => {
+                    let (literals, distances) = try!(self.dynamic_codes());
+                    try!(self.codes(&literals, &distances));
+                },
+                _ => return Err(DecodeError("invalid deflate block type")),
+            }
+            if last == 1 {
+                return Ok(self.out);
+            }
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, DecodeError> {
+        let mut value = 0;
+        for i in 0..count {
+            if self.pos >= self.data.len() {
+                return Err(DecodeError("unexpected end of deflate stream"));
+            }
+            value |= ((self.data[self.pos] as u32 >> self.bit) & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn stored(&mut self) -> Result<(), DecodeError> {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        if self.pos + 4 > self.data.len() {
+            return Err(DecodeError("unexpected end of deflate stream"));
+        }
+        let len = self.data[self.pos] as usize | (self.data[self.pos + 1] as usize) << 8;
+        let nlen = self.data[self.pos + 2] as usize | (self.data[self.pos + 3] as usize) << 8;
+        if len != !nlen & 0xFFFF {
+            return Err(DecodeError("stored block length mismatch"));
+        }
+        self.pos += 4;
+        if self.pos + len > self.data.len() {
+            return Err(DecodeError("unexpected end of deflate stream"));
+        }
+        self.out.extend_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(())
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, DecodeError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= try!(self.bits(1)) as i32;
+            let count = huffman.counts[len] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(DecodeError("invalid huffman code"))
+    }
+
+    fn dynamic_codes(&mut self) -> Result<(Huffman, Huffman), DecodeError> {
+        let literal_count = try!(self.bits(5)) as usize + 257;
+        let distance_count = try!(self.bits(5)) as usize + 1;
+        let code_count = try!(self.bits(4)) as usize + 4;
+
+        let mut code_lengths = [0u8; 19];
+        for &i in CODE_LENGTH_ORDER.iter().take(code_count) {
+            code_lengths[i] = try!(self.bits(3)) as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths);
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let symbol = try!(self.decode(&code_lengths));
+            let (value, repeat) = match symbol {
+                0...15 => (symbol as u8, 1),
+                16 => match lengths.last() {
+                    Some(&previous) => (previous, 3 + try!(self.bits(2))),
+                    None => return Err(DecodeError("repeat without a previous length")),
+                },
+                17 => (0, 3 + try!(self.bits(3))),
+                _ => (0, 11 + try!(self.bits(7))),
+            };
+            for _ in 0..repeat {
+                lengths.push(value);
+            }
+        }
+        if lengths.len() != literal_count + distance_count {
+            return Err(DecodeError("too many code lengths"));
+        }
+
+        Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+    }
+
+    fn codes(&mut self, literals: &Huffman, distances: &Huffman) -> Result<(), DecodeError> {
+        loop {
+            let symbol = try!(self.decode(literals)) as usize;
+            if symbol < 256 {
+                self.out.push(symbol as u8);
+            } else if symbol == 256 {
+                return Ok(());
+            } else {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(DecodeError("invalid length symbol"));
+                }
+                let length = LENGTH_BASE[symbol] as usize
+                    + try!(self.bits(LENGTH_EXTRA[symbol] as u32)) as usize;
+                let symbol = try!(self.decode(distances)) as usize;
+                if symbol >= DIST_BASE.len() {
+                    return Err(DecodeError("invalid distance symbol"));
+                }
+                let distance = DIST_BASE[symbol] as usize
+                    + try!(self.bits(DIST_EXTRA[symbol] as u32)) as usize;
+                if distance > self.out.len() {
+                    return Err(DecodeError("distance too far back"));
+                }
+                let start = self.out.len() - distance;
+                for i in 0..length {
+                    let byte = self.out[start + i];
+                    self.out.push(byte);
+                }
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0...143 => 8,
+            144...255 => 9,
+            256...279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{ adler32, crc32, zlib_decompress };
+    use ::raster::Image;
+
+    /// `zlib.compress(b"abracadabra abracadabra abracadabra", 9)`, one block
+    /// with the fixed huffman codes.
+    const FIXED: [u8; 21] = [
+        120, 218, 75, 76, 42, 74, 76, 78, 76, 73, 4, 82, 10, 137, 216, 217, 0, 238, 40, 13, 61];
+
+    /// `dynamic_text()` compressed by zlib, one block with dynamic codes.
+    const DYNAMIC: [u8; 156] = [
+        120, 218, 149, 212, 201, 25, 1, 81, 20, 68, 225, 189, 40, 110, 8, 110, 149, 49, 28, 116,
+        155, 121, 180, 215, 166, 232, 9, 128, 197, 217, 159, 221, 255, 85, 213, 109, 27, 215, 126,
+        183, 58, 196, 178, 43, 143, 115, 172, 203, 51, 134, 177, 239, 79, 151, 91, 148, 123, 219,
+        69, 253, 6, 199, 197, 251, 21, 77, 217, 12, 234, 143, 58, 81, 45, 84, 27, 213, 35, 84, 143,
+        81, 61, 65, 245, 20, 213, 51, 84, 207, 153, 14, 196, 100, 154, 201, 56, 147, 121, 38, 3, 77,
+        38, 154, 140, 52, 153, 105, 50, 212, 100, 170, 98, 170, 130, 27, 101, 170, 98, 170, 98, 170,
+        98, 170, 98, 170, 98, 170, 98, 170, 98, 170, 102, 170, 102, 170, 134, 215, 203, 84, 205, 84,
+        205, 84, 205, 84, 205, 84, 205, 84, 253, 95, 245, 3, 222, 208, 147, 167];
+
+    /// A 4x5 RGB image whose rows use the filters none, sub, up, average and
+    /// paeth in that order, with the pixels of `filtered_pixel`.
+    const FILTERED: [u8; 124] = [
+        137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 4, 0, 0, 0, 5, 8, 2,
+        0, 0, 0, 237, 207, 218, 140, 0, 0, 0, 67, 73, 68, 65, 84, 120, 218, 99, 96, 96, 56, 161,
+        193, 120, 34, 128, 233, 68, 5, 243, 9, 70, 118, 35, 32, 231, 59, 4, 49, 177, 27, 49, 176,
+        27, 125, 103, 55, 122, 199, 110, 244, 148, 153, 47, 37, 69, 66, 234, 157, 132, 212, 43, 9,
+        169, 167, 44, 32, 25, 198, 239, 236, 140, 239, 216, 25, 159, 2, 0, 123, 226, 19, 116, 252,
+        162, 243, 247, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..40).flat_map(|i| format!("the quick brown fox {} jumps over the lazy dog\n", i).into_bytes()).collect()
+    }
+
+    fn filtered_pixel(x: u32, y: u32) -> [u8; 4] {
+        [(x * 40 + y * 7) as u8, (y * 50 + x) as u8, (200 - x * y * 9) as u8, 255]
+    }
+
+    fn gradient(width: u32, height: u32) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, [(x * 13) as u8, (y * 29) as u8, (x * y) as u8, (x * 7 + y) as u8]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn checksums_match_the_reference_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn encoded_images_decode_to_the_same_pixels() {
+        for &(width, height) in [(1, 1), (3, 2), (17, 9), (300, 250)].iter() {
+            let image = gradient(width, height);
+            let decoded = decode(&encode(&image)).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (width, height));
+            assert_eq!(decoded.pixels(), image.pixels());
+        }
+    }
+
+    #[test]
+    fn large_images_span_several_stored_blocks() {
+        // More than the 65535 bytes a stored block can hold.
+        let image = gradient(200, 120);
+        assert!(image.pixels().len() > 65535);
+        assert_eq!(decode(&encode(&image)).unwrap().pixels(), image.pixels());
+    }
+
+    #[test]
+    fn inflates_fixed_huffman_blocks() {
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), b"abracadabra abracadabra abracadabra".to_vec());
+    }
+
+    #[test]
+    fn inflates_dynamic_huffman_blocks() {
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), dynamic_text());
+    }
+
+    #[test]
+    fn undoes_every_filter_type() {
+        let image = decode(&FILTERED).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 5));
+        for y in 0..5 {
+            for x in 0..4 {
+                assert_eq!(image.get_pixel(x, y), filtered_pixel(x, y), "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        assert!(decode(b"not a png").is_err());
+
+        let mut data = encode(&gradient(4, 4));
+        let last = data.len() - 20;
+        data[last] ^= 0xFF;
+        assert!(decode(&data).is_err());
+
+        let mut checksum = DYNAMIC.to_vec();
+        let end = checksum.len() - 1;
+        checksum[end] ^= 1;
+        assert!(zlib_decompress(&checksum).is_err());
+        assert!(zlib_decompress(&DYNAMIC[..40]).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! A set of dirty rectangles.
+
+use ::geometry::Rect;
+
+/// A region made of non-empty rectangles.
+///
+/// Adding a rectangle drops it if the region already covers it and merges
+/// it with its neighbours whenever their union covers no more area than
+/// the two did on their own, so repeated invalidation of the same or
+/// adjacent areas stays a handful of rectangles.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Region {
+    rects: Vec<Rect>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Region { rects: Vec::new() }
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    /// Empties the region, returning what it contained.
+    pub fn take(&mut self) -> Region {
+        Region { rects: ::std::mem::replace(&mut self.rects, Vec::new()) }
+    }
+
+    /// The smallest rectangle covering the whole region.
+    pub fn bounds(&self) -> Rect {
+        self.rects.iter().fold(Rect::default(), |acc, r| acc.union(r))
+    }
+
+    pub fn intersects(&self, rect: &Rect) -> bool {
+        self.rects.iter().any(|r| r.intersects(rect))
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() || self.rects.iter().any(|r| r.contains_rect(&rect)) {
+            return;
+        }
+
+        let mut rect = rect;
+        loop {
+            self.rects.retain(|r| !rect.contains_rect(r));
+            let mergeable = self.rects.iter().position(|r| can_merge(r, &rect));
+            match mergeable {
+                Some(i) => {
+                    let other = self.rects.swap_remove(i);
+                    rect = rect.union(&other);
+                },
+                None => break,
+            }
+        }
+        self.rects.push(rect);
+    }
+}
+
+fn area(r: &Rect) -> i64 {
+    if r.is_empty() { 0 } else { r.width() as i64 * r.height() as i64 }
+}
+
+/// Whether replacing `a` and `b` by their union does not add any area that
+/// was not dirty before.
+fn can_merge(a: &Rect, b: &Rect) -> bool {
+    let overlap = a.intersection(b).map_or(0, |r| area(&r));
+    area(&a.union(b)) <= area(a) + area(b) - overlap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::geometry::Rect;
+
+    fn region(rects: &[Rect]) -> Region {
+        let mut region = Region::new();
+        for &rect in rects {
+            region.add(rect);
+        }
+        region
+    }
+
+    #[test]
+    fn empty_rects_are_ignored() {
+        let region = region(&[Rect::default(), Rect::new(5, 5, 5, 10), Rect::new(10, 0, 0, 10)]);
+        assert!(region.is_empty());
+        assert_eq!(region.bounds(), Rect::default());
+    }
+
+    #[test]
+    fn contained_rects_are_dropped() {
+        let outer = Rect::new(0, 0, 100, 100);
+        assert_eq!(region(&[outer, Rect::new(10, 10, 20, 20), outer]).rects(), &[outer]);
+    }
+
+    #[test]
+    fn containing_rects_replace_what_they_cover() {
+        let outer = Rect::new(0, 0, 100, 100);
+        let region = region(&[Rect::new(10, 10, 20, 20), Rect::new(50, 60, 70, 80), outer]);
+        assert_eq!(region.rects(), &[outer]);
+    }
+
+    #[test]
+    fn adjacent_rects_of_the_same_height_merge() {
+        let region = region(&[Rect::new(0, 0, 10, 10), Rect::new(10, 0, 25, 10), Rect::new(25, 0, 30, 10)]);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 30, 10)]);
+
+        let column = self::region(&[Rect::new(0, 20, 10, 30), Rect::new(0, 0, 10, 20)]);
+        assert_eq!(column.rects(), &[Rect::new(0, 0, 10, 30)]);
+    }
+
+    #[test]
+    fn overlapping_rects_merge_when_nothing_new_gets_dirty() {
+        // The same rows, overlapping columns: the union is exactly the two.
+        let region = region(&[Rect::new(0, 0, 20, 10), Rect::new(15, 0, 40, 10)]);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 40, 10)]);
+    }
+
+    #[test]
+    fn rects_that_would_grow_the_region_stay_apart() {
+        // Diagonal neighbours and an L-shaped overlap: their unions would
+        // cover area that was never invalidated.
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(10, 10, 20, 20);
+        let c = Rect::new(5, 5, 30, 8);
+        let region = region(&[a, b, c]);
+        assert_eq!(region.rects().len(), 3);
+        for rect in &[a, b, c] {
+            assert!(region.rects().iter().any(|r| r.contains_rect(rect)), "{:?} got lost", rect);
+        }
+        assert_eq!(region.bounds(), Rect::new(0, 0, 30, 20));
+        assert!(region.intersects(&Rect::new(15, 15, 16, 16)));
+        assert!(!region.intersects(&Rect::new(12, 0, 20, 5)));
+    }
+
+    #[test]
+    fn merging_cascades() {
+        // The last rect bridges the two, and their union with it covers
+        // exactly the same area.
+        let region = region(&[Rect::new(0, 0, 10, 10), Rect::new(20, 0, 30, 10), Rect::new(10, 0, 20, 10)]);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 30, 10)]);
+    }
+
+    #[test]
+    fn take_empties_the_region() {
+        let mut region = region(&[Rect::new(0, 0, 10, 10)]);
+        let taken = region.take();
+        assert!(region.is_empty());
+        assert_eq!(taken.rects(), &[Rect::new(0, 0, 10, 10)]);
+    }
+}
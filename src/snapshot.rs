@@ -0,0 +1,324 @@
+//! Golden-image checks for windows and controls.
+//!
+//! A `Snapshot` paints something off-screen, compares the result against
+//! `<golden dir>/<name>.png` and, on a mismatch, leaves `<name>.actual.png`
+//! and `<name>.diff.png` next to it. A missing golden is an error too, so a
+//! deleted one can't make a check pass; set `UPDATE_GOLDEN=1` to write new
+//! goldens, or overwrite existing ones after an intended visual change.
+
+use ::colors::{ Color, WHITE };
+use ::ml::Paintable;
+use ::raster::{ self, Image };
+use ::raster::png::{ self, DecodeError };
+
+use std::env;
+use std::fmt;
+use std::fs::{ self, File };
+use std::io::{ self, Read, Write };
+use std::path::PathBuf;
+
+pub const DEFAULT_GOLDEN_DIR: &'static str = "tests/golden";
+pub const UPDATE_VARIABLE: &'static str = "UPDATE_GOLDEN";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// There is no golden image yet at this path.
+    MissingGolden(PathBuf),
+    Decode(DecodeError),
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    /// `pixels` pixels differ by more than the tolerance, the worst channel
+    /// by `max_difference`.
+    Mismatch { pixels: usize, max_difference: u8, diff: PathBuf },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::Io(ref e) => write!(f, "{}", e),
+            SnapshotError::MissingGolden(ref path) =>
+                write!(f, "{} is missing, run with {}=1 to create it", path.display(), UPDATE_VARIABLE),
+            SnapshotError::Decode(ref e) => write!(f, "{}", e),
+            SnapshotError::SizeMismatch { expected, actual } =>
+                write!(f, "golden is {}x{}, but got {}x{}", expected.0, expected.1, actual.0, actual.1),
+            SnapshotError::Mismatch { pixels, max_difference, ref diff } =>
+                write!(f, "{} pixels differ (by up to {}), see {}", pixels, max_difference, diff.display()),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<DecodeError> for SnapshotError {
+    fn from(e: DecodeError) -> Self {
+        SnapshotError::Decode(e)
+    }
+}
+
+pub struct Snapshot {
+    name: String,
+    width: u32,
+    height: u32,
+    background: Color,
+    tolerance: u8,
+    golden_dir: PathBuf,
+    /// Whether to write the golden instead of comparing against it.
+    update: bool,
+}
+
+impl Snapshot {
+    pub fn new<S: Into<String>>(name: S, width: u32, height: u32) -> Self {
+        Snapshot {
+            name: name.into(),
+            width: width,
+            height: height,
+            background: WHITE,
+            tolerance: 0,
+            golden_dir: PathBuf::from(DEFAULT_GOLDEN_DIR),
+            update: env::var(UPDATE_VARIABLE).map(|v| v == "1").unwrap_or(false),
+        }
+    }
+
+    /// The largest per-channel difference that still counts as a match.
+    pub fn set_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn set_background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn set_golden_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.golden_dir = dir.into();
+        self
+    }
+
+    /// Writes the golden instead of comparing against it. Defaults to
+    /// whether `UPDATE_GOLDEN=1` is set.
+    pub fn set_update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Paints `what` and compares it against the golden image.
+    pub fn check<P: Paintable + ?Sized>(&self, what: &P) -> Result<(), SnapshotError> {
+        let image = raster::render(what, self.width, self.height, self.background);
+        self.check_image(&image)
+    }
+
+    /// Like `check`, but panics with a readable message on a mismatch.
+    pub fn assert<P: Paintable + ?Sized>(&self, what: &P) {
+        if let Err(e) = self.check(what) {
+            panic!("snapshot '{}' does not match: {}", self.name, e);
+        }
+    }
+
+    pub fn check_image(&self, actual: &Image) -> Result<(), SnapshotError> {
+        let golden_path = self.path("png");
+        if self.update {
+            try!(fs::create_dir_all(&self.golden_dir));
+            return write_png(&golden_path, actual);
+        }
+        if !golden_path.exists() {
+            return Err(SnapshotError::MissingGolden(golden_path));
+        }
+
+        let mut data = Vec::new();
+        try!(try!(File::open(&golden_path)).read_to_end(&mut data));
+        let expected = try!(png::decode(&data));
+        if (expected.width(), expected.height()) != (actual.width(), actual.height()) {
+            try!(write_png(&self.path("actual.png"), actual));
+            return Err(SnapshotError::SizeMismatch {
+                expected: (expected.width(), expected.height()),
+                actual: (actual.width(), actual.height()),
+            });
+        }
+
+        let comparison = compare(&expected, actual, self.tolerance);
+        if comparison.mismatched == 0 {
+            return Ok(());
+        }
+
+        let diff_path = self.path("diff.png");
+        try!(write_png(&self.path("actual.png"), actual));
+        try!(write_png(&diff_path, &comparison.diff));
+        Err(SnapshotError::Mismatch {
+            pixels: comparison.mismatched,
+            max_difference: comparison.max_difference,
+            diff: diff_path,
+        })
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        self.golden_dir.join(format!("{}.{}", self.name, extension))
+    }
+}
+
+pub struct Comparison {
+    pub mismatched: usize,
+    pub max_difference: u8,
+    /// A faded copy of `actual` with every mismatching pixel painted red.
+    pub diff: Image,
+}
+
+/// Compares two images of the same size channel by channel.
+pub fn compare(expected: &Image, actual: &Image, tolerance: u8) -> Comparison {
+    assert_eq!((expected.width(), expected.height()), (actual.width(), actual.height()));
+    let mut diff = Image::new(actual.width(), actual.height());
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    for y in 0..actual.height() {
+        for x in 0..actual.width() {
+            let (e, a) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+            let difference = (0..4).map(|i| (e[i] as i16 - a[i] as i16).abs() as u8).max().unwrap();
+            if difference > max_difference {
+                max_difference = difference;
+            }
+            if difference > tolerance {
+                mismatched += 1;
+                diff.set_pixel(x, y, [255, 0, 0, 255]);
+            } else {
+                let gray = ((a[0] as u16 + a[1] as u16 + a[2] as u16) / 3) as u8;
+                let faded = 192 + gray / 4;
+                diff.set_pixel(x, y, [faded, faded, faded, 255]);
+            }
+        }
+    }
+    Comparison { mismatched: mismatched, max_difference: max_difference, diff: diff }
+}
+
+fn write_png(path: &PathBuf, image: &Image) -> Result<(), SnapshotError> {
+    let mut file = try!(File::create(path));
+    try!(file.write_all(&png::encode(image)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::colors::{ BLACK, BLUE };
+    use ::controls::Control;
+    use ::controls::button::Button;
+    use ::controls::label::Label;
+    use ::geometry::Rect;
+    use ::raster::{ self, Image };
+    use ::testing::{ self, open_window };
+
+    /// An empty scratch directory for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("windows-test-snapshot-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn checkerboard(width: u32, height: u32) -> Image {
+        let mut image = Image::filled(width, height, WHITE);
+        for y in 0..height {
+            for x in 0..width {
+                if (x + y) % 2 == 0 {
+                    image.set_pixel(x, y, [0, 0, 0, 255]);
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn label_matches_its_golden() {
+        HeadlessBackend::install();
+        let mut label = Label::new();
+        label.text = "Golden".to_string();
+        label.foreground_color = BLUE;
+        label.set_position(4, 2);
+        label.set_size(60, 12);
+        Snapshot::new("label", 64, 16).assert(&label);
+    }
+
+    #[test]
+    fn window_matches_its_golden() {
+        let (_backend, mut handle) = open_window(120, 60);
+        let mut label = Label::new();
+        label.text = "Name".to_string();
+        label.foreground_color = BLACK;
+        label.set_position(8, 8);
+        label.set_size(40, 12);
+        let mut button = Button::new("OK");
+        button.set_bounds(Rect::new(8, 28, 68, 52));
+        testing::core(&mut handle).add_control(Box::new(label));
+        testing::core(&mut handle).add_control(Box::new(button));
+
+        let image = raster::render_window(&handle, 120, 60, WHITE);
+        if let Err(e) = Snapshot::new("window", 120, 60).check_image(&image) {
+            panic!("snapshot 'window' does not match: {}", e);
+        }
+    }
+
+    #[test]
+    fn a_missing_golden_is_an_error() {
+        let dir = scratch_dir("missing");
+        let snapshot = Snapshot::new("missing", 4, 4).set_golden_dir(dir.clone()).set_update(false);
+        match snapshot.check_image(&checkerboard(4, 4)) {
+            Err(SnapshotError::MissingGolden(path)) => assert_eq!(path, dir.join("missing.png")),
+            other => panic!("expected a missing golden, got {:?}", other),
+        }
+        assert!(!dir.join("missing.png").exists());
+    }
+
+    #[test]
+    fn updating_writes_the_golden_it_then_matches() {
+        let dir = scratch_dir("update");
+        let image = checkerboard(6, 5);
+        Snapshot::new("board", 6, 5).set_golden_dir(dir.clone()).set_update(true).check_image(&image).unwrap();
+        assert!(dir.join("board.png").exists());
+        Snapshot::new("board", 6, 5).set_golden_dir(dir.clone()).set_update(false).check_image(&image).unwrap();
+    }
+
+    #[test]
+    fn mismatches_leave_the_actual_and_diff_images() {
+        let dir = scratch_dir("mismatch");
+        let board = |update: bool| Snapshot::new("board", 6, 5).set_golden_dir(dir.clone()).set_update(update);
+        board(true).check_image(&checkerboard(6, 5)).unwrap();
+
+        let mut changed = checkerboard(6, 5);
+        changed.set_pixel(2, 3, [10, 0, 0, 255]);
+        match board(false).check_image(&changed) {
+            Err(SnapshotError::Mismatch { pixels, max_difference, diff }) => {
+                assert_eq!((pixels, max_difference), (1, 255));
+                assert_eq!(diff, dir.join("board.diff.png"));
+            },
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        assert!(dir.join("board.actual.png").exists());
+        assert!(dir.join("board.diff.png").exists());
+
+        // Within the tolerance it is a match after all.
+        let mut close = checkerboard(6, 5);
+        close.set_pixel(0, 1, [250, 255, 255, 255]);
+        board(false).set_tolerance(5).check_image(&close).unwrap();
+
+        match board(false).check_image(&checkerboard(5, 5)) {
+            Err(SnapshotError::SizeMismatch { expected, actual }) => assert_eq!((expected, actual), ((6, 5), (5, 5))),
+            other => panic!("expected a size mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compare_marks_the_differing_pixels() {
+        let expected = checkerboard(3, 3);
+        let mut actual = checkerboard(3, 3);
+        actual.set_pixel(1, 1, [0, 0, 0, 255]);
+        actual.set_pixel(2, 2, [255, 255, 255, 255]);
+        let comparison = compare(&expected, &actual, 0);
+        assert_eq!(comparison.mismatched, 1);
+        assert_eq!(comparison.max_difference, 255);
+        assert_eq!(comparison.diff.get_pixel(2, 2), [255, 0, 0, 255]);
+        assert!(comparison.diff.get_pixel(0, 0) != [255, 0, 0, 255]);
+    }
+}
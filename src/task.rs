@@ -0,0 +1,407 @@
+//! Futures that run on the UI thread.
+//!
+//! A task is a `Future` spawned onto a window with `WindowCore::spawn_local`.
+//! It gets polled from the window's message handling, with the window at
+//! hand, so it can update controls directly. Waking a task posts `WM_WAKE`
+//! to the window; a task only gets polled again once that message arrives.
+//! Tasks still running when the window goes away are dropped with it.
+
+use ::backend;
+use ::window::WindowCore;
+use ::winapi::*;
+
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+
+/// Posted to a window with a task id as `WPARAM` to have that task polled.
+pub const WM_WAKE: UINT = WM_APP;
+
+/// Whether a future is done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Poll<T> {
+    Ready(T),
+    Pending,
+}
+
+/// A computation that finishes at some point on the UI thread.
+pub trait Future {
+    type Output;
+
+    /// Makes as much progress as possible. Returning `Pending` means
+    /// `waker` gets woken once it's worth polling again.
+    fn poll(&mut self, core: &mut WindowCore, waker: &Waker) -> Poll<Self::Output>;
+
+    /// Passes the result through `f` once there is one.
+    fn map<F, U>(self, f: F) -> Map<Self, F>
+            where F: FnOnce(&mut WindowCore, Self::Output) -> U, Self: Sized {
+        Map { future: self, f: Some(f) }
+    }
+
+    /// Continues with the future `f` makes out of the result.
+    fn and_then<F, B>(self, f: F) -> AndThen<Self, B, F>
+            where F: FnOnce(&mut WindowCore, Self::Output) -> B, B: Future, Self: Sized {
+        AndThen::First(self, Some(f))
+    }
+}
+
+/// Gets a task polled again, from any thread.
+#[derive(Clone)]
+pub struct Waker {
+    handle: usize,
+    task: usize,
+    woken: Arc<AtomicBool>,
+    poster: Arc<backend::Waker>,
+}
+
+impl Waker {
+    /// Posts `WM_WAKE` for the task, unless it is already on its way.
+    pub fn wake(&self) {
+        if !self.woken.swap(true, Ordering::SeqCst) {
+            self.poster.post(self.handle, WM_WAKE, self.task as WPARAM, 0);
+        }
+    }
+}
+
+/// Identifies a task spawned with `WindowCore::spawn_local`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// The `WPARAM` of the task's `WM_WAKE`.
+    pub fn raw(&self) -> usize {
+        self.0
+    }
+}
+
+struct Entry {
+    /// Taken out while it is being polled.
+    future: Option<Box<Future<Output=()>>>,
+    waker: Waker,
+}
+
+/// The tasks of one window.
+pub struct Tasks {
+    next_id: usize,
+    entries: HashMap<usize, Entry>,
+}
+
+impl Tasks {
+    pub fn new() -> Self {
+        Tasks {
+            next_id: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, id: TaskId) -> bool {
+        self.entries.contains_key(&id.0)
+    }
+
+    /// Adds a task for the window `handle` and wakes it for its first poll.
+    pub fn spawn(&mut self, handle: HWND, future: Box<Future<Output=()>>) -> TaskId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let waker = Waker {
+            handle: handle as usize,
+            task: id,
+            woken: Arc::new(AtomicBool::new(false)),
+            poster: backend::current().waker(),
+        };
+        waker.wake();
+        self.entries.insert(id, Entry { future: Some(future), waker: waker });
+        TaskId(id)
+    }
+
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        self.entries.remove(&id.0).is_some()
+    }
+
+    /// Takes the future of task `id` out to poll it, along with its waker.
+    /// Wakes from now on get it polled again.
+    pub fn take(&mut self, id: usize) -> Option<(TaskId, Box<Future<Output=()>>, Waker)> {
+        self.entries.get_mut(&id).and_then(|entry| {
+            entry.waker.woken.store(false, Ordering::SeqCst);
+            let waker = entry.waker.clone();
+            entry.future.take().map(|future| (TaskId(id), future, waker))
+        })
+    }
+
+    /// Puts a pending future back, unless the task got cancelled while it
+    /// was polled.
+    pub fn restore(&mut self, id: TaskId, future: Box<Future<Output=()>>) {
+        if let Some(entry) = self.entries.get_mut(&id.0) {
+            entry.future = Some(future);
+        }
+    }
+}
+
+/// A future that is done right away.
+pub struct Ready<T>(Option<T>);
+
+pub fn ready<T>(value: T) -> Ready<T> {
+    Ready(Some(value))
+}
+
+impl<T> Future for Ready<T> {
+    type Output = T;
+
+    fn poll(&mut self, _core: &mut WindowCore, _waker: &Waker) -> Poll<T> {
+        match self.0.take() {
+            Some(value) => Poll::Ready(value),
+            None => panic!("polled a Ready after it completed"),
+        }
+    }
+}
+
+struct Shared<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// The result of a function running on another thread.
+pub struct Background<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// Runs `f` on a thread of its own, finishing with its result.
+pub fn background<T, F>(f: F) -> Background<T>
+        where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+    let shared = Arc::new(Mutex::new(Shared { result: None, waker: None }));
+    let theirs = shared.clone();
+    thread::spawn(move || {
+        let result = f();
+        let waker = {
+            let mut shared = theirs.lock().unwrap();
+            shared.result = Some(result);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+    Background { shared: shared }
+}
+
+impl<T> Future for Background<T> {
+    type Output = T;
+
+    fn poll(&mut self, _core: &mut WindowCore, waker: &Waker) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(waker.clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+pub struct Map<A, F> {
+    future: A,
+    f: Option<F>,
+}
+
+impl<A, F, U> Future for Map<A, F>
+        where A: Future, F: FnOnce(&mut WindowCore, A::Output) -> U {
+    type Output = U;
+
+    fn poll(&mut self, core: &mut WindowCore, waker: &Waker) -> Poll<U> {
+        match self.future.poll(core, waker) {
+            Poll::Ready(value) => {
+                let f = self.f.take().expect("polled a Map after it completed");
+                Poll::Ready(f(core, value))
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub enum AndThen<A, B, F> {
+    First(A, Option<F>),
+    Second(B),
+}
+
+impl<A, B, F> Future for AndThen<A, B, F>
+        where A: Future, B: Future, F: FnOnce(&mut WindowCore, A::Output) -> B {
+    type Output = B::Output;
+
+    fn poll(&mut self, core: &mut WindowCore, waker: &Waker) -> Poll<B::Output> {
+        let second = match *self {
+            AndThen::First(ref mut first, ref mut f) => match first.poll(core, waker) {
+                Poll::Ready(value) => {
+                    let f = f.take().expect("polled an AndThen after it completed");
+                    f(core, value)
+                },
+                Poll::Pending => return Poll::Pending,
+            },
+            AndThen::Second(ref mut second) => return second.poll(core, waker),
+        };
+        *self = AndThen::Second(second);
+        self.poll(core, waker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::Backend;
+    use ::testing::{ self, open_window };
+
+    use std::cell::{ Cell, RefCell };
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// How a `Probe` got on.
+    #[derive(Default)]
+    struct Probed {
+        polls: Cell<usize>,
+        drops: Cell<usize>,
+        waker: RefCell<Option<Waker>>,
+    }
+
+    /// Pending until it was polled `ready_after` times, keeping the waker
+    /// around for the test to wake it.
+    struct Probe {
+        probed: Rc<Probed>,
+        ready_after: usize,
+    }
+
+    impl Future for Probe {
+        type Output = ();
+
+        fn poll(&mut self, _core: &mut WindowCore, waker: &Waker) -> Poll<()> {
+            let polls = self.probed.polls.get() + 1;
+            self.probed.polls.set(polls);
+            *self.probed.waker.borrow_mut() = Some(waker.clone());
+            if polls >= self.ready_after { Poll::Ready(()) } else { Poll::Pending }
+        }
+    }
+
+    impl Drop for Probe {
+        fn drop(&mut self) {
+            self.probed.drops.set(self.probed.drops.get() + 1);
+        }
+    }
+
+    fn probe(ready_after: usize) -> (Probe, Rc<Probed>) {
+        let probed = Rc::new(Probed::default());
+        (Probe { probed: probed.clone(), ready_after: ready_after }, probed)
+    }
+
+    fn wake(probed: &Probed) {
+        probed.waker.borrow().as_ref().unwrap().wake();
+    }
+
+    #[test]
+    fn tasks_are_polled_again_once_woken() {
+        let (backend, mut handle) = open_window(100, 100);
+        let (future, probed) = probe(2);
+        let id = testing::core(&mut handle).spawn_local(future);
+        assert_eq!(probed.polls.get(), 0);
+        assert_eq!(backend.pending_messages(), 1);
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 1);
+
+        // Nothing happens until the waker posts WM_WAKE, and only one of
+        // those however often it is woken.
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 1);
+        wake(&probed);
+        wake(&probed);
+        assert_eq!(backend.pending_messages(), 1);
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 2);
+
+        // Done: the future is gone and waking it again does nothing.
+        assert_eq!(probed.drops.get(), 1);
+        assert!(!testing::core(&mut handle).cancel_task(id));
+        wake(&probed);
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 2);
+    }
+
+    thread_local!(static UI_THREAD: Cell<bool> = Cell::new(false));
+
+    #[test]
+    fn background_results_arrive_on_the_ui_thread() {
+        let (backend, mut handle) = open_window(100, 100);
+        UI_THREAD.with(|ui| ui.set(true));
+        let result = Rc::new(RefCell::new(None));
+        {
+            let result = result.clone();
+            let task = background(|| (UI_THREAD.with(|ui| ui.get()), 6 * 7))
+                .map(move |_core, (worker_is_ui, value)| {
+                    *result.borrow_mut() = Some((worker_is_ui, UI_THREAD.with(|ui| ui.get()), value));
+                });
+            testing::core(&mut handle).spawn_local(task);
+        }
+
+        for _ in 0..500 {
+            backend.dispatch_pending();
+            if result.borrow().is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(*result.borrow(), Some((false, true, 42)));
+    }
+
+    #[test]
+    fn and_then_runs_the_second_future_with_the_result() {
+        let (backend, mut handle) = open_window(100, 100);
+        let result = Rc::new(Cell::new(0));
+        {
+            let result = result.clone();
+            let task = ready(20)
+                .and_then(|_core, value| ready(value + 1))
+                .map(move |_core, value| result.set(value * 2));
+            testing::core(&mut handle).spawn_local(task);
+        }
+        backend.dispatch_pending();
+        assert_eq!(result.get(), 42);
+    }
+
+    #[test]
+    fn cancelling_drops_the_future() {
+        let (backend, mut handle) = open_window(100, 100);
+        let (future, probed) = probe(usize::max_value());
+        let id = testing::core(&mut handle).spawn_local(future);
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 1);
+
+        wake(&probed);
+        assert!(testing::core(&mut handle).cancel_task(id));
+        assert_eq!(probed.drops.get(), 1);
+        // The wakeup already on its way finds nothing to poll.
+        backend.dispatch_pending();
+        assert_eq!(probed.polls.get(), 1);
+        assert!(!testing::core(&mut handle).cancel_task(id));
+    }
+
+    #[test]
+    fn destroying_the_window_drops_its_tasks() {
+        let (backend, mut handle) = open_window(100, 100);
+        let (first, first_probed) = probe(usize::max_value());
+        let (second, second_probed) = probe(usize::max_value());
+        testing::core(&mut handle).spawn_local(first);
+        backend.dispatch_pending();
+        testing::core(&mut handle).spawn_local(second);
+
+        backend.destroy_window(handle);
+        assert_eq!((first_probed.drops.get(), second_probed.drops.get()), (1, 1));
+        wake(&first_probed);
+        backend.dispatch_pending();
+        assert_eq!((first_probed.polls.get(), second_probed.polls.get()), (1, 0));
+    }
+}
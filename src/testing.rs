@@ -0,0 +1,120 @@
+//! Helpers for tests that drive windows through the headless backend.
+
+use winapi::*;
+
+use ::backend::Backend;
+use ::backend::headless::HeadlessBackend;
+use ::event::{ Event, KeyFlags, Modifiers, MouseButton };
+use ::geometry::Point;
+use ::ml::{ Paintable, PaintContext };
+use ::window::{ self, Window, WindowBuilder, WindowClass, WindowClassBuilder, WindowCore };
+
+use std::ptr;
+use std::rc::Rc;
+
+/// A window that only has controls.
+pub struct TestWindow {
+    core: Option<WindowCore>,
+}
+
+impl Window for TestWindow {
+    fn init_handle(&mut self, handle: HWND) {
+        self.core = Some(WindowCore::from_handle(handle));
+    }
+
+    fn get_core<'a>(&'a self) -> &'a WindowCore {
+        self.core.as_ref().unwrap()
+    }
+
+    fn get_core_mut<'a>(&'a mut self) -> &'a mut WindowCore {
+        self.core.as_mut().unwrap()
+    }
+}
+
+impl Paintable for TestWindow {
+    fn paint(&self, context: &PaintContext) {
+        self.get_core().paint(context);
+    }
+}
+
+impl WindowClass for TestWindow {
+    fn class_name() -> &'static str { "test_wnd_class" }
+    fn default_title() -> &'static str { "Test" }
+
+    fn new() -> Box<Window> {
+        Box::new(TestWindow {
+            core: None,
+        })
+    }
+}
+
+/// Makes a headless backend the one of this thread and opens a window with
+/// a client area of `width` by `height` on it.
+pub fn open_window(width: i32, height: i32) -> (Rc<HeadlessBackend>, HWND) {
+    let backend = HeadlessBackend::install();
+    WindowClassBuilder::<TestWindow>::new().register(ptr::null_mut());
+    let handle = WindowBuilder::<TestWindow>::new(ptr::null_mut())
+        .set_width(width)
+        .set_height(height)
+        .build()
+        .unwrap();
+    (backend, handle)
+}
+
+/// The core of a window opened with `open_window`.
+pub fn core<'a>(handle: &'a mut HWND) -> &'a mut WindowCore {
+    window::get_window_from_handle_mut(handle).get_core_mut()
+}
+
+pub fn send(backend: &HeadlessBackend, handle: HWND, event: Event) {
+    let (msg, w_param, l_param) = event.encode();
+    backend.send_message(handle, msg, w_param, l_param);
+}
+
+pub fn mouse_move(backend: &HeadlessBackend, handle: HWND, x: i32, y: i32) {
+    send(backend, handle, Event::MouseMove { position: Point::new(x, y), modifiers: Modifiers::empty() });
+}
+
+pub fn mouse_down(backend: &HeadlessBackend, handle: HWND, x: i32, y: i32) {
+    mouse_move(backend, handle, x, y);
+    send(backend, handle, Event::MouseDown {
+        button: MouseButton::Left,
+        position: Point::new(x, y),
+        modifiers: Modifiers::empty(),
+    });
+}
+
+pub fn mouse_up(backend: &HeadlessBackend, handle: HWND, x: i32, y: i32) {
+    send(backend, handle, Event::MouseUp {
+        button: MouseButton::Left,
+        position: Point::new(x, y),
+        modifiers: Modifiers::empty(),
+    });
+}
+
+/// Presses and releases the left button at one spot.
+pub fn click(backend: &HeadlessBackend, handle: HWND, x: i32, y: i32) {
+    mouse_down(backend, handle, x, y);
+    mouse_up(backend, handle, x, y);
+}
+
+/// Turns the wheel by `notches`, positive away from the user, over whatever
+/// the mouse was last moved to.
+pub fn wheel(backend: &HeadlessBackend, handle: HWND, notches: i32) {
+    send(backend, handle, Event::MouseWheel {
+        delta: notches * WHEEL_DELTA as i32,
+        horizontal: false,
+        position: Point::default(),
+        modifiers: Modifiers::empty(),
+    });
+}
+
+/// Presses and releases the key `vk`.
+pub fn key(backend: &HeadlessBackend, handle: HWND, vk: i32) {
+    send(backend, handle, Event::KeyDown { key: vk as u32, system: false, flags: KeyFlags::default() });
+    send(backend, handle, Event::KeyUp { key: vk as u32, system: false, flags: KeyFlags::default() });
+}
+
+pub fn type_char(backend: &HeadlessBackend, handle: HWND, c: char) {
+    send(backend, handle, Event::Char { code: c as u16, system: false, flags: KeyFlags::default() });
+}
@@ -0,0 +1,533 @@
+use super::{ Command, GapBuffer, Movement, next_word_start, word_at, word_start };
+
+use std::cmp::{ max, min };
+
+/// Which lines an edit replaced: `removed` lines starting at `first` made
+/// way for `inserted` new ones. Lines after them only moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LineChange {
+    pub first: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+/// Text of any number of lines with a caret and a selection.
+///
+/// Positions are character indices into the whole text, line breaks
+/// included; `Home` and `End` go to the ends of the caret's line. The text
+/// sits in a `GapBuffer` and the start of every line is kept up to date, so
+/// edits and line lookups stay cheap for large documents. Carriage returns
+/// are dropped, lines end with a single `'\n'`.
+#[derive(Clone, Debug)]
+pub struct TextEditor {
+    buffer: GapBuffer,
+    /// The index of the first character of every line.
+    line_starts: Vec<usize>,
+    caret: usize,
+    anchor: usize,
+    /// The lines changed since the last `take_change`.
+    change: Option<LineChange>,
+}
+
+impl Default for TextEditor {
+    fn default() -> Self {
+        TextEditor {
+            buffer: GapBuffer::new(),
+            line_starts: vec![0],
+            caret: 0,
+            anchor: 0,
+            change: None,
+        }
+    }
+}
+
+impl TextEditor {
+    pub fn new() -> Self {
+        TextEditor::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.text()
+    }
+
+    /// The number of characters, line breaks included.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn char_at(&self, index: usize) -> char {
+        self.buffer.char_at(index)
+    }
+
+    /// The characters from `start` up to `end`.
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.buffer.slice(start, end)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line]
+    }
+
+    /// The index just past the last character of `line`, before its line
+    /// break.
+    pub fn line_end(&self, line: usize) -> usize {
+        if line + 1 < self.line_starts.len() {
+            self.line_starts[line + 1] - 1
+        } else {
+            self.buffer.len()
+        }
+    }
+
+    /// The line `index` is on.
+    pub fn line_of(&self, index: usize) -> usize {
+        match self.line_starts.binary_search(&index) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// The text of `line`, without its line break.
+    pub fn line_text(&self, line: usize) -> String {
+        self.buffer.slice(self.line_start(line), self.line_end(line))
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// The selected range, start first. Empty if nothing is selected.
+    pub fn selection(&self) -> (usize, usize) {
+        (min(self.caret, self.anchor), max(self.caret, self.anchor))
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.caret != self.anchor
+    }
+
+    pub fn selected_text(&self) -> String {
+        let (start, end) = self.selection();
+        self.buffer.slice(start, end)
+    }
+
+    /// Replaces the text, leaving the caret at the start.
+    pub fn set_text(&mut self, text: &str) -> &mut Self {
+        let old_lines = self.line_starts.len();
+        self.buffer = GapBuffer::from_str(&normalize(text).into_iter().collect::<String>());
+        self.line_starts = vec![0];
+        for i in 0..self.buffer.len() {
+            if self.buffer.char_at(i) == '\n' {
+                self.line_starts.push(i + 1);
+            }
+        }
+        let new_lines = self.line_starts.len();
+        self.record_change(old_lines, LineChange { first: 0, removed: old_lines, inserted: new_lines });
+        self.caret = 0;
+        self.anchor = 0;
+        self
+    }
+
+    /// The lines changed since the last call, if any.
+    pub fn take_change(&mut self) -> Option<LineChange> {
+        self.change.take()
+    }
+
+    /// Moves the caret to `index`, extending the selection if `select` is
+    /// set and dropping it otherwise.
+    pub fn set_caret(&mut self, index: usize, select: bool) {
+        self.caret = min(index, self.buffer.len());
+        if !select {
+            self.anchor = self.caret;
+        }
+    }
+
+    pub fn select(&mut self, start: usize, end: usize) {
+        self.anchor = min(start, self.buffer.len());
+        self.caret = min(end, self.buffer.len());
+    }
+
+    pub fn select_all(&mut self) {
+        let len = self.buffer.len();
+        self.select(0, len);
+    }
+
+    /// Selects the word around `index`.
+    pub fn select_word(&mut self, index: usize) {
+        let (start, end) = word_at(&self.buffer, index);
+        self.select(start, end);
+    }
+
+    /// Where `movement` takes the caret.
+    pub fn target(&self, movement: Movement) -> usize {
+        match movement {
+            Movement::Left => if self.caret > 0 { self.caret - 1 } else { 0 },
+            Movement::Right => min(self.caret + 1, self.buffer.len()),
+            Movement::WordLeft => word_start(&self.buffer, self.caret),
+            Movement::WordRight => next_word_start(&self.buffer, self.caret),
+            Movement::Home => self.line_start(self.line_of(self.caret)),
+            Movement::End => self.line_end(self.line_of(self.caret)),
+        }
+    }
+
+    /// Moves the caret. Moving left or right without `select` only drops a
+    /// selection, leaving the caret at its start or end.
+    pub fn move_caret(&mut self, movement: Movement, select: bool) {
+        if self.has_selection() && !select {
+            let (start, end) = self.selection();
+            match movement {
+                Movement::Left => return self.set_caret(start, false),
+                Movement::Right => return self.set_caret(end, false),
+                _ => { },
+            }
+        }
+        let target = self.target(movement);
+        self.set_caret(target, select);
+    }
+
+    /// Replaces the selection with `text`, returning whether anything
+    /// changed.
+    pub fn insert(&mut self, text: &str) -> bool {
+        let (start, end) = self.selection();
+        let inserted = normalize(text);
+        if inserted.is_empty() && start == end {
+            return false;
+        }
+        self.replace(start, end, &inserted);
+        self.set_caret(start + inserted.len(), false);
+        true
+    }
+
+    /// Deletes the selection or, without one, from the caret to where
+    /// `movement` would take it. Returns whether anything changed.
+    pub fn delete(&mut self, movement: Movement) -> bool {
+        if !self.has_selection() {
+            let target = self.target(movement);
+            self.anchor = target;
+        }
+        let (start, end) = self.selection();
+        if start == end {
+            return false;
+        }
+        self.replace(start, end, &[]);
+        self.set_caret(start, false);
+        true
+    }
+
+    /// Carries out a command, returning whether the text changed.
+    pub fn apply(&mut self, command: Command) -> bool {
+        match command {
+            Command::Move(movement, select) => {
+                self.move_caret(movement, select);
+                false
+            },
+            Command::Insert(text) => self.insert(&text),
+            Command::Delete(movement) => self.delete(movement),
+            Command::SelectAll => {
+                self.select_all();
+                false
+            },
+        }
+    }
+
+    /// Replaces the characters from `start` up to `end`, keeping the line
+    /// starts in step.
+    fn replace(&mut self, start: usize, end: usize, text: &[char]) {
+        let old_lines = self.line_starts.len();
+        let first = self.line_of(start);
+        let last = self.line_of(end);
+        self.buffer.remove(start, end);
+        self.buffer.insert(start, text);
+
+        let breaks = text.iter().enumerate()
+            .filter(|&(_, &c)| c == '\n')
+            .map(|(i, _)| start + i + 1)
+            .collect::<Vec<_>>();
+        let inserted = breaks.len() + 1;
+        for line_start in self.line_starts[last + 1..].iter_mut() {
+            *line_start = *line_start + text.len() - (end - start);
+        }
+        let moved = self.line_starts.split_off(last + 1);
+        self.line_starts.truncate(first + 1);
+        self.line_starts.extend(breaks);
+        self.line_starts.extend(moved);
+
+        let change = LineChange { first: first, removed: last - first + 1, inserted: inserted };
+        self.record_change(old_lines, change);
+    }
+
+    /// Merges `change` into the changes not taken yet. `old_lines` is the
+    /// line count from before it.
+    fn record_change(&mut self, old_lines: usize, change: LineChange) {
+        let merged = match self.change {
+            None => change,
+            Some(previous) => {
+                // Whatever isn't covered by either change is unchanged,
+                // lines at the end included.
+                let original_lines = old_lines - previous.inserted + previous.removed;
+                let new_lines = self.line_starts.len();
+                let first = min(previous.first, change.first);
+                let tail = min(old_lines - previous.first - previous.inserted,
+                               old_lines - change.first - change.removed);
+                LineChange {
+                    first: first,
+                    removed: original_lines - first - tail,
+                    inserted: new_lines - first - tail,
+                }
+            },
+        };
+        self.change = Some(merged);
+    }
+}
+
+/// The characters of `text` with `"\r\n"` and lone `'\r'` turned into `'\n'`.
+fn normalize(text: &str) -> Vec<char> {
+    let mut chars = Vec::with_capacity(text.len());
+    let mut after_cr = false;
+    for c in text.chars() {
+        match c {
+            '\r' => chars.push('\n'),
+            '\n' if after_cr => { },
+            c => chars.push(c),
+        }
+        after_cr = c == '\r';
+    }
+    chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::text::{ Command, Movement };
+
+    fn editor(text: &str) -> TextEditor {
+        let mut editor = TextEditor::new();
+        editor.set_text(text);
+        editor.take_change();
+        editor
+    }
+
+    fn lines(editor: &TextEditor) -> Vec<String> {
+        (0..editor.line_count()).map(|line| editor.line_text(line)).collect()
+    }
+
+    /// Checks the line starts against the text, the slow way.
+    fn check_line_starts(editor: &TextEditor) {
+        let text = editor.text().chars().collect::<Vec<_>>();
+        let mut starts = vec![0];
+        starts.extend(text.iter().enumerate().filter(|&(_, &c)| c == '\n').map(|(i, _)| i + 1));
+        assert_eq!(editor.line_count(), starts.len(), "{:?}", editor.text());
+        for (line, &start) in starts.iter().enumerate() {
+            assert_eq!(editor.line_start(line), start, "line {} of {:?}", line, editor.text());
+            assert_eq!(editor.line_of(start), line);
+        }
+    }
+
+    /// Checks that `change` covers every line that differs between `before`
+    /// and `after`.
+    fn check_change(before: &[String], after: &[String], change: LineChange) {
+        let tail = before.len() - change.first - change.removed;
+        assert_eq!(after.len(), change.first + change.inserted + tail, "{:?}", change);
+        assert_eq!(before[..change.first], after[..change.first], "{:?}", change);
+        assert_eq!(before[before.len() - tail..], after[after.len() - tail..], "{:?}", change);
+    }
+
+    #[test]
+    fn line_breaks_are_normalized() {
+        let mut editor = editor("a\r\nb\rc\n\r\nd\r\r\n");
+        assert_eq!(editor.text(), "a\nb\nc\n\nd\n\n");
+        assert_eq!(editor.line_count(), 7);
+        check_line_starts(&editor);
+
+        editor.set_caret(1, false);
+        assert!(editor.insert("x\r\ny\rz"));
+        assert_eq!(editor.text(), "ax\ny\nz\nb\nc\n\nd\n\n");
+        assert_eq!(editor.caret(), 6);
+        check_line_starts(&editor);
+
+        // A lone carriage return still ends a line.
+        assert!(editor.insert("\r"));
+        assert_eq!(editor.line_text(3), "");
+    }
+
+    #[test]
+    fn lines_have_ends_and_texts() {
+        let editor = editor("first\n\nthird");
+        assert_eq!(lines(&editor), vec!["first", "", "third"]);
+        assert_eq!((editor.line_start(1), editor.line_end(1)), (6, 6));
+        assert_eq!((editor.line_start(2), editor.line_end(2)), (7, 12));
+        assert_eq!(editor.line_of(5), 0);
+        assert_eq!(editor.line_of(6), 1);
+        assert_eq!(editor.line_of(12), 2);
+    }
+
+    #[test]
+    fn replacing_keeps_line_starts_in_step() {
+        let mut editor = editor("one\ntwo\nthree\nfour");
+
+        // Within a line, then splitting it, then across several lines.
+        editor.set_caret(5, false);
+        editor.insert("-");
+        check_line_starts(&editor);
+        editor.insert("\n\n");
+        check_line_starts(&editor);
+        assert_eq!(lines(&editor), vec!["one", "t-", "", "wo", "three", "four"]);
+        editor.select(2, 12);
+        editor.insert("X\nY");
+        check_line_starts(&editor);
+        assert_eq!(lines(&editor), vec!["onX", "Yhree", "four"]);
+
+        // Deleting line breaks joins lines.
+        editor.set_caret(3, false);
+        assert!(editor.delete(Movement::Right));
+        check_line_starts(&editor);
+        assert_eq!(lines(&editor), vec!["onXYhree", "four"]);
+        editor.select_all();
+        assert!(editor.delete(Movement::Left));
+        check_line_starts(&editor);
+        assert_eq!(editor.line_count(), 1);
+        assert!(!editor.delete(Movement::Left));
+    }
+
+    #[test]
+    fn words_are_skipped_whole() {
+        let mut editor = editor("foo bar  baz.qux\nnext");
+        let mut stops = Vec::new();
+        for _ in 0..7 {
+            editor.move_caret(Movement::WordRight, false);
+            stops.push(editor.caret());
+        }
+        assert_eq!(stops, vec![4, 9, 12, 13, 17, 21, 21]);
+
+        stops.clear();
+        for _ in 0..7 {
+            editor.move_caret(Movement::WordLeft, false);
+            stops.push(editor.caret());
+        }
+        assert_eq!(stops, vec![17, 13, 12, 9, 4, 0, 0]);
+
+        editor.select_word(10);
+        assert_eq!(editor.selected_text(), "baz");
+        editor.set_caret(9, false);
+        assert!(editor.delete(Movement::WordRight));
+        assert_eq!(editor.text(), "foo bar  .qux\nnext");
+        editor.set_caret(7, false);
+        assert!(editor.delete(Movement::WordLeft));
+        assert_eq!(editor.text(), "foo   .qux\nnext");
+    }
+
+    #[test]
+    fn moving_without_shift_collapses_the_selection() {
+        let mut editor = editor("hello world\nsecond");
+        editor.set_caret(2, false);
+        for _ in 0..3 {
+            editor.move_caret(Movement::Right, true);
+        }
+        assert_eq!(editor.selection(), (2, 5));
+        assert_eq!(editor.selected_text(), "llo");
+
+        // Left and Right stop at the ends of the selection.
+        editor.move_caret(Movement::Left, false);
+        assert_eq!((editor.caret(), editor.has_selection()), (2, false));
+        editor.apply(Command::Move(Movement::WordRight, true));
+        assert_eq!(editor.selection(), (2, 6));
+        editor.apply(Command::Move(Movement::Right, false));
+        assert_eq!((editor.caret(), editor.has_selection()), (6, false));
+
+        // Selecting backwards keeps the anchor.
+        editor.move_caret(Movement::Home, true);
+        assert_eq!((editor.caret(), editor.selection()), (0, (0, 6)));
+        editor.move_caret(Movement::Left, true);
+        assert_eq!(editor.caret(), 0);
+
+        // Other movements start from the caret and drop the selection.
+        editor.apply(Command::SelectAll);
+        assert_eq!(editor.selection(), (0, 18));
+        editor.move_caret(Movement::Home, false);
+        assert_eq!((editor.caret(), editor.has_selection()), (12, false));
+        editor.move_caret(Movement::End, false);
+        assert_eq!(editor.caret(), 18);
+        editor.move_caret(Movement::Right, false);
+        assert_eq!(editor.caret(), 18);
+    }
+
+    #[test]
+    fn changes_report_the_lines_they_touch() {
+        let mut editor = editor("a\nb\nc\nd");
+        editor.set_caret(3, false);
+        editor.insert("x");
+        assert_eq!(editor.take_change(), Some(LineChange { first: 1, removed: 1, inserted: 1 }));
+        assert_eq!(editor.take_change(), None);
+
+        editor.insert("\n\n");
+        assert_eq!(editor.take_change(), Some(LineChange { first: 1, removed: 1, inserted: 3 }));
+        editor.select(0, 5);
+        editor.delete(Movement::Left);
+        assert_eq!(editor.take_change(), Some(LineChange { first: 0, removed: 3, inserted: 1 }));
+
+        editor.set_text("new");
+        assert_eq!(editor.take_change(), Some(LineChange { first: 0, removed: 4, inserted: 1 }));
+    }
+
+    #[test]
+    fn changes_merge_until_taken() {
+        let mut editor = editor("a\nb\nc\nd");
+        // A new line after "a", then an edit further down.
+        editor.set_caret(1, false);
+        editor.insert("\n");
+        editor.set_caret(5, false);
+        editor.insert("x");
+        assert_eq!(lines(&editor), vec!["a", "", "b", "xc", "d"]);
+        assert_eq!(editor.take_change(), Some(LineChange { first: 0, removed: 3, inserted: 4 }));
+
+        // The other way around: the later edit comes first.
+        editor.set_caret(editor.line_start(3) + 1, false);
+        editor.insert("y");
+        editor.select(0, 3);
+        editor.delete(Movement::Left);
+        assert_eq!(lines(&editor), vec!["b", "xyc", "d"]);
+        assert_eq!(editor.take_change(), Some(LineChange { first: 0, removed: 4, inserted: 2 }));
+
+        // Edits on the same line stay a single line.
+        editor.set_caret(0, false);
+        editor.insert("1");
+        editor.insert("2");
+        editor.delete(Movement::Left);
+        assert_eq!(editor.take_change(), Some(LineChange { first: 0, removed: 1, inserted: 1 }));
+    }
+
+    #[test]
+    fn merged_changes_cover_any_sequence_of_edits() {
+        let mut editor = editor("zero\none\ntwo\nthree\nfour\nfive");
+        let mut before = lines(&editor);
+        let mut seed = 4242u32;
+        let mut next = |bound: usize| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize % bound
+        };
+        let texts = ["", "x", "\n", "a\nb", "\n\n", "long line"];
+        for step in 0..500 {
+            let len = editor.len();
+            let start = next(len + 1);
+            let end = start + next(::std::cmp::min(len - start, 8) + 1);
+            editor.select(start, end);
+            editor.insert(texts[next(texts.len())]);
+            check_line_starts(&editor);
+
+            if step % 7 == 6 {
+                let after = lines(&editor);
+                match editor.take_change() {
+                    Some(change) => check_change(&before, &after, change),
+                    None => assert_eq!(before, after),
+                }
+                before = after;
+            }
+        }
+    }
+}
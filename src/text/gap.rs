@@ -0,0 +1,207 @@
+use super::CharSource;
+
+use std::cmp::max;
+
+/// Text kept as characters around a movable gap.
+///
+/// Inserting and removing at the gap is cheap, and typing keeps the gap at
+/// the caret, so editing large documents only ever moves the text between
+/// the old and the new editing position.
+#[derive(Clone, Debug, Default)]
+pub struct GapBuffer {
+    buffer: Vec<char>,
+    gap_start: usize,
+    gap_end: usize,
+}
+
+/// The smallest gap to open up when the old one is used up.
+const MIN_GAP: usize = 64;
+
+impl GapBuffer {
+    pub fn new() -> Self {
+        GapBuffer::default()
+    }
+
+    pub fn from_str(text: &str) -> Self {
+        let buffer = text.chars().collect::<Vec<_>>();
+        let len = buffer.len();
+        GapBuffer { buffer: buffer, gap_start: len, gap_end: len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len() - (self.gap_end - self.gap_start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn char_at(&self, index: usize) -> char {
+        if index < self.gap_start {
+            self.buffer[index]
+        } else {
+            self.buffer[index + self.gap_end - self.gap_start]
+        }
+    }
+
+    /// The characters from `start` up to `end`.
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        (start..end).map(|i| self.char_at(i)).collect()
+    }
+
+    pub fn text(&self) -> String {
+        self.slice(0, self.len())
+    }
+
+    pub fn insert(&mut self, index: usize, text: &[char]) {
+        self.move_gap(index);
+        if self.gap_end - self.gap_start < text.len() {
+            self.grow(text.len());
+        }
+        for (i, &c) in text.iter().enumerate() {
+            self.buffer[self.gap_start + i] = c;
+        }
+        self.gap_start += text.len();
+    }
+
+    /// Removes the characters from `start` up to `end`.
+    pub fn remove(&mut self, start: usize, end: usize) {
+        self.move_gap(end);
+        self.gap_start = start;
+    }
+
+    /// Moves the gap to start at `index`.
+    fn move_gap(&mut self, index: usize) {
+        let gap = self.gap_end - self.gap_start;
+        if index < self.gap_start {
+            let count = self.gap_start - index;
+            for i in (0..count).rev() {
+                self.buffer[index + gap + i] = self.buffer[index + i];
+            }
+        } else if index > self.gap_start {
+            let count = index - self.gap_start;
+            for i in 0..count {
+                self.buffer[self.gap_start + i] = self.buffer[self.gap_end + i];
+            }
+        }
+        self.gap_start = index;
+        self.gap_end = index + gap;
+    }
+
+    /// Makes the gap at least `needed` long, plus room to grow.
+    fn grow(&mut self, needed: usize) {
+        let gap = max(needed, max(MIN_GAP, self.len() / 8));
+        let old_gap = self.gap_end - self.gap_start;
+        let tail = self.buffer.len() - self.gap_end;
+        self.buffer.resize(self.gap_start + gap + tail, '\0');
+        for i in (0..tail).rev() {
+            self.buffer[self.gap_start + gap + i] = self.buffer[self.gap_start + old_gap + i];
+        }
+        self.gap_end = self.gap_start + gap;
+    }
+}
+
+impl CharSource for GapBuffer {
+    fn len(&self) -> usize {
+        GapBuffer::len(self)
+    }
+
+    fn char_at(&self, index: usize) -> char {
+        GapBuffer::char_at(self, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn gap(buffer: &GapBuffer) -> usize {
+        buffer.gap_end - buffer.gap_start
+    }
+
+    #[test]
+    fn the_gap_moves_both_ways() {
+        let mut buffer = GapBuffer::from_str("hello world");
+        buffer.insert(5, &chars(","));
+        assert_eq!(buffer.gap_start, 6);
+        buffer.insert(0, &chars(">"));
+        assert_eq!(buffer.gap_start, 1);
+        buffer.insert(13, &chars("!"));
+        assert_eq!(buffer.text(), ">hello, world!");
+        assert_eq!(buffer.len(), 14);
+
+        // Characters read the same on both sides of the gap.
+        buffer.insert(7, &[]);
+        assert_eq!((0..buffer.len()).map(|i| buffer.char_at(i)).collect::<String>(), ">hello, world!");
+        assert_eq!(buffer.slice(4, 10), "lo, wo");
+    }
+
+    #[test]
+    fn the_gap_grows_when_used_up() {
+        let mut buffer = GapBuffer::from_str("abc");
+        assert_eq!(gap(&buffer), 0);
+        buffer.insert(1, &chars("x"));
+        assert_eq!(gap(&buffer), MIN_GAP - 1);
+        assert_eq!(buffer.text(), "axbc");
+
+        // Large inserts get exactly the room they need.
+        let long = vec!['y'; 1000];
+        buffer.insert(4, &long);
+        assert_eq!(gap(&buffer), 0);
+        assert_eq!(buffer.len(), 1004);
+        assert_eq!(buffer.slice(0, 5), "axbcy");
+
+        // Large texts open up an eighth of their length.
+        buffer.insert(2, &chars("z"));
+        assert_eq!(gap(&buffer), 1004 / 8 - 1);
+        assert_eq!(buffer.slice(0, 6), "axzbcy");
+    }
+
+    #[test]
+    fn removing_closes_over_the_range() {
+        let mut buffer = GapBuffer::from_str("one two three");
+        buffer.remove(3, 7);
+        assert_eq!(buffer.text(), "one three");
+        buffer.remove(0, 0);
+        buffer.remove(9, 9);
+        assert_eq!(buffer.text(), "one three");
+        buffer.remove(0, 9);
+        assert!(buffer.is_empty());
+        buffer.insert(0, &chars("again"));
+        assert_eq!(buffer.text(), "again");
+    }
+
+    #[test]
+    fn edits_anywhere_match_a_plain_vector() {
+        let mut buffer = GapBuffer::new();
+        let mut expected = Vec::new();
+        // A fixed pseudo-random walk, so failures reproduce.
+        let mut seed = 12345u32;
+        let mut next = |bound: usize| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize % bound
+        };
+        for step in 0..2000 {
+            let len = expected.len();
+            if len > 0 && next(3) == 0 {
+                let start = next(len + 1);
+                let end = start + next(len - start + 1);
+                buffer.remove(start, end);
+                expected.drain(start..end);
+            } else {
+                let index = next(len + 1);
+                let text = (0..next(10)).map(|i| (b'a' + ((step + i) % 26) as u8) as char).collect::<Vec<_>>();
+                buffer.insert(index, &text);
+                for (i, &c) in text.iter().enumerate() {
+                    expected.insert(index + i, c);
+                }
+            }
+            assert_eq!(buffer.len(), expected.len());
+            assert_eq!(buffer.text(), expected.iter().cloned().collect::<String>(), "after step {}", step);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::{ Command, Movement, next_word_start, word_at, word_start };
+
+use std::cmp::{ max, min };
+
+/// A single line of text with a caret and a selection.
+///
+/// Positions are character indices, from zero before the first character to
+/// `len()` after the last one. The selection runs between the caret and the
+/// anchor, which stays where it was while the selection is extended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineEditor {
+    chars: Vec<char>,
+    caret: usize,
+    anchor: usize,
+    max_length: Option<usize>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        LineEditor::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().cloned().collect()
+    }
+
+    /// The number of characters.
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// The selected range, start first. Empty if nothing is selected.
+    pub fn selection(&self) -> (usize, usize) {
+        (min(self.caret, self.anchor), max(self.caret, self.anchor))
+    }
+
+    pub fn has_selection(&self) -> bool {
+        self.caret != self.anchor
+    }
+
+    pub fn selected_text(&self) -> String {
+        let (start, end) = self.selection();
+        self.chars[start..end].iter().cloned().collect()
+    }
+
+    pub fn max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+
+    /// Limits the number of characters, cutting off what is already too
+    /// much.
+    pub fn set_max_length(&mut self, max_length: Option<usize>) -> &mut Self {
+        self.max_length = max_length;
+        if let Some(limit) = max_length {
+            if self.chars.len() > limit {
+                self.chars.truncate(limit);
+                self.caret = min(self.caret, limit);
+                self.anchor = min(self.anchor, limit);
+            }
+        }
+        self
+    }
+
+    /// Replaces the text, leaving the caret at the end. Line breaks are
+    /// dropped and the text is cut off at the maximum length.
+    pub fn set_text(&mut self, text: &str) -> &mut Self {
+        self.chars = single_line(text);
+        if let Some(limit) = self.max_length {
+            self.chars.truncate(limit);
+        }
+        self.caret = self.chars.len();
+        self.anchor = self.caret;
+        self
+    }
+
+    /// Moves the caret to `index`, extending the selection if `select` is
+    /// set and dropping it otherwise.
+    pub fn set_caret(&mut self, index: usize, select: bool) {
+        self.caret = min(index, self.chars.len());
+        if !select {
+            self.anchor = self.caret;
+        }
+    }
+
+    pub fn select(&mut self, start: usize, end: usize) {
+        self.anchor = min(start, self.chars.len());
+        self.caret = min(end, self.chars.len());
+    }
+
+    pub fn select_all(&mut self) {
+        self.select(0, self.chars.len());
+    }
+
+    /// Selects the word around `index`.
+    pub fn select_word(&mut self, index: usize) {
+        let (start, end) = word_at(&self.chars[..], index);
+        self.select(start, end);
+    }
+
+    /// Where `movement` takes the caret.
+    pub fn target(&self, movement: Movement) -> usize {
+        match movement {
+            Movement::Left => if self.caret > 0 { self.caret - 1 } else { 0 },
+            Movement::Right => min(self.caret + 1, self.chars.len()),
+            Movement::WordLeft => word_start(&self.chars[..], self.caret),
+            Movement::WordRight => next_word_start(&self.chars[..], self.caret),
+            Movement::Home => 0,
+            Movement::End => self.chars.len(),
+        }
+    }
+
+    /// Moves the caret. Moving left or right without `select` only drops a
+    /// selection, leaving the caret at its start or end.
+    pub fn move_caret(&mut self, movement: Movement, select: bool) {
+        if self.has_selection() && !select {
+            let (start, end) = self.selection();
+            match movement {
+                Movement::Left => return self.set_caret(start, false),
+                Movement::Right => return self.set_caret(end, false),
+                _ => { },
+            }
+        }
+        let target = self.target(movement);
+        self.set_caret(target, select);
+    }
+
+    /// Replaces the selection with `text`, returning whether anything
+    /// changed. Line breaks are dropped, and so is whatever doesn't fit
+    /// into the maximum length.
+    pub fn insert(&mut self, text: &str) -> bool {
+        let (start, end) = self.selection();
+        let mut inserted = single_line(text);
+        if let Some(limit) = self.max_length {
+            let room = limit.saturating_sub(self.chars.len() - (end - start));
+            inserted.truncate(room);
+        }
+        if inserted.is_empty() && start == end {
+            return false;
+        }
+        let count = inserted.len();
+        let tail = self.chars.split_off(end);
+        self.chars.truncate(start);
+        self.chars.extend(inserted);
+        self.chars.extend(tail);
+        self.set_caret(start + count, false);
+        true
+    }
+
+    /// Deletes the selection or, without one, from the caret to where
+    /// `movement` would take it. Returns whether anything changed.
+    pub fn delete(&mut self, movement: Movement) -> bool {
+        if !self.has_selection() {
+            let target = self.target(movement);
+            self.anchor = target;
+        }
+        let (start, end) = self.selection();
+        if start == end {
+            return false;
+        }
+        self.chars.drain(start..end);
+        self.set_caret(start, false);
+        true
+    }
+
+    /// Carries out a command, returning whether the text changed.
+    pub fn apply(&mut self, command: Command) -> bool {
+        match command {
+            Command::Move(movement, select) => {
+                self.move_caret(movement, select);
+                false
+            },
+            Command::Insert(text) => self.insert(&text),
+            Command::Delete(movement) => self.delete(movement),
+            Command::SelectAll => {
+                self.select_all();
+                false
+            },
+        }
+    }
+}
+
+fn single_line(text: &str) -> Vec<char> {
+    text.chars().filter(|&c| c != '\r' && c != '\n').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> LineEditor {
+        let mut editor = LineEditor::new();
+        editor.set_text(text);
+        editor
+    }
+
+    #[test]
+    fn inserting_stops_at_the_maximum_length() {
+        let mut editor = LineEditor::new();
+        editor.set_max_length(Some(5));
+        assert!(editor.insert("hello world"));
+        assert_eq!((editor.text(), editor.caret()), ("hello".to_string(), 5));
+        assert!(!editor.insert("x"));
+        assert_eq!(editor.text(), "hello");
+
+        // A selection makes room for as much as it takes up.
+        editor.select(0, 2);
+        assert!(editor.insert("abc"));
+        assert_eq!((editor.text(), editor.caret()), ("abllo".to_string(), 2));
+        // Deleting the selection is still a change.
+        editor.select(0, 1);
+        assert!(editor.insert("xyz"));
+        assert_eq!(editor.text(), "xbllo");
+    }
+
+    #[test]
+    fn set_text_is_cut_at_the_maximum_length() {
+        let mut editor = LineEditor::new();
+        editor.set_max_length(Some(5)).set_text("123456789");
+        assert_eq!((editor.text(), editor.caret()), ("12345".to_string(), 5));
+
+        // Lowering the limit cuts off what is there, keeping the caret and
+        // selection inside.
+        editor.select(1, 5);
+        editor.set_max_length(Some(3));
+        assert_eq!(editor.text(), "123");
+        assert_eq!(editor.selection(), (1, 3));
+        editor.set_max_length(None);
+        editor.set_caret(3, false);
+        assert!(editor.insert("456"));
+        assert_eq!(editor.text(), "123456");
+    }
+
+    #[test]
+    fn line_breaks_are_dropped() {
+        let mut editor = editor("one\r\ntwo\nthree\r");
+        assert_eq!(editor.text(), "onetwothree");
+        assert_eq!(editor.caret(), 11);
+        editor.set_caret(3, false);
+        assert!(editor.insert(" \n"));
+        assert_eq!(editor.text(), "one twothree");
+        assert!(!editor.insert("\r\n"));
+    }
+
+    #[test]
+    fn left_and_right_collapse_the_selection() {
+        let mut editor = editor("hello");
+        editor.select(1, 4);
+        editor.move_caret(Movement::Left, false);
+        assert_eq!((editor.caret(), editor.has_selection()), (1, false));
+
+        // Whichever way round it was made.
+        editor.select(4, 1);
+        editor.move_caret(Movement::Right, false);
+        assert_eq!((editor.caret(), editor.has_selection()), (4, false));
+
+        // Extending moves the caret, other movements drop the selection.
+        editor.select(1, 4);
+        editor.move_caret(Movement::Left, true);
+        assert_eq!(editor.selection(), (1, 3));
+        editor.move_caret(Movement::Home, false);
+        assert_eq!((editor.caret(), editor.has_selection()), (0, false));
+        editor.move_caret(Movement::Left, false);
+        assert_eq!(editor.caret(), 0);
+    }
+
+    #[test]
+    fn deleting_removes_the_selection_or_one_step() {
+        let mut editor = editor("hello world");
+        assert!(editor.delete(Movement::Left));
+        assert!(!editor.delete(Movement::Right));
+        assert!(editor.apply(Command::Delete(Movement::WordLeft)));
+        assert_eq!(editor.text(), "hello ");
+        editor.select(1, 3);
+        assert!(editor.delete(Movement::Right));
+        assert_eq!((editor.text(), editor.caret()), ("hlo ".to_string(), 1));
+    }
+}
@@ -0,0 +1,128 @@
+//! Text editing without any windows attached.
+//!
+//! The models in here keep the text, the caret and the selection and know
+//! how to apply editing commands to them. The text controls only translate
+//! input into commands and draw the result.
+
+pub mod editor;
+pub mod gap;
+pub mod line;
+
+pub use self::editor::{ LineChange, TextEditor };
+pub use self::gap::GapBuffer;
+pub use self::line::LineEditor;
+
+/// Where a caret movement goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Movement {
+    /// One character back.
+    Left,
+    /// One character forward.
+    Right,
+    /// To the start of the word before the caret.
+    WordLeft,
+    /// To the start of the next word.
+    WordRight,
+    /// To the start of the line.
+    Home,
+    /// To the end of the line.
+    End,
+}
+
+/// An editing command, usually the result of a key press.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    /// Moves the caret, extending the selection instead of dropping it if
+    /// `select` is set.
+    Move(Movement, bool),
+    /// Replaces the selection with the text.
+    Insert(String),
+    /// Deletes the selection, or from the caret to where the movement
+    /// would take it.
+    Delete(Movement),
+    SelectAll,
+}
+
+/// Characters that can be looked at one index at a time, however they are
+/// stored.
+pub trait CharSource {
+    fn len(&self) -> usize;
+    fn char_at(&self, index: usize) -> char;
+}
+
+impl CharSource for [char] {
+    fn len(&self) -> usize {
+        <[char]>::len(self)
+    }
+
+    fn char_at(&self, index: usize) -> char {
+        self[index]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CharClass {
+    Space,
+    Word,
+    Other,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Other
+    }
+}
+
+/// The start of the word before `index`, skipping whitespace first.
+pub fn word_start<S: CharSource + ?Sized>(chars: &S, index: usize) -> usize {
+    let mut i = index;
+    while i > 0 && class(chars.char_at(i - 1)) == CharClass::Space {
+        i -= 1;
+    }
+    if i > 0 {
+        let word = class(chars.char_at(i - 1));
+        while i > 0 && class(chars.char_at(i - 1)) == word {
+            i -= 1;
+        }
+    }
+    i
+}
+
+/// The start of the word after the one at `index`, the way Ctrl+Right goes.
+pub fn next_word_start<S: CharSource + ?Sized>(chars: &S, index: usize) -> usize {
+    let len = chars.len();
+    let mut i = index;
+    if i < len && class(chars.char_at(i)) != CharClass::Space {
+        let word = class(chars.char_at(i));
+        while i < len && class(chars.char_at(i)) == word {
+            i += 1;
+        }
+    }
+    while i < len && class(chars.char_at(i)) == CharClass::Space {
+        i += 1;
+    }
+    i
+}
+
+/// The word around `index`, as a range of character indices.
+pub fn word_at<S: CharSource + ?Sized>(chars: &S, index: usize) -> (usize, usize) {
+    let len = chars.len();
+    if len == 0 {
+        return (0, 0);
+    }
+    let index = if index >= len { len - 1 } else { index };
+    let word = class(chars.char_at(index));
+    let mut start = index;
+    while start > 0 && class(chars.char_at(start - 1)) == word {
+        start -= 1;
+    }
+    let mut end = index;
+    while end < len && class(chars.char_at(end)) == word {
+        end += 1;
+    }
+    (start, end)
+}
@@ -0,0 +1,211 @@
+//! Bookkeeping for the timers of a window.
+//!
+//! The backend only knows timer ids and sends `WM_TIMER` for them; which
+//! callback belongs to an id and whether it repeats is kept here.
+
+use ::window::WindowCore;
+use ::winapi::UINT;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Identifies a timer set with `WindowCore::set_timer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(usize);
+
+impl TimerId {
+    /// The id the backend knows the timer by, the `WPARAM` of its `WM_TIMER`.
+    pub fn raw(&self) -> usize {
+        self.0
+    }
+}
+
+/// Timer ids start here, leaving the small ones to `Window::on_timer` users.
+const FIRST_ID: usize = 0x1000;
+
+/// The `SetTimer` interval for `duration`, in whole milliseconds.
+pub fn interval(duration: Duration) -> UINT {
+    let millis = duration.as_secs().saturating_mul(1000) + (duration.subsec_nanos() / 1_000_000) as u64;
+    if millis > ::std::u32::MAX as u64 { ::std::u32::MAX } else { millis as UINT }
+}
+
+struct Entry {
+    repeat: bool,
+    /// Taken out while the callback runs.
+    callback: Option<Box<FnMut(&mut WindowCore)>>,
+}
+
+pub struct Timers {
+    next_id: usize,
+    entries: HashMap<usize, Entry>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            next_id: FIRST_ID,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, id: TimerId) -> bool {
+        self.entries.contains_key(&id.0)
+    }
+
+    pub fn ids(&self) -> Vec<TimerId> {
+        self.entries.keys().map(|&id| TimerId(id)).collect()
+    }
+
+    pub fn add(&mut self, repeat: bool, callback: Box<FnMut(&mut WindowCore)>) -> TimerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(id, Entry { repeat: repeat, callback: Some(callback) });
+        TimerId(id)
+    }
+
+    pub fn remove(&mut self, id: TimerId) -> bool {
+        self.entries.remove(&id.0).is_some()
+    }
+
+    /// Takes the callback of the timer with the raw id `id` out so it can be
+    /// called, along with whether it repeats.
+    pub fn take(&mut self, id: usize) -> Option<(TimerId, bool, Box<FnMut(&mut WindowCore)>)> {
+        self.entries.get_mut(&id).and_then(|entry| {
+            let repeat = entry.repeat;
+            entry.callback.take().map(|callback| (TimerId(id), repeat, callback))
+        })
+    }
+
+    /// Puts a callback back after it ran, unless the timer was cancelled in
+    /// the meantime. Returns whether the timer is still there.
+    pub fn restore(&mut self, id: TimerId, callback: Box<FnMut(&mut WindowCore)>) -> bool {
+        match self.entries.get_mut(&id.0) {
+            Some(entry) => {
+                entry.callback = Some(callback);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::HeadlessBackend;
+    use ::testing::{ self, open_window };
+
+    use std::cell::{ Cell, RefCell };
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    type Log = Rc<RefCell<Vec<(&'static str, u64)>>>;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// A callback noting down `name` and the virtual time it ran at.
+    fn note(backend: &Rc<HeadlessBackend>, log: &Log, name: &'static str) -> Box<FnMut(&mut WindowCore)> {
+        let backend = backend.clone();
+        let log = log.clone();
+        Box::new(move |_: &mut WindowCore| {
+            let now = backend.now();
+            log.borrow_mut().push((name, now.as_secs() * 1000 + (now.subsec_nanos() / 1_000_000) as u64));
+        })
+    }
+
+    #[test]
+    fn timers_fire_by_deadline_then_in_the_order_they_were_set() {
+        let (backend, mut handle) = open_window(100, 100);
+        let log = Log::default();
+        testing::core(&mut handle).set_timer(millis(30), false, note(&backend, &log, "a"));
+        testing::core(&mut handle).set_timer(millis(30), false, note(&backend, &log, "b"));
+        testing::core(&mut handle).set_timer(millis(20), false, note(&backend, &log, "c"));
+        testing::core(&mut handle).set_timer(millis(30), false, note(&backend, &log, "d"));
+
+        backend.advance(millis(29));
+        assert_eq!(*log.borrow(), vec![("c", 20)]);
+        backend.advance(millis(1));
+        assert_eq!(*log.borrow(), vec![("c", 20), ("a", 30), ("b", 30), ("d", 30)]);
+        assert_eq!(backend.now(), millis(30));
+    }
+
+    #[test]
+    fn repeating_timers_keep_going_and_one_shots_stop() {
+        let (backend, mut handle) = open_window(100, 100);
+        let log = Log::default();
+        let repeating = testing::core(&mut handle).set_timer(millis(10), true, note(&backend, &log, "repeat"));
+        let once = testing::core(&mut handle).set_timer(millis(25), false, note(&backend, &log, "once"));
+
+        backend.advance(millis(50));
+        assert_eq!(*log.borrow(), vec![
+            ("repeat", 10), ("repeat", 20), ("once", 25), ("repeat", 30), ("repeat", 40), ("repeat", 50),
+        ]);
+        assert_eq!(backend.timer_count(handle), 1);
+        assert!(!testing::core(&mut handle).cancel_timer(once));
+        assert!(testing::core(&mut handle).cancel_timer(repeating));
+        assert_eq!(backend.timer_count(handle), 0);
+
+        backend.advance(millis(50));
+        assert_eq!(log.borrow().len(), 6);
+    }
+
+    #[test]
+    fn short_intervals_are_rounded_up() {
+        let (backend, mut handle) = open_window(100, 100);
+        let log = Log::default();
+        testing::core(&mut handle).set_timer(millis(1), false, note(&backend, &log, "short"));
+        backend.advance(millis(9));
+        assert!(log.borrow().is_empty());
+        backend.advance(millis(1));
+        assert_eq!(*log.borrow(), vec![("short", 10)]);
+    }
+
+    #[test]
+    fn timers_can_be_cancelled_from_callbacks() {
+        let (backend, mut handle) = open_window(100, 100);
+        let log = Log::default();
+        let other = Rc::new(Cell::new(None));
+        let calls = Rc::new(Cell::new(0));
+
+        // Cancels the other timer, which is due at the same time, the first
+        // time around and itself the third time.
+        let canceller = {
+            let mut noted = note(&backend, &log, "canceller");
+            let other = other.clone();
+            let calls = calls.clone();
+            let own = Rc::new(Cell::new(None));
+            let id = {
+                let own = own.clone();
+                testing::core(&mut handle).set_timer(millis(10), true, move |core: &mut WindowCore| {
+                    noted(core);
+                    calls.set(calls.get() + 1);
+                    if let Some(id) = other.get() {
+                        other.set(None);
+                        assert!(core.cancel_timer(id));
+                    }
+                    if calls.get() == 3 {
+                        assert!(core.cancel_timer(own.get().unwrap()));
+                    }
+                })
+            };
+            own.set(Some(id));
+            id
+        };
+        other.set(Some(testing::core(&mut handle).set_timer(millis(10), true, note(&backend, &log, "other"))));
+
+        backend.advance(millis(100));
+        assert_eq!(*log.borrow(), vec![("canceller", 10), ("canceller", 20), ("canceller", 30)]);
+        assert_eq!(backend.timer_count(handle), 0);
+        assert!(!testing::core(&mut handle).cancel_timer(canceller));
+    }
+}
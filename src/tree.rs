@@ -0,0 +1,499 @@
+//! The retained tree of controls inside a window.
+
+use ::controls::Control;
+use ::geometry::{ Point, Rect, Size };
+use ::layout::{ self, Layout, DEFAULT_LAYOUT };
+use ::ml::PaintContext;
+use ::region::Region;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeError {
+    /// There is no control with this id.
+    NoSuchControl(i32),
+    /// The id is already in use.
+    DuplicateId(i32),
+    /// A control can't become a child of itself or one of its descendants.
+    WouldCreateCycle(i32),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TreeError::NoSuchControl(id) => write!(f, "there is no control with id {}", id),
+            TreeError::DuplicateId(id) => write!(f, "a control with id {} already exists", id),
+            TreeError::WouldCreateCycle(id) => write!(f, "control {} can't be moved into its own subtree", id),
+        }
+    }
+}
+
+struct Node {
+    parent: Option<i32>,
+    children: Vec<i32>,
+    control: Box<Control>,
+    layout: Option<Layout>,
+    /// What the last measure pass came up with.
+    desired: Cell<Size>,
+}
+
+/// Controls with a parent and ordered children each.
+///
+/// A control's bounds are relative to the origin of its parent's bounds,
+/// top-level controls are relative to the client area. Siblings are kept in
+/// z-order: later ones are painted on top of earlier ones.
+///
+/// Children of controls with a `Panel` are placed by `update_layout`. Other
+/// controls only get laid out if they were given a `Layout`, filling their
+/// parent according to it; the rest keep the bounds they have.
+pub struct ControlTree {
+    nodes: HashMap<i32, Node>,
+    roots: Vec<i32>,
+}
+
+impl ControlTree {
+    pub fn new() -> Self {
+        ControlTree {
+            nodes: HashMap::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    /// Adds `control` as the topmost child of `parent`, or as a top-level
+    /// control if there is no parent.
+    pub fn insert(&mut self, id: i32, parent: Option<i32>, control: Box<Control>) -> Result<(), TreeError> {
+        if self.contains(id) {
+            return Err(TreeError::DuplicateId(id));
+        }
+        try!(self.siblings_mut(parent)).push(id);
+        self.nodes.insert(id, Node {
+            parent: parent,
+            children: Vec::new(),
+            control: control,
+            layout: None,
+            desired: Cell::new(Size::default()),
+        });
+        Ok(())
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Control> {
+        self.nodes.get(&id).map(|n| &*n.control)
+    }
+
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut Control> {
+        match self.nodes.get_mut(&id) {
+            Some(n) => Some(&mut *n.control),
+            None => None,
+        }
+    }
+
+    pub fn layout(&self, id: i32) -> Option<&Layout> {
+        self.nodes.get(&id).and_then(|n| n.layout.as_ref())
+    }
+
+    /// The layout of a control, giving it the default one if it had none.
+    pub fn layout_mut(&mut self, id: i32) -> Option<&mut Layout> {
+        match self.nodes.get_mut(&id) {
+            Some(n) => {
+                if n.layout.is_none() {
+                    n.layout = Some(Layout::new());
+                }
+                n.layout.as_mut()
+            },
+            None => None,
+        }
+    }
+
+    pub fn set_layout(&mut self, id: i32, layout: Layout) -> Result<(), TreeError> {
+        match self.nodes.get_mut(&id) {
+            Some(n) => {
+                n.layout = Some(layout);
+                Ok(())
+            },
+            None => Err(TreeError::NoSuchControl(id)),
+        }
+    }
+
+    /// The parent of a control, `None` for top-level and unknown controls.
+    pub fn parent(&self, id: i32) -> Option<i32> {
+        self.nodes.get(&id).and_then(|n| n.parent)
+    }
+
+    /// The children of `parent` bottom to top, or the top-level controls.
+    pub fn children(&self, parent: Option<i32>) -> &[i32] {
+        match parent {
+            Some(id) => self.nodes.get(&id).map_or(&[], |n| &n.children[..]),
+            None => &self.roots,
+        }
+    }
+
+    /// Removes a control and all of its descendants, returning the control.
+    pub fn remove(&mut self, id: i32) -> Option<Box<Control>> {
+        let parent = match self.nodes.get(&id) {
+            Some(n) => n.parent,
+            None => return None,
+        };
+        if let Ok(siblings) = self.siblings_mut(parent) {
+            siblings.retain(|&c| c != id);
+        }
+
+        let node = self.nodes.remove(&id).unwrap();
+        let mut orphans = node.children;
+        while let Some(orphan) = orphans.pop() {
+            if let Some(n) = self.nodes.remove(&orphan) {
+                orphans.extend(n.children);
+            }
+        }
+        Some(node.control)
+    }
+
+    /// Moves a control with its descendants to become the topmost child of
+    /// `parent`. Its bounds are kept as they are, i.e. relative to the new
+    /// parent.
+    pub fn reparent(&mut self, id: i32, parent: Option<i32>) -> Result<(), TreeError> {
+        if !self.contains(id) {
+            return Err(TreeError::NoSuchControl(id));
+        }
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            if a == id {
+                return Err(TreeError::WouldCreateCycle(id));
+            }
+            ancestor = self.parent(a);
+        }
+        try!(self.siblings_mut(parent)).push(id);
+
+        let old_parent = self.nodes[&id].parent;
+        let old_siblings = try!(self.siblings_mut(old_parent));
+        let position = old_siblings.iter().position(|&c| c == id).unwrap();
+        old_siblings.remove(position);
+        self.nodes.get_mut(&id).unwrap().parent = parent;
+        Ok(())
+    }
+
+    /// Moves a control to `index` among its siblings, 0 being the bottom.
+    /// Indices past the end move it to the top.
+    pub fn reorder(&mut self, id: i32, index: usize) -> Result<(), TreeError> {
+        let parent = match self.nodes.get(&id) {
+            Some(n) => n.parent,
+            None => return Err(TreeError::NoSuchControl(id)),
+        };
+        let siblings = try!(self.siblings_mut(parent));
+        let position = siblings.iter().position(|&c| c == id).unwrap();
+        siblings.remove(position);
+        let index = ::std::cmp::min(index, siblings.len());
+        siblings.insert(index, id);
+        Ok(())
+    }
+
+    pub fn bring_to_front(&mut self, id: i32) -> Result<(), TreeError> {
+        self.reorder(id, usize::max_value())
+    }
+
+    pub fn send_to_back(&mut self, id: i32) -> Result<(), TreeError> {
+        self.reorder(id, 0)
+    }
+
+    /// All controls below `parent`, each before its children and siblings
+    /// bottom to top, the order they get painted in.
+    pub fn descendants(&self, parent: Option<i32>) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for &id in self.children(parent) {
+            ids.push(id);
+            ids.extend(self.descendants(Some(id)));
+        }
+        ids
+    }
+
+    /// Where the coordinate space of a control's children starts, in client
+    /// coordinates.
+    pub fn origin(&self, id: Option<i32>) -> Point {
+        let mut origin = Point::default();
+        let mut current = id;
+        while let Some(node) = current.and_then(|id| self.nodes.get(&id)) {
+            if let Some(bounds) = node.control.bounds() {
+                origin = origin + bounds.origin();
+            }
+            current = node.parent;
+        }
+        origin
+    }
+
+    /// A control's bounds in client coordinates, if it knows them.
+    pub fn client_bounds(&self, id: i32) -> Option<Rect> {
+        let bounds = match self.nodes.get(&id).and_then(|n| n.control.bounds()) {
+            Some(bounds) => bounds,
+            None => return None,
+        };
+        let origin = self.origin(self.parent(id));
+        Some(bounds.offset(origin.x, origin.y))
+    }
+
+    /// The topmost control under a point in client coordinates.
+    pub fn hit_test(&self, point: Point) -> Option<i32> {
+        self.hit_test_children(None, point)
+    }
+
+    fn hit_test_children(&self, parent: Option<i32>, point: Point) -> Option<i32> {
+        for &id in self.children(parent).iter().rev() {
+            let bounds = match self.nodes[&id].control.bounds() {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            if bounds.contains(point) {
+                let local = point - bounds.origin();
+                return self.hit_test_children(Some(id), local).or(Some(id));
+            }
+        }
+        None
+    }
+
+    /// Measures and arranges the whole tree to fit a client area of `size`.
+    pub fn update_layout(&mut self, size: Size) {
+        let client = Rect::from_origin_size(Point::default(), size);
+        for id in self.roots.clone() {
+            if self.nodes[&id].layout.is_some() {
+                self.measure(id, size);
+                self.arrange(id, client);
+            } else {
+                self.layout_in_place(id);
+            }
+        }
+    }
+
+    /// Lays out the children of a control that keeps its own bounds.
+    fn layout_in_place(&mut self, id: i32) {
+        let size = self.nodes[&id].control.bounds().map_or(Size::default(), |b| b.size());
+        self.measure(id, size);
+        self.arrange_children(id, Rect::from_origin_size(Point::default(), size));
+    }
+
+    /// The measure pass for a control and its descendants.
+    fn measure(&self, id: i32, available: Size) -> Size {
+        let node = &self.nodes[&id];
+        let desired = layout::measure(self.layout_or_default(id), available, &mut |content| {
+            match node.control.panel() {
+                Some(panel) => {
+                    let layouts = node.children.iter().map(|&c| self.layout_or_default(c)).collect::<Vec<_>>();
+                    panel.measure(&layouts, content, &mut |i, available| self.measure(node.children[i], available))
+                },
+                None => {
+                    for &child in node.children.iter() {
+                        self.measure(child, content);
+                    }
+                    node.control.measure(content)
+                },
+            }
+        });
+        node.desired.set(desired);
+        desired
+    }
+
+    /// The arrange pass for a control that was just measured, and its
+    /// descendants.
+    fn arrange(&mut self, id: i32, slot: Rect) {
+        let (bounds, content) = {
+            let layout = self.layout_or_default(id);
+            let bounds = layout::arrange(layout, self.nodes[&id].desired.get(), slot);
+            let local = Rect::from_origin_size(Point::default(), bounds.size());
+            (bounds, layout.padding.deflate(&local))
+        };
+        self.nodes.get_mut(&id).unwrap().control.set_bounds(bounds);
+        self.arrange_children(id, content);
+    }
+
+    fn arrange_children(&mut self, id: i32, content: Rect) {
+        let children = self.children(Some(id)).to_vec();
+        let slots = {
+            let layouts = children.iter().map(|&c| self.layout_or_default(c)).collect::<Vec<_>>();
+            let desired = children.iter().map(|&c| self.nodes[&c].desired.get()).collect::<Vec<_>>();
+            self.nodes[&id].control.panel().map(|panel| panel.arrange(&layouts, &desired, content))
+        };
+        match slots {
+            Some(slots) => {
+                for (&child, slot) in children.iter().zip(slots) {
+                    self.arrange(child, slot);
+                }
+            },
+            // Without a panel, only children with a layout of their own move.
+            None => {
+                for &child in children.iter() {
+                    if self.nodes[&child].layout.is_some() {
+                        self.arrange(child, content);
+                    } else {
+                        self.layout_in_place(child);
+                    }
+                }
+            },
+        }
+    }
+
+    fn layout_or_default(&self, id: i32) -> &Layout {
+        self.nodes[&id].layout.as_ref().unwrap_or(&DEFAULT_LAYOUT)
+    }
+
+    /// Paints the tree bottom to top. With a `dirty` region, which is in
+    /// client coordinates, controls that don't touch it are skipped.
+    pub fn paint(&self, context: &PaintContext, dirty: Option<&Region>) {
+        self.paint_children(None, context, Point::default(), dirty);
+    }
+
+    fn paint_children(&self, parent: Option<i32>, context: &PaintContext, origin: Point, dirty: Option<&Region>) {
+        for &id in self.children(parent) {
+            let control = &self.nodes[&id].control;
+            let bounds = control.bounds();
+            let is_dirty = match (dirty, bounds) {
+                (Some(dirty), Some(b)) => dirty.intersects(&b.offset(origin.x, origin.y)),
+                _ => true,
+            };
+            if is_dirty && control.needs_repaint(context) {
+                control.paint(context);
+            }
+
+            if !self.nodes[&id].children.is_empty() {
+                let local = bounds.map_or(Point::default(), |b| b.origin());
+                let child_context = context.translate(local);
+                self.paint_children(Some(id), &child_context, origin + local, dirty);
+            }
+        }
+    }
+
+    fn siblings_mut(&mut self, parent: Option<i32>) -> Result<&mut Vec<i32>, TreeError> {
+        match parent {
+            Some(id) => match self.nodes.get_mut(&id) {
+                Some(n) => Ok(&mut n.children),
+                None => Err(TreeError::NoSuchControl(id)),
+            },
+            None => Ok(&mut self.roots),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::backend::headless::DrawCall;
+    use ::controls::label::Label;
+    use ::testing::{ self, open_window };
+
+    fn label(text: &str, left: i32, top: i32, width: i32, height: i32) -> Box<Control> {
+        let mut label = Label::new();
+        label.text = text.to_string();
+        label.set_position(left, top);
+        label.set_size(width, height);
+        Box::new(label)
+    }
+
+    /// 1 at (10, 10) with 2 at (10, 10) in it and 3 at (5, 5) in that, each
+    /// 50 by 50; 4 is a top-level sibling of 1.
+    fn nested() -> ControlTree {
+        let mut tree = ControlTree::new();
+        tree.insert(1, None, label("1", 10, 10, 50, 50)).unwrap();
+        tree.insert(2, Some(1), label("2", 10, 10, 50, 50)).unwrap();
+        tree.insert(3, Some(2), label("3", 5, 5, 50, 50)).unwrap();
+        tree.insert(4, None, label("4", 100, 0, 50, 50)).unwrap();
+        tree
+    }
+
+    #[test]
+    fn inserting_checks_ids_and_parents() {
+        let mut tree = nested();
+        assert_eq!(tree.insert(2, None, label("", 0, 0, 1, 1)), Err(TreeError::DuplicateId(2)));
+        assert_eq!(tree.insert(5, Some(9), label("", 0, 0, 1, 1)), Err(TreeError::NoSuchControl(9)));
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.children(None), &[1, 4]);
+        assert_eq!(tree.descendants(None), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn hit_testing_goes_through_child_origins() {
+        let tree = nested();
+        assert_eq!(tree.client_bounds(3), Some(Rect::new(25, 25, 75, 75)));
+        assert_eq!(tree.hit_test(Point::new(15, 15)), Some(1));
+        assert_eq!(tree.hit_test(Point::new(22, 22)), Some(2));
+        assert_eq!(tree.hit_test(Point::new(25, 25)), Some(3));
+        // Outside its parent a child isn't hit, even inside its own bounds.
+        assert_eq!(tree.hit_test(Point::new(70, 70)), None);
+        assert_eq!(tree.hit_test(Point::new(120, 10)), Some(4));
+    }
+
+    #[test]
+    fn reordering_changes_hit_testing_order() {
+        let mut tree = ControlTree::new();
+        for id in 1..4 {
+            tree.insert(id, None, label("", 0, 0, 50, 50)).unwrap();
+        }
+        assert_eq!(tree.hit_test(Point::new(10, 10)), Some(3));
+        tree.bring_to_front(1).unwrap();
+        assert_eq!(tree.children(None), &[2, 3, 1]);
+        assert_eq!(tree.hit_test(Point::new(10, 10)), Some(1));
+        tree.send_to_back(1).unwrap();
+        tree.reorder(2, 1).unwrap();
+        assert_eq!(tree.children(None), &[1, 2, 3]);
+        assert_eq!(tree.reorder(7, 0), Err(TreeError::NoSuchControl(7)));
+    }
+
+    #[test]
+    fn reordering_changes_paint_order() {
+        let (backend, mut handle) = open_window(200, 100);
+        let ids = ["a", "b", "c"].iter()
+            .map(|text| testing::core(&mut handle).add_control(label(text, 0, 0, 50, 20)))
+            .collect::<Vec<_>>();
+        let painted = |handle| backend.paint(handle).into_iter().filter_map(|call| match call {
+            DrawCall::Text { text, .. } => Some(text),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(painted(handle), vec!["a", "b", "c"]);
+
+        testing::core(&mut handle).reorder(ids[0], usize::max_value()).unwrap();
+        testing::core(&mut handle).reorder(ids[2], 0).unwrap();
+        assert_eq!(painted(handle), vec!["c", "b", "a"]);
+        assert_eq!(testing::core(&mut handle).control_at(Point::new(10, 10)), Some(ids[0]));
+    }
+
+    #[test]
+    fn reparenting_into_a_descendant_is_refused() {
+        let mut tree = nested();
+        assert_eq!(tree.reparent(1, Some(3)), Err(TreeError::WouldCreateCycle(1)));
+        assert_eq!(tree.reparent(2, Some(2)), Err(TreeError::WouldCreateCycle(2)));
+        assert_eq!(tree.reparent(1, Some(8)), Err(TreeError::NoSuchControl(8)));
+        assert_eq!(tree.descendants(None), vec![1, 2, 3, 4]);
+
+        // Moving a subtree keeps its children and local bounds.
+        tree.reparent(2, Some(4)).unwrap();
+        assert_eq!(tree.children(Some(1)), &[] as &[i32]);
+        assert_eq!(tree.parent(2), Some(4));
+        assert_eq!(tree.descendants(None), vec![1, 4, 2, 3]);
+        assert_eq!(tree.client_bounds(3), Some(Rect::new(115, 15, 165, 65)));
+        tree.reparent(2, None).unwrap();
+        assert_eq!(tree.children(None), &[1, 4, 2]);
+    }
+
+    #[test]
+    fn removing_drops_all_descendants() {
+        let mut tree = nested();
+        tree.insert(5, Some(1), label("5", 0, 0, 5, 5)).unwrap();
+        assert!(tree.remove(1).is_some());
+        assert_eq!(tree.len(), 1);
+        for id in 1..4 {
+            assert!(!tree.contains(id));
+        }
+        assert!(!tree.contains(5));
+        assert_eq!(tree.children(None), &[4]);
+        assert!(tree.remove(1).is_none());
+    }
+}
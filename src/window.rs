@@ -1,19 +1,28 @@
 #![allow(non_upper_case_globals)]
 
 use winapi::*;
-use user32::*;
-use kernel32::*;
-use wio::wide::*;
-use ::message::{ MessageHandlerBase, MessageHandler };
+use ::backend::{ self, Backend, ClassDesc, WindowDesc };
+use ::message::{ self, MessageHandlerBase, MessageHandler, MessageQueue, WindowSender };
 
 use ::ml::*;
 use ::colors::*;
+use ::constraints::{ Anchor, Constraint, Constraints, Unsatisfiable };
+use ::event::{ Event, KeyFlags, Modifiers, MouseButton, SizeKind, MODIFIER_CONTROL, MODIFIER_SHIFT };
+use ::controls::{ Control, InputContext };
+use ::geometry::{ Point, Rect, Size };
+use ::region::Region;
+use ::layout::Layout;
+use ::task::{ self, Future, Poll, TaskId, Tasks };
+use ::timer::{ self, TimerId, Timers };
+use ::tree::{ ControlTree, TreeError };
 
 use std::any::{ Any, TypeId };
+use std::cell::{ Cell, RefCell };
 use std::collections::HashMap;
 use std::marker::Reflect;
-use std::ffi::OsString;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 
 // TODO: rewrite this, using constants in the `WindowClass` trait!
@@ -44,27 +53,16 @@ impl<T: WindowClass> WindowClassBuilder<T> {
     }
 
     pub fn register(self, instance: HINSTANCE) {
-        unsafe {
-            let mut class_name = self.class_name.to_wide_null();
-            let mut menu_name_raw = self.menu_name.to_wide_null();
-            let menu_name = if self.menu_name.is_empty() {
-                ::std::ptr::null_mut()
-            } else {
-                menu_name_raw.as_mut_ptr()
-            };
-            let mut wndclass: WNDCLASSEXW = ::std::mem::zeroed();
-            wndclass.cbSize = ::std::mem::size_of::<WNDCLASSEXW>() as UINT;
-            wndclass.style = self.style;
-            wndclass.lpfnWndProc = Some(window_proc);
-            wndclass.hInstance = instance;
-            wndclass.hIcon = self.icon;
-            wndclass.hIconSm = self.icon_small;
-            wndclass.hCursor = self.cursor;
-            wndclass.lpszClassName = class_name.as_mut_ptr();
-            wndclass.lpszMenuName = menu_name;
-            wndclass.hbrBackground = self.background_brush;
-            RegisterClassExW(&wndclass);
-        }
+        backend::current().register_class(&ClassDesc {
+            class_name: self.class_name,
+            style: self.style,
+            icon: self.icon,
+            icon_small: self.icon_small,
+            cursor: self.cursor,
+            background_brush: self.background_brush,
+            menu_name: self.menu_name,
+            instance: instance,
+        });
     }
 
     // TODO: setters for the variables
@@ -88,7 +86,7 @@ pub struct WindowBuilder<T: Window> {
 
 pub fn get_window_from_handle<'a>(handle: &'a HWND) -> &'a Box<Window> {
     unsafe {
-        let ptr = GetWindowLongPtrW(*handle, GWLP_USERDATA) as *mut Box<Window>;
+        let ptr = backend::current().user_data(*handle) as *mut Box<Window>;
         if ptr.is_null() {
             println!("Getting NULL as window from handle..");
         }
@@ -98,7 +96,7 @@ pub fn get_window_from_handle<'a>(handle: &'a HWND) -> &'a Box<Window> {
 
 pub fn get_window_from_handle_mut<'a>(handle: &'a mut HWND) -> &'a mut Box<Window> {
     unsafe {
-        let ptr = GetWindowLongPtrW(*handle, GWLP_USERDATA) as *mut Box<Window>;
+        let ptr = backend::current().user_data(*handle) as *mut Box<Window>;
         &mut *ptr
     }
 }
@@ -123,31 +121,20 @@ impl<T: Window + WindowClass> WindowBuilder<T> {
     }
 
     pub fn build(self) -> Result<HWND, DWORD> {
-        unsafe {
-            let class_name = OsString::from(&self.class_name).to_wide_null();
-            let window_name = OsString::from(&self.window_name).to_wide_null();
-            let wnd_struct = Box::new(T::new());
-            let handle = CreateWindowExW(
-                self.ex_style,
-                class_name.as_ptr(),
-                window_name.as_ptr(),
-                self.style,
-                self.pos_x,
-                self.pos_y,
-                self.width,
-                self.height,
-                self.parent,
-                self.menu,
-                self.hinstance,
-                Box::into_raw(wnd_struct) as LPVOID
-            );
-
-            if handle.is_null() {
-                Err(GetLastError())
-            } else {
-                Ok(handle)
-            }
-        }
+        let desc = WindowDesc {
+            ex_style: self.ex_style,
+            class_name: self.class_name,
+            window_name: self.window_name,
+            style: self.style,
+            pos_x: self.pos_x,
+            pos_y: self.pos_y,
+            width: self.width,
+            height: self.height,
+            parent: self.parent,
+            menu: self.menu,
+            instance: self.hinstance,
+        };
+        backend::current().create_window(&desc, Box::new(T::new()))
     }
 
     pub fn set_title<S: Into<String>>(mut self, name: S) -> Self {
@@ -194,22 +181,27 @@ pub trait WindowClass {
     fn default_style() -> UINT { WS_TILEDWINDOW }
     fn default_class_style() -> UINT { CS_DROPSHADOW | CS_HREDRAW | CS_VREDRAW }
     fn default_icon() -> HICON {
-        unsafe { LoadIconW(::std::ptr::null_mut(), IDI_APPLICATION) }
+        backend::current().load_icon(IDI_APPLICATION)
     }
     fn default_icon_small() -> HICON {
-        unsafe { LoadIconW(::std::ptr::null_mut(), IDI_APPLICATION) }
+        backend::current().load_icon(IDI_APPLICATION)
     }
     fn default_menu_name() -> &'static str { "" }
     fn default_background_brush() -> HBRUSH {
-        unsafe { ::gdi32::CreateSolidBrush(Color(0, 255, 255, 255).to_int()) }
+        backend::current().create_solid_brush(Color(0, 255, 255, 255))
     }
     fn default_cursor() -> HCURSOR {
-        unsafe { LoadCursorW(::std::ptr::null_mut(), IDC_ARROW) }
+        backend::current().load_cursor(IDC_ARROW)
     }
 
     fn new() -> Box<Window>;
 }
 
+/// A window, with handlers for the messages it gets.
+///
+/// Handlers returning `bool` return whether they handled the message; the
+/// default ones don't, so the message goes on to `DefWindowProc`.
+#[allow(unused_variables)]
 pub trait Window : Paintable {
     fn init_handle(&mut self, handle: HWND);
     fn get_core<'a>(&'a self) -> &'a WindowCore;
@@ -217,12 +209,64 @@ pub trait Window : Paintable {
     fn on_create(&mut self) { }
     fn get_debug_name(&self) -> String { "Window Base".to_string() }
     fn get_unique_id(&mut self) -> i32 { self.get_core_mut().get_unique_id() }
+
+    /// Returning `false` keeps the window open.
+    fn on_close(&mut self) -> bool { true }
+    /// The last thing a window gets before it is dropped.
+    fn on_destroy(&mut self) { }
+    /// Whether destroying the window ends the event loop, as it should for
+    /// the main window but not for popups.
+    fn quits_on_destroy(&self) -> bool { true }
+    /// Called after the controls got laid out for the new size.
+    fn on_resize(&mut self, kind: SizeKind, size: Size) -> bool { false }
+    /// Called before `on_resize` when the constraints contradict each other
+    /// at the new size. The controls are left as close as the solver got
+    /// them.
+    fn on_layout_error(&mut self, error: &Unsatisfiable) { }
+    /// `position` is where the client area is now, in screen coordinates
+    /// for top-level windows.
+    fn on_move(&mut self, position: Point) -> bool { false }
+    fn on_mouse_down(&mut self, button: MouseButton, position: Point, modifiers: Modifiers) -> bool { false }
+    fn on_mouse_up(&mut self, button: MouseButton, position: Point, modifiers: Modifiers) -> bool { false }
+    /// Only with `CS_DBLCLKS` in the class style, handled like another
+    /// button press by default.
+    fn on_double_click(&mut self, button: MouseButton, position: Point, modifiers: Modifiers) -> bool {
+        self.on_mouse_down(button, position, modifiers)
+    }
+    fn on_mouse_move(&mut self, position: Point, modifiers: Modifiers) -> bool { false }
+    /// Unlike the other mouse handlers `position` is in screen coordinates.
+    fn on_mouse_wheel(&mut self, delta: i32, horizontal: bool, position: Point, modifiers: Modifiers) -> bool { false }
+    /// Keys pressed with ALT or F10 (`WM_SYSKEYDOWN`) don't end up here, so
+    /// ALT+F4 and the menu keep working.
+    fn on_key_down(&mut self, key: u32, flags: KeyFlags) -> bool { false }
+    fn on_key_up(&mut self, key: u32, flags: KeyFlags) -> bool { false }
+    /// Surrogate pairs are put back together before this is called.
+    fn on_char(&mut self, c: char, flags: KeyFlags) -> bool { false }
+    fn on_focus(&mut self, previous: HWND) -> bool { false }
+    fn on_blur(&mut self, next: HWND) -> bool { false }
+    fn on_timer(&mut self, id: usize) -> bool { false }
+    fn on_command(&mut self, id: u16, code: u16, control: HWND) -> bool { false }
 }
 
 pub struct WindowCore {
     handle:     HWND,
-    controls:   Vec<Box<Paintable>>,
+    backend:    Rc<Backend>,
+    controls:   ControlTree,
+    dirty:      RefCell<Region>,
+    all_dirty:  Cell<bool>,
+    client_size: Size,
+    constraints: Constraints,
+    /// What the last layout pass couldn't satisfy.
+    layout_error: Option<Unsatisfiable>,
+    high_surrogate: Option<u16>,
     handlers:   HashMap<::std::any::TypeId, Rc<Fn(&mut WindowCore, Box<Any>)>>,
+    queue:      Arc<MessageQueue>,
+    timers:     Timers,
+    tasks:      Tasks,
+    hovered:    Option<i32>,
+    focused:    Option<i32>,
+    captured:   Option<i32>,
+    modifiers:  Modifiers,
     ids:        Box<Iterator<Item=i32>>,
 }
 
@@ -230,38 +274,507 @@ impl WindowCore {
     pub fn from_handle(handle: HWND) -> Self {
         WindowCore {
             handle: handle,
-            controls: Vec::new(),
+            backend: backend::current(),
+            controls: ControlTree::new(),
+            dirty: RefCell::new(Region::new()),
+            all_dirty: Cell::new(false),
+            client_size: Size::default(),
+            constraints: Constraints::new(),
+            layout_error: None,
+            high_surrogate: None,
             handlers: HashMap::new(),
+            queue: Arc::new(MessageQueue::new()),
+            timers: Timers::new(),
+            tasks: Tasks::new(),
+            hovered: None,
+            focused: None,
+            captured: None,
+            modifiers: Modifiers::empty(),
             ids: Box::new((0..).into_iter()),
         }
     }
 
-    pub fn add_control(&mut self, control: Box<Paintable>) {
-        self.controls.push(control);
+    /// Adds a top-level control, returning the id to refer to it later.
+    pub fn add_control(&mut self, control: Box<Control>) -> i32 {
+        let id = self.get_unique_id();
+        self.controls.insert(id, None, control).unwrap();
+        self.invalidate(id);
+        id
     }
 
-    pub fn show(&self, cmd_show: i32) {
-        unsafe {
-            ShowWindow(self.handle, cmd_show);
+    /// Adds a control on top of the children of `parent`.
+    pub fn add_child(&mut self, parent: i32, control: Box<Control>) -> Result<i32, TreeError> {
+        if !self.controls.contains(parent) {
+            return Err(TreeError::NoSuchControl(parent));
+        }
+        let id = self.get_unique_id();
+        try!(self.controls.insert(id, Some(parent), control));
+        self.invalidate(id);
+        Ok(id)
+    }
+
+    pub fn control(&self, id: i32) -> Option<&Control> {
+        self.controls.get(id)
+    }
+
+    /// Changes to the control should be followed by `invalidate`.
+    pub fn control_mut(&mut self, id: i32) -> Option<&mut Control> {
+        self.controls.get_mut(id)
+    }
+
+    pub fn controls(&self) -> &ControlTree {
+        &self.controls
+    }
+
+    /// Removes a control along with its children.
+    pub fn remove_control(&mut self, id: i32) -> Option<Box<Control>> {
+        self.invalidate(id);
+        self.constraints.remove(id);
+        let removed = self.controls.remove(id);
+        if self.captured.map_or(false, |id| !self.controls.contains(id)) {
+            self.captured = None;
+            self.backend.release_capture();
         }
+        if self.hovered.map_or(false, |id| !self.controls.contains(id)) {
+            self.hovered = None;
+        }
+        if self.focused.map_or(false, |id| !self.controls.contains(id)) {
+            self.focused = None;
+        }
+        removed
+    }
+
+    /// Moves a control to `parent`, or to the top level if there is none.
+    pub fn reparent(&mut self, id: i32, parent: Option<i32>) -> Result<(), TreeError> {
+        self.invalidate(id);
+        try!(self.controls.reparent(id, parent));
+        self.invalidate(id);
+        Ok(())
+    }
+
+    /// Moves a control to `index` in the z-order of its siblings.
+    pub fn reorder(&mut self, id: i32, index: usize) -> Result<(), TreeError> {
+        try!(self.controls.reorder(id, index));
+        self.invalidate(id);
+        Ok(())
+    }
+
+    /// Changes to the layout take effect with the next `update_layout`.
+    pub fn layout_mut(&mut self, id: i32) -> Option<&mut Layout> {
+        self.controls.layout_mut(id)
+    }
+
+    pub fn set_layout(&mut self, id: i32, layout: Layout) -> Result<(), TreeError> {
+        self.controls.set_layout(id, layout)
+    }
+
+    pub fn client_size(&self) -> Size {
+        self.client_size
+    }
+
+    /// Keeps the control's distance to the given edges of its parent as it
+    /// is on the next layout pass.
+    pub fn set_anchor(&mut self, id: i32, anchor: Anchor) {
+        self.constraints.set_anchor(id, anchor);
+    }
+
+    /// Adds a constraint, solved with the others on every layout pass.
+    pub fn add_constraint(&mut self, constraint: Constraint) {
+        self.constraints.add(constraint);
+    }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    /// Called when the window got resized, lays the controls out again.
+    pub fn set_client_size(&mut self, size: Size) -> Result<(), Unsatisfiable> {
+        self.client_size = size;
+        self.update_layout()
+    }
+
+    /// Measures and arranges all controls to fit the client area, then
+    /// applies anchors and constraints.
+    pub fn update_layout(&mut self) -> Result<(), Unsatisfiable> {
+        self.controls.update_layout(self.client_size);
+        let result = self.constraints.apply(&mut self.controls, self.client_size);
+        self.layout_error = result.clone().err();
+        self.invalidate_all();
+        result
+    }
+
+    /// The constraints the last layout pass left unsatisfied, if any.
+    pub fn layout_error(&self) -> Option<&Unsatisfiable> {
+        self.layout_error.as_ref()
+    }
+
+    /// The topmost control at a point in client coordinates.
+    pub fn control_at(&self, point: Point) -> Option<i32> {
+        self.controls.hit_test(point)
+    }
+
+    /// The control with the keyboard focus.
+    pub fn focused(&self) -> Option<i32> {
+        self.focused
+    }
+
+    /// Moves the keyboard focus to a control, or away from all of them for
+    /// `None`. Returns false, leaving the focus alone, if the control can't
+    /// take it.
+    pub fn focus(&mut self, id: Option<i32>) -> bool {
+        if let Some(id) = id {
+            if !self.controls.get(id).map_or(false, |c| c.focusable()) {
+                return false;
+            }
+        }
+        if id == self.focused {
+            return true;
+        }
+        if let Some(old) = self.focused.take() {
+            self.send_event(old, &Event::Blur { next: ::std::ptr::null_mut() });
+        }
+        self.focused = id;
+        if let Some(new) = id {
+            self.send_event(new, &Event::Focus { previous: ::std::ptr::null_mut() });
+        }
+        true
+    }
+
+    /// Moves the focus to the next focusable control in paint order, or the
+    /// previous one, wrapping around. Returns whether there was one.
+    pub fn focus_next(&mut self, forward: bool) -> bool {
+        let mut candidates = self.controls.descendants(None).into_iter()
+            .filter(|&id| self.controls.get(id).map_or(false, |c| c.focusable()))
+            .collect::<Vec<_>>();
+        if !forward {
+            candidates.reverse();
+        }
+        let next = match self.focused.and_then(|f| candidates.iter().position(|&id| id == f)) {
+            Some(index) => candidates.get(index + 1).or(candidates.first()).cloned(),
+            None => candidates.first().cloned(),
+        };
+        next.is_some() && self.focus(next)
+    }
+
+    fn input_context(&self, id: i32) -> InputContext {
+        let mut context = InputContext::new(id, self.handle, self.queue.clone());
+        context.modifiers = self.modifiers;
+        context.origin = self.controls.origin(Some(id));
+        context
+    }
+
+    /// Keeps track of Shift and Ctrl, which keyboard messages don't carry.
+    fn update_modifiers(&mut self, event: &Event) {
+        let (key, down) = match *event {
+            Event::MouseMove { modifiers, .. } | Event::MouseDown { modifiers, .. } |
+            Event::MouseUp { modifiers, .. } | Event::DoubleClick { modifiers, .. } => {
+                self.modifiers = modifiers & (MODIFIER_SHIFT | MODIFIER_CONTROL);
+                return;
+            },
+            Event::KeyDown { key, .. } => (key, true),
+            Event::KeyUp { key, .. } => (key, false),
+            _ => return,
+        };
+        let modifier = if key == VK_SHIFT as u32 {
+            MODIFIER_SHIFT
+        } else if key == VK_CONTROL as u32 {
+            MODIFIER_CONTROL
+        } else {
+            return;
+        };
+        if down {
+            self.modifiers.insert(modifier);
+        } else {
+            self.modifiers.remove(modifier);
+        }
+    }
+
+    /// Hands an event in client coordinates to a single control, in its own
+    /// coordinates, returning whether it handled it. Popups use this to
+    /// pass their input on to the control that opened them.
+    pub fn send_event(&mut self, id: i32, event: &Event) -> bool {
+        let local = event.relative_to(self.controls.origin(Some(id)));
+        let mut context = self.input_context(id);
+        let handled = match self.controls.get_mut(id) {
+            Some(control) => control.handle_event(&local, &mut context),
+            None => return false,
+        };
+        if context.is_invalidated() {
+            self.invalidate(id);
+        }
+        handled
+    }
+
+    /// Hands an event to a control and then its parents until one of them
+    /// handles it.
+    fn bubble_event(&mut self, id: i32, event: &Event) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if self.send_event(id, event) {
+                return true;
+            }
+            current = self.controls.parent(id);
+        }
+        false
+    }
+
+    /// Passes mouse and keyboard input on to the controls, keeping track of
+    /// which one is under the mouse, has the focus or the mouse capture.
+    /// Returns whether a control handled it.
+    fn route_input(&mut self, event: &Event) -> bool {
+        self.update_modifiers(event);
+        match *event {
+            Event::MouseMove { position, .. } => {
+                let hit = self.control_at(position);
+                if hit != self.hovered {
+                    if let Some(old) = self.hovered.take() {
+                        self.send_event(old, &Event::MouseLeave);
+                    } else {
+                        self.backend.track_mouse_leave(self.handle);
+                    }
+                    self.hovered = hit;
+                }
+                match self.captured.or(hit) {
+                    Some(id) => self.bubble_event(id, event),
+                    None => false,
+                }
+            },
+            Event::MouseLeave => {
+                if let Some(old) = self.hovered.take() {
+                    self.send_event(old, &Event::MouseLeave);
+                }
+                false
+            },
+            Event::MouseDown { position, .. } | Event::DoubleClick { position, .. } => {
+                let hit = match self.control_at(position) {
+                    Some(hit) => hit,
+                    None => return false,
+                };
+                let mut focusable = Some(hit);
+                while let Some(id) = focusable {
+                    if self.focus(Some(id)) {
+                        break;
+                    }
+                    focusable = self.controls.parent(id);
+                }
+                if self.captured.is_none() {
+                    self.captured = Some(hit);
+                    self.backend.set_capture(self.handle);
+                }
+                self.bubble_event(hit, event)
+            },
+            Event::MouseUp { position, .. } => {
+                let target = match self.captured.take() {
+                    Some(id) => {
+                        self.backend.release_capture();
+                        Some(id)
+                    },
+                    None => self.control_at(position),
+                };
+                match target {
+                    Some(id) => self.bubble_event(id, event),
+                    None => false,
+                }
+            },
+            Event::MouseWheel { .. } => match self.hovered {
+                Some(id) => self.bubble_event(id, event),
+                None => false,
+            },
+            Event::KeyDown { key, system: false, .. } => {
+                let handled = match self.focused {
+                    Some(id) => self.bubble_event(id, event),
+                    None => false,
+                };
+                if !handled && key == VK_TAB as u32 {
+                    let forward = !self.modifiers.contains(MODIFIER_SHIFT);
+                    return self.focus_next(forward);
+                }
+                handled
+            },
+            Event::KeyUp { system: false, .. } => match self.focused {
+                Some(id) => self.bubble_event(id, event),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Hands a typed character to the focused control and its parents.
+    fn route_char(&mut self, c: char) -> bool {
+        let mut current = self.focused;
+        while let Some(id) = current {
+            let mut context = self.input_context(id);
+            let handled = match self.controls.get_mut(id) {
+                Some(control) => control.handle_char(c, &mut context),
+                None => false,
+            };
+            if context.is_invalidated() {
+                self.invalidate(id);
+            }
+            if handled {
+                return true;
+            }
+            current = self.controls.parent(id);
+        }
+        false
+    }
+
+    /// Schedules a repaint of the control with the given id. Controls that
+    /// don't know their bounds invalidate the whole window.
+    pub fn invalidate(&mut self, control_id: i32) {
+        if !self.controls.contains(control_id) {
+            return;
+        }
+        match self.controls.client_bounds(control_id) {
+            Some(rect) => self.invalidate_rect(rect),
+            None => self.invalidate_all(),
+        }
+    }
+
+    pub fn invalidate_rect(&mut self, rect: Rect) {
+        self.dirty.borrow_mut().add(rect);
+        self.backend.invalidate_rect(self.handle, Some(&rect));
+    }
+
+    pub fn invalidate_all(&mut self) {
+        self.all_dirty.set(true);
+        self.backend.invalidate_rect(self.handle, None);
+    }
+
+    /// The areas invalidated since the last paint.
+    pub fn dirty_region(&self) -> Region {
+        self.dirty.borrow().clone()
+    }
+
+    pub fn handle(&self) -> HWND {
+        self.handle
+    }
+
+    pub fn show(&self, cmd_show: i32) {
+        self.backend.show_window(self.handle, cmd_show);
     }
 
     pub fn update(&self) {
-        unsafe {
-            UpdateWindow(self.handle);
+        self.backend.update_window(self.handle);
+    }
+
+    /// Collects `WM_CHAR` code units into characters, returning `None` while
+    /// waiting for the second half of a surrogate pair.
+    fn take_char(&mut self, code: u16) -> Option<char> {
+        match code {
+            0xD800...0xDBFF => {
+                self.high_surrogate = Some(code);
+                None
+            },
+            0xDC00...0xDFFF => {
+                let high = self.high_surrogate.take();
+                high.and_then(|high| {
+                    let c = 0x10000 + (((high as u32) - 0xD800) << 10) + ((code as u32) - 0xDC00);
+                    ::std::char::from_u32(c)
+                })
+            },
+            _ => {
+                self.high_surrogate = None;
+                ::std::char::from_u32(code as u32)
+            },
         }
     }
 
     pub fn get_unique_id(&mut self) -> i32 {
         self.ids.next().unwrap()
     }
+
+    /// A handle for posting messages of type `T` to this window, from this
+    /// or any other thread.
+    pub fn sender<T: Reflect + Send + 'static>(&self) -> WindowSender<T> {
+        WindowSender::new(self.handle, self.queue.clone())
+    }
+
+    /// Calls `callback` after `duration`, and every `duration` after that if
+    /// `repeat` is set, until the timer is cancelled.
+    pub fn set_timer<F>(&mut self, duration: Duration, repeat: bool, callback: F) -> TimerId
+            where F: FnMut(&mut WindowCore) + 'static {
+        let id = self.timers.add(repeat, Box::new(callback));
+        self.backend.set_timer(self.handle, id.raw(), timer::interval(duration));
+        id
+    }
+
+    /// Stops a timer, returning whether it was still running.
+    pub fn cancel_timer(&mut self, id: TimerId) -> bool {
+        if self.timers.remove(id) {
+            self.backend.kill_timer(self.handle, id.raw());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Runs the callback for a `WM_TIMER`, returning whether the timer was
+    /// one of ours.
+    fn fire_timer(&mut self, raw: usize) -> bool {
+        let (id, repeat, mut callback) = match self.timers.take(raw) {
+            Some(timer) => timer,
+            None => return false,
+        };
+        if !repeat {
+            self.cancel_timer(id);
+        }
+        callback(self);
+        if repeat {
+            self.timers.restore(id, callback);
+        }
+        true
+    }
+
+    /// Runs `future` on this window until it completes, the task gets
+    /// cancelled or the window is destroyed.
+    pub fn spawn_local<F: Future<Output=()> + 'static>(&mut self, future: F) -> TaskId {
+        self.tasks.spawn(self.handle, Box::new(future))
+    }
+
+    /// Drops a task, returning whether it was still running.
+    pub fn cancel_task(&mut self, id: TaskId) -> bool {
+        self.tasks.cancel(id)
+    }
+
+    /// Polls the task a `WM_WAKE` was for.
+    fn run_task(&mut self, raw: usize) {
+        let (id, mut future, waker) = match self.tasks.take(raw) {
+            Some(task) => task,
+            None => return,
+        };
+        match future.poll(self, &waker) {
+            Poll::Ready(()) => { self.tasks.cancel(id); },
+            Poll::Pending => self.tasks.restore(id, future),
+        }
+    }
+
+    /// Handles the messages posted so far, in order. Ones without a handler
+    /// are dropped, there is nobody waiting to hear about it.
+    fn deliver_posted(&mut self) {
+        for mut envelope in self.queue.take_all() {
+            let _ = MessageHandlerBase::handle_message(self, &mut envelope);
+        }
+    }
+}
+
+impl Drop for WindowCore {
+    fn drop(&mut self) {
+        for id in self.timers.ids() {
+            self.cancel_timer(id);
+        }
+        self.queue.close();
+    }
 }
 
 impl Paintable for WindowCore {
+    /// Paints the controls touching the dirty region, or everything within
+    /// the paint rectangle if nothing in particular was invalidated.
     fn paint(&self, context: &PaintContext) {
-        for c in self.controls.iter().filter(|c| c.needs_repaint(context)) {
-            c.paint(context);
-        }
+        let dirty = self.dirty.borrow_mut().take();
+        let everything = self.all_dirty.get() || dirty.is_empty();
+        self.all_dirty.set(false);
+        self.controls.paint(context, if everything { None } else { Some(&dirty) });
     }
 }
 
@@ -269,21 +782,56 @@ impl MessageHandlerBase for WindowCore {
     fn register_message<T: Reflect + 'static>(&mut self)
             where Self: MessageHandler<T> {
         let func = Rc::new(|wnd: &mut Self, msg: Box<Any>| {
-            let msg = msg.downcast_ref::<T>().unwrap();
-            <Self as MessageHandler<T>>::handle_message(wnd, msg);
+            if let Ok(msg) = msg.downcast::<T>() {
+                <Self as MessageHandler<T>>::handle_message(wnd, &msg);
+            }
         });
         self.handlers.insert(TypeId::of::<T>(), func);
     }
 
-    fn get_message_handler(&self, t: TypeId) -> Rc<Fn(&mut Self, Box<Any>)> {
-        self.handlers.get(&t).unwrap().clone()
+    fn get_message_handler(&self, t: TypeId) -> Option<Rc<Fn(&mut Self, Box<Any>)>> {
+        self.handlers.get(&t).cloned()
     }
 }
 
-#[allow(unused_variables)]
-unsafe extern "system" fn window_proc(mut hwnd: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+/// Calls the handler for `event`, returning whether it got handled.
+/// Input the controls handle doesn't reach the window's handlers.
+fn dispatch(window: &mut Box<Window>, event: Event) -> bool {
+    if window.get_core_mut().route_input(&event) {
+        return true;
+    }
+    match event {
+        Event::Size { kind, size } => {
+            if let Err(e) = window.get_core_mut().set_client_size(size) {
+                window.on_layout_error(&e);
+            }
+            window.on_resize(kind, size)
+        },
+        Event::Move { position } => window.on_move(position),
+        Event::MouseDown { button, position, modifiers } => window.on_mouse_down(button, position, modifiers),
+        Event::MouseUp { button, position, modifiers } => window.on_mouse_up(button, position, modifiers),
+        Event::DoubleClick { button, position, modifiers } => window.on_double_click(button, position, modifiers),
+        Event::MouseMove { position, modifiers } => window.on_mouse_move(position, modifiers),
+        Event::MouseWheel { delta, horizontal, position, modifiers } =>
+            window.on_mouse_wheel(delta, horizontal, position, modifiers),
+        Event::KeyDown { key, system: false, flags } => window.on_key_down(key, flags),
+        Event::KeyUp { key, system: false, flags } => window.on_key_up(key, flags),
+        Event::Char { code, system: false, flags } => match window.get_core_mut().take_char(code) {
+            Some(c) => window.get_core_mut().route_char(c) || window.on_char(c, flags),
+            None => true,
+        },
+        Event::Focus { previous } => window.on_focus(previous),
+        Event::Blur { next } => window.on_blur(next),
+        Event::Timer { id } => window.get_core_mut().fire_timer(id) || window.on_timer(id),
+        Event::Command { id, code, control } => window.on_command(id, code, control),
+        _ => false,
+    }
+}
+
+/// Handles a message for one of our windows, whichever backend it came from.
+pub unsafe fn window_proc(mut hwnd: HWND, msg: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    let backend = backend::current();
     match msg {
-        // TODO: override *all* the messages.
         WM_CREATE => {
             let st = l_param as *mut CREATESTRUCTW;
             if l_param == 0 || (*st).lpCreateParams.is_null() {
@@ -291,22 +839,317 @@ unsafe extern "system" fn window_proc(mut hwnd: HWND, msg: UINT, w_param: WPARAM
             }
             let mut wnd = Box::from_raw((*st).lpCreateParams as *mut Box<Window>);
             wnd.init_handle(hwnd);
-            SetWindowLongPtrW(hwnd, GWLP_USERDATA, Box::into_raw(wnd) as LONG_PTR);
+            backend.set_user_data(hwnd, Box::into_raw(wnd) as LONG_PTR);
             get_window_from_handle_mut(&mut hwnd).on_create();
             0
         },
         WM_CLOSE => {
-            println!("WM_CLOSE");
-            DestroyWindow(hwnd);
+            if backend.user_data(hwnd) == 0 || get_window_from_handle_mut(&mut hwnd).on_close() {
+                backend.destroy_window(hwnd);
+            }
             0
         },
         WM_DESTROY => {
-            PostQuitMessage(0);
+            let ptr = backend.user_data(hwnd) as *mut Box<Window>;
+            let mut quit = true;
+            if !ptr.is_null() {
+                quit = (*ptr).quits_on_destroy();
+                (*ptr).on_destroy();
+                backend.set_user_data(hwnd, 0);
+                drop(Box::from_raw(ptr));
+            }
+            if quit {
+                backend.post_quit_message(0);
+            }
             0
         },
         WM_PAINT =>  {
+            let context = PaintContext::begin_paint(&hwnd);
+            if backend.user_data(hwnd) != 0 {
+                get_window_from_handle(&hwnd).paint(&context);
+            }
             0
         },
-        msg => DefWindowProcW(hwnd, msg, w_param, l_param)
+        task::WM_WAKE => {
+            if backend.user_data(hwnd) != 0 {
+                get_window_from_handle_mut(&mut hwnd).get_core_mut().run_task(w_param as usize);
+            }
+            0
+        },
+        msg if message::is_message_id(msg) => {
+            if backend.user_data(hwnd) == 0 {
+                return 0;
+            }
+            let core = get_window_from_handle_mut(&mut hwnd).get_core_mut();
+            if w_param == message::POSTED {
+                core.deliver_posted();
+                0
+            } else {
+                message::receive(core, l_param)
+            }
+        },
+        msg => {
+            let handled = backend.user_data(hwnd) != 0 &&
+                dispatch(get_window_from_handle_mut(&mut hwnd), Event::decode(msg, w_param, l_param));
+            if handled { 0 } else { backend.def_window_proc(hwnd, msg, w_param, l_param) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winapi::*;
+
+    use super::*;
+    use ::backend::headless::{ DrawCall, HeadlessBackend };
+    use ::constraints::Attribute;
+    use ::controls::container::Container;
+    use ::controls::label::Label;
+    use ::geometry::Rect;
+    use ::layout::{ Dock, DockPanel, Grid, GridLength, Layout, Orientation, Panel, StackPanel };
+    use ::testing::{ self, open_window };
+
+    use std::ptr;
+    use std::rc::Rc;
+
+    fn label(left: i32, top: i32, width: i32, height: i32) -> Box<Label> {
+        let mut label = Label::new();
+        label.text = "x".to_string();
+        label.set_position(left, top);
+        label.set_size(width, height);
+        Box::new(label)
+    }
+
+    /// Fills the client area of a new window with a container laid out by
+    /// `panel`, returning the window and the container.
+    fn window_with<P: Panel + 'static>(panel: P) -> (Rc<HeadlessBackend>, HWND, i32) {
+        let (backend, mut handle) = open_window(200, 100);
+        let id = testing::core(&mut handle).add_control(Box::new(Container::new(panel)));
+        testing::core(&mut handle).set_layout(id, Layout::new()).unwrap();
+        (backend, handle, id)
+    }
+
+    fn add_laid_out(handle: &mut HWND, parent: i32, layout: Layout) -> i32 {
+        let mut label = Label::new();
+        label.text = "x".to_string();
+        let id = testing::core(handle).add_child(parent, Box::new(label)).unwrap();
+        testing::core(handle).set_layout(id, layout).unwrap();
+        id
+    }
+
+    fn bounds_of(handle: &mut HWND, ids: &[i32]) -> Vec<Rect> {
+        ids.iter().map(|&id| testing::core(handle).controls().client_bounds(id).unwrap()).collect()
+    }
+
+    #[test]
+    fn grid_cells_follow_the_window_size() {
+        let mut grid = Grid::new();
+        grid.add_column(GridLength::Fixed(50)).add_column(GridLength::Star(1.0))
+            .add_row(GridLength::Auto).add_row(GridLength::Star(1.0));
+        let (backend, mut handle, parent) = window_with(grid);
+        let mut header = Layout::new();
+        header.set_height(20).set_span(1, 2);
+        let mut sidebar = Layout::new();
+        sidebar.set_cell(1, 0);
+        let mut content = Layout::new();
+        content.set_cell(1, 1);
+        let ids = [add_laid_out(&mut handle, parent, header),
+                   add_laid_out(&mut handle, parent, sidebar),
+                   add_laid_out(&mut handle, parent, content)];
+
+        backend.resize(handle, 200, 100);
+        assert_eq!(bounds_of(&mut handle, &ids),
+                   vec![Rect::new(0, 0, 200, 20), Rect::new(0, 20, 50, 100), Rect::new(50, 20, 200, 100)]);
+
+        backend.resize(handle, 300, 150);
+        assert_eq!(bounds_of(&mut handle, &ids),
+                   vec![Rect::new(0, 0, 300, 20), Rect::new(0, 20, 50, 150), Rect::new(50, 20, 300, 150)]);
+    }
+
+    #[test]
+    fn stacked_children_follow_the_window_width() {
+        let mut stack = StackPanel::new(Orientation::Vertical);
+        stack.set_spacing(5);
+        let (backend, mut handle, parent) = window_with(stack);
+        let mut first = Layout::new();
+        first.set_height(20);
+        let mut second = Layout::new();
+        second.set_height(30);
+        let ids = [add_laid_out(&mut handle, parent, first), add_laid_out(&mut handle, parent, second)];
+
+        backend.resize(handle, 200, 100);
+        assert_eq!(bounds_of(&mut handle, &ids), vec![Rect::new(0, 0, 200, 20), Rect::new(0, 25, 200, 55)]);
+
+        backend.resize(handle, 120, 40);
+        assert_eq!(bounds_of(&mut handle, &ids), vec![Rect::new(0, 0, 120, 20), Rect::new(0, 25, 120, 55)]);
+    }
+
+    #[test]
+    fn docked_children_follow_the_window_size() {
+        let (backend, mut handle, parent) = window_with(DockPanel::new());
+        let mut top = Layout::new();
+        top.set_dock(Dock::Top).set_height(20);
+        let mut left = Layout::new();
+        left.set_dock(Dock::Left).set_width(40);
+        let mut bottom = Layout::new();
+        bottom.set_dock(Dock::Bottom).set_height(10);
+        let ids = [add_laid_out(&mut handle, parent, top),
+                   add_laid_out(&mut handle, parent, left),
+                   add_laid_out(&mut handle, parent, bottom),
+                   add_laid_out(&mut handle, parent, Layout::new())];
+
+        backend.resize(handle, 200, 100);
+        assert_eq!(bounds_of(&mut handle, &ids), vec![
+            Rect::new(0, 0, 200, 20), Rect::new(0, 20, 40, 100),
+            Rect::new(40, 90, 200, 100), Rect::new(40, 20, 200, 90),
+        ]);
+
+        backend.resize(handle, 300, 150);
+        assert_eq!(bounds_of(&mut handle, &ids), vec![
+            Rect::new(0, 0, 300, 20), Rect::new(0, 20, 40, 150),
+            Rect::new(40, 140, 300, 150), Rect::new(40, 20, 300, 140),
+        ]);
+    }
+
+    #[test]
+    fn invalidating_a_control_dirties_its_bounds() {
+        let (backend, mut handle) = open_window(200, 100);
+        let first = testing::core(&mut handle).add_control(label(10, 10, 40, 10));
+        let second = testing::core(&mut handle).add_control(label(50, 10, 40, 10));
+        let far = testing::core(&mut handle).add_control(label(10, 70, 40, 10));
+        backend.paint(handle);
+        assert!(backend.invalid_region(handle).is_empty());
+        assert!(testing::core(&mut handle).dirty_region().is_empty());
+
+        testing::core(&mut handle).invalidate(first);
+        assert_eq!(backend.invalid_region(handle), vec![Rect::new(10, 10, 50, 20)]);
+
+        // Neighbours of the same height coalesce, the one further down not.
+        testing::core(&mut handle).invalidate(second);
+        testing::core(&mut handle).invalidate(far);
+        testing::core(&mut handle).invalidate(first);
+        let expected = vec![Rect::new(10, 10, 90, 20), Rect::new(10, 70, 50, 80)];
+        assert_eq!(backend.invalid_region(handle), expected);
+        assert_eq!(testing::core(&mut handle).dirty_region().rects(), &expected[..]);
+
+        backend.paint(handle);
+        assert!(backend.invalid_region(handle).is_empty());
+        assert!(testing::core(&mut handle).dirty_region().is_empty());
+    }
+
+    #[test]
+    fn controls_added_after_painting_get_painted() {
+        let (backend, mut handle) = open_window(200, 100);
+        backend.paint(handle);
+        testing::core(&mut handle).add_control(label(10, 10, 40, 10));
+        assert_eq!(backend.invalid_region(handle), vec![Rect::new(10, 10, 50, 20)]);
+        let texts = backend.paint(handle).into_iter().filter(|call| match *call {
+            DrawCall::Text { ref text, .. } => text == "x",
+            _ => false,
+        }).count();
+        assert_eq!(texts, 1);
+    }
+
+    #[test]
+    fn children_are_invalidated_in_client_coordinates() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut container = Container::new(StackPanel::new(Orientation::Vertical));
+        container.bounds = Rect::new(100, 50, 180, 90);
+        let parent = testing::core(&mut handle).add_control(Box::new(container));
+        let child = testing::core(&mut handle).add_child(parent, label(0, 0, 30, 12)).unwrap();
+        backend.paint(handle);
+
+        let bounds = testing::core(&mut handle).controls().client_bounds(child).unwrap();
+        assert_eq!(bounds.origin(), ::geometry::Point::new(100, 50));
+        testing::core(&mut handle).invalidate(child);
+        assert_eq!(backend.invalid_region(handle), vec![bounds]);
+    }
+
+    #[test]
+    fn controls_without_bounds_invalidate_everything() {
+        let (backend, mut handle) = open_window(200, 100);
+        let mut growing = Label::new();
+        growing.text = "grows with its text".to_string();
+        let id = testing::core(&mut handle).add_control(Box::new(growing));
+        backend.paint(handle);
+
+        testing::core(&mut handle).invalidate(id);
+        assert_eq!(backend.invalid_region(handle), vec![Rect::new(0, 0, 200, 100)]);
+    }
+
+    #[test]
+    fn unknown_controls_and_outside_areas_dirty_nothing() {
+        let (backend, mut handle) = open_window(200, 100);
+        let id = testing::core(&mut handle).add_control(label(10, 10, 40, 10));
+        testing::core(&mut handle).remove_control(id);
+        backend.paint(handle);
+
+        testing::core(&mut handle).invalidate(id);
+        testing::core(&mut handle).invalidate(1234);
+        testing::core(&mut handle).invalidate_rect(Rect::new(300, 300, 400, 400));
+        assert!(backend.invalid_region(handle).is_empty());
+
+        // Partly outside gets clipped to the client area.
+        testing::core(&mut handle).invalidate_rect(Rect::new(150, -20, 260, 30));
+        assert_eq!(backend.invalid_region(handle), vec![Rect::new(150, 0, 200, 30)]);
+    }
+
+    thread_local!(static LAYOUT_ERRORS: RefCell<Vec<usize>> = RefCell::new(Vec::new()));
+
+    /// Remembers how many constraints every layout error was about.
+    struct LayoutWindow {
+        core: Option<WindowCore>,
+    }
+
+    impl Window for LayoutWindow {
+        fn init_handle(&mut self, handle: HWND) {
+            self.core = Some(WindowCore::from_handle(handle));
+        }
+
+        fn get_core<'a>(&'a self) -> &'a WindowCore {
+            self.core.as_ref().unwrap()
+        }
+
+        fn get_core_mut<'a>(&'a mut self) -> &'a mut WindowCore {
+            self.core.as_mut().unwrap()
+        }
+
+        fn on_layout_error(&mut self, error: &Unsatisfiable) {
+            LAYOUT_ERRORS.with(|e| e.borrow_mut().push(error.0.len()));
+        }
+    }
+
+    impl Paintable for LayoutWindow {
+        fn paint(&self, context: &PaintContext) {
+            self.get_core().paint(context);
+        }
+    }
+
+    impl WindowClass for LayoutWindow {
+        fn class_name() -> &'static str { "layout_wnd_class" }
+        fn default_title() -> &'static str { "Layout" }
+
+        fn new() -> Box<Window> {
+            Box::new(LayoutWindow {
+                core: None,
+            })
+        }
+    }
+
+    #[test]
+    fn resizing_reports_unsatisfiable_constraints() {
+        let backend = HeadlessBackend::install();
+        WindowClassBuilder::<LayoutWindow>::new().register(ptr::null_mut());
+        let mut handle = WindowBuilder::<LayoutWindow>::new(ptr::null_mut())
+            .set_width(200).set_height(100).build().unwrap();
+        let id = testing::core(&mut handle).add_control(label(10, 10, 90, 20));
+        backend.resize(handle, 200, 100);
+        assert!(LAYOUT_ERRORS.with(|e| e.borrow().is_empty()));
+
+        testing::core(&mut handle).add_constraint(Constraint::new(id, Attribute::Width).at_least(80));
+        testing::core(&mut handle).add_constraint(Constraint::new(id, Attribute::Width).at_most(50));
+        backend.resize(handle, 300, 100);
+        assert_eq!(LAYOUT_ERRORS.with(|e| e.borrow().clone()), vec![1]);
+        assert!(testing::core(&mut handle).layout_error().is_some());
     }
 }